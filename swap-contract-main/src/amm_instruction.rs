@@ -4,13 +4,18 @@
 
 use crate::curve::{base::SwapCurve, fees::Fees};
 use crate::error::AmmError;
+use crate::governance::MAX_GOVERNANCE_SIGNERS;
+use crate::registry::MintListMode;
+use arrayref::array_ref;
 use solana_program::{
+    clock::UnixTimestamp,
     instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
     program_pack::Pack,
     pubkey::Pubkey,
+    system_program, sysvar,
 };
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::mem::size_of;
 
 #[cfg(feature = "fuzz")]
@@ -32,6 +37,16 @@ pub struct UpdateStateInstruction {
     pub swap_curve: SwapCurve,
 }
 
+/// QueueStateUpdate instruction data
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub struct QueueStateUpdateInstruction {
+    /// Seconds before the update can be executed, at least the minimum
+    /// delay of the swap constraints
+    pub delay: UnixTimestamp,
+    /// New program state parameters
+    pub update: UpdateStateInstruction,
+}
 
 /// Initialize instruction data
 #[repr(C)]
@@ -39,6 +54,17 @@ pub struct UpdateStateInstruction {
 pub struct InitializeInstruction {
     /// nonce used to create valid program address
     pub nonce: u8,
+    /// all swap fees of the pool, the program state fees are used
+    /// when not provided
+    pub fees: Option<Fees>,
+    /// swap curve info for pool, including CurveType and anything
+    /// else that may be required, the program state curve is used
+    /// when not provided
+    pub swap_curve: Option<SwapCurve>,
+    /// create the swap account at the address derived from the mints and
+    /// the curve type, see [find_pool_address](../registry/fn.find_pool_address.html),
+    /// instead of using a keypair account
+    pub derived_address: bool,
 }
 
 /// Swap instruction data
@@ -52,6 +78,53 @@ pub struct SwapInstruction {
     pub minimum_amount_out: u64,
 }
 
+/// SwapExactAmountOut instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapExactAmountOutInstruction {
+    /// DESTINATION amount to receive, input from SOURCE is based on the exchange rate
+    pub amount_out: u64,
+    /// Maximum amount of SOURCE token to input, fees included, prevents excessive slippage
+    pub maximum_amount_in: u64,
+}
+
+/// Number of accounts of each pool in a route swap
+pub const ROUTE_SWAP_HOP_ACCOUNTS: usize = 8;
+
+/// RouteSwap instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteSwapInstruction {
+    /// SOURCE amount to transfer into the first pool of the route
+    pub amount_in: u64,
+    /// Minimum amount of DESTINATION token out of the last pool of the
+    /// route, prevents excessive slippage
+    pub minimum_amount_out: u64,
+}
+
+/// Accounts of one pool in a route swap
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteSwapHop {
+    /// Token-swap
+    pub swap_pubkey: Pubkey,
+    /// swap authority
+    pub authority_pubkey: Pubkey,
+    /// token SOURCE Account of the user
+    pub source_pubkey: Pubkey,
+    /// token Base Account to swap INTO
+    pub swap_source_pubkey: Pubkey,
+    /// token Base Account to swap FROM
+    pub swap_destination_pubkey: Pubkey,
+    /// token DESTINATION Account of the user, SOURCE of the next pool
+    pub destination_pubkey: Pubkey,
+    /// Pool token mint
+    pub pool_mint_pubkey: Pubkey,
+    /// Fee token account, to receive trading fees
+    pub fee_account_pubkey: Pubkey,
+}
+
 /// Instruction instruction data
 #[cfg_attr(feature = "fuzz", derive(Arbitrary))]
 #[repr(C)]
@@ -104,114 +177,803 @@ pub struct WithdrawSingleTokenTypeExactAmountOut {
     pub maximum_pool_token_amount: u64,
 }
 
+/// InitializeConcentratedPool instruction data
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub struct InitializeConcentratedPoolInstruction {
+    /// nonce used to create valid program address
+    pub nonce: u8,
+    /// Position ticks must be multiples of the tick spacing
+    pub tick_spacing: u16,
+    /// Initial square root price of token A in token B, as Q64.64
+    pub initial_sqrt_price: u128,
+    /// all swap fees of the pool, the program state fees are used
+    /// when not provided
+    pub fees: Option<Fees>,
+}
+
+/// OpenPosition instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenPositionInstruction {
+    /// Lower tick of the price range
+    pub tick_lower: i32,
+    /// Upper tick of the price range
+    pub tick_upper: i32,
+}
+
+/// IncreaseLiquidity instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IncreaseLiquidityInstruction {
+    /// Liquidity to add to the position. token_a and token_b amounts are
+    /// set by the current price and the price range of the position
+    pub liquidity: u128,
+    /// Maximum token A amount to deposit, prevents excessive slippage
+    pub maximum_token_a_amount: u64,
+    /// Maximum token B amount to deposit, prevents excessive slippage
+    pub maximum_token_b_amount: u64,
+}
+
+/// DecreaseLiquidity instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecreaseLiquidityInstruction {
+    /// Liquidity to remove from the position
+    pub liquidity: u128,
+    /// Minimum amount of token A to receive, prevents excessive slippage
+    pub minimum_token_a_amount: u64,
+    /// Minimum amount of token B to receive, prevents excessive slippage
+    pub minimum_token_b_amount: u64,
+}
+
+/// ConcentratedSwap instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConcentratedSwapInstruction {
+    /// SOURCE amount to transfer, output to DESTINATION is based on the exchange rate
+    pub amount_in: u64,
+    /// Minimum amount of DESTINATION token to output, prevents excessive slippage
+    pub minimum_amount_out: u64,
+    /// Square root price, as Q64.64, at which the swap stops even if the
+    /// amount in isn't used up. Zero for no limit.
+    pub sqrt_price_limit: u128,
+}
+
+/// RampAmp instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RampAmpInstruction {
+    /// Amplification coefficient at the end of the ramp
+    pub target_amp: u64,
+    /// Unix timestamp of the end of the ramp
+    pub stop_ramp_ts: UnixTimestamp,
+}
+
+/// SetPause instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetPauseInstruction {
+    /// Halt or resume swaps, deposits and single-sided withdrawals
+    pub is_paused: bool,
+}
+
+/// SetOrderbook instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetOrderbookInstruction {
+    /// Share of each reserve posted on the book, in basis points. Zero
+    /// disables the hybrid mode.
+    pub depth_bps: u16,
+    /// Distance between the orders and the curve's spot price, in basis points
+    pub spread_bps: u16,
+    /// Number of orders per side
+    pub levels: u8,
+}
+
+/// SplitSwap instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplitSwapInstruction {
+    /// SOURCE amount to transfer, split between the pool and the market
+    pub amount_in: u64,
+    /// Minimum amount of DESTINATION token received from both venues,
+    /// prevents excessive slippage
+    pub minimum_amount_out: u64,
+}
+
+/// SetProtocolFeeMode instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetProtocolFeeModeInstruction {
+    /// Leave the owner fee in the pool and pay it as pool tokens minted to
+    /// the fee owner, instead of transferring it on every swap
+    pub lp_protocol_fee: bool,
+}
+
+/// SetPoolCreation instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetPoolCreationInstruction {
+    /// Lets anyone create pools, not only the state owner
+    pub permissionless_pools: bool,
+    /// Restriction of the mints of pools created by others than the state
+    /// owner
+    pub mint_list_mode: MintListMode,
+    /// Lamports paid to the fee owner by others than the state owner to
+    /// create a pool
+    pub pool_creation_fee: u64,
+}
+
+/// UpdateMintList instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpdateMintListInstruction {
+    /// Mint to list or unlist
+    pub mint: Pubkey,
+    /// Adds the mint to the list, or removes it
+    pub listed: bool,
+}
+
+/// ClosePool instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosePoolInstruction {
+    /// Close a paused pool even though pool tokens above the locked minimum
+    /// supply are outstanding
+    pub wind_down: bool,
+}
+
+/// CreateGovernance instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateGovernanceInstruction {
+    /// Bump seed of the governance authority
+    pub nonce: u8,
+    /// Approvals needed to execute a proposal
+    pub threshold: u8,
+    /// Signers allowed to propose and approve
+    pub signers: Vec<Pubkey>,
+}
+
+/// CreateProposal instruction data, an instruction of the program to
+/// execute with the governance authority as a signer
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateProposalInstruction {
+    /// Accounts of the proposed instruction
+    pub accounts: Vec<AccountMeta>,
+    /// Data of the proposed instruction
+    pub data: Vec<u8>,
+}
+
+/// Serum market accounts of a split swap
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplitSwapMarketAccounts {
+    /// Serum dex program id
+    pub dex_program_id: Pubkey,
+    /// Serum market of the pool
+    pub market_pubkey: Pubkey,
+    /// open orders account of the user transfer authority on the market
+    pub open_orders_pubkey: Pubkey,
+    /// request queue of the market
+    pub request_queue_pubkey: Pubkey,
+    /// event queue of the market
+    pub event_queue_pubkey: Pubkey,
+    /// bids of the market
+    pub bids_pubkey: Pubkey,
+    /// asks of the market
+    pub asks_pubkey: Pubkey,
+    /// base token vault of the market
+    pub coin_vault_pubkey: Pubkey,
+    /// quote token vault of the market
+    pub pc_vault_pubkey: Pubkey,
+    /// vault signer of the market
+    pub vault_signer_pubkey: Pubkey,
+}
+
+/// Accounts of a pool and of its Serum market, used by the orderbook
+/// instructions
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolMarketAccounts {
+    /// Token-swap
+    pub swap_pubkey: Pubkey,
+    /// swap authority, owner of the open orders account
+    pub authority_pubkey: Pubkey,
+    /// token_a Base Account of the pool
+    pub token_a_pubkey: Pubkey,
+    /// token_b Base Account of the pool
+    pub token_b_pubkey: Pubkey,
+    /// Serum dex program id
+    pub dex_program_id: Pubkey,
+    /// Serum market
+    pub market_pubkey: Pubkey,
+    /// open orders account of the pool
+    pub open_orders_pubkey: Pubkey,
+    /// request queue of the market
+    pub request_queue_pubkey: Pubkey,
+    /// event queue of the market
+    pub event_queue_pubkey: Pubkey,
+    /// bids of the market
+    pub bids_pubkey: Pubkey,
+    /// asks of the market
+    pub asks_pubkey: Pubkey,
+    /// base token vault of the market
+    pub coin_vault_pubkey: Pubkey,
+    /// quote token vault of the market
+    pub pc_vault_pubkey: Pubkey,
+    /// vault signer of the market
+    pub vault_signer_pubkey: Pubkey,
+}
+
 /// Instructions supported by the token swap program.
 #[repr(C)]
 #[derive(Debug, PartialEq)]
 pub enum AmmInstruction {
     ///   Initializes a new AmmInfo.
     ///
-    ///   0. `[writable, signer]` New Token-swap to create.
+    ///   0. `[writable, signer]` New Token-swap to create, or `[writable]`
+    ///      empty account at the derived pool address.
     ///   1. `[]` swap authority derived from `create_program_address(&[Token-swap account])`
-    ///   2. `[]` AMMID of this account`
-    ///   3. `[]` token_a Account. Must be non zero, owned by swap authority.
-    ///   4. `[]` token_b Account. Must be non zero, owned by swap authority.
-    ///   5. `[writable]` Pool Token Mint. Must be empty, owned by swap authority.
-    ///   6. `[]` Token A Account to transfer fees when swap.
-    ///   7. `[]` Token B Account to transfer fees when swap.
-    ///   Must be empty, not owned by swap authority
-    ///   8. `[writable]` Pool Token Account to deposit the initial pool token
+    ///   2. `[]` Program state account
+    ///   3. `[]` AMMID of this account`
+    ///   4. `[]` token_a Account. Must be non zero, owned by swap authority.
+    ///   5. `[]` token_b Account. Must be non zero, owned by swap authority.
+    ///   6. `[writable]` Pool Token Mint. Must be empty, owned by swap authority.
+    ///   7. `[writable]` Pool Token Account to deposit the initial pool token
     ///   supply.  Must be empty, not owned by swap authority.
+    ///   8. `[]` Market ID
     ///   9. '[]` Token program id
-    ///   10. []  Dex Program ID
-    ///   11. []  Market ID
+    ///   10. `[]` Dex Program ID
+    ///   11. `[writable, signer]` Pool creator, pays for the created
+    ///       accounts. The state owner unless the program state allows
+    ///       permissionless pools.
+    ///   12. `[writable]` Pool marker account, derived from the mints and curve type
+    ///   13. `[]` System program
+    ///   14. `[]` Rent sysvar
+    ///   15. `[]` Mint list account, derived from the program id
+    ///   16. `[writable]` Fee owner of the program state, receives the pool
+    ///       creation fee
+    ///   17. `[writable]` Pool Token Account to lock the minimum pool token
+    ///       supply. Must be empty, owned by the lock address of the swap.
+    ///
+    ///   The pool fees and curve are validated against the swap constraints
+    ///   and may not be lower than the program state fees. If they are
+    ///   omitted, the pool is created with the program state defaults.
+    ///
+    ///   Pools created by others than the state owner pay the pool creation
+    ///   fee, and their mints are checked against the mint list.
+    ///
+    ///   The pool marker is created and marked, which fails if the pair
+    ///   already has a pool with the same curve type.
+    ///
+    ///   The minimum pool token supply is minted to the lock account, where
+    ///   no one can burn it, and the rest of the initial supply to the
+    ///   destination.
     Initialize(InitializeInstruction),
 
     ///   Swap the tokens in the pool.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
-    ///   2. `[]` user transfer authority
-    ///   3. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
-    ///   4. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
-    ///   5. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
-    ///   6. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
-    ///   7. `[writable]` Pool token mint, to generate trading fees
-    ///   8. `[writable]` Fee token account, to receive trading fees
-    ///   9. `[writable]` Fee wallet account, to receive fees when swap from SOL
-    ///   10. '[]` Token program id
-    ///   11 `[]  System Program ID to send SOL
+    ///   2. `[writable, signer]` user transfer authority
+    ///   3. `[]` Program state account
+    ///   4. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
+    ///   5. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
+    ///   6. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   7. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
+    ///   8. `[writable]` Pool token mint
+    ///   9. `[writable]` Fee token account, to receive trading fees
+    ///   10. `[writable]` Fee wallet account, to receive fees when swap from SOL
+    ///   11. '[]` Token program id
+    ///   12. `[]` System Program ID to send SOL
+    ///   13. `[writable]` Optional host fee token account of the SOURCE token,
+    ///       to receive the host share of the owner fee. Can't be a vault of
+    ///       the pool nor the fee token account.
     Swap(SwapInstruction),
 
     ///   Deposit both types of tokens into the pool.  The output is a "pool"
     ///   token representing ownership in the pool. Inputs are converted to
     ///   the current ratio.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
-    ///   2. `[]` user transfer authority
-    ///   3. `[writable]` token_a user transfer authority can transfer amount,
-    ///   4. `[writable]` token_b user transfer authority can transfer amount,
-    ///   5. `[writable]` token_a Base Account to deposit into.
-    ///   6. `[writable]` token_b Base Account to deposit into.
-    ///   7. `[writable]` Pool MINT account, swap authority is the owner.
-    ///   8. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
-    ///   9. '[]` Token program id
+    ///   2. `[signer]` user transfer authority
+    ///   3. `[]` Program state account
+    ///   4. `[writable]` token_a user transfer authority can transfer amount,
+    ///   5. `[writable]` token_b user transfer authority can transfer amount,
+    ///   6. `[writable]` token_a Base Account to deposit into.
+    ///   7. `[writable]` token_b Base Account to deposit into.
+    ///   8. `[writable]` Pool MINT account, swap authority is the owner.
+    ///   9. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
+    ///   10. `[writable]` Fee account, pool token account of the fee owner to
+    ///       receive the owner fee when it is paid as pool tokens.
+    ///   11. '[]` Token program id
     DepositAllTokenTypes(DepositInstruction),
 
     ///   Withdraw both types of tokens from the pool at the current ratio, given
     ///   pool tokens.  The pool tokens are burned in exchange for an equivalent
     ///   amount of token A and B.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
-    ///   2. `[]` user transfer authority
-    ///   3. `[writable]` Pool mint account, swap authority is the owner
-    ///   4. `[writable]` SOURCE Pool account, amount is transferable by user transfer authority.
-    ///   5. `[writable]` token_a Swap Account to withdraw FROM.
-    ///   6. `[writable]` token_b Swap Account to withdraw FROM.
-    ///   7. `[writable]` token_a user Account to credit.
-    ///   8. `[writable]` token_b user Account to credit.
-    ///   9. `[writable]` Fee account, to receive withdrawal fees
-    ///   10 '[]` Token program id
+    ///   2. `[signer]` user transfer authority
+    ///   3. `[]` Program state account
+    ///   4. `[writable]` Pool mint account, swap authority is the owner
+    ///   5. `[writable]` SOURCE Pool account, amount is transferable by user transfer authority.
+    ///   6. `[writable]` token_a Swap Account to withdraw FROM.
+    ///   7. `[writable]` token_b Swap Account to withdraw FROM.
+    ///   8. `[writable]` token_a user Account to credit.
+    ///   9. `[writable]` token_b user Account to credit.
+    ///   10. `[writable]` Fee account, pool token account of the fee owner to
+    ///       receive the owner withdraw fee, and the owner fee when it is
+    ///       paid as pool tokens.
+    ///   11. '[]` Token program id
     WithdrawAllTokenTypes(WithdrawInstruction),
 
     ///   Deposit one type of tokens into the pool.  The output is a "pool" token
     ///   representing ownership into the pool. Input token is converted as if
     ///   a swap and deposit all token types were performed.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
-    ///   2. `[]` user transfer authority
-    ///   3. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
-    ///   4. `[writable]` token_a Swap Account, may deposit INTO.
-    ///   5. `[writable]` token_b Swap Account, may deposit INTO.
-    ///   6. `[writable]` Pool MINT account, swap authority is the owner.
-    ///   7. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
-    ///   8. '[]` Token program id
+    ///   2. `[signer]` user transfer authority
+    ///   3. `[]` Program state account
+    ///   4. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
+    ///   5. `[writable]` token_a Swap Account, may deposit INTO.
+    ///   6. `[writable]` token_b Swap Account, may deposit INTO.
+    ///   7. `[writable]` Pool MINT account, swap authority is the owner.
+    ///   8. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
+    ///   9. `[writable]` Fee account, pool token account of the fee owner to
+    ///      receive the owner fee when it is paid as pool tokens.
+    ///   10. `[writable]` Fee account, token_(A|B) account of the fee owner to
+    ///       receive the owner share of the trading fee.
+    ///   11. '[]` Token program id
     DepositSingleTokenTypeExactAmountIn(DepositSingleTokenTypeExactAmountIn),
 
     ///   Withdraw one token type from the pool at the current ratio given the
     ///   exact amount out expected.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
-    ///   2. `[]` user transfer authority
-    ///   3. `[writable]` Pool mint account, swap authority is the owner
-    ///   4. `[writable]` SOURCE Pool account, amount is transferable by user transfer authority.
-    ///   5. `[writable]` token_a Swap Account to potentially withdraw from.
-    ///   6. `[writable]` token_b Swap Account to potentially withdraw from.
-    ///   7. `[writable]` token_(A|B) User Account to credit
-    ///   8. `[writable]` Fee account, to receive withdrawal fees
-    ///   9. '[]` Token program id
+    ///   2. `[signer]` user transfer authority
+    ///   3. `[]` Program state account
+    ///   4. `[writable]` Pool mint account, swap authority is the owner
+    ///   5. `[writable]` SOURCE Pool account, amount is transferable by user transfer authority.
+    ///   6. `[writable]` token_a Swap Account to potentially withdraw from.
+    ///   7. `[writable]` token_b Swap Account to potentially withdraw from.
+    ///   8. `[writable]` token_(A|B) User Account to credit
+    ///   9. `[writable]` Fee account, pool token account of the fee owner to
+    ///      receive the owner withdraw fee, and the owner fee when it is paid
+    ///      as pool tokens.
+    ///   10. `[writable]` Fee account, token_(A|B) account of the fee owner to
+    ///       receive the owner share of the trading fee.
+    ///   11. '[]` Token program id
     WithdrawSingleTokenTypeExactAmountOut(WithdrawSingleTokenTypeExactAmountOut),
     
-    ///   Update the program state
+    ///   Creates and initializes the program state. Once initialized, its
+    ///   parameters change with
+    ///   [QueueStateUpdate](enum.AmmInstruction.html#variant.QueueStateUpdate)
+    ///   and the state owner with
+    ///   [ProposeStateOwner](enum.AmmInstruction.html#variant.ProposeStateOwner)
     ///
-    ///   0. `[]` State account
-    ///   1. `[]` state authority
+    ///   0. `[writable]` State account
+    ///   1. `[writable, signer]` state owner, pays for the state account creation
     ///   2. `[]` fee owner account
+    ///   3. `[]` System Program ID
+    ///   4. `[]` Rent sysvar
+    UpdateState(UpdateStateInstruction),
+
+    ///   Swap the tokens in the pool, receiving an exact amount of the
+    ///   DESTINATION token.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[writable, signer]` user transfer authority
+    ///   3. `[]` Program state account
+    ///   4. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
+    ///   5. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
+    ///   6. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   7. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
+    ///   8. `[writable]` Pool token mint
+    ///   9. `[writable]` Fee token account, to receive trading fees
+    ///   10. `[writable]` Fee wallet account, to receive fees when swap from SOL
+    ///   11. '[]` Token program id
+    ///   12. `[]` System Program ID to send SOL
+    ///   13. `[writable]` Optional host fee token account of the SOURCE token,
+    ///       to receive the host share of the owner fee. Can't be a vault of
+    ///       the pool nor the fee token account.
+    SwapExactAmountOut(SwapExactAmountOutInstruction),
+
+    ///   Swap the tokens through several pools, the output of each pool is
+    ///   the input of the next one. Slippage is only checked on the output
+    ///   of the last pool.
+    ///
+    ///   0. `[writable, signer]` user transfer authority
+    ///   1. `[]` Program state account
+    ///   2. `[writable]` Fee wallet account, to receive fees when swap from SOL
+    ///   3. '[]` Token program id
+    ///   4. `[]` System Program ID to send SOL
+    ///
+    ///   Followed by `ROUTE_SWAP_HOP_ACCOUNTS` accounts for each pool:
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` token SOURCE Account, amount is transferable by user transfer authority,
+    ///   3. `[writable]` token Base Account to swap INTO.  Must be the SOURCE token.
+    ///   4. `[writable]` token Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   5. `[writable]` token DESTINATION Account assigned to USER as the owner,
+    ///      must be the SOURCE Account of the next pool
+    ///   6. `[writable]` Pool token mint
+    ///   7. `[writable]` Fee token account, to receive trading fees
+    RouteSwap(RouteSwapInstruction),
+
+    ///   Initializes a new concentrated liquidity pool.
+    ///
+    ///   0. `[writable]` New concentrated pool to create.
+    ///   1. `[]` pool authority derived from `create_program_address(&[Pool account])`
+    ///   2. `[]` Program state account
+    ///   3. `[]` token_a Account. Must be owned by pool authority.
+    ///   4. `[]` token_b Account. Must be owned by pool authority.
+    ///   5. '[]` Token program id
+    ///   6. `[signer]` state owner
+    ///
+    ///   The pool fees are validated like the Initialize fees, the program
+    ///   state fees are used when they are omitted.
+    InitializeConcentratedPool(InitializeConcentratedPoolInstruction),
+
+    ///   Opens an empty position in a price range of a concentrated pool.
+    ///
+    ///   0. `[]` Concentrated pool
+    ///   1. `[writable]` New position to create, owned by the program.
+    ///   2. `[signer]` Position owner
+    OpenPosition(OpenPositionInstruction),
+
+    ///   Deposits token A and B into a position, the fees earned so far
+    ///   are credited to the position.
+    ///
+    ///   0. `[writable]` Concentrated pool
+    ///   1. `[]` pool authority
+    ///   2. `[writable]` Position
+    ///   3. `[signer]` Position owner, can transfer the deposited amounts
+    ///   4. `[writable]` token_a user Account to deposit from.
+    ///   5. `[writable]` token_b user Account to deposit from.
+    ///   6. `[writable]` token_a Base Account to deposit into.
+    ///   7. `[writable]` token_b Base Account to deposit into.
+    ///   8. '[]` Token program id
+    IncreaseLiquidity(IncreaseLiquidityInstruction),
+
+    ///   Withdraws token A and B from a position, the fees earned so far
+    ///   are credited to the position and stay until collected.
+    ///
+    ///   0. `[writable]` Concentrated pool
+    ///   1. `[]` pool authority
+    ///   2. `[writable]` Position
+    ///   3. `[signer]` Position owner
+    ///   4. `[writable]` token_a user Account to credit.
+    ///   5. `[writable]` token_b user Account to credit.
+    ///   6. `[writable]` token_a Base Account to withdraw FROM.
+    ///   7. `[writable]` token_b Base Account to withdraw FROM.
+    ///   8. '[]` Token program id
+    DecreaseLiquidity(DecreaseLiquidityInstruction),
+
+    ///   Transfers the fees earned by a position to its owner.
+    ///
+    ///   0. `[writable]` Concentrated pool
+    ///   1. `[]` pool authority
+    ///   2. `[writable]` Position
+    ///   3. `[signer]` Position owner
+    ///   4. `[writable]` token_a user Account to credit.
+    ///   5. `[writable]` token_b user Account to credit.
+    ///   6. `[writable]` token_a Base Account to withdraw FROM.
+    ///   7. `[writable]` token_b Base Account to withdraw FROM.
+    ///   8. '[]` Token program id
+    CollectFees,
+
+    ///   Swap the tokens in a concentrated pool.
+    ///
+    ///   0. `[writable]` Concentrated pool
+    ///   1. `[]` pool authority
+    ///   2. `[signer]` user transfer authority
+    ///   3. `[]` Program state account
+    ///   4. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
+    ///   5. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
+    ///   6. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   7. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
+    ///   8. `[writable]` Fee token account, to receive the owner fees
+    ///   9. '[]` Token program id
+    ConcentratedSwap(ConcentratedSwapInstruction),
+
+    ///   Starts moving the amp of a stable curve pool linearly from its
+    ///   current value to the target, bounded by the limits in `curve::stable`.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` Program state account
+    ///   2. `[signer]` state owner
+    RampAmp(RampAmpInstruction),
+
+    ///   Stops the amp ramp of a stable curve pool at its current value.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` Program state account
+    ///   2. `[signer]` state owner
+    StopRampAmp,
+
+    ///   Halts or resumes swaps, deposits and single-sided withdrawals, of
+    ///   one pool when the Token-swap is given or of the whole program
+    ///   otherwise. Pausing the program also halts ConcentratedSwap, a
+    ///   paused concentrated pool halts its swaps, OpenPosition and
+    ///   IncreaseLiquidity. WithdrawAllTokenTypes, DecreaseLiquidity and
+    ///   CollectFees are never paused so liquidity providers can always
+    ///   exit.
+    ///
+    ///   0. `[writable]` Program state account
+    ///   1. `[signer]` state owner
+    ///   2. `[writable]` Optional Token-swap or concentrated pool
+    SetPause(SetPauseInstruction),
+
+    ///   Sets the open orders account and the order settings the pool uses
+    ///   to post part of its reserves on the Serum market of the pool. The
+    ///   open orders account can't be changed while orders are outstanding.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` Program state account
+    ///   2. `[signer]` state owner
+    ///   3. `[]` open orders account, owned by the Serum dex program and
+    ///      initialized with the swap authority as its owner
+    SetOrderbook(SetOrderbookInstruction),
+
+    ///   Cancels the pool orders, settles the funds back into the pool and
+    ///   posts new orders around the current curve price. Deposits and
+    ///   withdrawals are refused until CancelAllOrders is run.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` token_a Base Account
+    ///   3. `[writable]` token_b Base Account
+    ///   4. `[]` Serum dex program id
+    ///   5. `[writable]` Serum market
+    ///   6. `[writable]` open orders account of the pool
+    ///   7. `[writable]` request queue of the market
+    ///   8. `[writable]` event queue of the market
+    ///   9. `[writable]` bids of the market
+    ///   10. `[writable]` asks of the market
+    ///   11. `[writable]` base token vault of the market
+    ///   12. `[writable]` quote token vault of the market
+    ///   13. `[]` vault signer of the market
+    ///   14. '[]` Token program id
+    ///   15. '[]` Rent sysvar
+    ///   16. `[]` Program state account
+    ///   17. `[signer]` state owner
+    RefreshOrders,
+
+    ///   Moves the filled amounts of the pool orders from the open orders
+    ///   account back into the pool.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` token_a Base Account
+    ///   3. `[writable]` token_b Base Account
+    ///   4. `[]` Serum dex program id
+    ///   5. `[writable]` Serum market
+    ///   6. `[writable]` open orders account of the pool
+    ///   7. `[writable]` request queue of the market
+    ///   8. `[writable]` event queue of the market
+    ///   9. `[writable]` bids of the market
+    ///   10. `[writable]` asks of the market
+    ///   11. `[writable]` base token vault of the market
+    ///   12. `[writable]` quote token vault of the market
+    ///   13. `[]` vault signer of the market
+    ///   14. '[]` Token program id
+    ///   15. '[]` Rent sysvar
+    SettleFunds,
+
+    ///   Cancels the pool orders and settles all funds back into the pool,
+    ///   which allows deposits and withdrawals again once the open orders
+    ///   account is empty.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` token_a Base Account
+    ///   3. `[writable]` token_b Base Account
+    ///   4. `[]` Serum dex program id
+    ///   5. `[writable]` Serum market
+    ///   6. `[writable]` open orders account of the pool
+    ///   7. `[writable]` request queue of the market
+    ///   8. `[writable]` event queue of the market
+    ///   9. `[writable]` bids of the market
+    ///   10. `[writable]` asks of the market
+    ///   11. `[writable]` base token vault of the market
+    ///   12. `[writable]` quote token vault of the market
+    ///   13. `[]` vault signer of the market
+    ///   14. '[]` Token program id
+    ///   15. '[]` Rent sysvar
+    CancelAllOrders,
+
+    ///   Swap the tokens across the pool and its Serum market, the amount in
+    ///   is split between the pool curve and a taker order on the book for
+    ///   the largest total output. Slippage is checked on the sum received
+    ///   from both venues. Tokens the open orders account held before the
+    ///   order are settled to the user but aren't counted in the output.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[writable, signer]` user transfer authority, owner of the open orders account
+    ///   3. `[]` Program state account
+    ///   4. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
+    ///   5. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
+    ///   6. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   7. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
+    ///   8. `[writable]` Pool token mint
+    ///   9. `[writable]` Fee token account, to receive trading fees
+    ///   10. `[writable]` Fee wallet account, to receive fees when swap from SOL
+    ///   11. '[]` Token program id
+    ///   12. `[]` System Program ID to send SOL
+    ///   13. `[]` Serum dex program id
+    ///   14. `[writable]` Serum market of the pool
+    ///   15. `[writable]` open orders account of the user transfer authority
+    ///   16. `[writable]` request queue of the market
+    ///   17. `[writable]` event queue of the market
+    ///   18. `[writable]` bids of the market
+    ///   19. `[writable]` asks of the market
+    ///   20. `[writable]` base token vault of the market
+    ///   21. `[writable]` quote token vault of the market
+    ///   22. `[]` vault signer of the market
+    ///   23. '[]` Rent sysvar
+    ///   24. `[writable]` Optional host fee token account of the SOURCE token,
+    ///       to receive the host share of the owner fee. Can't be a vault of
+    ///       the pool nor the fee token account.
+    SplitSwap(SplitSwapInstruction),
+
+    ///   Switches how a constant product pool pays its owner fee. In pool
+    ///   token mode the owner fee stays in the reserves and its share of
+    ///   the invariant growth is minted to the fee owner on the next
+    ///   deposit or withdrawal, Uniswap v2 `kLast` style. The fees accrued
+    ///   so far are minted before switching. Pools with a dynamic fee can't
+    ///   switch to pool token mode.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` Program state account
+    ///   3. `[signer]` state owner
+    ///   4. `[]` token_a Base Account
+    ///   5. `[]` token_b Base Account
+    ///   6. `[writable]` Pool mint account, swap authority is the owner
+    ///   7. `[writable]` Fee account, pool token account of the fee owner
+    ///   8. '[]` Token program id
+    SetProtocolFeeMode(SetProtocolFeeModeInstruction),
+
+    ///   Upgrades a swap or the program state account of an older layout to
+    ///   the latest one, growing the account in place. Anyone may migrate
+    ///   an account, its content is unchanged. Swaps of the first layout
+    ///   take the fees and curve of the program state they used so far.
+    ///
+    ///   0. `[writable]` Token-swap or program state account
+    ///   1. `[writable, signer]` Payer of the rent of the added space
+    ///   2. `[]` System program
+    ///   3. `[]` Rent sysvar
+    ///   4. `[]` Program state account
+    Migrate,
+
+    ///   Proposes a new state owner, replacing any pending proposal. The
+    ///   current owner keeps its rights until the proposed one accepts.
+    ///
+    ///   0. `[writable]` Program state account
+    ///   1. `[signer]` state owner
+    ///   2. `[]` proposed state owner
+    ProposeStateOwner,
+
+    ///   Makes the proposed state owner the state owner
+    ///
+    ///   0. `[writable]` Program state account
+    ///   1. `[signer]` proposed state owner
+    AcceptStateOwner,
+
+    ///   Drops the pending state owner proposal
+    ///
+    ///   0. `[writable]` Program state account
+    ///   1. `[signer]` state owner
+    CancelStateOwnerProposal,
+
+    ///   Queues new program state parameters, replacing any pending ones.
+    ///   They can be executed once the given delay has passed, which can't
+    ///   be shorter than the minimum delay of the swap constraints.
+    ///
+    ///   0. `[writable]` Program state account
+    ///   1. `[signer]` state owner
+    ///   2. `[]` new fee owner account
+    QueueStateUpdate(QueueStateUpdateInstruction),
+
+    ///   Applies the pending program state parameters after their delay
+    ///
+    ///   0. `[writable]` Program state account
+    ///   1. `[signer]` state owner
+    ExecuteStateUpdate,
+
+    ///   Drops the pending program state parameters
+    ///
+    ///   0. `[writable]` Program state account
+    ///   1. `[signer]` state owner
+    CancelStateUpdate,
+
+    ///   Initializes an M-of-N governance. Its authority, derived from
+    ///   `[GOVERNANCE_SEED, governance]`, signs the approved proposals and
+    ///   can be made the state owner.
+    ///
+    ///   0. `[writable]` Governance account, owned by the program
+    CreateGovernance(CreateGovernanceInstruction),
+
+    ///   Proposes an instruction of the program, approved by the proposer
+    ///
+    ///   0. `[writable]` Proposal account, owned by the program
+    ///   1. `[]` Governance account
+    ///   2. `[signer]` Governance signer
+    CreateProposal(CreateProposalInstruction),
+
+    ///   Approves a proposal
+    ///
+    ///   0. `[writable]` Proposal account
+    ///   1. `[]` Governance account
+    ///   2. `[signer]` Governance signer
+    ApproveProposal,
 
-    UpdateState(UpdateStateInstruction)
+    ///   Executes a proposal approved by the threshold of the governance,
+    ///   with the governance authority as a signer
+    ///
+    ///   0. `[writable]` Proposal account
+    ///   1. `[]` Governance account
+    ///   2. `[writable]` Governance authority
+    ///   3. `[]` This program id
+    ///   4. ..4+N `[]` Accounts of the proposed instruction, but the
+    ///      governance authority
+    ExecuteProposal,
+
+    ///   Configures who may create pools
+    ///
+    ///   0. `[writable]` Program state account
+    ///   1. `[signer]` state owner
+    SetPoolCreation(SetPoolCreationInstruction),
+
+    ///   Adds a mint to the mint list or removes it
+    ///
+    ///   0. `[]` Program state account
+    ///   1. `[writable, signer]` state owner, pays for the mint list creation
+    ///   2. `[writable]` Mint list account, derived from the program id
+    ///   3. `[]` System program
+    ///   4. `[]` Rent sysvar
+    UpdateMintList(UpdateMintListInstruction),
+
+    ///   Retires a pool whose pool token supply is down to the locked
+    ///   minimum. A pool is wound down by pausing it and letting its
+    ///   liquidity providers withdraw. The tokens left in the pool go to the
+    ///   fee owner, the token accounts of the pool and its marker are closed.
+    ///
+    ///   In an emergency wind-down, the state owner closes a paused pool
+    ///   with pool tokens still outstanding. They can't be redeemed anymore,
+    ///   the fee owner receives all the tokens of the pool instead.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` Program state account
+    ///   3. `[signer]` state owner
+    ///   4. `[writable]` token_a Swap Account
+    ///   5. `[writable]` token_b Swap Account
+    ///   6. `[]` Pool token mint
+    ///   7. `[writable]` token_a fee owner Account
+    ///   8. `[writable]` token_b fee owner Account
+    ///   9. `[writable]` Pool marker account
+    ///   10. `[writable]` Recipient of the lamports of the closed accounts
+    ///   11. `[]` Token program id
+    ClosePool(ClosePoolInstruction),
 }
 
 impl AmmInstruction {
@@ -220,14 +982,30 @@ impl AmmInstruction {
         let (&tag, rest) = input.split_first().ok_or(AmmError::InvalidInstruction)?;
         Ok(match tag {
             0 => {// Initial 
-                if rest.len() == 1 {
-                    let (&nonce, _rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
-                    Self::Initialize(InitializeInstruction {
-                        nonce,
-                    })
+                let (&nonce, rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                let (fees, swap_curve, rest) = if rest.len() >= Fees::LEN + SwapCurve::LEN {
+                    let (fees, rest) = rest.split_at(Fees::LEN);
+                    let (swap_curve, rest) = rest.split_at(SwapCurve::LEN);
+                    (
+                        Some(Fees::unpack_unchecked(fees)?),
+                        Some(SwapCurve::unpack_unchecked(swap_curve)?),
+                        rest,
+                    )
                 } else {
-                    return Err(AmmError::InvalidInstruction.into());
-                }
+                    (None, None, rest)
+                };
+                // the address flag is optional for older clients
+                let derived_address = match rest {
+                    [] | [0] => false,
+                    [1] => true,
+                    _ => return Err(AmmError::InvalidInstruction.into()),
+                };
+                Self::Initialize(InitializeInstruction {
+                    nonce,
+                    fees,
+                    swap_curve,
+                    derived_address,
+                })
             }
             1 => {
                 let (amount_in, rest) = Self::unpack_u64(rest)?;
@@ -274,59 +1052,342 @@ impl AmmInstruction {
                 })
             }
             6 => {// Upgrade Program State
-                let (initial_supply, rest) = Self::unpack_u64(rest)?;
-                if rest.len() >= Fees::LEN {
-                    let (fees, rest) = rest.split_at(Fees::LEN);
-                    let fees = Fees::unpack_unchecked(fees)?;
-                    let swap_curve = SwapCurve::unpack_unchecked(rest)?;
-                    Self::UpdateState(UpdateStateInstruction {
-                        initial_supply,
-                        fees,
-                        swap_curve,
-                    })
+                Self::UpdateState(Self::unpack_update_state(rest)?)
+            }
+            7 => {
+                let (amount_out, rest) = Self::unpack_u64(rest)?;
+                let (maximum_amount_in, _rest) = Self::unpack_u64(rest)?;
+                Self::SwapExactAmountOut(SwapExactAmountOutInstruction {
+                    amount_out,
+                    maximum_amount_in,
+                })
+            }
+            8 => {
+                let (amount_in, rest) = Self::unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
+                Self::RouteSwap(RouteSwapInstruction {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            9 => {
+                let (&nonce, rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                let (tick_spacing, rest) = Self::unpack_u16(rest)?;
+                let (initial_sqrt_price, rest) = Self::unpack_u128(rest)?;
+                let fees = if rest.is_empty() {
+                    None
+                } else if rest.len() == Fees::LEN {
+                    Some(Fees::unpack_unchecked(rest)?)
                 } else {
                     return Err(AmmError::InvalidInstruction.into());
-                }
-            }
-            _ => return Err(AmmError::InvalidInstruction.into()),
-        })
-    }
-
-    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
-        if input.len() >= 8 {
-            let (amount, rest) = input.split_at(8);
-            let amount = amount
-                .get(..8)
-                .and_then(|slice| slice.try_into().ok())
-                .map(u64::from_le_bytes)
-                .ok_or(AmmError::InvalidInstruction)?;
-            Ok((amount, rest))
-        } else {
-            Err(AmmError::InvalidInstruction.into())
-        }
-    }
-
-    /// Packs a [AmmInstruction](enum.AmmInstruction.html) into a byte buffer.
-    pub fn pack(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(size_of::<Self>());
-        match &*self {
-            Self::Initialize(InitializeInstruction {
-                nonce,
-            }) => {
-                buf.push(0);
-                buf.push(*nonce);
+                };
+                Self::InitializeConcentratedPool(InitializeConcentratedPoolInstruction {
+                    nonce,
+                    tick_spacing,
+                    initial_sqrt_price,
+                    fees,
+                })
             }
-            Self::Swap(SwapInstruction {
-                amount_in,
-                minimum_amount_out,
-            }) => {
-                buf.push(1);
-                buf.extend_from_slice(&amount_in.to_le_bytes());
-                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            10 => {
+                let (tick_lower, rest) = Self::unpack_i32(rest)?;
+                let (tick_upper, _rest) = Self::unpack_i32(rest)?;
+                Self::OpenPosition(OpenPositionInstruction {
+                    tick_lower,
+                    tick_upper,
+                })
             }
-            Self::DepositAllTokenTypes(DepositInstruction {
-                pool_token_amount,
-                maximum_token_a_amount,
+            11 => {
+                let (liquidity, rest) = Self::unpack_u128(rest)?;
+                let (maximum_token_a_amount, rest) = Self::unpack_u64(rest)?;
+                let (maximum_token_b_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::IncreaseLiquidity(IncreaseLiquidityInstruction {
+                    liquidity,
+                    maximum_token_a_amount,
+                    maximum_token_b_amount,
+                })
+            }
+            12 => {
+                let (liquidity, rest) = Self::unpack_u128(rest)?;
+                let (minimum_token_a_amount, rest) = Self::unpack_u64(rest)?;
+                let (minimum_token_b_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::DecreaseLiquidity(DecreaseLiquidityInstruction {
+                    liquidity,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                })
+            }
+            13 => Self::CollectFees,
+            15 => {
+                let (target_amp, rest) = Self::unpack_u64(rest)?;
+                let (stop_ramp_ts, _rest) = Self::unpack_u64(rest)?;
+                Self::RampAmp(RampAmpInstruction {
+                    target_amp,
+                    stop_ramp_ts: stop_ramp_ts as UnixTimestamp,
+                })
+            }
+            16 => Self::StopRampAmp,
+            17 => {
+                let (&is_paused, _rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                Self::SetPause(SetPauseInstruction {
+                    is_paused: match is_paused {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(AmmError::InvalidInstruction.into()),
+                    },
+                })
+            }
+            14 => {
+                let (amount_in, rest) = Self::unpack_u64(rest)?;
+                let (minimum_amount_out, rest) = Self::unpack_u64(rest)?;
+                let (sqrt_price_limit, _rest) = Self::unpack_u128(rest)?;
+                Self::ConcentratedSwap(ConcentratedSwapInstruction {
+                    amount_in,
+                    minimum_amount_out,
+                    sqrt_price_limit,
+                })
+            }
+            18 => {
+                let (depth_bps, rest) = Self::unpack_u16(rest)?;
+                let (spread_bps, rest) = Self::unpack_u16(rest)?;
+                let (&levels, _rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                Self::SetOrderbook(SetOrderbookInstruction {
+                    depth_bps,
+                    spread_bps,
+                    levels,
+                })
+            }
+            19 => Self::RefreshOrders,
+            20 => Self::SettleFunds,
+            21 => Self::CancelAllOrders,
+            22 => {
+                let (amount_in, rest) = Self::unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
+                Self::SplitSwap(SplitSwapInstruction {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            23 => {
+                let (&lp_protocol_fee, _rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                Self::SetProtocolFeeMode(SetProtocolFeeModeInstruction {
+                    lp_protocol_fee: match lp_protocol_fee {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(AmmError::InvalidInstruction.into()),
+                    },
+                })
+            }
+            24 => Self::Migrate,
+            25 => Self::ProposeStateOwner,
+            26 => Self::AcceptStateOwner,
+            27 => Self::CancelStateOwnerProposal,
+            28 => {
+                let (delay, rest) = Self::unpack_u64(rest)?;
+                Self::QueueStateUpdate(QueueStateUpdateInstruction {
+                    delay: delay as UnixTimestamp,
+                    update: Self::unpack_update_state(rest)?,
+                })
+            }
+            29 => Self::ExecuteStateUpdate,
+            30 => Self::CancelStateUpdate,
+            31 => {
+                let (&nonce, rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                let (&threshold, rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                let (&count, rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                let count = count as usize;
+                if count > MAX_GOVERNANCE_SIGNERS || rest.len() < count * 32 {
+                    return Err(AmmError::InvalidInstruction.into());
+                }
+                Self::CreateGovernance(CreateGovernanceInstruction {
+                    nonce,
+                    threshold,
+                    signers: rest[..count * 32]
+                        .chunks_exact(32)
+                        .map(|key| Pubkey::new_from_array(*array_ref![key, 0, 32]))
+                        .collect(),
+                })
+            }
+            32 => {
+                let (&count, rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                let count = count as usize;
+                if rest.len() < count * 34 {
+                    return Err(AmmError::InvalidInstruction.into());
+                }
+                let (accounts, data) = rest.split_at(count * 34);
+                Self::CreateProposal(CreateProposalInstruction {
+                    accounts: accounts
+                        .chunks_exact(34)
+                        .map(|meta| AccountMeta {
+                            pubkey: Pubkey::new_from_array(*array_ref![meta, 0, 32]),
+                            is_signer: meta[32] != 0,
+                            is_writable: meta[33] != 0,
+                        })
+                        .collect(),
+                    data: data.to_vec(),
+                })
+            }
+            33 => Self::ApproveProposal,
+            34 => Self::ExecuteProposal,
+            35 => {
+                let (&permissionless_pools, rest) =
+                    rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                let (&mint_list_mode, rest) =
+                    rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                let (pool_creation_fee, _rest) = Self::unpack_u64(rest)?;
+                Self::SetPoolCreation(SetPoolCreationInstruction {
+                    permissionless_pools: match permissionless_pools {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(AmmError::InvalidInstruction.into()),
+                    },
+                    mint_list_mode: MintListMode::try_from(mint_list_mode)
+                        .or(Err(AmmError::InvalidInstruction))?,
+                    pool_creation_fee,
+                })
+            }
+            36 => {
+                if rest.len() < 33 {
+                    return Err(AmmError::InvalidInstruction.into());
+                }
+                let (mint, rest) = rest.split_at(32);
+                Self::UpdateMintList(UpdateMintListInstruction {
+                    mint: Pubkey::new_from_array(*array_ref![mint, 0, 32]),
+                    listed: match rest[0] {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(AmmError::InvalidInstruction.into()),
+                    },
+                })
+            }
+            37 => {
+                let (&wind_down, _rest) = rest.split_first().ok_or(AmmError::InvalidInstruction)?;
+                Self::ClosePool(ClosePoolInstruction {
+                    wind_down: match wind_down {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(AmmError::InvalidInstruction.into()),
+                    },
+                })
+            }
+            _ => return Err(AmmError::InvalidInstruction.into()),
+        })
+    }
+
+    fn unpack_update_state(input: &[u8]) -> Result<UpdateStateInstruction, ProgramError> {
+        let (initial_supply, rest) = Self::unpack_u64(input)?;
+        if rest.len() >= Fees::LEN {
+            let (fees, rest) = rest.split_at(Fees::LEN);
+            let fees = Fees::unpack_unchecked(fees)?;
+            let swap_curve = SwapCurve::unpack_unchecked(rest)?;
+            Ok(UpdateStateInstruction {
+                initial_supply,
+                fees,
+                swap_curve,
+            })
+        } else {
+            Err(AmmError::InvalidInstruction.into())
+        }
+    }
+
+    fn pack_update_state(instruction: &UpdateStateInstruction, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&instruction.initial_supply.to_le_bytes());
+        let mut fees_slice = [0u8; Fees::LEN];
+        Pack::pack_into_slice(&instruction.fees, &mut fees_slice[..]);
+        buf.extend_from_slice(&fees_slice);
+        let mut swap_curve_slice = [0u8; SwapCurve::LEN];
+        Pack::pack_into_slice(&instruction.swap_curve, &mut swap_curve_slice[..]);
+        buf.extend_from_slice(&swap_curve_slice);
+    }
+
+    fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+        if input.len() >= 2 {
+            let (amount, rest) = input.split_at(2);
+            let amount = amount
+                .get(..2)
+                .and_then(|slice| slice.try_into().ok())
+                .map(u16::from_le_bytes)
+                .ok_or(AmmError::InvalidInstruction)?;
+            Ok((amount, rest))
+        } else {
+            Err(AmmError::InvalidInstruction.into())
+        }
+    }
+
+    fn unpack_i32(input: &[u8]) -> Result<(i32, &[u8]), ProgramError> {
+        if input.len() >= 4 {
+            let (amount, rest) = input.split_at(4);
+            let amount = amount
+                .get(..4)
+                .and_then(|slice| slice.try_into().ok())
+                .map(i32::from_le_bytes)
+                .ok_or(AmmError::InvalidInstruction)?;
+            Ok((amount, rest))
+        } else {
+            Err(AmmError::InvalidInstruction.into())
+        }
+    }
+
+    fn unpack_u128(input: &[u8]) -> Result<(u128, &[u8]), ProgramError> {
+        if input.len() >= 16 {
+            let (amount, rest) = input.split_at(16);
+            let amount = amount
+                .get(..16)
+                .and_then(|slice| slice.try_into().ok())
+                .map(u128::from_le_bytes)
+                .ok_or(AmmError::InvalidInstruction)?;
+            Ok((amount, rest))
+        } else {
+            Err(AmmError::InvalidInstruction.into())
+        }
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        if input.len() >= 8 {
+            let (amount, rest) = input.split_at(8);
+            let amount = amount
+                .get(..8)
+                .and_then(|slice| slice.try_into().ok())
+                .map(u64::from_le_bytes)
+                .ok_or(AmmError::InvalidInstruction)?;
+            Ok((amount, rest))
+        } else {
+            Err(AmmError::InvalidInstruction.into())
+        }
+    }
+
+    /// Packs a [AmmInstruction](enum.AmmInstruction.html) into a byte buffer.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(size_of::<Self>());
+        match &*self {
+            Self::Initialize(InitializeInstruction {
+                nonce,
+                fees,
+                swap_curve,
+                derived_address,
+            }) => {
+                buf.push(0);
+                buf.push(*nonce);
+                if let (Some(fees), Some(swap_curve)) = (fees, swap_curve) {
+                    let mut fees_slice = [0u8; Fees::LEN];
+                    Pack::pack_into_slice(fees, &mut fees_slice[..]);
+                    buf.extend_from_slice(&fees_slice);
+                    let mut swap_curve_slice = [0u8; SwapCurve::LEN];
+                    Pack::pack_into_slice(swap_curve, &mut swap_curve_slice[..]);
+                    buf.extend_from_slice(&swap_curve_slice);
+                }
+                buf.push(*derived_address as u8);
+            }
+            Self::Swap(SwapInstruction {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(1);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::DepositAllTokenTypes(DepositInstruction {
+                pool_token_amount,
+                maximum_token_a_amount,
                 maximum_token_b_amount,
             }) => {
                 buf.push(2);
@@ -362,19 +1423,199 @@ impl AmmInstruction {
                 buf.extend_from_slice(&destination_token_amount.to_le_bytes());
                 buf.extend_from_slice(&maximum_pool_token_amount.to_le_bytes());
             }
-            Self::UpdateState(UpdateStateInstruction {
-                initial_supply,
+            Self::UpdateState(instruction) => {
+                buf.push(6);
+                Self::pack_update_state(instruction, &mut buf);
+            }
+            Self::SwapExactAmountOut(SwapExactAmountOutInstruction {
+                amount_out,
+                maximum_amount_in,
+            }) => {
+                buf.push(7);
+                buf.extend_from_slice(&amount_out.to_le_bytes());
+                buf.extend_from_slice(&maximum_amount_in.to_le_bytes());
+            }
+            Self::RouteSwap(RouteSwapInstruction {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(8);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::InitializeConcentratedPool(InitializeConcentratedPoolInstruction {
+                nonce,
+                tick_spacing,
+                initial_sqrt_price,
                 fees,
-                swap_curve,
             }) => {
-                buf.push(6);
-                buf.extend_from_slice(&initial_supply.to_le_bytes());
-                let mut fees_slice = [0u8; Fees::LEN];
-                Pack::pack_into_slice(fees, &mut fees_slice[..]);
-                buf.extend_from_slice(&fees_slice);
-                let mut swap_curve_slice = [0u8; SwapCurve::LEN];
-                Pack::pack_into_slice(swap_curve, &mut swap_curve_slice[..]);
-                buf.extend_from_slice(&swap_curve_slice);
+                buf.push(9);
+                buf.push(*nonce);
+                buf.extend_from_slice(&tick_spacing.to_le_bytes());
+                buf.extend_from_slice(&initial_sqrt_price.to_le_bytes());
+                if let Some(fees) = fees {
+                    let mut fees_slice = [0u8; Fees::LEN];
+                    Pack::pack_into_slice(fees, &mut fees_slice[..]);
+                    buf.extend_from_slice(&fees_slice);
+                }
+            }
+            Self::OpenPosition(OpenPositionInstruction {
+                tick_lower,
+                tick_upper,
+            }) => {
+                buf.push(10);
+                buf.extend_from_slice(&tick_lower.to_le_bytes());
+                buf.extend_from_slice(&tick_upper.to_le_bytes());
+            }
+            Self::IncreaseLiquidity(IncreaseLiquidityInstruction {
+                liquidity,
+                maximum_token_a_amount,
+                maximum_token_b_amount,
+            }) => {
+                buf.push(11);
+                buf.extend_from_slice(&liquidity.to_le_bytes());
+                buf.extend_from_slice(&maximum_token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&maximum_token_b_amount.to_le_bytes());
+            }
+            Self::DecreaseLiquidity(DecreaseLiquidityInstruction {
+                liquidity,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                buf.push(12);
+                buf.extend_from_slice(&liquidity.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+            }
+            Self::CollectFees => {
+                buf.push(13);
+            }
+            Self::ConcentratedSwap(ConcentratedSwapInstruction {
+                amount_in,
+                minimum_amount_out,
+                sqrt_price_limit,
+            }) => {
+                buf.push(14);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+                buf.extend_from_slice(&sqrt_price_limit.to_le_bytes());
+            }
+            Self::RampAmp(RampAmpInstruction {
+                target_amp,
+                stop_ramp_ts,
+            }) => {
+                buf.push(15);
+                buf.extend_from_slice(&target_amp.to_le_bytes());
+                buf.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+            }
+            Self::StopRampAmp => {
+                buf.push(16);
+            }
+            Self::SetPause(SetPauseInstruction { is_paused }) => {
+                buf.push(17);
+                buf.push(*is_paused as u8);
+            }
+            Self::SetOrderbook(SetOrderbookInstruction {
+                depth_bps,
+                spread_bps,
+                levels,
+            }) => {
+                buf.push(18);
+                buf.extend_from_slice(&depth_bps.to_le_bytes());
+                buf.extend_from_slice(&spread_bps.to_le_bytes());
+                buf.push(*levels);
+            }
+            Self::RefreshOrders => {
+                buf.push(19);
+            }
+            Self::SettleFunds => {
+                buf.push(20);
+            }
+            Self::CancelAllOrders => {
+                buf.push(21);
+            }
+            Self::SplitSwap(SplitSwapInstruction {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(22);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::SetProtocolFeeMode(SetProtocolFeeModeInstruction { lp_protocol_fee }) => {
+                buf.push(23);
+                buf.push(*lp_protocol_fee as u8);
+            }
+            Self::Migrate => {
+                buf.push(24);
+            }
+            Self::ProposeStateOwner => {
+                buf.push(25);
+            }
+            Self::AcceptStateOwner => {
+                buf.push(26);
+            }
+            Self::CancelStateOwnerProposal => {
+                buf.push(27);
+            }
+            Self::QueueStateUpdate(QueueStateUpdateInstruction { delay, update }) => {
+                buf.push(28);
+                buf.extend_from_slice(&delay.to_le_bytes());
+                Self::pack_update_state(update, &mut buf);
+            }
+            Self::ExecuteStateUpdate => {
+                buf.push(29);
+            }
+            Self::CancelStateUpdate => {
+                buf.push(30);
+            }
+            Self::CreateGovernance(CreateGovernanceInstruction {
+                nonce,
+                threshold,
+                signers,
+            }) => {
+                buf.push(31);
+                buf.push(*nonce);
+                buf.push(*threshold);
+                buf.push(signers.len() as u8);
+                for signer in signers {
+                    buf.extend_from_slice(signer.as_ref());
+                }
+            }
+            Self::CreateProposal(CreateProposalInstruction { accounts, data }) => {
+                buf.push(32);
+                buf.push(accounts.len() as u8);
+                for meta in accounts {
+                    buf.extend_from_slice(meta.pubkey.as_ref());
+                    buf.push(meta.is_signer as u8);
+                    buf.push(meta.is_writable as u8);
+                }
+                buf.extend_from_slice(data);
+            }
+            Self::ApproveProposal => {
+                buf.push(33);
+            }
+            Self::ExecuteProposal => {
+                buf.push(34);
+            }
+            Self::SetPoolCreation(SetPoolCreationInstruction {
+                permissionless_pools,
+                mint_list_mode,
+                pool_creation_fee,
+            }) => {
+                buf.push(35);
+                buf.push(*permissionless_pools as u8);
+                buf.push(*mint_list_mode as u8);
+                buf.extend_from_slice(&pool_creation_fee.to_le_bytes());
+            }
+            Self::UpdateMintList(UpdateMintListInstruction { mint, listed }) => {
+                buf.push(36);
+                buf.extend_from_slice(mint.as_ref());
+                buf.push(*listed as u8);
+            }
+            Self::ClosePool(ClosePoolInstruction { wind_down }) => {
+                buf.push(37);
+                buf.push(*wind_down as u8);
             }
         }
         buf
@@ -387,25 +1628,76 @@ pub fn initialize(
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    amm_id_pubkey: &Pubkey,
     token_a_pubkey: &Pubkey,
     token_b_pubkey: &Pubkey,
     pool_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
+    lock_pubkey: &Pubkey,
+    market_pubkey: &Pubkey,
+    dex_program_id: &Pubkey,
+    creator_pubkey: &Pubkey,
+    pool_marker_pubkey: &Pubkey,
+    mint_list_pubkey: &Pubkey,
+    fee_owner_pubkey: &Pubkey,
     nonce: u8,
+    fees: Option<Fees>,
+    swap_curve: Option<SwapCurve>,
+    derived_address: bool,
 ) -> Result<Instruction, ProgramError> {
     let init_data = AmmInstruction::Initialize(InitializeInstruction {
         nonce,
+        fees,
+        swap_curve,
+        derived_address,
     });
     let data = init_data.pack();
 
     let accounts = vec![
-        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new(*swap_pubkey, !derived_address),
         AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new_readonly(*amm_id_pubkey, false),
         AccountMeta::new_readonly(*token_a_pubkey, false),
         AccountMeta::new_readonly(*token_b_pubkey, false),
         AccountMeta::new(*pool_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*market_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*dex_program_id, false),
+        AccountMeta::new(*creator_pubkey, true),
+        AccountMeta::new(*pool_marker_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(*mint_list_pubkey, false),
+        AccountMeta::new(*fee_owner_pubkey, false),
+        AccountMeta::new(*lock_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'update_state' instruction.
+pub fn update_state(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    fee_owner_pubkey: &Pubkey,
+    instruction: UpdateStateInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::UpdateState(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new(*state_owner_pubkey, true),
+        AccountMeta::new_readonly(*fee_owner_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
     ];
 
     Ok(Instruction {
@@ -422,26 +1714,30 @@ pub fn deposit_all_token_types(
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
     deposit_token_a_pubkey: &Pubkey,
     deposit_token_b_pubkey: &Pubkey,
     swap_token_a_pubkey: &Pubkey,
     swap_token_b_pubkey: &Pubkey,
     pool_mint_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
     instruction: DepositInstruction,
 ) -> Result<Instruction, ProgramError> {
     let data = AmmInstruction::DepositAllTokenTypes(instruction).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
         AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
         AccountMeta::new(*deposit_token_a_pubkey, false),
         AccountMeta::new(*deposit_token_b_pubkey, false),
         AccountMeta::new(*swap_token_a_pubkey, false),
         AccountMeta::new(*swap_token_b_pubkey, false),
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
 
@@ -459,26 +1755,30 @@ pub fn withdraw_all_token_types(
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
     pool_mint_pubkey: &Pubkey,
     source_pubkey: &Pubkey,
     swap_token_a_pubkey: &Pubkey,
     swap_token_b_pubkey: &Pubkey,
     destination_token_a_pubkey: &Pubkey,
     destination_token_b_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
     instruction: WithdrawInstruction,
 ) -> Result<Instruction, ProgramError> {
     let data = AmmInstruction::WithdrawAllTokenTypes(instruction).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
         AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*source_pubkey, false),
         AccountMeta::new(*swap_token_a_pubkey, false),
         AccountMeta::new(*swap_token_b_pubkey, false),
         AccountMeta::new(*destination_token_a_pubkey, false),
         AccountMeta::new(*destination_token_b_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
 
@@ -496,24 +1796,30 @@ pub fn deposit_single_token_type_exact_amount_in(
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
     source_token_pubkey: &Pubkey,
     swap_token_a_pubkey: &Pubkey,
     swap_token_b_pubkey: &Pubkey,
     pool_mint_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    fee_pubkey: &Pubkey,
     instruction: DepositSingleTokenTypeExactAmountIn,
 ) -> Result<Instruction, ProgramError> {
     let data = AmmInstruction::DepositSingleTokenTypeExactAmountIn(instruction).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
         AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
         AccountMeta::new(*source_token_pubkey, false),
         AccountMeta::new(*swap_token_a_pubkey, false),
         AccountMeta::new(*swap_token_b_pubkey, false),
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new(*fee_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
 
@@ -531,24 +1837,30 @@ pub fn withdraw_single_token_type_exact_amount_out(
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
     pool_mint_pubkey: &Pubkey,
     pool_token_source_pubkey: &Pubkey,
     swap_token_a_pubkey: &Pubkey,
     swap_token_b_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    fee_pubkey: &Pubkey,
     instruction: WithdrawSingleTokenTypeExactAmountOut,
 ) -> Result<Instruction, ProgramError> {
     let data = AmmInstruction::WithdrawSingleTokenTypeExactAmountOut(instruction).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
         AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*pool_token_source_pubkey, false),
         AccountMeta::new(*swap_token_a_pubkey, false),
         AccountMeta::new(*swap_token_b_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new(*fee_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
 
@@ -566,29 +1878,79 @@ pub fn swap(
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
     source_pubkey: &Pubkey,
     swap_source_pubkey: &Pubkey,
     swap_destination_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
     pool_mint_pubkey: &Pubkey,
-    fee_a_pubkey: &Pubkey,
+    fee_account_pubkey: &Pubkey,
+    fee_wallet_pubkey: &Pubkey,
     host_fee_pubkey: Option<&Pubkey>,
     instruction: SwapInstruction,
 ) -> Result<Instruction, ProgramError> {
     let data = AmmInstruction::Swap(instruction).pack();
 
     let mut accounts = vec![
-        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
-        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
         AccountMeta::new(*source_pubkey, false),
         AccountMeta::new(*swap_source_pubkey, false),
         AccountMeta::new(*swap_destination_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
         AccountMeta::new(*pool_mint_pubkey, false),
-        AccountMeta::new(*fee_a_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new(*fee_wallet_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(host_fee_pubkey) = host_fee_pubkey {
+        accounts.push(AccountMeta::new(*host_fee_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap_exact_amount_out' instruction.
+pub fn swap_exact_amount_out(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    fee_account_pubkey: &Pubkey,
+    fee_wallet_pubkey: &Pubkey,
+    host_fee_pubkey: Option<&Pubkey>,
+    instruction: SwapExactAmountOutInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::SwapExactAmountOut(instruction).pack();
 
+    let mut accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new(*fee_wallet_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(system_program::id(), false),
     ];
     if let Some(host_fee_pubkey) = host_fee_pubkey {
         accounts.push(AccountMeta::new(*host_fee_pubkey, false));
@@ -600,3 +1962,870 @@ pub fn swap(
         data,
     })
 }
+
+/// Creates a 'route_swap' instruction.
+pub fn route_swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    fee_wallet_pubkey: &Pubkey,
+    hops: &[RouteSwapHop],
+    instruction: RouteSwapInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::RouteSwap(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new(*fee_wallet_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    for hop in hops {
+        accounts.extend_from_slice(&[
+            AccountMeta::new(hop.swap_pubkey, false),
+            AccountMeta::new_readonly(hop.authority_pubkey, false),
+            AccountMeta::new(hop.source_pubkey, false),
+            AccountMeta::new(hop.swap_source_pubkey, false),
+            AccountMeta::new(hop.swap_destination_pubkey, false),
+            AccountMeta::new(hop.destination_pubkey, false),
+            AccountMeta::new(hop.pool_mint_pubkey, false),
+            AccountMeta::new(hop.fee_account_pubkey, false),
+        ]);
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'initialize_concentrated_pool' instruction.
+pub fn initialize_concentrated_pool(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    pool_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    token_a_pubkey: &Pubkey,
+    token_b_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    instruction: InitializeConcentratedPoolInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::InitializeConcentratedPool(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new_readonly(*token_a_pubkey, false),
+        AccountMeta::new_readonly(*token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'open_position' instruction.
+pub fn open_position(
+    program_id: &Pubkey,
+    pool_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    instruction: OpenPositionInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::OpenPosition(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*pool_pubkey, false),
+        AccountMeta::new(*position_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+fn position_accounts(
+    token_program_id: &Pubkey,
+    pool_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    user_token_a_pubkey: &Pubkey,
+    user_token_b_pubkey: &Pubkey,
+    pool_token_a_pubkey: &Pubkey,
+    pool_token_b_pubkey: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*position_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new(*user_token_a_pubkey, false),
+        AccountMeta::new(*user_token_b_pubkey, false),
+        AccountMeta::new(*pool_token_a_pubkey, false),
+        AccountMeta::new(*pool_token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ]
+}
+
+/// Creates an 'increase_liquidity' instruction.
+pub fn increase_liquidity(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    pool_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    source_token_a_pubkey: &Pubkey,
+    source_token_b_pubkey: &Pubkey,
+    pool_token_a_pubkey: &Pubkey,
+    pool_token_b_pubkey: &Pubkey,
+    instruction: IncreaseLiquidityInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::IncreaseLiquidity(instruction).pack();
+
+    let accounts = position_accounts(
+        token_program_id,
+        pool_pubkey,
+        authority_pubkey,
+        position_pubkey,
+        owner_pubkey,
+        source_token_a_pubkey,
+        source_token_b_pubkey,
+        pool_token_a_pubkey,
+        pool_token_b_pubkey,
+    );
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'decrease_liquidity' instruction.
+pub fn decrease_liquidity(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    pool_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    destination_token_a_pubkey: &Pubkey,
+    destination_token_b_pubkey: &Pubkey,
+    pool_token_a_pubkey: &Pubkey,
+    pool_token_b_pubkey: &Pubkey,
+    instruction: DecreaseLiquidityInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::DecreaseLiquidity(instruction).pack();
+
+    let accounts = position_accounts(
+        token_program_id,
+        pool_pubkey,
+        authority_pubkey,
+        position_pubkey,
+        owner_pubkey,
+        destination_token_a_pubkey,
+        destination_token_b_pubkey,
+        pool_token_a_pubkey,
+        pool_token_b_pubkey,
+    );
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'collect_fees' instruction.
+pub fn collect_fees(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    pool_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    destination_token_a_pubkey: &Pubkey,
+    destination_token_b_pubkey: &Pubkey,
+    pool_token_a_pubkey: &Pubkey,
+    pool_token_b_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::CollectFees.pack();
+
+    let accounts = position_accounts(
+        token_program_id,
+        pool_pubkey,
+        authority_pubkey,
+        position_pubkey,
+        owner_pubkey,
+        destination_token_a_pubkey,
+        destination_token_b_pubkey,
+        pool_token_a_pubkey,
+        pool_token_b_pubkey,
+    );
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'concentrated_swap' instruction.
+pub fn concentrated_swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    pool_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    fee_account_pubkey: &Pubkey,
+    instruction: ConcentratedSwapInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::ConcentratedSwap(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'ramp_amp' instruction.
+pub fn ramp_amp(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    instruction: RampAmpInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::RampAmp(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'stop_ramp_amp' instruction.
+pub fn stop_ramp_amp(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::StopRampAmp.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_pause' instruction, pausing a single pool when
+/// `swap_pubkey` is given and the whole program otherwise.
+pub fn set_pause(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    swap_pubkey: Option<&Pubkey>,
+    instruction: SetPauseInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::SetPause(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+    ];
+    if let Some(swap_pubkey) = swap_pubkey {
+        accounts.push(AccountMeta::new(*swap_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_orderbook' instruction.
+pub fn set_orderbook(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    open_orders_pubkey: &Pubkey,
+    instruction: SetOrderbookInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::SetOrderbook(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+        AccountMeta::new_readonly(*open_orders_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+fn market_accounts(token_program_id: &Pubkey, market: &PoolMarketAccounts) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(market.swap_pubkey, false),
+        AccountMeta::new_readonly(market.authority_pubkey, false),
+        AccountMeta::new(market.token_a_pubkey, false),
+        AccountMeta::new(market.token_b_pubkey, false),
+        AccountMeta::new_readonly(market.dex_program_id, false),
+        AccountMeta::new(market.market_pubkey, false),
+        AccountMeta::new(market.open_orders_pubkey, false),
+        AccountMeta::new(market.request_queue_pubkey, false),
+        AccountMeta::new(market.event_queue_pubkey, false),
+        AccountMeta::new(market.bids_pubkey, false),
+        AccountMeta::new(market.asks_pubkey, false),
+        AccountMeta::new(market.coin_vault_pubkey, false),
+        AccountMeta::new(market.pc_vault_pubkey, false),
+        AccountMeta::new_readonly(market.vault_signer_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ]
+}
+
+/// Creates a 'refresh_orders' instruction.
+pub fn refresh_orders(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    market: &PoolMarketAccounts,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::RefreshOrders.pack();
+
+    let mut accounts = market_accounts(token_program_id, market);
+    accounts.push(AccountMeta::new_readonly(*state_pubkey, false));
+    accounts.push(AccountMeta::new_readonly(*state_owner_pubkey, true));
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'settle_funds' instruction.
+pub fn settle_funds(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    market: &PoolMarketAccounts,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::SettleFunds.pack();
+
+    let accounts = market_accounts(token_program_id, market);
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'cancel_all_orders' instruction.
+pub fn cancel_all_orders(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    market: &PoolMarketAccounts,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::CancelAllOrders.pack();
+
+    let accounts = market_accounts(token_program_id, market);
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'split_swap' instruction.
+pub fn split_swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    fee_account_pubkey: &Pubkey,
+    fee_wallet_pubkey: &Pubkey,
+    market: &SplitSwapMarketAccounts,
+    host_fee_pubkey: Option<&Pubkey>,
+    instruction: SplitSwapInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::SplitSwap(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new(*fee_wallet_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(market.dex_program_id, false),
+        AccountMeta::new(market.market_pubkey, false),
+        AccountMeta::new(market.open_orders_pubkey, false),
+        AccountMeta::new(market.request_queue_pubkey, false),
+        AccountMeta::new(market.event_queue_pubkey, false),
+        AccountMeta::new(market.bids_pubkey, false),
+        AccountMeta::new(market.asks_pubkey, false),
+        AccountMeta::new(market.coin_vault_pubkey, false),
+        AccountMeta::new(market.pc_vault_pubkey, false),
+        AccountMeta::new_readonly(market.vault_signer_pubkey, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    if let Some(host_fee_pubkey) = host_fee_pubkey {
+        accounts.push(AccountMeta::new(*host_fee_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_protocol_fee_mode' instruction.
+pub fn set_protocol_fee_mode(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    instruction: SetProtocolFeeModeInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::SetProtocolFeeMode(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+        AccountMeta::new_readonly(*swap_token_a_pubkey, false),
+        AccountMeta::new_readonly(*swap_token_b_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'migrate' instruction.
+pub fn migrate(
+    program_id: &Pubkey,
+    account_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::Migrate.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*account_pubkey, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(*state_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'propose_state_owner' instruction.
+pub fn propose_state_owner(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    new_state_owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::ProposeStateOwner.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+        AccountMeta::new_readonly(*new_state_owner_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'accept_state_owner' instruction.
+pub fn accept_state_owner(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    new_state_owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::AcceptStateOwner.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new_readonly(*new_state_owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'cancel_state_owner_proposal' instruction.
+pub fn cancel_state_owner_proposal(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::CancelStateOwnerProposal.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'queue_state_update' instruction.
+pub fn queue_state_update(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    fee_owner_pubkey: &Pubkey,
+    instruction: QueueStateUpdateInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::QueueStateUpdate(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+        AccountMeta::new_readonly(*fee_owner_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'execute_state_update' instruction.
+pub fn execute_state_update(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::ExecuteStateUpdate.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'cancel_state_update' instruction.
+pub fn cancel_state_update(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::CancelStateUpdate.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'create_governance' instruction.
+pub fn create_governance(
+    program_id: &Pubkey,
+    governance_pubkey: &Pubkey,
+    nonce: u8,
+    threshold: u8,
+    signers: Vec<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::CreateGovernance(CreateGovernanceInstruction {
+        nonce,
+        threshold,
+        signers,
+    })
+    .pack();
+
+    let accounts = vec![AccountMeta::new(*governance_pubkey, false)];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'create_proposal' instruction proposing `instruction`, an
+/// instruction of the program.
+pub fn create_proposal(
+    program_id: &Pubkey,
+    proposal_pubkey: &Pubkey,
+    governance_pubkey: &Pubkey,
+    signer_pubkey: &Pubkey,
+    instruction: Instruction,
+) -> Result<Instruction, ProgramError> {
+    if instruction.program_id != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = AmmInstruction::CreateProposal(CreateProposalInstruction {
+        accounts: instruction.accounts,
+        data: instruction.data,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*proposal_pubkey, false),
+        AccountMeta::new_readonly(*governance_pubkey, false),
+        AccountMeta::new_readonly(*signer_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'approve_proposal' instruction.
+pub fn approve_proposal(
+    program_id: &Pubkey,
+    proposal_pubkey: &Pubkey,
+    governance_pubkey: &Pubkey,
+    signer_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::ApproveProposal.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*proposal_pubkey, false),
+        AccountMeta::new_readonly(*governance_pubkey, false),
+        AccountMeta::new_readonly(*signer_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'execute_proposal' instruction, `proposal_accounts` are the
+/// accounts of the proposed instruction.
+pub fn execute_proposal(
+    program_id: &Pubkey,
+    proposal_pubkey: &Pubkey,
+    governance_pubkey: &Pubkey,
+    governance_authority_pubkey: &Pubkey,
+    proposal_accounts: &[AccountMeta],
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::ExecuteProposal.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*proposal_pubkey, false),
+        AccountMeta::new_readonly(*governance_pubkey, false),
+        AccountMeta::new(*governance_authority_pubkey, false),
+        AccountMeta::new_readonly(*program_id, false),
+    ];
+    accounts.extend(
+        proposal_accounts
+            .iter()
+            .filter(|meta| meta.pubkey != *governance_authority_pubkey)
+            .cloned(),
+    );
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_pool_creation' instruction.
+pub fn set_pool_creation(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    instruction: SetPoolCreationInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::SetPoolCreation(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'update_mint_list' instruction.
+pub fn update_mint_list(
+    program_id: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    mint_list_pubkey: &Pubkey,
+    instruction: UpdateMintListInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::UpdateMintList(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new(*state_owner_pubkey, true),
+        AccountMeta::new(*mint_list_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'close_pool' instruction.
+pub fn close_pool(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    state_pubkey: &Pubkey,
+    state_owner_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    fee_token_a_pubkey: &Pubkey,
+    fee_token_b_pubkey: &Pubkey,
+    pool_marker_pubkey: &Pubkey,
+    recipient_pubkey: &Pubkey,
+    instruction: ClosePoolInstruction,
+) -> Result<Instruction, ProgramError> {
+    let data = AmmInstruction::ClosePool(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*state_pubkey, false),
+        AccountMeta::new_readonly(*state_owner_pubkey, true),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new_readonly(*pool_mint_pubkey, false),
+        AccountMeta::new(*fee_token_a_pubkey, false),
+        AccountMeta::new(*fee_token_b_pubkey, false),
+        AccountMeta::new(*pool_marker_pubkey, false),
+        AccountMeta::new(*recipient_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
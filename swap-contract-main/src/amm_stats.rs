@@ -1,13 +1,19 @@
 //! State transition types
 use crate::error::AmmError;
+use crate::constraints::{SwapConstraints, SWAP_CONSTRAINTS};
 use crate::curve::{base::SwapCurve, fees::Fees};
+use crate::dex::Orderbook;
+use crate::oracle::{PriceOracle, Volatility};
+use crate::registry::MintListMode;
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use enum_dispatch::enum_dispatch;
 use solana_program::{
+    clock::UnixTimestamp,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
+use std::convert::TryFrom;
 
 /// Trait representing access to program state across all versions
 #[enum_dispatch]
@@ -30,13 +36,37 @@ pub trait AmmStatus {
     /// Address of token B mint
     fn token_b_mint(&self) -> &Pubkey;
 
+    /// Program ID of the linked Serum market
+    fn dex_program_id(&self) -> &Pubkey;
+    /// Address of the linked Serum market
+    fn market_id(&self) -> &Pubkey;
+
+    /// Fees associated with swap
+    fn fees(&self) -> &Fees;
+    /// Curve associated with swap
+    fn swap_curve(&self) -> &SwapCurve;
+    /// Cumulative prices of the swap
+    fn price_oracle(&self) -> &PriceOracle;
+    /// Orders of the swap on its Serum market
+    fn orderbook(&self) -> &Orderbook;
+    /// Are swaps, deposits and single-sided withdrawals of the swap halted
+    fn is_paused(&self) -> bool;
+    /// Is the owner fee left in the pool and paid as pool tokens minted to
+    /// the fee owner on liquidity changes
+    fn lp_protocol_fee(&self) -> bool;
+    /// Square root of the reserves product after the last liquidity change,
+    /// zero unless the owner fee is paid as pool tokens
+    fn root_k_last(&self) -> u128;
+    /// Recent price movement of the swap, raising its dynamic fees
+    fn volatility(&self) -> &Volatility;
 }
 
-/// All versions of AmmStatus
+/// All versions of AmmStatus, swaps of the first layout are upgraded to the
+/// latest one in memory when unpacked with the program state
 #[enum_dispatch(AmmStatus)]
 pub enum SwapVersion {
     /// Latest version, used for all new swaps
-    SwapV1,
+    SwapV2,
 }
 
 /// SwapVersion does not implement program_pack::Pack because there are size
@@ -44,41 +74,149 @@ pub enum SwapVersion {
 /// special implementations are provided here
 impl SwapVersion {
     /// Size of the latest version of the AmmStatus
-    pub const LATEST_LEN: usize = 1 + SwapV1::LEN; // add one for the version enum
+    pub const LATEST_LEN: usize = 1 + SwapV2::LEN; // add one for the version enum
 
-    /// Pack a swap into a byte array, based on its version
+    /// Size of the swap accounts of the first layout, as deployed. No other
+    /// account of the program has this size.
+    pub const V1_LEN: usize = 1 + SwapV1::LEN;
+
+    /// Offset of the price oracle in the swap account data, other programs
+    /// can read the cumulative prices here without unpacking the whole swap
+    pub const PRICE_ORACLE_OFFSET: usize = 1 + SwapV2::PRICE_ORACLE_OFFSET;
+
+    /// Pack a swap into a byte array, based on its version. Accounts of an
+    /// older layout are too small and must be migrated first.
     pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() < Self::LATEST_LEN {
+            return Err(AmmError::AccountNotMigrated.into());
+        }
         match src {
-            Self::SwapV1(swap_info) => {
-                dst[0] = 1;
-                SwapV1::pack(swap_info, &mut dst[1..])
+            Self::SwapV2(swap_info) => {
+                dst[0] = 2;
+                SwapV2::pack(swap_info, &mut dst[1..Self::LATEST_LEN])
             }
         }
     }
 
-    /// Unpack the swap account based on its version, returning the result as a
-    /// AmmStatus trait object
-    pub fn unpack(input: &[u8]) -> Result<Box<dyn AmmStatus>, ProgramError> {
+    /// Unpack the swap account based on its version. Swaps of the first
+    /// layout have neither fees nor curve, see
+    /// [unpack_with_state](#method.unpack_with_state).
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         let (&version, rest) = input
             .split_first()
             .ok_or(ProgramError::InvalidAccountData)?;
         match version {
-            1 => Ok(Box::new(SwapV1::unpack(rest)?)),
+            1 => Err(AmmError::AccountNotMigrated.into()),
+            2 => {
+                let rest = rest.get(..SwapV2::LEN).ok_or(ProgramError::InvalidAccountData)?;
+                Ok(Self::SwapV2(SwapV2::unpack(rest)?))
+            }
             _ => Err(ProgramError::UninitializedAccount),
         }
     }
 
+    /// Unpack the swap account based on its version. Swaps of the first
+    /// layout take the fees and curve of the program state, as they used to,
+    /// and are upgraded to the latest layout. Their account must still be
+    /// migrated before packing them.
+    pub fn unpack_with_state(input: &[u8], state: ProgramStateV2) -> Result<Self, ProgramError> {
+        match Self::unpack_v1(input) {
+            Some(swap) => Ok(Self::SwapV2(SwapV2::from_v1(swap?, state.fees, state.swap_curve))),
+            None => Self::unpack(input),
+        }
+    }
+
+    /// Unpack a swap of the first layout, told apart by its version byte
+    /// and the exact size of its account
+    fn unpack_v1(input: &[u8]) -> Option<Result<SwapV1, ProgramError>> {
+        match input.split_first() {
+            Some((1, rest)) if input.len() == Self::V1_LEN => Some(SwapV1::unpack(rest)),
+            _ => None,
+        }
+    }
+
+    /// Is the swap account stored with the latest layout
+    pub fn is_latest(input: &[u8]) -> bool {
+        input.first() == Some(&2) && input.len() >= Self::LATEST_LEN
+    }
+
+    /// Unpack only the price oracle of the swap account
+    pub fn unpack_price_oracle(input: &[u8]) -> Result<PriceOracle, ProgramError> {
+        match input.first() {
+            Some(2) if input.len() >= Self::PRICE_ORACLE_OFFSET + PriceOracle::LEN => {
+                PriceOracle::unpack_from_slice(&input[Self::PRICE_ORACLE_OFFSET..])
+            }
+            Some(_) => Err(ProgramError::InvalidAccountData),
+            None => Err(ProgramError::UninitializedAccount),
+        }
+    }
+
+    /// Mutable access to the recent price movement of the swap
+    pub fn volatility_mut(&mut self) -> &mut Volatility {
+        match self {
+            Self::SwapV2(swap_info) => &mut swap_info.volatility,
+        }
+    }
+
+    /// Mutable access to the cumulative prices of the swap
+    pub fn price_oracle_mut(&mut self) -> &mut PriceOracle {
+        match self {
+            Self::SwapV2(swap_info) => &mut swap_info.price_oracle,
+        }
+    }
+
+    /// Halts or resumes swaps, deposits and single-sided withdrawals
+    pub fn set_paused(&mut self, is_paused: bool) {
+        match self {
+            Self::SwapV2(swap_info) => swap_info.is_paused = is_paused,
+        }
+    }
+
+    /// Switches the owner fee between source tokens and pool tokens
+    pub fn set_lp_protocol_fee(&mut self, lp_protocol_fee: bool) {
+        match self {
+            Self::SwapV2(swap_info) => swap_info.lp_protocol_fee = lp_protocol_fee,
+        }
+    }
+
+    /// Records the square root of the reserves product after a liquidity
+    /// change, to measure the fees accrued until the next one
+    pub fn set_root_k_last(&mut self, root_k_last: u128) {
+        match self {
+            Self::SwapV2(swap_info) => swap_info.root_k_last = root_k_last,
+        }
+    }
+
+    /// Mutable access to the orderbook settings of the swap
+    pub fn orderbook_mut(&mut self) -> &mut Orderbook {
+        match self {
+            Self::SwapV2(swap_info) => &mut swap_info.orderbook,
+        }
+    }
+
+    /// Mutable access to the swap curve, to set its timestamp or parameters
+    pub fn swap_curve_mut(&mut self) -> &mut SwapCurve {
+        match self {
+            Self::SwapV2(swap_info) => &mut swap_info.swap_curve,
+        }
+    }
+
     /// Special check to be done before any instruction processing, works for
     /// all versions
     pub fn is_initialized(input: &[u8]) -> bool {
-        match Self::unpack(input) {
-            Ok(swap) => swap.is_initialized(),
-            Err(_) => false,
+        match Self::unpack_v1(input) {
+            Some(swap) => swap.map(|swap| swap.is_initialized).unwrap_or(false),
+            None => match Self::unpack(input) {
+                Ok(swap) => swap.is_initialized(),
+                Err(_) => false,
+            },
         }
     }
 }
 
-///Program State
+/// Program state of the first layout, without a version byte and told
+/// apart by its length. Only read to upgrade older state accounts, see
+/// [ProgramStateV2](struct.ProgramStateV2.html).
 #[repr(C)]
 #[derive(Debug, Default, PartialEq)]
 pub struct ProgramState {
@@ -94,7 +232,8 @@ pub struct ProgramState {
     /// owner address to update the program state
     pub initial_supply: u64,
 
-    ///Fee ratio to redistribute
+    ///Fee ratio to redistribute, only the return fee, the fixed fee and
+    ///their denominator were stored
     pub fees: Fees,
 
     ///Curve Type to swap
@@ -103,7 +242,7 @@ pub struct ProgramState {
 impl Sealed for ProgramState {}
 impl Pack for ProgramState{
     /// Size of the Program State
-    const LEN:usize = 130; // add one for the version enum
+    const LEN:usize = 130;
 
     /// Pack a swap into a byte array, based on its version
     fn pack_into_slice(&self, output: &mut [u8]) {
@@ -113,18 +252,22 @@ impl Pack for ProgramState{
             state_owner,
             fee_owner,
             initial_supply,
-            fees,
+            return_fee_numerator,
+            fixed_fee_numerator,
+            fee_denominator,
             swap_curve,
-        ) = mut_array_refs![output, 1, 32, 32, 8, 24, 33];
+        ) = mut_array_refs![output, 1, 32, 32, 8, 8, 8, 8, 33];
         is_initialized[0] = self.is_initialized as u8;
         state_owner.copy_from_slice(self.state_owner.as_ref());
         fee_owner.copy_from_slice(self.fee_owner.as_ref());
         *initial_supply = self.initial_supply.to_le_bytes();
-        self.fees.pack_into_slice(&mut fees[..]);
+        *return_fee_numerator = self.fees.return_fee_numerator.to_le_bytes();
+        *fixed_fee_numerator = self.fees.fixed_fee_numerator.to_le_bytes();
+        *fee_denominator = self.fees.fee_denominator.to_le_bytes();
         self.swap_curve.pack_into_slice(&mut swap_curve[..]);
     }
 
-    /// Unpacks a byte buffer into a [SwapV1](struct.SwapV1.html).
+    /// Unpacks a byte buffer into a [ProgramState](struct.ProgramState.html).
     fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
         if input.len() < ProgramState::LEN{
             return Err(AmmError::InvalidInstruction.into());    
@@ -136,9 +279,11 @@ impl Pack for ProgramState{
             state_owner,
             fee_owner,
             initial_supply,
-            fees,
+            return_fee_numerator,
+            fixed_fee_numerator,
+            fee_denominator,
             swap_curve,
-        ) = array_refs![input, 1, 32, 32, 8,  24, 33];
+        ) = array_refs![input, 1, 32, 32, 8, 8, 8, 8, 33];
         Ok(Self {
             is_initialized: match is_initialized {
                 [0] => false,
@@ -148,14 +293,239 @@ impl Pack for ProgramState{
             state_owner: Pubkey::new_from_array(*state_owner),
             fee_owner: Pubkey::new_from_array(*fee_owner),
             initial_supply:u64::from_le_bytes(*initial_supply),
+            fees: Fees {
+                return_fee_numerator: u64::from_le_bytes(*return_fee_numerator),
+                fixed_fee_numerator: u64::from_le_bytes(*fixed_fee_numerator),
+                fee_denominator: u64::from_le_bytes(*fee_denominator),
+                ..Fees::default()
+            },
+            swap_curve: SwapCurve::unpack_from_slice(swap_curve)?,
+        })
+    }
+}
+
+
+
+/// Program State, versioned and followed by reserved bytes so that fields
+/// can be added without resizing the account
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct ProgramStateV2 {
+    /// Initialized state.
+    pub is_initialized: bool,
+
+    /// owner address to update the program state
+    pub state_owner: Pubkey,
+
+    /// Fee owner address to redistribute
+    pub fee_owner: Pubkey,
+
+    /// initial pool token supply of new pools
+    pub initial_supply: u64,
+
+    ///Default fees for new pools, pools may not charge less than these
+    pub fees: Fees,
+
+    ///Default curve for new pools
+    pub swap_curve: SwapCurve,
+
+    /// Halts swaps, deposits and single-sided withdrawals of all pools
+    pub is_paused: bool,
+
+    /// Owner proposed by the state owner, takes over once it accepts,
+    /// the default pubkey when no transfer is pending
+    pub pending_state_owner: Pubkey,
+
+    /// Parameters queued by the state owner, applied once their
+    /// timelock expires
+    pub pending_update: Option<PendingStateUpdate>,
+
+    /// Lets anyone create pools, not only the state owner
+    pub permissionless_pools: bool,
+
+    /// Restriction of the mints of pools created by others than the state
+    /// owner
+    pub mint_list_mode: MintListMode,
+
+    /// Lamports paid to the fee owner by others than the state owner to
+    /// create a pool
+    pub pool_creation_fee: u64,
+}
+
+impl From<ProgramState> for ProgramStateV2 {
+    fn from(state: ProgramState) -> Self {
+        Self {
+            is_initialized: state.is_initialized,
+            state_owner: state.state_owner,
+            fee_owner: state.fee_owner,
+            initial_supply: state.initial_supply,
+            fees: state.fees,
+            swap_curve: state.swap_curve,
+            is_paused: false,
+            pending_state_owner: Pubkey::default(),
+            pending_update: None,
+            permissionless_pools: false,
+            mint_list_mode: MintListMode::Disabled,
+            pool_creation_fee: 0,
+        }
+    }
+}
+
+impl Sealed for ProgramStateV2 {}
+impl Pack for ProgramStateV2 {
+    const LEN: usize = 207 + PendingStateUpdate::LEN + ProgramStateV2::RESERVED_LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, ProgramStateV2::LEN];
+        let (
+            version,
+            is_initialized,
+            state_owner,
+            fee_owner,
+            initial_supply,
+            fees,
+            swap_curve,
+            is_paused,
+            pending_state_owner,
+            has_pending_update,
+            pending_update,
+            permissionless_pools,
+            mint_list_mode,
+            pool_creation_fee,
+            reserved,
+        ) = mut_array_refs![
+            output,
+            1,
+            1,
+            32,
+            32,
+            8,
+            56,
+            33,
+            1,
+            32,
+            1,
+            PendingStateUpdate::LEN,
+            1,
+            1,
+            8,
+            ProgramStateV2::RESERVED_LEN
+        ];
+        version[0] = ProgramStateV2::VERSION;
+        is_initialized[0] = self.is_initialized as u8;
+        state_owner.copy_from_slice(self.state_owner.as_ref());
+        fee_owner.copy_from_slice(self.fee_owner.as_ref());
+        *initial_supply = self.initial_supply.to_le_bytes();
+        self.fees.pack_into_slice(&mut fees[..]);
+        self.swap_curve.pack_into_slice(&mut swap_curve[..]);
+        is_paused[0] = self.is_paused as u8;
+        pending_state_owner.copy_from_slice(self.pending_state_owner.as_ref());
+        match &self.pending_update {
+            Some(update) => {
+                has_pending_update[0] = 1;
+                update.pack_into_slice(&mut pending_update[..]);
+            }
+            None => {
+                has_pending_update[0] = 0;
+                *pending_update = [0u8; PendingStateUpdate::LEN];
+            }
+        }
+        permissionless_pools[0] = self.permissionless_pools as u8;
+        mint_list_mode[0] = self.mint_list_mode as u8;
+        *pool_creation_fee = self.pool_creation_fee.to_le_bytes();
+        *reserved = [0u8; ProgramStateV2::RESERVED_LEN];
+    }
+
+    /// Unpacks the state of any layout, older ones are upgraded in memory
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() == ProgramState::LEN {
+            return Ok(ProgramState::unpack_from_slice(input)?.into());
+        }
+        if input.len() < ProgramStateV2::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        match input[0] {
+            ProgramStateV2::VERSION => {}
+            // a newly allocated account
+            0 => return Ok(Self::default()),
+            _ => return Err(ProgramError::InvalidAccountData),
+        }
+        let input = array_ref![input, 0, ProgramStateV2::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            _version,
+            is_initialized,
+            state_owner,
+            fee_owner,
+            initial_supply,
+            fees,
+            swap_curve,
+            is_paused,
+            pending_state_owner,
+            has_pending_update,
+            pending_update,
+            permissionless_pools,
+            mint_list_mode,
+            pool_creation_fee,
+            _reserved,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            32,
+            32,
+            8,
+            56,
+            33,
+            1,
+            32,
+            1,
+            PendingStateUpdate::LEN,
+            1,
+            1,
+            8,
+            ProgramStateV2::RESERVED_LEN
+        ];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            state_owner: Pubkey::new_from_array(*state_owner),
+            fee_owner: Pubkey::new_from_array(*fee_owner),
+            initial_supply: u64::from_le_bytes(*initial_supply),
             fees: Fees::unpack_from_slice(fees)?,
             swap_curve: SwapCurve::unpack_from_slice(swap_curve)?,
+            is_paused: match is_paused {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            pending_state_owner: Pubkey::new_from_array(*pending_state_owner),
+            pending_update: match has_pending_update {
+                [0] => None,
+                [1] => Some(PendingStateUpdate::unpack_from_slice(pending_update)?),
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            permissionless_pools: match permissionless_pools {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            mint_list_mode: MintListMode::try_from(mint_list_mode[0])?,
+            pool_creation_fee: u64::from_le_bytes(*pool_creation_fee),
         })
     }
 }
 
+impl ProgramStateV2 {
+    /// Version byte of the layout, state accounts of the first layout have
+    /// none and are told apart by their length
+    pub const VERSION: u8 = 2;
+    /// Bytes reserved for future fields
+    pub const RESERVED_LEN: usize = 76;
 
-impl ProgramState{
     /// is program account initialized
     pub fn is_initialized(&self) -> bool {
         return self.is_initialized
@@ -185,9 +555,110 @@ impl ProgramState{
         &self.swap_curve
     }
 
+    /// are swaps, deposits and single-sided withdrawals of all pools halted
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    /// owner proposed to take over the program state, if any
+    pub fn pending_state_owner(&self) -> Option<&Pubkey> {
+        if self.pending_state_owner == Pubkey::default() {
+            None
+        } else {
+            Some(&self.pending_state_owner)
+        }
+    }
+
+    /// parameters waiting for their timelock, if any
+    pub fn pending_update(&self) -> Option<&PendingStateUpdate> {
+        self.pending_update.as_ref()
+    }
+
+    /// can others than the state owner create pools
+    pub fn permissionless_pools(&self) -> bool {
+        self.permissionless_pools
+    }
+
+    /// restriction of the mints of pools created by others than the state
+    /// owner
+    pub fn mint_list_mode(&self) -> MintListMode {
+        self.mint_list_mode
+    }
+
+    /// lamports paid by others than the state owner to create a pool
+    pub fn pool_creation_fee(&self) -> u64 {
+        self.pool_creation_fee
+    }
+
+    /// Checks that the fees of a new pool are within the program
+    /// constraints, taking the program defaults as the lowest fees
+    pub fn validate_pool_fees(&self, fees: &Fees) -> Result<(), ProgramError> {
+        SwapConstraints {
+            fees: &self.fees,
+            ..SWAP_CONSTRAINTS
+        }
+        .validate_fees(fees)
+    }
+
+}
+
+/// Program state parameters queued by the state owner, so that LPs see a
+/// change of the pool defaults before it applies
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub struct PendingStateUpdate {
+    /// Timestamp from which the update can be executed
+    pub activation_timestamp: UnixTimestamp,
+    /// New initial pool token supply of new pools
+    pub initial_supply: u64,
+    /// New fee owner
+    pub fee_owner: Pubkey,
+    /// New default fees
+    pub fees: Fees,
+    /// New default curve
+    pub swap_curve: SwapCurve,
+}
+
+impl PendingStateUpdate {
+    /// Can the update be executed at the given time
+    pub fn is_active(&self, timestamp: UnixTimestamp) -> bool {
+        timestamp >= self.activation_timestamp
+    }
 }
 
-/// Pool states.
+impl Sealed for PendingStateUpdate {}
+impl Pack for PendingStateUpdate {
+    const LEN: usize = 137;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, PendingStateUpdate::LEN];
+        let (activation_timestamp, initial_supply, fee_owner, fees, swap_curve) =
+            mut_array_refs![output, 8, 8, 32, 56, 33];
+        *activation_timestamp = self.activation_timestamp.to_le_bytes();
+        *initial_supply = self.initial_supply.to_le_bytes();
+        fee_owner.copy_from_slice(self.fee_owner.as_ref());
+        self.fees.pack_into_slice(&mut fees[..]);
+        self.swap_curve.pack_into_slice(&mut swap_curve[..]);
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, PendingStateUpdate::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (activation_timestamp, initial_supply, fee_owner, fees, swap_curve) =
+            array_refs![input, 8, 8, 32, 56, 33];
+        Ok(Self {
+            activation_timestamp: UnixTimestamp::from_le_bytes(*activation_timestamp),
+            initial_supply: u64::from_le_bytes(*initial_supply),
+            fee_owner: Pubkey::new_from_array(*fee_owner),
+            fees: Fees::unpack_from_slice(fees)?,
+            swap_curve: SwapCurve::unpack_from_slice(swap_curve)?,
+        })
+    }
+}
+
+/// Pool state of the first layout, which used the fees and curve of the
+/// program state. Only read to upgrade older swap accounts, see
+/// [SwapV2](struct.SwapV2.html).
 #[repr(C)]
 #[derive(Debug, Default, PartialEq)]
 pub struct SwapV1 {
@@ -227,7 +698,184 @@ pub struct SwapV1 {
     pub token_b_mint: Pubkey,
 }
 
-impl AmmStatus for SwapV1 {
+impl Sealed for SwapV1 {}
+impl IsInitialized for SwapV1 {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for SwapV1 {
+    const LEN: usize = 290;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, SwapV1::LEN];
+        let (
+            is_initialized,
+            nonce,
+            amm_id,
+            dex_program_id,
+            market_id,
+            token_program_id,
+            token_a,
+            token_b,
+            pool_mint,
+            token_a_mint,
+            token_b_mint,
+        ) = mut_array_refs![output, 1, 1, 32, 32, 32, 32, 32, 32, 32, 32, 32];
+        is_initialized[0] = self.is_initialized as u8;
+        nonce[0] = self.nonce;
+        amm_id.copy_from_slice(self.amm_id.as_ref());
+        dex_program_id.copy_from_slice(self.dex_program_id.as_ref());
+        market_id.copy_from_slice(self.market_id.as_ref());
+        token_program_id.copy_from_slice(self.token_program_id.as_ref());
+        token_a.copy_from_slice(self.token_a.as_ref());
+        token_b.copy_from_slice(self.token_b.as_ref());
+        pool_mint.copy_from_slice(self.pool_mint.as_ref());
+        token_a_mint.copy_from_slice(self.token_a_mint.as_ref());
+        token_b_mint.copy_from_slice(self.token_b_mint.as_ref());
+    }
+
+    /// Unpacks a byte buffer into a [SwapV1](struct.SwapV1.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() < Self::LEN{
+            return Err(AmmError::InvalidInstruction.into());    
+        }
+        let input = array_ref![input, 0, SwapV1::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            nonce,
+            amm_id,
+            dex_program_id,
+            market_id,
+            token_program_id,
+            token_a,
+            token_b,
+            pool_mint,
+            token_a_mint,
+            token_b_mint,
+        ) = array_refs![input, 1, 1, 32, 32, 32, 32, 32, 32, 32, 32, 32];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: nonce[0],
+            amm_id: Pubkey::new_from_array(*amm_id),
+            dex_program_id: Pubkey::new_from_array(*dex_program_id),
+            market_id: Pubkey::new_from_array(*market_id),
+            token_program_id: Pubkey::new_from_array(*token_program_id),
+            token_a: Pubkey::new_from_array(*token_a),
+            token_b: Pubkey::new_from_array(*token_b),
+            pool_mint: Pubkey::new_from_array(*pool_mint),
+            token_a_mint: Pubkey::new_from_array(*token_a_mint),
+            token_b_mint: Pubkey::new_from_array(*token_b_mint),
+        })
+    }
+}
+
+/// Pool states, followed by reserved bytes so that fields can be added
+/// without resizing the account
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct SwapV2 {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Nonce used in program address.
+    /// The program address is created deterministically with the nonce,
+    /// swap program id, and swap account pubkey.  This program address has
+    /// authority over the swap's token A account, token B account, and pool
+    /// token mint.
+    pub nonce: u8,
+    
+    ///ID of current amm account 
+    pub amm_id: Pubkey,
+
+    ///Program ID of Serum Market
+    pub dex_program_id: Pubkey,
+
+    ///Market ID of Serum
+    pub market_id: Pubkey,
+
+    /// Program ID of the tokens being exchanged.
+    pub token_program_id: Pubkey,
+
+    /// Token A
+    pub token_a: Pubkey,
+    /// Token B
+    pub token_b: Pubkey,
+
+    /// Pool tokens are issued when A or B tokens are deposited.
+    /// Pool tokens can be withdrawn back to the original A or B token.
+    pub pool_mint: Pubkey,
+
+    /// Mint information for token A
+    pub token_a_mint: Pubkey,
+    /// Mint information for token B
+    pub token_b_mint: Pubkey,
+
+    /// All fee information
+    pub fees: Fees,
+
+    /// Swap curve parameters, to be unpacked and used by the SwapCurve, which
+    /// calculates swaps, deposits, and withdrawals
+    pub swap_curve: SwapCurve,
+
+    /// Part of the reserves posted as limit orders on the Serum market
+    pub orderbook: Orderbook,
+
+    /// Swaps, deposits and single-sided withdrawals are halted, LPs can
+    /// still withdraw both tokens
+    pub is_paused: bool,
+
+    /// The owner fee stays in the pool and is paid as pool tokens minted to
+    /// the fee owner on deposits and withdrawals
+    pub lp_protocol_fee: bool,
+
+    /// Square root of the reserves product after the last liquidity change
+    pub root_k_last: u128,
+
+    /// Decayed price movement of the recent trades, the return fee rises
+    /// with it up to `fees.max_return_fee_numerator`
+    pub volatility: Volatility,
+
+    /// Cumulative prices, at a fixed offset so other programs can read
+    /// them without unpacking the swap
+    pub price_oracle: PriceOracle,
+}
+
+impl SwapV2 {
+    /// Bytes reserved for future fields, after the price oracle
+    pub const RESERVED_LEN: usize = 256;
+    /// Offset of the price oracle, new fields are taken from the reserved
+    /// bytes after it so that it never moves
+    pub const PRICE_ORACLE_OFFSET: usize = 451;
+
+    /// Upgrades a swap of the first layout, which charged the fees and used
+    /// the curve of the program state
+    pub fn from_v1(swap: SwapV1, fees: Fees, swap_curve: SwapCurve) -> Self {
+        Self {
+            is_initialized: swap.is_initialized,
+            nonce: swap.nonce,
+            amm_id: swap.amm_id,
+            dex_program_id: swap.dex_program_id,
+            market_id: swap.market_id,
+            token_program_id: swap.token_program_id,
+            token_a: swap.token_a,
+            token_b: swap.token_b,
+            pool_mint: swap.pool_mint,
+            token_a_mint: swap.token_a_mint,
+            token_b_mint: swap.token_b_mint,
+            fees,
+            swap_curve,
+            ..Self::default()
+        }
+    }
+}
+
+impl AmmStatus for SwapV2 {
     fn is_initialized(&self) -> bool {
         self.is_initialized
     }
@@ -259,20 +907,61 @@ impl AmmStatus for SwapV1 {
     fn token_b_mint(&self) -> &Pubkey {
         &self.token_b_mint
     }
+
+    fn dex_program_id(&self) -> &Pubkey {
+        &self.dex_program_id
+    }
+
+    fn market_id(&self) -> &Pubkey {
+        &self.market_id
+    }
+
+    fn fees(&self) -> &Fees {
+        &self.fees
+    }
+
+    fn swap_curve(&self) -> &SwapCurve {
+        &self.swap_curve
+    }
+
+    fn price_oracle(&self) -> &PriceOracle {
+        &self.price_oracle
+    }
+
+    fn orderbook(&self) -> &Orderbook {
+        &self.orderbook
+    }
+
+    fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    fn lp_protocol_fee(&self) -> bool {
+        self.lp_protocol_fee
+    }
+
+    fn root_k_last(&self) -> u128 {
+        self.root_k_last
+    }
+
+    fn volatility(&self) -> &Volatility {
+        &self.volatility
+    }
 }
 
-impl Sealed for SwapV1 {}
-impl IsInitialized for SwapV1 {
+
+impl Sealed for SwapV2 {}
+impl IsInitialized for SwapV2 {
     fn is_initialized(&self) -> bool {
         self.is_initialized
     }
 }
 
-impl Pack for SwapV1 {
-    const LEN: usize = 290;
+impl Pack for SwapV2 {
+    const LEN: usize = SwapV2::PRICE_ORACLE_OFFSET + PriceOracle::LEN + SwapV2::RESERVED_LEN;
 
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let output = array_mut_ref![output, 0, SwapV1::LEN];
+        let output = array_mut_ref![output, 0, SwapV2::LEN];
         let (
             is_initialized,
             nonce,
@@ -285,7 +974,19 @@ impl Pack for SwapV1 {
             pool_mint,
             token_a_mint,
             token_b_mint,
-        ) = mut_array_refs![output, 1, 1, 32, 32, 32, 32, 32, 32, 32, 32, 32];
+            fees,
+            swap_curve,
+            orderbook,
+            is_paused,
+            lp_protocol_fee,
+            root_k_last,
+            volatility,
+            price_oracle,
+            reserved,
+        ) = mut_array_refs![
+            output, 1, 1, 32, 32, 32, 32, 32, 32, 32, 32, 32, 56, 33, 38, 1, 1, 16, 16, 48,
+            SwapV2::RESERVED_LEN
+        ];
         is_initialized[0] = self.is_initialized as u8;
         nonce[0] = self.nonce;
         amm_id.copy_from_slice(self.amm_id.as_ref());
@@ -297,14 +998,23 @@ impl Pack for SwapV1 {
         pool_mint.copy_from_slice(self.pool_mint.as_ref());
         token_a_mint.copy_from_slice(self.token_a_mint.as_ref());
         token_b_mint.copy_from_slice(self.token_b_mint.as_ref());
+        self.fees.pack_into_slice(&mut fees[..]);
+        self.swap_curve.pack_into_slice(&mut swap_curve[..]);
+        self.orderbook.pack_into_slice(&mut orderbook[..]);
+        is_paused[0] = self.is_paused as u8;
+        lp_protocol_fee[0] = self.lp_protocol_fee as u8;
+        *root_k_last = self.root_k_last.to_le_bytes();
+        self.volatility.pack_into_slice(&mut volatility[..]);
+        self.price_oracle.pack_into_slice(&mut price_oracle[..]);
+        *reserved = [0u8; SwapV2::RESERVED_LEN];
     }
 
-    /// Unpacks a byte buffer into a [SwapV1](struct.SwapV1.html).
+    /// Unpacks a byte buffer into a [SwapV2](struct.SwapV2.html).
     fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
         if input.len() < Self::LEN{
             return Err(AmmError::InvalidInstruction.into());    
         }
-        let input = array_ref![input, 0, SwapV1::LEN];
+        let input = array_ref![input, 0, SwapV2::LEN];
         #[allow(clippy::ptr_offset_with_cast)]
         let (
             is_initialized,
@@ -318,7 +1028,19 @@ impl Pack for SwapV1 {
             pool_mint,
             token_a_mint,
             token_b_mint,
-        ) = array_refs![input, 1, 1, 32, 32, 32, 32, 32, 32, 32, 32, 32];
+            fees,
+            swap_curve,
+            orderbook,
+            is_paused,
+            lp_protocol_fee,
+            root_k_last,
+            volatility,
+            price_oracle,
+            _reserved,
+        ) = array_refs![
+            input, 1, 1, 32, 32, 32, 32, 32, 32, 32, 32, 32, 56, 33, 38, 1, 1, 16, 16, 48,
+            SwapV2::RESERVED_LEN
+        ];
         Ok(Self {
             is_initialized: match is_initialized {
                 [0] => false,
@@ -335,6 +1057,22 @@ impl Pack for SwapV1 {
             pool_mint: Pubkey::new_from_array(*pool_mint),
             token_a_mint: Pubkey::new_from_array(*token_a_mint),
             token_b_mint: Pubkey::new_from_array(*token_b_mint),
+            fees: Fees::unpack_from_slice(fees)?,
+            swap_curve: SwapCurve::unpack_from_slice(swap_curve)?,
+            orderbook: Orderbook::unpack_from_slice(orderbook)?,
+            is_paused: match is_paused {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            lp_protocol_fee: match lp_protocol_fee {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            root_k_last: u128::from_le_bytes(*root_k_last),
+            volatility: Volatility::unpack_from_slice(volatility)?,
+            price_oracle: PriceOracle::unpack_from_slice(price_oracle)?,
         })
     }
 }
\ No newline at end of file
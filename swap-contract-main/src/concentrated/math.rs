@@ -0,0 +1,355 @@
+//! Tick and price math of the concentrated liquidity pools, prices are
+//! stored as square roots in Q64.64 fixed point
+
+use spl_math::uint::U256;
+
+/// Smallest tick, its price is about 2^-64
+pub const MIN_TICK: i32 = -443636;
+/// Largest tick, its price is about 2^64
+pub const MAX_TICK: i32 = 443636;
+/// Square root price of `MIN_TICK`
+pub const MIN_SQRT_PRICE: u128 = 4295048016;
+/// Square root price of `MAX_TICK`
+pub const MAX_SQRT_PRICE: u128 = 79226673521066979257578248091;
+
+/// 2^64 / sqrt(1.0001)^(2^i), rounded down
+const TICK_RATIOS: [u128; 19] = [
+    18445821805675392311,
+    18444899583751176498,
+    18443055278223354162,
+    18439367220385604838,
+    18431993317065449817,
+    18417254355718160513,
+    18387811781193591352,
+    18329067761203520168,
+    18212142134806087854,
+    17980523815641551639,
+    17526086738831147013,
+    16651378430235024244,
+    15030750278693429944,
+    12247334978882834399,
+    8131365268884726200,
+    3584323654723342297,
+    696457651847595233,
+    26294789957452057,
+    37481735321082,
+];
+
+/// Square root of the price 1.0001^tick as a Q64.64 number
+pub fn sqrt_price_from_tick(tick: i32) -> Option<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+    let abs_tick = tick.unsigned_abs();
+    // ratio never exceeds 2^64 and the tick ratios are below it, so the
+    // products fit in a u128
+    let mut ratio: u128 = 1 << 64;
+    for (i, tick_ratio) in TICK_RATIOS.iter().enumerate() {
+        if abs_tick & (1 << i) != 0 {
+            ratio = ratio.checked_mul(*tick_ratio)? >> 64;
+        }
+    }
+    if tick > 0 {
+        Some(u128::MAX / ratio)
+    } else {
+        Some(ratio)
+    }
+}
+
+/// Greatest tick whose square root price is at or below the given one
+pub fn tick_from_sqrt_price(sqrt_price: u128) -> Option<i32> {
+    if !(MIN_SQRT_PRICE..=MAX_SQRT_PRICE).contains(&sqrt_price) {
+        return None;
+    }
+    let mut low = MIN_TICK;
+    let mut high = MAX_TICK;
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if sqrt_price_from_tick(mid)? <= sqrt_price {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Some(low)
+}
+
+fn div(numerator: U256, denominator: U256, round_up: bool) -> Option<U256> {
+    let quotient = numerator.checked_div(denominator)?;
+    if round_up && quotient.checked_mul(denominator)? != numerator {
+        quotient.checked_add(U256::one())
+    } else {
+        Some(quotient)
+    }
+}
+
+fn to_u128(value: U256) -> Option<u128> {
+    if value > U256::from(u128::MAX) {
+        None
+    } else {
+        Some(value.as_u128())
+    }
+}
+
+/// Amount of token A held by the liquidity between two square root prices
+pub fn token_a_delta(
+    sqrt_price_lower: u128,
+    sqrt_price_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    // L * (upper - lower) / (upper * lower), scaled by 2^64
+    let numerator = (U256::from(liquidity) << 64)
+        .checked_mul(U256::from(sqrt_price_upper.checked_sub(sqrt_price_lower)?))?;
+    let amount = div(numerator, U256::from(sqrt_price_upper), round_up)?;
+    to_u128(div(amount, U256::from(sqrt_price_lower), round_up)?)
+}
+
+/// Amount of token B held by the liquidity between two square root prices
+pub fn token_b_delta(
+    sqrt_price_lower: u128,
+    sqrt_price_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    // L * (upper - lower), scaled down by 2^64
+    let numerator = U256::from(liquidity)
+        .checked_mul(U256::from(sqrt_price_upper.checked_sub(sqrt_price_lower)?))?;
+    to_u128(div(numerator, U256::from(1u128 << 64), round_up)?)
+}
+
+/// Amounts of tokens A and B needed for the liquidity of a position
+/// between two ticks, at the current price
+pub fn token_amounts_for_liquidity(
+    sqrt_price: u128,
+    sqrt_price_lower: u128,
+    sqrt_price_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<(u128, u128)> {
+    if sqrt_price < sqrt_price_lower {
+        Some((
+            token_a_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up)?,
+            0,
+        ))
+    } else if sqrt_price < sqrt_price_upper {
+        Some((
+            token_a_delta(sqrt_price, sqrt_price_upper, liquidity, round_up)?,
+            token_b_delta(sqrt_price_lower, sqrt_price, liquidity, round_up)?,
+        ))
+    } else {
+        Some((
+            0,
+            token_b_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up)?,
+        ))
+    }
+}
+
+/// Result of swapping inside a range of constant liquidity
+#[derive(Debug, PartialEq)]
+pub struct SwapStep {
+    /// Square root price at the end of the step
+    pub sqrt_price: u128,
+    /// Amount of source token swapped, without fees
+    pub amount_in: u128,
+    /// Amount of destination token swapped
+    pub amount_out: u128,
+}
+
+/// Swaps at most `amount_remaining` source tokens, without fees, moving the
+/// price towards the target price. Rounds in favor of the pool.
+pub fn compute_swap_step(
+    sqrt_price: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u128,
+    a_to_b: bool,
+) -> Option<SwapStep> {
+    if a_to_b {
+        let max_amount_in = token_a_delta(sqrt_price_target, sqrt_price, liquidity, true)?;
+        let (next_sqrt_price, amount_in) = if amount_remaining >= max_amount_in {
+            (sqrt_price_target, max_amount_in)
+        } else {
+            // L * P / (L + amount * P), rounded up to keep the price high
+            let liquidity = U256::from(liquidity) << 64;
+            let numerator = liquidity.checked_mul(U256::from(sqrt_price))?;
+            let denominator = liquidity
+                .checked_add(U256::from(amount_remaining).checked_mul(U256::from(sqrt_price))?)?;
+            (to_u128(div(numerator, denominator, true)?)?, amount_remaining)
+        };
+        Some(SwapStep {
+            sqrt_price: next_sqrt_price,
+            amount_in,
+            amount_out: token_b_delta(next_sqrt_price, sqrt_price, liquidity, false)?,
+        })
+    } else {
+        let max_amount_in = token_b_delta(sqrt_price, sqrt_price_target, liquidity, true)?;
+        let (next_sqrt_price, amount_in) = if amount_remaining >= max_amount_in {
+            (sqrt_price_target, max_amount_in)
+        } else {
+            // P + amount / L, rounded down to keep the price low
+            let delta = div(
+                U256::from(amount_remaining) << 64,
+                U256::from(liquidity),
+                false,
+            )?;
+            (
+                sqrt_price.checked_add(to_u128(delta)?)?,
+                amount_remaining,
+            )
+        };
+        Some(SwapStep {
+            sqrt_price: next_sqrt_price,
+            amount_in,
+            amount_out: token_a_delta(sqrt_price, next_sqrt_price, liquidity, false)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Q64: u128 = 1 << 64;
+
+    #[test]
+    fn sqrt_price_bounds() {
+        assert_eq!(sqrt_price_from_tick(0), Some(Q64));
+        assert_eq!(sqrt_price_from_tick(MIN_TICK), Some(MIN_SQRT_PRICE));
+        assert_eq!(sqrt_price_from_tick(MAX_TICK), Some(MAX_SQRT_PRICE));
+        assert_eq!(sqrt_price_from_tick(MIN_TICK - 1), None);
+        assert_eq!(sqrt_price_from_tick(MAX_TICK + 1), None);
+
+        assert_eq!(tick_from_sqrt_price(MIN_SQRT_PRICE), Some(MIN_TICK));
+        assert_eq!(tick_from_sqrt_price(MAX_SQRT_PRICE), Some(MAX_TICK));
+        assert_eq!(tick_from_sqrt_price(MIN_SQRT_PRICE - 1), None);
+        assert_eq!(tick_from_sqrt_price(MAX_SQRT_PRICE + 1), None);
+    }
+
+    #[test]
+    fn sqrt_price_known_values() {
+        // sqrt(1.0001) and 1 / sqrt(1.0001) as Q64.64, and 1.0001^6932 ~ 2
+        assert_eq!(sqrt_price_from_tick(1), Some(18447666387855959851));
+        assert_eq!(sqrt_price_from_tick(-1), Some(18445821805675392311));
+        let sqrt_two = (std::f64::consts::SQRT_2 * Q64 as f64) as u128;
+        let sqrt_price = sqrt_price_from_tick(6932).unwrap();
+        assert!(sqrt_price > sqrt_two - sqrt_two / 10_000);
+        assert!(sqrt_price < sqrt_two + sqrt_two / 10_000);
+    }
+
+    #[test]
+    fn tick_sqrt_price_round_trip() {
+        let ticks = (-1_000..=1_000)
+            .chain((MIN_TICK..=MAX_TICK).step_by(997))
+            .chain([MIN_TICK, MIN_TICK + 1, MAX_TICK - 1, MAX_TICK]);
+        for tick in ticks {
+            let sqrt_price = sqrt_price_from_tick(tick).unwrap();
+            assert_eq!(tick_from_sqrt_price(sqrt_price), Some(tick));
+            if tick < MAX_TICK {
+                // prices strictly between two ticks belong to the lower one
+                let next_sqrt_price = sqrt_price_from_tick(tick + 1).unwrap();
+                assert!(next_sqrt_price > sqrt_price);
+                assert_eq!(tick_from_sqrt_price(next_sqrt_price - 1), Some(tick));
+            }
+        }
+    }
+
+    #[test]
+    fn swap_step_a_to_b_exact_in() {
+        let sqrt_price = Q64;
+        let sqrt_price_target = sqrt_price_from_tick(-100).unwrap();
+        let liquidity = 1_000_000_000;
+        let max_amount_in = token_a_delta(sqrt_price_target, sqrt_price, liquidity, true).unwrap();
+        let max_amount_out = token_b_delta(sqrt_price_target, sqrt_price, liquidity, false).unwrap();
+        assert_eq!(max_amount_in, 5_012_270);
+        assert_eq!(max_amount_out, 4_987_272);
+
+        // enough to reach the target, the rest is left
+        let step =
+            compute_swap_step(sqrt_price, sqrt_price_target, liquidity, max_amount_in + 10, true)
+                .unwrap();
+        assert_eq!(
+            step,
+            SwapStep {
+                sqrt_price: sqrt_price_target,
+                amount_in: max_amount_in,
+                amount_out: max_amount_out,
+            }
+        );
+
+        // partial fill, the price stays above the target
+        let amount_remaining = max_amount_in / 2;
+        let step =
+            compute_swap_step(sqrt_price, sqrt_price_target, liquidity, amount_remaining, true)
+                .unwrap();
+        assert_eq!(step.amount_in, amount_remaining);
+        assert!(step.sqrt_price > sqrt_price_target && step.sqrt_price < sqrt_price);
+        assert!(step.amount_out < max_amount_out);
+        assert!(
+            token_a_delta(step.sqrt_price, sqrt_price, liquidity, true).unwrap()
+                <= amount_remaining
+        );
+        // about 1:1 near the middle of the range
+        assert!(step.amount_out > amount_remaining * 99 / 100);
+
+        // nothing in, nothing out
+        let step = compute_swap_step(sqrt_price, sqrt_price_target, liquidity, 0, true).unwrap();
+        assert_eq!(step.sqrt_price, sqrt_price);
+        assert_eq!(step.amount_out, 0);
+    }
+
+    #[test]
+    fn swap_step_b_to_a_exact_in() {
+        let sqrt_price = Q64;
+        let sqrt_price_target = sqrt_price_from_tick(100).unwrap();
+        let liquidity = 1_000_000_000;
+        let max_amount_in = token_b_delta(sqrt_price, sqrt_price_target, liquidity, true).unwrap();
+        let max_amount_out = token_a_delta(sqrt_price, sqrt_price_target, liquidity, false).unwrap();
+
+        let step =
+            compute_swap_step(sqrt_price, sqrt_price_target, liquidity, u64::MAX as u128, false)
+                .unwrap();
+        assert_eq!(
+            step,
+            SwapStep {
+                sqrt_price: sqrt_price_target,
+                amount_in: max_amount_in,
+                amount_out: max_amount_out,
+            }
+        );
+
+        let amount_remaining = max_amount_in / 3;
+        let step =
+            compute_swap_step(sqrt_price, sqrt_price_target, liquidity, amount_remaining, false)
+                .unwrap();
+        assert_eq!(step.amount_in, amount_remaining);
+        assert!(step.sqrt_price > sqrt_price && step.sqrt_price < sqrt_price_target);
+        assert!(step.amount_out < amount_remaining);
+        assert!(
+            token_b_delta(sqrt_price, step.sqrt_price, liquidity, true).unwrap()
+                <= amount_remaining
+        );
+    }
+
+    #[test]
+    fn swap_steps_round_in_favor_of_the_pool() {
+        let liquidity = 123_456_789;
+        let sqrt_price_lower = sqrt_price_from_tick(-500).unwrap();
+        let sqrt_price_upper = sqrt_price_from_tick(500).unwrap();
+        for amount_remaining in [1, 2, 17, 1_000, 99_999] {
+            // swapping A for B and back never gives more A than was put in
+            let there =
+                compute_swap_step(Q64, sqrt_price_lower, liquidity, amount_remaining, true)
+                    .unwrap();
+            let back = compute_swap_step(
+                there.sqrt_price,
+                sqrt_price_upper,
+                liquidity,
+                there.amount_out,
+                false,
+            )
+            .unwrap();
+            assert!(back.amount_out <= amount_remaining);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! Concentrated liquidity pools, where liquidity is provided inside price
+//! ranges by positions instead of across the whole curve by a pool mint
+
+pub mod math;
+pub mod state;
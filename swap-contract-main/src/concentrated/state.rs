@@ -0,0 +1,868 @@
+//! Concentrated liquidity pool and position accounts
+
+use crate::{
+    concentrated::math::{
+        compute_swap_step, sqrt_price_from_tick, tick_from_sqrt_price,
+        token_amounts_for_liquidity, MAX_SQRT_PRICE, MAX_TICK, MIN_SQRT_PRICE, MIN_TICK,
+    },
+    curve::fees::Fees,
+    error::AmmError,
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+use spl_math::uint::U256;
+
+/// Maximum number of initialized ticks in a pool
+pub const MAX_TICKS: usize = 64;
+
+/// First byte of an initialized pool account, distinct from the
+/// `SwapVersion` tags so the accounts can't be mistaken for each other
+pub const CONCENTRATED_POOL_TYPE: u8 = 0x80;
+/// First byte of an initialized position account
+pub const POSITION_TYPE: u8 = 0x81;
+
+/// Liquidity boundary of one or more positions
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TickInfo {
+    /// Index of the tick, its price is 1.0001^index
+    pub index: i32,
+    /// Liquidity added when the price crosses the tick upwards, and removed
+    /// when it crosses it downwards
+    pub liquidity_net: i128,
+    /// Total liquidity of the positions using the tick
+    pub liquidity_gross: u128,
+    /// Token A fee growth on the other side of the tick from the current
+    /// price, per unit of liquidity as Q64.64
+    pub fee_growth_outside_a: u128,
+    /// Token B fee growth on the other side of the tick from the current
+    /// price, per unit of liquidity as Q64.64
+    pub fee_growth_outside_b: u128,
+}
+
+impl TickInfo {
+    /// Size of a packed tick
+    pub const LEN: usize = 68;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, TickInfo::LEN];
+        let (index, liquidity_net, liquidity_gross, fee_growth_outside_a, fee_growth_outside_b) =
+            mut_array_refs![output, 4, 16, 16, 16, 16];
+        *index = self.index.to_le_bytes();
+        *liquidity_net = self.liquidity_net.to_le_bytes();
+        *liquidity_gross = self.liquidity_gross.to_le_bytes();
+        *fee_growth_outside_a = self.fee_growth_outside_a.to_le_bytes();
+        *fee_growth_outside_b = self.fee_growth_outside_b.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Self {
+        let input = array_ref![input, 0, TickInfo::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (index, liquidity_net, liquidity_gross, fee_growth_outside_a, fee_growth_outside_b) =
+            array_refs![input, 4, 16, 16, 16, 16];
+        Self {
+            index: i32::from_le_bytes(*index),
+            liquidity_net: i128::from_le_bytes(*liquidity_net),
+            liquidity_gross: u128::from_le_bytes(*liquidity_gross),
+            fee_growth_outside_a: u128::from_le_bytes(*fee_growth_outside_a),
+            fee_growth_outside_b: u128::from_le_bytes(*fee_growth_outside_b),
+        }
+    }
+}
+
+/// Result of a swap through a concentrated pool
+#[derive(Debug, PartialEq)]
+pub struct ConcentratedSwapResult {
+    /// Amount of source token taken from the user, including fees
+    pub amount_in: u128,
+    /// Amount of destination token sent to the user
+    pub amount_out: u128,
+    /// Part of the amount in sent to the owner fee account
+    pub owner_fee: u128,
+}
+
+/// Concentrated liquidity pool
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct ConcentratedPool {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Nonce used in program address, the pool authority owns the token
+    /// accounts of the pool
+    pub nonce: u8,
+    /// Program ID of the tokens being exchanged.
+    pub token_program_id: Pubkey,
+    /// Token A
+    pub token_a: Pubkey,
+    /// Token B
+    pub token_b: Pubkey,
+    /// Mint information for token A
+    pub token_a_mint: Pubkey,
+    /// Mint information for token B
+    pub token_b_mint: Pubkey,
+    /// All fee information, the return fee goes to the positions in range
+    pub fees: Fees,
+    /// Position ticks must be multiples of the tick spacing
+    pub tick_spacing: u16,
+    /// Square root of the price of token A in token B, as Q64.64
+    pub sqrt_price: u128,
+    /// Greatest tick at or below the current price
+    pub tick_current: i32,
+    /// Liquidity of the positions in range
+    pub liquidity: u128,
+    /// Token A fees earned per unit of liquidity, as Q64.64
+    pub fee_growth_global_a: u128,
+    /// Token B fees earned per unit of liquidity, as Q64.64
+    pub fee_growth_global_b: u128,
+    /// Swaps and liquidity additions are halted, positions can still be
+    /// decreased and their fees collected
+    pub is_paused: bool,
+    /// Initialized ticks, sorted by index
+    pub ticks: Vec<TickInfo>,
+}
+
+impl ConcentratedPool {
+    /// Checks that a position range can be opened in the pool
+    pub fn validate_tick_range(&self, tick_lower: i32, tick_upper: i32) -> Result<(), AmmError> {
+        let tick_spacing = i32::from(self.tick_spacing);
+        if tick_lower >= tick_upper
+            || tick_lower < MIN_TICK
+            || tick_upper > MAX_TICK
+            || tick_lower % tick_spacing != 0
+            || tick_upper % tick_spacing != 0
+        {
+            Err(AmmError::InvalidTickRange)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn tick(&self, index: i32) -> Option<&TickInfo> {
+        self.ticks
+            .binary_search_by_key(&index, |tick| tick.index)
+            .ok()
+            .map(|position| &self.ticks[position])
+    }
+
+    fn update_tick(
+        &mut self,
+        index: i32,
+        liquidity_delta: i128,
+        upper: bool,
+    ) -> Result<(), ProgramError> {
+        let position = match self.ticks.binary_search_by_key(&index, |tick| tick.index) {
+            Ok(position) => position,
+            Err(position) => {
+                if self.ticks.len() >= MAX_TICKS {
+                    return Err(AmmError::TickListFull.into());
+                }
+                // by convention all the fees so far were earned below the tick
+                let (fee_growth_outside_a, fee_growth_outside_b) = if index <= self.tick_current {
+                    (self.fee_growth_global_a, self.fee_growth_global_b)
+                } else {
+                    (0, 0)
+                };
+                self.ticks.insert(
+                    position,
+                    TickInfo {
+                        index,
+                        fee_growth_outside_a,
+                        fee_growth_outside_b,
+                        ..TickInfo::default()
+                    },
+                );
+                position
+            }
+        };
+        let tick = &mut self.ticks[position];
+        tick.liquidity_gross = if liquidity_delta >= 0 {
+            tick.liquidity_gross.checked_add(liquidity_delta as u128)
+        } else {
+            tick.liquidity_gross.checked_sub(liquidity_delta.wrapping_neg() as u128)
+        }
+        .ok_or(AmmError::CalculationFailure)?;
+        tick.liquidity_net = if upper {
+            tick.liquidity_net.checked_sub(liquidity_delta)
+        } else {
+            tick.liquidity_net.checked_add(liquidity_delta)
+        }
+        .ok_or(AmmError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Fee growth per unit of liquidity between two initialized ticks
+    fn fee_growth_inside(&self, tick_lower: i32, tick_upper: i32) -> (u128, u128) {
+        let lower = self.tick(tick_lower).copied().unwrap_or_default();
+        let upper = self.tick(tick_upper).copied().unwrap_or_default();
+        let (below_a, below_b) = if self.tick_current >= tick_lower {
+            (lower.fee_growth_outside_a, lower.fee_growth_outside_b)
+        } else {
+            (
+                self.fee_growth_global_a.wrapping_sub(lower.fee_growth_outside_a),
+                self.fee_growth_global_b.wrapping_sub(lower.fee_growth_outside_b),
+            )
+        };
+        let (above_a, above_b) = if self.tick_current < tick_upper {
+            (upper.fee_growth_outside_a, upper.fee_growth_outside_b)
+        } else {
+            (
+                self.fee_growth_global_a.wrapping_sub(upper.fee_growth_outside_a),
+                self.fee_growth_global_b.wrapping_sub(upper.fee_growth_outside_b),
+            )
+        };
+        (
+            self.fee_growth_global_a
+                .wrapping_sub(below_a)
+                .wrapping_sub(above_a),
+            self.fee_growth_global_b
+                .wrapping_sub(below_b)
+                .wrapping_sub(above_b),
+        )
+    }
+
+    /// Adds liquidity to a position, or removes it for a negative delta,
+    /// and credits the fees it earned so far. Returns the token A and B
+    /// amounts to deposit, or to withdraw, rounded in favor of the pool.
+    pub fn modify_position(
+        &mut self,
+        position: &mut Position,
+        liquidity_delta: i128,
+    ) -> Result<(u128, u128), ProgramError> {
+        let (tick_lower, tick_upper) = (position.tick_lower, position.tick_upper);
+        if liquidity_delta != 0 {
+            self.update_tick(tick_lower, liquidity_delta, false)?;
+            self.update_tick(tick_upper, liquidity_delta, true)?;
+        }
+
+        let (fee_growth_inside_a, fee_growth_inside_b) =
+            self.fee_growth_inside(tick_lower, tick_upper);
+        position.update_fees(fee_growth_inside_a, fee_growth_inside_b)?;
+
+        position.liquidity = if liquidity_delta >= 0 {
+            position.liquidity.checked_add(liquidity_delta as u128)
+        } else {
+            position
+                .liquidity
+                .checked_sub(liquidity_delta.wrapping_neg() as u128)
+        }
+        .ok_or(AmmError::InsufficientLiquidity)?;
+
+        if tick_lower <= self.tick_current && self.tick_current < tick_upper {
+            self.liquidity = if liquidity_delta >= 0 {
+                self.liquidity.checked_add(liquidity_delta as u128)
+            } else {
+                self.liquidity
+                    .checked_sub(liquidity_delta.wrapping_neg() as u128)
+            }
+            .ok_or(AmmError::CalculationFailure)?;
+        }
+        if liquidity_delta < 0 {
+            self.ticks.retain(|tick| tick.liquidity_gross > 0);
+        }
+
+        let amounts = token_amounts_for_liquidity(
+            self.sqrt_price,
+            sqrt_price_from_tick(tick_lower).ok_or(AmmError::InvalidTickRange)?,
+            sqrt_price_from_tick(tick_upper).ok_or(AmmError::InvalidTickRange)?,
+            if liquidity_delta >= 0 {
+                liquidity_delta as u128
+            } else {
+                liquidity_delta.wrapping_neg() as u128
+            },
+            liquidity_delta > 0,
+        )
+        .ok_or(AmmError::CalculationFailure)?;
+        Ok(amounts)
+    }
+
+    fn next_initialized_tick(&self, a_to_b: bool) -> Option<TickInfo> {
+        if a_to_b {
+            self.ticks
+                .iter()
+                .rev()
+                .find(|tick| tick.index <= self.tick_current)
+                .copied()
+        } else {
+            self.ticks
+                .iter()
+                .find(|tick| tick.index > self.tick_current)
+                .copied()
+        }
+    }
+
+    fn cross_tick(&mut self, index: i32, a_to_b: bool) -> Result<(), ProgramError> {
+        let (fee_growth_global_a, fee_growth_global_b) =
+            (self.fee_growth_global_a, self.fee_growth_global_b);
+        let position = self
+            .ticks
+            .binary_search_by_key(&index, |tick| tick.index)
+            .map_err(|_| AmmError::CalculationFailure)?;
+        let tick = &mut self.ticks[position];
+        tick.fee_growth_outside_a = fee_growth_global_a.wrapping_sub(tick.fee_growth_outside_a);
+        tick.fee_growth_outside_b = fee_growth_global_b.wrapping_sub(tick.fee_growth_outside_b);
+        let liquidity_net = if a_to_b {
+            tick.liquidity_net.checked_neg()
+        } else {
+            Some(tick.liquidity_net)
+        }
+        .ok_or(AmmError::CalculationFailure)?;
+        self.liquidity = if liquidity_net >= 0 {
+            self.liquidity.checked_add(liquidity_net as u128)
+        } else {
+            self.liquidity.checked_sub(liquidity_net.wrapping_neg() as u128)
+        }
+        .ok_or(AmmError::CalculationFailure)?;
+        self.tick_current = if a_to_b { index - 1 } else { index };
+        Ok(())
+    }
+
+    /// Swaps an exact amount in, crossing ticks until the amount is used
+    /// or the price reaches the limit. A zero limit means no limit.
+    pub fn swap(
+        &mut self,
+        amount_in: u128,
+        sqrt_price_limit: u128,
+        a_to_b: bool,
+    ) -> Result<ConcentratedSwapResult, ProgramError> {
+        let sqrt_price_limit = match (sqrt_price_limit, a_to_b) {
+            (0, true) => MIN_SQRT_PRICE,
+            (0, false) => MAX_SQRT_PRICE,
+            (limit, _) => limit,
+        };
+        if (a_to_b && (sqrt_price_limit >= self.sqrt_price || sqrt_price_limit < MIN_SQRT_PRICE))
+            || (!a_to_b
+                && (sqrt_price_limit <= self.sqrt_price || sqrt_price_limit > MAX_SQRT_PRICE))
+        {
+            return Err(AmmError::InvalidInput.into());
+        }
+
+        let mut amount_remaining = amount_in;
+        let mut amount_out: u128 = 0;
+        let mut owner_fee: u128 = 0;
+        while amount_remaining > 0 && self.sqrt_price != sqrt_price_limit {
+            let next_tick = self.next_initialized_tick(a_to_b);
+            let next_tick_sqrt_price = match next_tick {
+                Some(tick) => {
+                    Some(sqrt_price_from_tick(tick.index).ok_or(AmmError::CalculationFailure)?)
+                }
+                None => None,
+            };
+            let sqrt_price_target = match (next_tick_sqrt_price, a_to_b) {
+                (Some(sqrt_price), true) => sqrt_price.max(sqrt_price_limit),
+                (Some(sqrt_price), false) => sqrt_price.min(sqrt_price_limit),
+                (None, _) => sqrt_price_limit,
+            };
+
+            // fees are taken on the amount in, the remaining amount may
+            // only partly reach the target
+            let amount_available = amount_remaining
+                .checked_sub(self.fees.return_fee(amount_remaining).ok_or(AmmError::FeeCalculationFailure)?)
+                .and_then(|amount| {
+                    amount.checked_sub(self.fees.fixed_fee(amount_remaining)?)
+                })
+                .unwrap_or(0);
+            let step = compute_swap_step(
+                self.sqrt_price,
+                sqrt_price_target,
+                self.liquidity,
+                amount_available,
+                a_to_b,
+            )
+            .ok_or(AmmError::CalculationFailure)?;
+            let step_amount_in = if amount_available == 0 {
+                // the fees take all that is left of the amount in
+                amount_remaining
+            } else if step.amount_in == 0 {
+                0
+            } else if step.amount_in < amount_available {
+                self.fees
+                    .pre_fee_amount(step.amount_in)
+                    .ok_or(AmmError::FeeCalculationFailure)?
+                    .min(amount_remaining)
+            } else {
+                amount_remaining
+            };
+            let step_owner_fee = self
+                .fees
+                .fixed_fee(step_amount_in)
+                .ok_or(AmmError::FeeCalculationFailure)?;
+            let step_return_fee = step_amount_in
+                .checked_sub(step.amount_in)
+                .and_then(|amount| amount.checked_sub(step_owner_fee))
+                .ok_or(AmmError::FeeCalculationFailure)?;
+            if self.liquidity > 0 {
+                let fee_growth = (U256::from(step_return_fee) << 64)
+                    .checked_div(U256::from(self.liquidity))
+                    .ok_or(AmmError::CalculationFailure)?;
+                let fee_growth = if fee_growth > U256::from(u128::MAX) {
+                    return Err(AmmError::CalculationFailure.into());
+                } else {
+                    fee_growth.as_u128()
+                };
+                if a_to_b {
+                    self.fee_growth_global_a = self.fee_growth_global_a.wrapping_add(fee_growth);
+                } else {
+                    self.fee_growth_global_b = self.fee_growth_global_b.wrapping_add(fee_growth);
+                }
+            }
+
+            amount_remaining -= step_amount_in;
+            amount_out = amount_out
+                .checked_add(step.amount_out)
+                .ok_or(AmmError::CalculationFailure)?;
+            owner_fee = owner_fee
+                .checked_add(step_owner_fee)
+                .ok_or(AmmError::CalculationFailure)?;
+
+            self.sqrt_price = step.sqrt_price;
+            match (next_tick, next_tick_sqrt_price) {
+                (Some(tick), Some(tick_sqrt_price)) if step.sqrt_price == tick_sqrt_price => {
+                    self.cross_tick(tick.index, a_to_b)?;
+                }
+                _ => {
+                    self.tick_current = tick_from_sqrt_price(self.sqrt_price)
+                        .ok_or(AmmError::CalculationFailure)?;
+                }
+            }
+        }
+
+        Ok(ConcentratedSwapResult {
+            amount_in: amount_in - amount_remaining,
+            amount_out,
+            owner_fee,
+        })
+    }
+}
+
+impl Sealed for ConcentratedPool {}
+impl IsInitialized for ConcentratedPool {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for ConcentratedPool {
+    const LEN: usize = 290 + MAX_TICKS * TickInfo::LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, ConcentratedPool::LEN];
+        let (
+            account_type,
+            nonce,
+            token_program_id,
+            token_a,
+            token_b,
+            token_a_mint,
+            token_b_mint,
+            fees,
+            tick_spacing,
+            sqrt_price,
+            tick_current,
+            liquidity,
+            fee_growth_global_a,
+            fee_growth_global_b,
+            is_paused,
+            ticks_len,
+            ticks,
+        ) = mut_array_refs![
+            output,
+            1,
+            1,
+            32,
+            32,
+            32,
+            32,
+            32,
+            56,
+            2,
+            16,
+            4,
+            16,
+            16,
+            16,
+            1,
+            1,
+            MAX_TICKS * TickInfo::LEN
+        ];
+        account_type[0] = if self.is_initialized {
+            CONCENTRATED_POOL_TYPE
+        } else {
+            0
+        };
+        nonce[0] = self.nonce;
+        token_program_id.copy_from_slice(self.token_program_id.as_ref());
+        token_a.copy_from_slice(self.token_a.as_ref());
+        token_b.copy_from_slice(self.token_b.as_ref());
+        token_a_mint.copy_from_slice(self.token_a_mint.as_ref());
+        token_b_mint.copy_from_slice(self.token_b_mint.as_ref());
+        self.fees.pack_into_slice(&mut fees[..]);
+        *tick_spacing = self.tick_spacing.to_le_bytes();
+        *sqrt_price = self.sqrt_price.to_le_bytes();
+        *tick_current = self.tick_current.to_le_bytes();
+        *liquidity = self.liquidity.to_le_bytes();
+        *fee_growth_global_a = self.fee_growth_global_a.to_le_bytes();
+        *fee_growth_global_b = self.fee_growth_global_b.to_le_bytes();
+        is_paused[0] = self.is_paused as u8;
+        ticks_len[0] = self.ticks.len() as u8;
+        for (tick, output) in self.ticks.iter().zip(ticks.chunks_mut(TickInfo::LEN)) {
+            tick.pack_into_slice(output);
+        }
+    }
+
+    /// Unpacks a byte buffer into a [ConcentratedPool](struct.ConcentratedPool.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() < Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let input = array_ref![input, 0, ConcentratedPool::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            account_type,
+            nonce,
+            token_program_id,
+            token_a,
+            token_b,
+            token_a_mint,
+            token_b_mint,
+            fees,
+            tick_spacing,
+            sqrt_price,
+            tick_current,
+            liquidity,
+            fee_growth_global_a,
+            fee_growth_global_b,
+            is_paused,
+            ticks_len,
+            ticks,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            32,
+            32,
+            32,
+            32,
+            32,
+            56,
+            2,
+            16,
+            4,
+            16,
+            16,
+            16,
+            1,
+            1,
+            MAX_TICKS * TickInfo::LEN
+        ];
+        let ticks_len = ticks_len[0] as usize;
+        if ticks_len > MAX_TICKS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self {
+            is_initialized: match account_type[0] {
+                0 => false,
+                CONCENTRATED_POOL_TYPE => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: nonce[0],
+            token_program_id: Pubkey::new_from_array(*token_program_id),
+            token_a: Pubkey::new_from_array(*token_a),
+            token_b: Pubkey::new_from_array(*token_b),
+            token_a_mint: Pubkey::new_from_array(*token_a_mint),
+            token_b_mint: Pubkey::new_from_array(*token_b_mint),
+            fees: Fees::unpack_from_slice(fees)?,
+            tick_spacing: u16::from_le_bytes(*tick_spacing),
+            sqrt_price: u128::from_le_bytes(*sqrt_price),
+            tick_current: i32::from_le_bytes(*tick_current),
+            liquidity: u128::from_le_bytes(*liquidity),
+            fee_growth_global_a: u128::from_le_bytes(*fee_growth_global_a),
+            fee_growth_global_b: u128::from_le_bytes(*fee_growth_global_b),
+            is_paused: match is_paused {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            ticks: ticks
+                .chunks(TickInfo::LEN)
+                .take(ticks_len)
+                .map(TickInfo::unpack_from_slice)
+                .collect(),
+        })
+    }
+}
+
+/// Liquidity of an owner between two ticks of a concentrated pool
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct Position {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Pool of the position
+    pub pool: Pubkey,
+    /// Owner allowed to change the position and collect its fees
+    pub owner: Pubkey,
+    /// Lower tick of the price range
+    pub tick_lower: i32,
+    /// Upper tick of the price range
+    pub tick_upper: i32,
+    /// Liquidity of the position
+    pub liquidity: u128,
+    /// Token A fee growth inside the range at the last update
+    pub fee_growth_inside_a_last: u128,
+    /// Token B fee growth inside the range at the last update
+    pub fee_growth_inside_b_last: u128,
+    /// Token A fees earned and not collected yet
+    pub tokens_owed_a: u64,
+    /// Token B fees earned and not collected yet
+    pub tokens_owed_b: u64,
+}
+
+impl Position {
+    /// Credits the fees earned by the position liquidity since the last update
+    pub fn update_fees(
+        &mut self,
+        fee_growth_inside_a: u128,
+        fee_growth_inside_b: u128,
+    ) -> Result<(), AmmError> {
+        let earned = |fee_growth_inside: u128, fee_growth_inside_last: u128| {
+            let fees = (U256::from(fee_growth_inside.wrapping_sub(fee_growth_inside_last))
+                * U256::from(self.liquidity))
+                >> 64;
+            if fees > U256::from(u64::MAX) {
+                Err(AmmError::CalculationFailure)
+            } else {
+                Ok(fees.as_u64())
+            }
+        };
+        let earned_a = earned(fee_growth_inside_a, self.fee_growth_inside_a_last)?;
+        let earned_b = earned(fee_growth_inside_b, self.fee_growth_inside_b_last)?;
+        self.tokens_owed_a = self
+            .tokens_owed_a
+            .checked_add(earned_a)
+            .ok_or(AmmError::CalculationFailure)?;
+        self.tokens_owed_b = self
+            .tokens_owed_b
+            .checked_add(earned_b)
+            .ok_or(AmmError::CalculationFailure)?;
+        self.fee_growth_inside_a_last = fee_growth_inside_a;
+        self.fee_growth_inside_b_last = fee_growth_inside_b;
+        Ok(())
+    }
+}
+
+impl Sealed for Position {}
+impl IsInitialized for Position {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Position {
+    const LEN: usize = 137;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, Position::LEN];
+        let (
+            account_type,
+            pool,
+            owner,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            fee_growth_inside_a_last,
+            fee_growth_inside_b_last,
+            tokens_owed_a,
+            tokens_owed_b,
+        ) = mut_array_refs![output, 1, 32, 32, 4, 4, 16, 16, 16, 8, 8];
+        account_type[0] = if self.is_initialized { POSITION_TYPE } else { 0 };
+        pool.copy_from_slice(self.pool.as_ref());
+        owner.copy_from_slice(self.owner.as_ref());
+        *tick_lower = self.tick_lower.to_le_bytes();
+        *tick_upper = self.tick_upper.to_le_bytes();
+        *liquidity = self.liquidity.to_le_bytes();
+        *fee_growth_inside_a_last = self.fee_growth_inside_a_last.to_le_bytes();
+        *fee_growth_inside_b_last = self.fee_growth_inside_b_last.to_le_bytes();
+        *tokens_owed_a = self.tokens_owed_a.to_le_bytes();
+        *tokens_owed_b = self.tokens_owed_b.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [Position](struct.Position.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() < Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let input = array_ref![input, 0, Position::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            account_type,
+            pool,
+            owner,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            fee_growth_inside_a_last,
+            fee_growth_inside_b_last,
+            tokens_owed_a,
+            tokens_owed_b,
+        ) = array_refs![input, 1, 32, 32, 4, 4, 16, 16, 16, 8, 8];
+        Ok(Self {
+            is_initialized: match account_type[0] {
+                0 => false,
+                POSITION_TYPE => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            pool: Pubkey::new_from_array(*pool),
+            owner: Pubkey::new_from_array(*owner),
+            tick_lower: i32::from_le_bytes(*tick_lower),
+            tick_upper: i32::from_le_bytes(*tick_upper),
+            liquidity: u128::from_le_bytes(*liquidity),
+            fee_growth_inside_a_last: u128::from_le_bytes(*fee_growth_inside_a_last),
+            fee_growth_inside_b_last: u128::from_le_bytes(*fee_growth_inside_b_last),
+            tokens_owed_a: u64::from_le_bytes(*tokens_owed_a),
+            tokens_owed_b: u64::from_le_bytes(*tokens_owed_b),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(return_fee_numerator: u64, fixed_fee_numerator: u64) -> ConcentratedPool {
+        ConcentratedPool {
+            is_initialized: true,
+            fees: Fees {
+                return_fee_numerator,
+                fixed_fee_numerator,
+                fee_denominator: 10_000,
+                ..Fees::default()
+            },
+            tick_spacing: 10,
+            sqrt_price: sqrt_price_from_tick(0).unwrap(),
+            ..ConcentratedPool::default()
+        }
+    }
+
+    fn open_position(
+        pool: &mut ConcentratedPool,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u128,
+    ) -> Position {
+        let mut position = Position {
+            is_initialized: true,
+            tick_lower,
+            tick_upper,
+            ..Position::default()
+        };
+        pool.modify_position(&mut position, liquidity as i128).unwrap();
+        position
+    }
+
+    /// Credits the fees earned so far and returns the token A and B owed
+    fn poke(pool: &mut ConcentratedPool, position: &mut Position) -> (u64, u64) {
+        pool.modify_position(position, 0).unwrap();
+        (position.tokens_owed_a, position.tokens_owed_b)
+    }
+
+    #[test]
+    fn swap_fees_consume_the_whole_amount() {
+        let mut pool = pool(30, 20);
+        open_position(&mut pool, -1_000, 1_000, 1_000_000_000);
+        let sqrt_price = pool.sqrt_price;
+
+        // one token only pays the minimum owner fee
+        let result = pool.swap(1, 0, true).unwrap();
+        assert_eq!(
+            result,
+            ConcentratedSwapResult {
+                amount_in: 1,
+                amount_out: 0,
+                owner_fee: 1,
+            }
+        );
+        assert_eq!(pool.sqrt_price, sqrt_price);
+
+        // two tokens pay both minimum fees
+        let result = pool.swap(2, 0, false).unwrap();
+        assert_eq!(result.amount_in, 2);
+        assert_eq!(result.amount_out, 0);
+        assert_eq!(result.owner_fee, 1);
+        assert_eq!(pool.sqrt_price, sqrt_price);
+    }
+
+    #[test]
+    fn swap_stops_at_the_price_limit() {
+        let mut pool = pool(30, 0);
+        open_position(&mut pool, -100, 100, 1_000_000);
+        let sqrt_price_limit = sqrt_price_from_tick(-50).unwrap();
+        let result = pool.swap(1_000_000_000, sqrt_price_limit, true).unwrap();
+        assert!(result.amount_in < 1_000_000_000);
+        assert_eq!(pool.sqrt_price, sqrt_price_limit);
+        assert_eq!(pool.tick_current, -50);
+        assert_eq!(pool.liquidity, 1_000_000);
+    }
+
+    #[test]
+    fn fee_growth_across_tick_crossings() {
+        let mut pool = pool(30, 0);
+        let mut wide = open_position(&mut pool, -1_000, 1_000, 1_000_000_000);
+        let mut narrow = open_position(&mut pool, -100, 100, 3_000_000_000);
+        assert_eq!(pool.liquidity, 4_000_000_000);
+        // ticks initialized at or below the current one start with the
+        // global growth outside, which is still zero
+        assert_eq!(pool.ticks.len(), 4);
+
+        // in range of both positions, the 3000 tokens of fees are shared by
+        // liquidity, rounded down so the pool keeps the dust
+        let result = pool.swap(1_000_000, 0, true).unwrap();
+        assert_eq!(result.owner_fee, 0);
+        assert_eq!(pool.tick_current, -5);
+        let (wide_a, wide_b) = poke(&mut pool, &mut wide);
+        let (narrow_a, narrow_b) = poke(&mut pool, &mut narrow);
+        assert_eq!((wide_b, narrow_b), (0, 0));
+        assert_eq!(wide_a, 749);
+        assert_eq!(narrow_a, 2_249);
+
+        // crossing tick -100 downwards leaves only the wide position
+        let result = pool.swap(30_000_000, 0, true).unwrap();
+        assert_eq!(result.amount_in, 30_000_000);
+        assert!(pool.tick_current < -100 && pool.tick_current >= -1_000);
+        assert_eq!(pool.liquidity, 1_000_000_000);
+        // the crossed tick flipped its growth outside to the one at the
+        // crossing, the ticks above weren't crossed
+        let lower = pool.tick(-100).copied().unwrap();
+        assert!(lower.fee_growth_outside_a > 0);
+        assert!(lower.fee_growth_outside_a < pool.fee_growth_global_a);
+        assert_eq!(pool.tick(100).unwrap().fee_growth_outside_a, 0);
+        let (wide_a_crossed, _) = poke(&mut pool, &mut wide);
+        let (narrow_a_crossed, _) = poke(&mut pool, &mut narrow);
+        // all fees of the trade are earned by the positions in range, the
+        // narrow one only up to the crossing
+        let total_fees: u64 = 30_000_000 * 30 / 10_000;
+        let earned = (wide_a_crossed - wide_a) + (narrow_a_crossed - narrow_a);
+        assert!(earned <= total_fees && earned + 3 >= total_fees);
+        assert!(narrow_a_crossed > narrow_a);
+
+        // below the narrow range, only the wide position earns
+        pool.swap(1_000_000, 0, true).unwrap();
+        let (wide_a_below, _) = poke(&mut pool, &mut wide);
+        let (narrow_a_below, _) = poke(&mut pool, &mut narrow);
+        assert_eq!(narrow_a_below, narrow_a_crossed);
+        assert!(wide_a_below - wide_a_crossed + 1 >= 3_000);
+
+        // crossing back upwards, both earn token B fees again
+        pool.swap(60_000_000, sqrt_price_from_tick(50).unwrap(), false).unwrap();
+        assert_eq!(pool.tick_current, 50);
+        assert_eq!(pool.liquidity, 4_000_000_000);
+        pool.swap(1_000_000, 0, false).unwrap();
+        let (_, wide_b_back) = poke(&mut pool, &mut wide);
+        let (narrow_a_back, narrow_b_back) = poke(&mut pool, &mut narrow);
+        assert_eq!(narrow_a_back, narrow_a_crossed);
+        assert!(narrow_b_back > 0 && wide_b_back > 0);
+        assert!(narrow_b_back > wide_b_back);
+    }
+}
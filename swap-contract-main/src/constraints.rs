@@ -23,6 +23,9 @@ pub struct SwapConstraints<'a> {
     pub valid_curve_types: &'a [CurveType],
     /// Valid fees
     pub fees: &'a Fees,
+    /// Minimum delay, in seconds, between queuing a program state update
+    /// and executing it
+    pub min_state_update_delay: i64,
 }
 
 impl<'a> SwapConstraints<'a> {
@@ -42,6 +45,10 @@ impl<'a> SwapConstraints<'a> {
         if fees.return_fee_numerator >= self.fees.return_fee_numerator
             && fees.fixed_fee_numerator >= self.fees.fixed_fee_numerator
             && fees.fee_denominator == self.fees.fee_denominator
+            && fees.host_fee_numerator <= self.fees.host_fee_numerator
+            && fees.host_fee_denominator == self.fees.host_fee_denominator
+            && fees.owner_withdraw_fee_numerator >= self.fees.owner_withdraw_fee_numerator
+            && fees.max_return_fee_numerator <= self.fees.max_return_fee_numerator
         {
             Ok(())
         } else {
@@ -58,17 +65,27 @@ const FEES: &Fees = &Fees {
     fixed_fee_numerator: 20,
     return_fee_numerator: 10,
     fee_denominator: 10000,
+    host_fee_numerator: 20,
+    host_fee_denominator: 100,
+    owner_withdraw_fee_numerator: 0,
+    max_return_fee_numerator: 100,
 };
-const VALID_CURVE_TYPES: &[CurveType] = &[CurveType::ConstantProduct];
+const MIN_STATE_UPDATE_DELAY: i64 = 2 * 24 * 60 * 60;
+const VALID_CURVE_TYPES: &[CurveType] = &[
+    CurveType::ConstantProduct,
+    CurveType::Stable,
+    CurveType::Weighted,
+];
 
 /// Fee structure defined by program creator in order to enforce certain
 /// fees when others use the program.  Adds checks on pool creation and
 /// swapping to ensure the correct fees and account owners are passed.
 /// Fees provided during production build currently are considered min
-/// fees that creator of the pool can specify. Host fee is a fixed
-/// percentage that host receives as a portion of owner fees
+/// fees that creator of the pool can specify. Host fee is a percentage,
+/// up to the constraint one, that host receives as a portion of owner fees
 pub const SWAP_CONSTRAINTS:SwapConstraints = SwapConstraints {
     owner_key: OWNER_KEY,
     valid_curve_types: VALID_CURVE_TYPES,
     fees: FEES,
+    min_state_update_delay: MIN_STATE_UPDATE_DELAY,
 };
\ No newline at end of file
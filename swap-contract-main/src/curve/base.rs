@@ -12,6 +12,7 @@ use crate::curve::{
     fees::Fees,
     offset::OffsetCurve,
     stable::StableCurve,
+    weighted::WeightedCurve,
 };
 use crate::error::AmmError;
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
@@ -34,6 +35,9 @@ pub enum CurveType {
     Stable,
     /// Offset curve, like Uniswap, but the token B side has a faked offset
     Offset,
+    /// Balancer-style constant mean curve,
+    /// invariant = token_a_amount ^ weight_a * token_b_amount ^ weight_b
+    Weighted,
 }
 
 /// Encodes all results of swapping from a source token to a destination token
@@ -53,6 +57,17 @@ pub struct SwapResult {
     pub owner_fee: u128,
 }
 
+/// Encodes the results of depositing or withdrawing a single token type
+#[derive(Debug, PartialEq)]
+pub struct SingleTokenResult {
+    /// Amount of pool tokens minted on deposit or burned on withdrawal
+    pub pool_token_amount: u128,
+    /// Amount of the deposited or withdrawn token going to pool holders
+    pub trade_fee: u128,
+    /// Amount of the deposited or withdrawn token going to owner
+    pub owner_fee: u128,
+}
+
 /// Concrete struct to wrap around the trait object which performs calculation.
 #[repr(C)]
 #[derive(Debug)]
@@ -67,7 +82,8 @@ pub struct SwapCurve {
 
 impl SwapCurve {
     /// Subtract fees and calculate how much destination token will be provided
-    /// given an amount of source token.
+    /// given an amount of source token. The fees are the effective ones of
+    /// the trade, with the dynamic return fee already applied.
     pub fn swap(
         &self,
         source_amount: u128,
@@ -105,7 +121,46 @@ impl SwapCurve {
         })
     }
 
-    /// Get the amount of pool tokens for the deposited amount of token A or B
+    /// Calculate how much source token must be provided, fees included, to
+    /// receive exactly the given amount of destination token, with the
+    /// same effective fees as [swap](#method.swap).
+    pub fn swap_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        let SwapWithoutFeesResult {
+            source_amount_swapped,
+            destination_amount_swapped,
+        } = self.calculator.swap_without_fees_exact_out(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_direction,
+        )?;
+
+        // credit the fees on top of the amount required by the curve
+        let source_amount_swapped = fees.pre_fee_amount(source_amount_swapped)?;
+        let trade_fee = fees.return_fee(source_amount_swapped)?;
+        let owner_fee = fees.fixed_fee(source_amount_swapped)?;
+
+        Some(SwapResult {
+            new_swap_source_amount: swap_source_amount.checked_add(source_amount_swapped)?,
+            new_swap_destination_amount: swap_destination_amount
+                .checked_sub(destination_amount_swapped)?,
+            source_amount_swapped,
+            destination_amount_swapped,
+            trade_fee,
+            owner_fee,
+        })
+    }
+
+    /// Get the amount of pool tokens for the deposited amount of token A or B,
+    /// net of the trading fees on half of the deposit. None when the fees take
+    /// the whole deposit.
     pub fn deposit_single_token_type(
         &self,
         source_amount: u128,
@@ -113,27 +168,42 @@ impl SwapCurve {
         swap_token_b_amount: u128,
         pool_supply: u128,
         trade_direction: TradeDirection,
-        _fees: &Fees,
-    ) -> Option<u128> {
+        fees: &Fees,
+    ) -> Option<SingleTokenResult> {
         if source_amount == 0 {
-            return Some(0);
+            return Some(SingleTokenResult {
+                pool_token_amount: 0,
+                trade_fee: 0,
+                owner_fee: 0,
+            });
         }
         // Get the trading fee incurred if *half* the source amount is swapped
         // for the other side. Reference at:
         // https://github.com/balancer-labs/balancer-core/blob/f4ed5d65362a8d6cec21662fb6eae233b0babc1f/contracts/BMath.sol#L117
-        // let half_source_amount = std::cmp::max(1, source_amount.checked_div(2)?);
-        let trade_fee = 0;//fees.trading_fee(half_source_amount)?;
-        let source_amount = source_amount.checked_sub(trade_fee)?;
-        self.calculator.deposit_single_token_type(
+        let half_source_amount = std::cmp::max(1, source_amount.checked_div(2)?);
+        let trade_fee = fees.return_fee(half_source_amount)?;
+        let owner_fee = fees.fixed_fee(half_source_amount)?;
+        let fee_amount = trade_fee.checked_add(owner_fee)?;
+        if fee_amount >= source_amount {
+            return None;
+        }
+        let source_amount = source_amount.checked_sub(fee_amount)?;
+        let pool_token_amount = self.calculator.deposit_single_token_type(
             source_amount,
             swap_token_a_amount,
             swap_token_b_amount,
             pool_supply,
             trade_direction,
-        )
+        )?;
+        Some(SingleTokenResult {
+            pool_token_amount,
+            trade_fee,
+            owner_fee,
+        })
     }
 
-    /// Get the amount of pool tokens for the withdrawn amount of token A or B
+    /// Get the amount of pool tokens for the withdrawn amount of token A or B,
+    /// including the trading fees on half of the withdrawal
     pub fn withdraw_single_token_type_exact_out(
         &self,
         source_amount: u128,
@@ -141,24 +211,36 @@ impl SwapCurve {
         swap_token_b_amount: u128,
         pool_supply: u128,
         trade_direction: TradeDirection,
-        _fees: &Fees,
-    ) -> Option<u128> {
+        fees: &Fees,
+    ) -> Option<SingleTokenResult> {
         if source_amount == 0 {
-            return Some(0);
+            return Some(SingleTokenResult {
+                pool_token_amount: 0,
+                trade_fee: 0,
+                owner_fee: 0,
+            });
         }
         // Get the trading fee incurred if *half* the source amount is swapped
         // for the other side. Reference at:
         // https://github.com/balancer-labs/balancer-core/blob/f4ed5d65362a8d6cec21662fb6eae233b0babc1f/contracts/BMath.sol#L117
-        // let half_source_amount = std::cmp::max(1, source_amount.checked_div(2)?);
-        let trade_fee = 0;//fees.trading_fee(half_source_amount)?;
-        let source_amount = source_amount.checked_sub(trade_fee)?;
-        self.calculator.withdraw_single_token_type_exact_out(
+        let half_source_amount = std::cmp::max(1, source_amount.checked_div(2)?);
+        let trade_fee = fees.return_fee(half_source_amount)?;
+        let owner_fee = fees.fixed_fee(half_source_amount)?;
+        let source_amount = source_amount
+            .checked_add(trade_fee)?
+            .checked_add(owner_fee)?;
+        let pool_token_amount = self.calculator.withdraw_single_token_type_exact_out(
             source_amount,
             swap_token_a_amount,
             swap_token_b_amount,
             pool_supply,
             trade_direction,
-        )
+        )?;
+        Some(SingleTokenResult {
+            pool_token_amount,
+            trade_fee,
+            owner_fee,
+        })
     }
 }
 
@@ -227,6 +309,7 @@ impl Pack for SwapCurve {
                 }
                 CurveType::Stable => Box::new(StableCurve::unpack_from_slice(calculator)?),
                 CurveType::Offset => Box::new(OffsetCurve::unpack_from_slice(calculator)?),
+                CurveType::Weighted => Box::new(WeightedCurve::unpack_from_slice(calculator)?),
             },
         })
     }
@@ -257,7 +340,138 @@ impl TryFrom<u8> for CurveType {
             1 => Ok(CurveType::ConstantPrice),
             2 => Ok(CurveType::Stable),
             3 => Ok(CurveType::Offset),
+            4 => Ok(CurveType::Weighted),
             _ => Err(ProgramError::InvalidAccountData),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SWAP_TOKEN_A_AMOUNT: u128 = 1_000_000;
+    const SWAP_TOKEN_B_AMOUNT: u128 = 2_000_000;
+    const POOL_SUPPLY: u128 = 1_000_000;
+
+    fn fees(return_fee_numerator: u64, fixed_fee_numerator: u64) -> Fees {
+        Fees {
+            return_fee_numerator,
+            fixed_fee_numerator,
+            fee_denominator: 1_000,
+            ..Fees::default()
+        }
+    }
+
+    #[test]
+    fn deposit_single_token_type_charges_fees_on_half() {
+        let curve = SwapCurve::default();
+        let fees = fees(25, 5);
+        let result = curve
+            .deposit_single_token_type(
+                10_000,
+                SWAP_TOKEN_A_AMOUNT,
+                SWAP_TOKEN_B_AMOUNT,
+                POOL_SUPPLY,
+                TradeDirection::AtoB,
+                &fees,
+            )
+            .unwrap();
+        assert_eq!(result.trade_fee, 125);
+        assert_eq!(result.owner_fee, 25);
+        let pool_token_amount = curve
+            .calculator
+            .deposit_single_token_type(
+                10_000 - 150,
+                SWAP_TOKEN_A_AMOUNT,
+                SWAP_TOKEN_B_AMOUNT,
+                POOL_SUPPLY,
+                TradeDirection::AtoB,
+            )
+            .unwrap();
+        assert_eq!(result.pool_token_amount, pool_token_amount);
+
+        let without_fees = curve
+            .deposit_single_token_type(
+                10_000,
+                SWAP_TOKEN_A_AMOUNT,
+                SWAP_TOKEN_B_AMOUNT,
+                POOL_SUPPLY,
+                TradeDirection::AtoB,
+                &Fees::default(),
+            )
+            .unwrap();
+        assert_eq!(without_fees.trade_fee, 0);
+        assert_eq!(without_fees.owner_fee, 0);
+        assert!(without_fees.pool_token_amount > result.pool_token_amount);
+    }
+
+    #[test]
+    fn deposit_single_token_type_dust() {
+        let curve = SwapCurve::default();
+        let fees = fees(25, 5);
+        let deposit = |source_amount| {
+            curve.deposit_single_token_type(
+                source_amount,
+                SWAP_TOKEN_A_AMOUNT,
+                SWAP_TOKEN_B_AMOUNT,
+                POOL_SUPPLY,
+                TradeDirection::BtoA,
+                &fees,
+            )
+        };
+        let result = deposit(0).unwrap();
+        assert_eq!(result.pool_token_amount, 0);
+        assert_eq!(result.trade_fee, 0);
+        assert_eq!(result.owner_fee, 0);
+        // the minimum fee of one token each takes the whole deposit
+        assert!(deposit(1).is_none());
+        assert!(deposit(2).is_none());
+        let result = deposit(3).unwrap();
+        assert_eq!(result.trade_fee, 1);
+        assert_eq!(result.owner_fee, 1);
+    }
+
+    #[test]
+    fn withdraw_single_token_type_exact_out_charges_fees_on_half() {
+        let curve = SwapCurve::default();
+        let fees = fees(25, 5);
+        let withdraw = |source_amount, fees: &Fees| {
+            curve.withdraw_single_token_type_exact_out(
+                source_amount,
+                SWAP_TOKEN_A_AMOUNT,
+                SWAP_TOKEN_B_AMOUNT,
+                POOL_SUPPLY,
+                TradeDirection::AtoB,
+                fees,
+            )
+        };
+        let result = withdraw(10_000, &fees).unwrap();
+        assert_eq!(result.trade_fee, 125);
+        assert_eq!(result.owner_fee, 25);
+        let pool_token_amount = curve
+            .calculator
+            .withdraw_single_token_type_exact_out(
+                10_000 + 150,
+                SWAP_TOKEN_A_AMOUNT,
+                SWAP_TOKEN_B_AMOUNT,
+                POOL_SUPPLY,
+                TradeDirection::AtoB,
+            )
+            .unwrap();
+        assert_eq!(result.pool_token_amount, pool_token_amount);
+        assert!(withdraw(10_000, &Fees::default()).unwrap().pool_token_amount < pool_token_amount);
+
+        let result = withdraw(0, &fees).unwrap();
+        assert_eq!(result.pool_token_amount, 0);
+        assert_eq!(result.trade_fee, 0);
+        assert_eq!(result.owner_fee, 0);
+        // dust withdrawals pay the minimum fee of one token each
+        let result = withdraw(1, &fees).unwrap();
+        assert_eq!(result.trade_fee, 1);
+        assert_eq!(result.owner_fee, 1);
+        assert!(result.pool_token_amount > 0);
+        // the whole side of the pool can't be taken
+        assert!(withdraw(SWAP_TOKEN_A_AMOUNT, &fees).is_none());
+    }
+}
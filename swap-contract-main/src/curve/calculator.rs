@@ -1,6 +1,9 @@
 //! Swap calculations
 
-use {crate::error::AmmError, spl_math::precise_number::PreciseNumber, std::fmt::Debug};
+use {
+    crate::error::AmmError, solana_program::clock::UnixTimestamp,
+    spl_math::precise_number::PreciseNumber, std::fmt::Debug,
+};
 use crate::curve::base::CurveType;
 
 #[cfg(feature = "fuzz")]
@@ -96,6 +99,17 @@ pub trait CurveCalculator: Debug + DynPack {
         trade_direction: TradeDirection,
     ) -> Option<SwapWithoutFeesResult>;
 
+    /// Calculate how much source token must be provided to receive exactly
+    /// the given amount of destination token, the inverse of
+    /// `swap_without_fees`. Rounding is always in favor of the pool.
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult>;
+
     /// Get the supply for a new pool
     /// The default implementation is a Balancer-style fixed initial supply
     fn new_pool_supply(&self) -> u64 {
@@ -155,6 +169,11 @@ pub trait CurveCalculator: Debug + DynPack {
     
     /// Get the curve type for calculator
     fn get_curve_type(&self) -> CurveType;
+
+    /// Set the current time, for curves whose parameters change over time.
+    /// The processor calls it with the `Clock` sysvar after unpacking a
+    /// pool, off-chain users need to call it before any calculation.
+    fn set_timestamp(&mut self, _timestamp: UnixTimestamp) {}
     
     /// Validate the given supply on initialization. This is useful for curves
     /// that allow zero supply on one or both sides, since the standard constant
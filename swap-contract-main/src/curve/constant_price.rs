@@ -99,6 +99,35 @@ impl CurveCalculator for ConstantPriceCurve {
         })
     }
 
+    /// Inverse of the constant price swap, the price is applied to the
+    /// destination amount instead
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        _swap_source_amount: u128,
+        _swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult> {
+        let token_b_price = self.token_b_price as u128;
+
+        let source_amount_swapped = match trade_direction {
+            TradeDirection::AtoB => destination_amount.checked_mul(token_b_price)?,
+            TradeDirection::BtoA => {
+                // round up the token B required, the remainder of token A
+                // stays in the pool
+                destination_amount
+                    .checked_add(token_b_price.checked_sub(1)?)?
+                    .checked_div(token_b_price)?
+            }
+        };
+        let source_amount_swapped = map_zero_to_none(source_amount_swapped)?;
+        let destination_amount_swapped = map_zero_to_none(destination_amount)?;
+        Some(SwapWithoutFeesResult {
+            source_amount_swapped,
+            destination_amount_swapped,
+        })
+    }
+
     /// Get the amount of trading tokens for the given amount of pool tokens,
     /// provided the total trading tokens and supply of pool tokens.
     /// For the constant price curve, the total value of the pool is weighted
@@ -1,6 +1,6 @@
 //! All fee information, to be used for validation currently
 
-use crate::error::AmmError;
+use crate::{error::AmmError, oracle::VOLATILITY_SATURATION_BPS};
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
     program_error::ProgramError,
@@ -21,7 +21,23 @@ pub struct Fees {
     pub fixed_fee_numerator: u64,
 
     /// fee dominator 
-    pub fee_denominator: u64
+    pub fee_denominator: u64,
+
+    /// share of the owner fee paid to the host of the swap, when a host
+    /// fee account is provided
+    pub host_fee_numerator: u64,
+
+    /// host fee denominator
+    pub host_fee_denominator: u64,
+
+    /// fee numerator charged in pool tokens to the fee owner when
+    /// liquidity is withdrawn, over `fee_denominator`
+    pub owner_withdraw_fee_numerator: u64,
+
+    /// upper bound of the return fee numerator in dynamic fee mode, the
+    /// effective return fee rises towards it with the recent volatility of
+    /// the pool, 0 keeps the return fee static
+    pub max_return_fee_numerator: u64,
 }
 
 /// Helper function for calculating swap fee
@@ -44,15 +60,15 @@ pub fn calculate_fee(
     }
 }
 
-// fn validate_fraction(numerator: u64, denominator: u64) -> Result<(), AmmError> {
-//     if denominator == 0 && numerator == 0 {
-//         Ok(())
-//     } else if numerator >= denominator {
-//         Err(AmmError::InvalidFee)
-//     } else {
-//         Ok(())
-//     }
-// }
+fn validate_fraction(numerator: u64, denominator: u64) -> Result<(), AmmError> {
+    if denominator == 0 && numerator == 0 {
+        Ok(())
+    } else if numerator >= denominator {
+        Err(AmmError::InvalidFee)
+    } else {
+        Ok(())
+    }
+}
 
 impl Fees {
     /// Calculate the withdraw fee in pool tokens
@@ -73,9 +89,110 @@ impl Fees {
         )
     }
     
+    /// Calculate the host share of the owner fee
+    pub fn host_fee(&self, owner_fee: u128) -> Option<u128> {
+        calculate_fee(
+            owner_fee,
+            u128::from(self.host_fee_numerator),
+            u128::from(self.host_fee_denominator),
+        )
+    }
+
+    /// Calculate the owner withdraw fee in pool tokens
+    pub fn owner_withdraw_fee(&self, pool_tokens: u128) -> Option<u128> {
+        calculate_fee(
+            pool_tokens,
+            u128::from(self.owner_withdraw_fee_numerator),
+            u128::from(self.fee_denominator),
+        )
+    }
+
+    /// Calculate the pool tokens minted to the owner for its share of the
+    /// trading fees accrued while the square root of the reserves product
+    /// grew from `root_k_last` to `root_k`, Uniswap v2 style
+    pub fn protocol_fee_pool_tokens(
+        &self,
+        pool_supply: u128,
+        root_k: u128,
+        root_k_last: u128,
+    ) -> Option<u128> {
+        if root_k_last == 0 || root_k <= root_k_last || self.fixed_fee_numerator == 0 {
+            return Some(0);
+        }
+        // the owner share of the growth is fixed / (return + fixed), minted
+        // so that it is not diluted by its own issuance
+        let fixed_fee_numerator = u128::from(self.fixed_fee_numerator);
+        let return_fee_numerator = u128::from(self.return_fee_numerator);
+        let denominator = return_fee_numerator
+            .checked_mul(root_k)?
+            .checked_add(fixed_fee_numerator.checked_mul(root_k_last)?)?;
+        pool_supply
+            .checked_mul(root_k.checked_sub(root_k_last)?)?
+            .checked_mul(fixed_fee_numerator)?
+            .checked_div(denominator)
+    }
+
+    /// Fees to charge given the recent volatility of the pool, the return
+    /// fee rises linearly from `return_fee_numerator` to
+    /// `max_return_fee_numerator` as the volatility reaches
+    /// `VOLATILITY_SATURATION_BPS`
+    pub fn with_volatility(&self, volatility_bps: u64) -> Option<Fees> {
+        if self.max_return_fee_numerator == 0 {
+            return Some(self.clone());
+        }
+        let fee_range = self
+            .max_return_fee_numerator
+            .checked_sub(self.return_fee_numerator)?;
+        let increase = u128::from(fee_range)
+            .checked_mul(u128::from(volatility_bps.min(VOLATILITY_SATURATION_BPS)))?
+            .checked_div(u128::from(VOLATILITY_SATURATION_BPS))?;
+        Some(Fees {
+            return_fee_numerator: self
+                .return_fee_numerator
+                .checked_add(u64::try_from(increase).ok()?)?,
+            ..self.clone()
+        })
+    }
+
+    /// Calculate the amount of trading tokens which leaves exactly
+    /// `post_fee_amount` once the return and fixed fees are debited
+    pub fn pre_fee_amount(&self, post_fee_amount: u128) -> Option<u128> {
+        let fee_numerator = u128::from(self.return_fee_numerator)
+            .checked_add(u128::from(self.fixed_fee_numerator))?;
+        if fee_numerator == 0 || post_fee_amount == 0 {
+            return Some(post_fee_amount);
+        }
+        let fee_denominator = u128::from(self.fee_denominator);
+        let remaining_numerator = fee_denominator.checked_sub(fee_numerator)?;
+        let mut pre_fee_amount = post_fee_amount
+            .checked_mul(fee_denominator)?
+            .checked_add(remaining_numerator.checked_sub(1)?)?
+            .checked_div(remaining_numerator)?;
+
+        // the minimum fee of one token can take more than the exact ratio
+        while pre_fee_amount
+            .checked_sub(self.return_fee(pre_fee_amount)?)?
+            .checked_sub(self.fixed_fee(pre_fee_amount)?)?
+            < post_fee_amount
+        {
+            pre_fee_amount = pre_fee_amount.checked_add(1)?;
+        }
+        Some(pre_fee_amount)
+    }
+
     /// Validate that the fees are reasonable
     pub fn validate(&self) -> Result<(), AmmError> {
 
+        validate_fraction(self.host_fee_numerator, self.host_fee_denominator)?;
+        validate_fraction(self.owner_withdraw_fee_numerator, self.fee_denominator)?;
+        if self.max_return_fee_numerator != 0
+            && (self.max_return_fee_numerator < self.return_fee_numerator
+                || self.max_return_fee_numerator >= self.fee_denominator
+                || self.fixed_fee_numerator
+                    >= self.fee_denominator - self.max_return_fee_numerator)
+        {
+            return Err(AmmError::InvalidFee);
+        }
         if self.fee_denominator == 0 && self.fixed_fee_numerator == 0  && self.return_fee_numerator == 0{
             Ok(())
         } else if   self.fixed_fee_numerator >= self.fee_denominator ||  
@@ -98,34 +215,219 @@ impl IsInitialized for Fees {
 
 impl Sealed for Fees {}
 impl Pack for Fees {
-    const LEN: usize = 24;
+    const LEN: usize = 56;
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let output = array_mut_ref![output, 0, 24];
+        let output = array_mut_ref![output, 0, 56];
         let (
             return_fee_numerator,
             fixed_fee_numerator,
             fee_denominator,
-        ) = mut_array_refs![output, 8, 8, 8];
+            host_fee_numerator,
+            host_fee_denominator,
+            owner_withdraw_fee_numerator,
+            max_return_fee_numerator,
+        ) = mut_array_refs![output, 8, 8, 8, 8, 8, 8, 8];
         *return_fee_numerator = self.return_fee_numerator.to_le_bytes();
         *fixed_fee_numerator = self.fixed_fee_numerator.to_le_bytes();
         *fee_denominator = self.fee_denominator.to_le_bytes();
+        *host_fee_numerator = self.host_fee_numerator.to_le_bytes();
+        *host_fee_denominator = self.host_fee_denominator.to_le_bytes();
+        *owner_withdraw_fee_numerator = self.owner_withdraw_fee_numerator.to_le_bytes();
+        *max_return_fee_numerator = self.max_return_fee_numerator.to_le_bytes();
     }
 
     fn unpack_from_slice(input: &[u8]) -> Result<Fees, ProgramError> {
         if input.len() < Self::LEN{
             return Err(AmmError::InvalidInstruction.into());    
         }
-        let input = array_ref![input, 0, 24];
+        let input = array_ref![input, 0, 56];
         #[allow(clippy::ptr_offset_with_cast)]
         let (
             return_fee_numerator,
             fixed_fee_numerator,
             fee_denominator,
-        ) = array_refs![input, 8, 8, 8];
+            host_fee_numerator,
+            host_fee_denominator,
+            owner_withdraw_fee_numerator,
+            max_return_fee_numerator,
+        ) = array_refs![input, 8, 8, 8, 8, 8, 8, 8];
         Ok(Self {
             return_fee_numerator: u64::from_le_bytes(*return_fee_numerator),
             fixed_fee_numerator: u64::from_le_bytes(*fixed_fee_numerator),
             fee_denominator: u64::from_le_bytes(*fee_denominator),
+            host_fee_numerator: u64::from_le_bytes(*host_fee_numerator),
+            host_fee_denominator: u64::from_le_bytes(*host_fee_denominator),
+            owner_withdraw_fee_numerator: u64::from_le_bytes(*owner_withdraw_fee_numerator),
+            max_return_fee_numerator: u64::from_le_bytes(*max_return_fee_numerator),
         })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::{
+        base::{CurveType, SwapCurve},
+        calculator::{CurveCalculator, TradeDirection},
+        constant_price::ConstantPriceCurve,
+        constant_product::ConstantProductCurve,
+        offset::OffsetCurve,
+        stable::StableCurve,
+        weighted::WeightedCurve,
+    };
+    use proptest::prelude::*;
+
+    fn fees(return_fee_numerator: u64, fixed_fee_numerator: u64, fee_denominator: u64) -> Fees {
+        Fees {
+            return_fee_numerator,
+            fixed_fee_numerator,
+            fee_denominator,
+            ..Fees::default()
+        }
+    }
+
+    fn amount_less_fees(fees: &Fees, amount: u128) -> u128 {
+        amount - fees.return_fee(amount).unwrap() - fees.fixed_fee(amount).unwrap()
+    }
+
+    #[test]
+    fn pre_fee_amount_without_fees() {
+        let fees = fees(0, 0, 10_000);
+        assert_eq!(fees.pre_fee_amount(0), Some(0));
+        assert_eq!(fees.pre_fee_amount(1), Some(1));
+        assert_eq!(fees.pre_fee_amount(1_000_000), Some(1_000_000));
+        assert_eq!(Fees::default().pre_fee_amount(1_000), Some(1_000));
+    }
+
+    #[test]
+    fn pre_fee_amount_minimum_fee() {
+        // both fees round down to zero and are raised to one token each
+        let fees = fees(10, 20, 10_000);
+        assert_eq!(fees.pre_fee_amount(1), Some(3));
+        assert_eq!(amount_less_fees(&fees, 3), 1);
+        assert_eq!(fees.pre_fee_amount(100), Some(102));
+        assert_eq!(amount_less_fees(&fees, 102), 100);
+
+        // only the return fee is charged
+        let fees = self::fees(10, 0, 10_000);
+        assert_eq!(fees.pre_fee_amount(1), Some(2));
+        assert_eq!(fees.pre_fee_amount(10_000), Some(10_011));
+        assert!(amount_less_fees(&fees, 10_011) >= 10_000);
+    }
+
+    #[test]
+    fn pre_fee_amount_exact_ratio() {
+        let fees = fees(25, 5, 1_000);
+        assert_eq!(fees.pre_fee_amount(97_000), Some(100_000));
+        assert_eq!(amount_less_fees(&fees, 100_000), 97_000);
+    }
+
+    #[test]
+    fn with_volatility_saturates() {
+        let dynamic = Fees {
+            max_return_fee_numerator: 75,
+            ..fees(25, 5, 10_000)
+        };
+        let return_fee_numerator =
+            |volatility_bps| dynamic.with_volatility(volatility_bps).unwrap().return_fee_numerator;
+        assert_eq!(return_fee_numerator(0), 25);
+        assert_eq!(return_fee_numerator(VOLATILITY_SATURATION_BPS / 2), 50);
+        assert_eq!(return_fee_numerator(VOLATILITY_SATURATION_BPS), 75);
+        assert_eq!(return_fee_numerator(u64::MAX), 75);
+
+        // static fees ignore the volatility
+        let fees = fees(25, 5, 10_000);
+        assert_eq!(fees.with_volatility(u64::MAX), Some(fees.clone()));
+    }
+
+    proptest! {
+        #[test]
+        fn pre_fee_amount_covers_fees(
+            post_fee_amount in 1..u64::MAX as u128 / 2,
+            return_fee_numerator in 0..500u64,
+            fixed_fee_numerator in 0..500u64,
+            fee_denominator in 1_000..100_000u64,
+        ) {
+            let fees = fees(return_fee_numerator, fixed_fee_numerator, fee_denominator);
+            let pre_fee_amount = fees.pre_fee_amount(post_fee_amount).unwrap();
+            prop_assert!(pre_fee_amount >= post_fee_amount);
+            prop_assert!(amount_less_fees(&fees, pre_fee_amount) >= post_fee_amount);
+        }
+    }
+
+    fn curves() -> Vec<SwapCurve> {
+        let curves: Vec<(CurveType, Box<dyn CurveCalculator>)> = vec![
+            (CurveType::ConstantProduct, Box::new(ConstantProductCurve)),
+            (
+                CurveType::ConstantPrice,
+                Box::new(ConstantPriceCurve { token_b_price: 3 }),
+            ),
+            (
+                CurveType::Stable,
+                Box::new(StableCurve {
+                    amp: 100,
+                    ..StableCurve::default()
+                }),
+            ),
+            (
+                CurveType::Offset,
+                Box::new(OffsetCurve {
+                    token_b_offset: 1_000_000,
+                }),
+            ),
+            (
+                CurveType::Weighted,
+                Box::new(WeightedCurve {
+                    token_a_weight: 80,
+                    token_b_weight: 20,
+                }),
+            ),
+        ];
+        curves
+            .into_iter()
+            .map(|(curve_type, calculator)| SwapCurve {
+                curve_type,
+                calculator,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn swap_exact_out_then_exact_in() {
+        let swap_source_amount = 50_000_000;
+        let swap_destination_amount = 40_000_000;
+        for fees in [fees(0, 0, 0), fees(10, 20, 10_000), fees(25, 5, 1_000)] {
+            for swap_curve in curves() {
+                for trade_direction in [TradeDirection::AtoB, TradeDirection::BtoA] {
+                    for destination_amount in [1, 7, 999, 12_345, 100_000] {
+                        let exact_out = swap_curve
+                            .swap_exact_out(
+                                destination_amount,
+                                swap_source_amount,
+                                swap_destination_amount,
+                                trade_direction,
+                                &fees,
+                            )
+                            .unwrap();
+                        assert!(exact_out.destination_amount_swapped >= destination_amount);
+                        let exact_in = swap_curve
+                            .swap(
+                                exact_out.source_amount_swapped,
+                                swap_source_amount,
+                                swap_destination_amount,
+                                trade_direction,
+                                &fees,
+                            )
+                            .unwrap();
+                        assert!(
+                            exact_in.destination_amount_swapped >= destination_amount,
+                            "{:?} {:?} {}",
+                            swap_curve.curve_type,
+                            trade_direction,
+                            destination_amount
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
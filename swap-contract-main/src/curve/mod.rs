@@ -7,3 +7,4 @@ pub mod constant_product;
 pub mod fees;
 pub mod offset;
 pub mod stable;
+pub mod weighted;
@@ -8,8 +8,9 @@ use {
         },
         error::AmmError,
     },
-    arrayref::{array_mut_ref, array_ref},
+    arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs},
     solana_program::{
+        clock::UnixTimestamp,
         program_error::ProgramError,
         program_pack::{IsInitialized, Pack, Sealed},
     },
@@ -21,6 +22,16 @@ const N_COINS: u8 = 2;
 const N_COINS_SQUARED: u8 = 4;
 const ITERATIONS: u8 = 32;
 
+/// Minimum amplification coefficient
+pub const MIN_AMP: u64 = 1;
+/// Maximum amplification coefficient
+pub const MAX_AMP: u64 = 1_000_000;
+/// Maximum factor between the amp at the start of a ramp and its target
+pub const MAX_AMP_CHANGE: u64 = 10;
+/// Minimum duration of a ramp, and minimum time between the starts of two
+/// ramps, in seconds
+pub const MIN_RAMP_DURATION: UnixTimestamp = 86_400;
+
 /// Returns self to the power of b
 fn checked_u8_power(a: &U256, b: u8) -> Option<U256> {
     let mut result = *a;
@@ -40,10 +51,96 @@ fn checked_u8_mul(a: &U256, b: u8) -> Option<U256> {
 }
 
 /// StableCurve struct implementing CurveCalculator
+///
+/// The amplification coefficient moves linearly from `amp` to `target_amp`
+/// between `ramp_start_ts` and `ramp_stop_ts`. A zero `target_amp` means the
+/// curve has never been ramped and `amp` is constant.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct StableCurve {
-    /// Amplifier constant
+    /// Amplifier constant, or its value at the start of the ramp
     pub amp: u64,
+    /// Amplifier constant at the end of the ramp
+    pub target_amp: u64,
+    /// Unix timestamp of the start of the ramp
+    pub ramp_start_ts: UnixTimestamp,
+    /// Unix timestamp of the end of the ramp
+    pub ramp_stop_ts: UnixTimestamp,
+    /// Current time, not packed, set from the `Clock` sysvar before the
+    /// curve is used
+    pub timestamp: UnixTimestamp,
+}
+
+impl StableCurve {
+    /// Amplifier constant at the current timestamp
+    pub fn current_amp(&self) -> Option<u64> {
+        if self.target_amp == 0 {
+            return Some(self.amp);
+        }
+        if self.timestamp >= self.ramp_stop_ts {
+            return Some(self.target_amp);
+        }
+        if self.timestamp <= self.ramp_start_ts {
+            return Some(self.amp);
+        }
+        let elapsed = u128::try_from(self.timestamp.checked_sub(self.ramp_start_ts)?).ok()?;
+        let duration = u128::try_from(self.ramp_stop_ts.checked_sub(self.ramp_start_ts)?).ok()?;
+        let (amp, target_amp) = (self.amp as u128, self.target_amp as u128);
+        let current_amp = if target_amp > amp {
+            amp.checked_add((target_amp - amp).checked_mul(elapsed)?.checked_div(duration)?)?
+        } else {
+            amp.checked_sub((amp - target_amp).checked_mul(elapsed)?.checked_div(duration)?)?
+        };
+        u64::try_from(current_amp).ok()
+    }
+
+    /// Starts moving the amp from its current value to `target_amp`,
+    /// reaching it at `ramp_stop_ts`
+    pub fn ramp_amp(
+        &mut self,
+        target_amp: u64,
+        ramp_stop_ts: UnixTimestamp,
+    ) -> Result<(), AmmError> {
+        let now = self.timestamp;
+        if self.target_amp != 0
+            && now < self.ramp_start_ts.saturating_add(MIN_RAMP_DURATION)
+        {
+            return Err(AmmError::InvalidAmpRamp);
+        }
+        if ramp_stop_ts < now.saturating_add(MIN_RAMP_DURATION) {
+            return Err(AmmError::InvalidAmpRamp);
+        }
+        if !(MIN_AMP..=MAX_AMP).contains(&target_amp) {
+            return Err(AmmError::InvalidAmpRamp);
+        }
+        let current_amp = self.current_amp().ok_or(AmmError::CalculationFailure)?;
+        let (low, high) = if target_amp > current_amp {
+            (current_amp, target_amp)
+        } else {
+            (target_amp, current_amp)
+        };
+        if (high as u128) > (low as u128) * (MAX_AMP_CHANGE as u128) {
+            return Err(AmmError::InvalidAmpRamp);
+        }
+        self.amp = current_amp;
+        self.target_amp = target_amp;
+        self.ramp_start_ts = now;
+        self.ramp_stop_ts = ramp_stop_ts;
+        Ok(())
+    }
+
+    /// Stops the ramp, keeping the current amp
+    pub fn stop_ramp_amp(&mut self) -> Result<(), AmmError> {
+        let current_amp = self.current_amp().ok_or(AmmError::CalculationFailure)?;
+        self.amp = current_amp;
+        self.target_amp = current_amp;
+        self.ramp_start_ts = self.timestamp;
+        self.ramp_stop_ts = self.timestamp;
+        Ok(())
+    }
+
+    fn leverage(&self) -> Option<u64> {
+        self.current_amp()?.checked_mul(N_COINS as u64)
+    }
 }
 
 /// d = (leverage * sum_x + d_product * n_coins) * initial_d / ((leverage - 1) * initial_d + (n_coins + 1) * d_product)
@@ -142,7 +239,7 @@ impl CurveCalculator for StableCurve {
         swap_destination_amount: u128,
         _trade_direction: TradeDirection,
     ) -> Option<SwapWithoutFeesResult> {
-        let leverage = self.amp.checked_mul(N_COINS as u64)?;
+        let leverage = self.leverage()?;
 
         let new_source_amount = swap_source_amount.checked_add(source_amount)?;
         let new_destination_amount = compute_new_destination_amount(
@@ -159,6 +256,41 @@ impl CurveCalculator for StableCurve {
         })
     }
 
+    /// Inverse of the stable swap, solving the invariant for the source side
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        _trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult> {
+        if destination_amount == 0 {
+            return None;
+        }
+        let leverage = self.leverage()?;
+
+        let new_destination_amount = swap_destination_amount.checked_sub(destination_amount)?;
+        if new_destination_amount == 0 {
+            return None;
+        }
+        let new_source_amount = compute_new_destination_amount(
+            leverage,
+            new_destination_amount,
+            compute_d(leverage, swap_source_amount, swap_destination_amount)?,
+        )?;
+
+        // the invariant is only approximated, so round up by one token to
+        // never give out more than what is paid for
+        let amount_swapped = new_source_amount
+            .checked_sub(swap_source_amount)?
+            .checked_add(1)?;
+
+        Some(SwapWithoutFeesResult {
+            source_amount_swapped: amount_swapped,
+            destination_amount_swapped: destination_amount,
+        })
+    }
+
     /// Re-implementation of `remove_liquidty`:
     ///
     /// <https://github.com/curvefi/curve-contract/blob/80bbe179083c9a7062e4c482b0be3bfb7501f2bd/contracts/pool-templates/base/SwapTemplateBase.vy#L513>
@@ -216,7 +348,7 @@ impl CurveCalculator for StableCurve {
         if source_amount == 0 {
             return Some(0);
         }
-        let leverage = self.amp.checked_mul(N_COINS as u64)?;
+        let leverage = self.leverage()?;
         let d0 = PreciseNumber::new(compute_d(
             leverage,
             swap_token_a_amount,
@@ -249,7 +381,7 @@ impl CurveCalculator for StableCurve {
         if source_amount == 0 {
             return Some(0);
         }
-        let leverage = self.amp.checked_mul(N_COINS as u64)?;
+        let leverage = self.leverage()?;
         let d0 = PreciseNumber::new(compute_d(
             leverage,
             swap_token_a_amount,
@@ -278,7 +410,7 @@ impl CurveCalculator for StableCurve {
     ) -> Option<PreciseNumber> {
         #[cfg(not(any(test, feature = "fuzz")))]
         {
-            let leverage = self.amp.checked_mul(N_COINS as u64)?;
+            let leverage = self.leverage()?;
             PreciseNumber::new(compute_d(
                 leverage,
                 swap_token_a_amount,
@@ -290,8 +422,9 @@ impl CurveCalculator for StableCurve {
             use roots::{find_roots_cubic_normalized, Roots};
             let x = swap_token_a_amount as f64;
             let y = swap_token_b_amount as f64;
-            let c = (4.0 * (self.amp as f64)) - 1.0;
-            let d = 16.0 * (self.amp as f64) * x * y * (x + y);
+            let amp = self.current_amp()? as f64;
+            let c = (4.0 * amp) - 1.0;
+            let d = 16.0 * amp * x * y * (x + y);
             let roots = find_roots_cubic_normalized(0.0, c, d);
             let x0 = match roots {
                 Roots::No(_) => panic!("No roots found for cubic equations"),
@@ -310,13 +443,24 @@ impl CurveCalculator for StableCurve {
         }
     }
 
+    /// New curves have an amp in range and no ramp, only `ramp_amp` sets
+    /// the ramp of the curve of an existing pool
     fn validate(&self) -> Result<(), AmmError> {
-        // TODO are all amps valid?
+        if !(MIN_AMP..=MAX_AMP).contains(&self.amp) {
+            return Err(AmmError::InvalidCurve);
+        }
+        if self.target_amp != 0 || self.ramp_start_ts != 0 || self.ramp_stop_ts != 0 {
+            return Err(AmmError::InvalidCurve);
+        }
         Ok(())
     }
     fn get_curve_type(&self) ->CurveType{
         return CurveType::Stable;
     }
+
+    fn set_timestamp(&mut self, timestamp: UnixTimestamp) {
+        self.timestamp = timestamp;
+    }
 }
 
 /// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
@@ -327,7 +471,7 @@ impl IsInitialized for StableCurve {
 }
 impl Sealed for StableCurve {}
 impl Pack for StableCurve {
-    const LEN: usize = 8;
+    const LEN: usize = 32;
     fn pack_into_slice(&self, output: &mut [u8]) {
         (self as &dyn DynPack).pack_into_slice(output);
     }
@@ -337,16 +481,80 @@ impl Pack for StableCurve {
             return Err(AmmError::InvalidInstruction.into());    
         }
 
-        let amp = array_ref![input, 0, 8];
+        let input = array_ref![input, 0, 32];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (amp, target_amp, ramp_start_ts, ramp_stop_ts) = array_refs![input, 8, 8, 8, 8];
         Ok(Self {
             amp: u64::from_le_bytes(*amp),
+            target_amp: u64::from_le_bytes(*target_amp),
+            ramp_start_ts: UnixTimestamp::from_le_bytes(*ramp_start_ts),
+            ramp_stop_ts: UnixTimestamp::from_le_bytes(*ramp_stop_ts),
+            timestamp: 0,
         })
     }
 }
 
 impl DynPack for StableCurve {
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let amp = array_mut_ref![output, 0, 8];
+        let output = array_mut_ref![output, 0, 32];
+        let (amp, target_amp, ramp_start_ts, ramp_stop_ts) = mut_array_refs![output, 8, 8, 8, 8];
         *amp = self.amp.to_le_bytes();
+        *target_amp = self.target_amp.to_le_bytes();
+        *ramp_start_ts = self.ramp_start_ts.to_le_bytes();
+        *ramp_stop_ts = self.ramp_stop_ts.to_le_bytes();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(amp: u64) -> StableCurve {
+        StableCurve {
+            amp,
+            ..StableCurve::default()
+        }
+    }
+
+    #[test]
+    fn validate_amp_bounds() {
+        assert_eq!(curve(MIN_AMP).validate(), Ok(()));
+        assert_eq!(curve(100).validate(), Ok(()));
+        assert_eq!(curve(MAX_AMP).validate(), Ok(()));
+        assert_eq!(curve(MIN_AMP - 1).validate(), Err(AmmError::InvalidCurve));
+        assert_eq!(curve(MAX_AMP + 1).validate(), Err(AmmError::InvalidCurve));
+        assert_eq!(curve(u64::MAX).validate(), Err(AmmError::InvalidCurve));
+    }
+
+    #[test]
+    fn validate_rejects_a_ramp() {
+        let with_target_amp = StableCurve {
+            target_amp: 200,
+            ..curve(100)
+        };
+        assert_eq!(with_target_amp.validate(), Err(AmmError::InvalidCurve));
+        let with_ramp_start = StableCurve {
+            ramp_start_ts: 1,
+            ..curve(100)
+        };
+        assert_eq!(with_ramp_start.validate(), Err(AmmError::InvalidCurve));
+        let with_ramp_stop = StableCurve {
+            ramp_stop_ts: MIN_RAMP_DURATION,
+            ..curve(100)
+        };
+        assert_eq!(with_ramp_stop.validate(), Err(AmmError::InvalidCurve));
+
+        // the timestamp isn't packed and is set before every use
+        let with_timestamp = StableCurve {
+            timestamp: 1_000,
+            ..curve(100)
+        };
+        assert_eq!(with_timestamp.validate(), Ok(()));
+
+        // only ramp_amp sets a ramp
+        let mut ramped = curve(100);
+        ramped.ramp_amp(200, MIN_RAMP_DURATION).unwrap();
+        assert_eq!(ramped.target_amp, 200);
+        assert_eq!(ramped.validate(), Err(AmmError::InvalidCurve));
+    }
+}
@@ -0,0 +1,634 @@
+//! The Balancer constant mean invariant calculator.
+
+use {
+    crate::{
+        curve::{
+            base::CurveType,
+            calculator::{
+                map_zero_to_none, CurveCalculator, DynPack, RoundDirection, SwapWithoutFeesResult,
+                TradeDirection, TradingTokenResult,
+            },
+            constant_product::pool_tokens_to_trading_tokens,
+        },
+        error::AmmError,
+    },
+    arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs},
+    solana_program::{
+        program_error::ProgramError,
+        program_pack::{IsInitialized, Pack, Sealed},
+    },
+    spl_math::precise_number::PreciseNumber,
+};
+
+/// Maximum ratio of the total weight to the weight of one token, ie. every
+/// normalized weight must be at least 1/50 like Balancer
+pub const MAX_WEIGHT_RATIO: u64 = 50;
+
+/// Maximum amount in of a swap or deposit, as a fraction of the pool token
+/// balance, keeps the power approximation in its convergence range
+const MAX_IN_RATIO_DENOMINATOR: u128 = 2;
+
+/// Maximum amount out of a swap or withdrawal, as a fraction of the pool
+/// token balance
+const MAX_OUT_RATIO_DENOMINATOR: u128 = 3;
+
+/// The power approximation stops once its terms are below 1 / this
+const POW_PRECISION_DENOMINATOR: u128 = 10_000_000_000;
+
+/// Upper bound on the number of terms of the power approximation
+const MAX_POW_ITERATIONS: u128 = 100;
+
+/// WeightedCurve struct implementing CurveCalculator
+///
+/// The invariant is `token_a_amount ^ w_a * token_b_amount ^ w_b`, with the
+/// normalized weights `w_a = token_a_weight / (token_a_weight + token_b_weight)`
+/// and `w_b = 1 - w_a`. Equal weights give the constant product curve.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WeightedCurve {
+    /// Weight of token A
+    pub token_a_weight: u64,
+    /// Weight of token B
+    pub token_b_weight: u64,
+}
+
+/// Returns the absolute difference of two numbers and whether it's negative
+fn sub_sign(a: &PreciseNumber, b: &PreciseNumber) -> Option<(PreciseNumber, bool)> {
+    if a.greater_than_or_equal(b) {
+        Some((a.checked_sub(b)?, false))
+    } else {
+        Some((b.checked_sub(a)?, true))
+    }
+}
+
+/// Smallest term of the power approximation, and smallest fractional
+/// exponent worth approximating
+fn pow_precision() -> Option<PreciseNumber> {
+    PreciseNumber::new(1)?.checked_div(&PreciseNumber::new(POW_PRECISION_DENOMINATOR)?)
+}
+
+/// Approximates `base ^ exponent` with the binomial series, for a fractional
+/// exponent and a base in (0, 2).
+///
+/// Reference at:
+/// <https://github.com/balancer-labs/balancer-core/blob/f4ed5d65362a8d6cec21662fb6eae233b0babc1f/contracts/BNum.sol#L128>
+fn pow_approximation(base: &PreciseNumber, exponent: &PreciseNumber) -> Option<PreciseNumber> {
+    let one = PreciseNumber::new(1)?;
+    let precision = pow_precision()?;
+    let (x, x_negative) = sub_sign(base, &one)?;
+    let mut term = one.clone();
+    let mut sum = one;
+    let mut negative = false;
+    for i in 1..=MAX_POW_ITERATIONS {
+        let (c, c_negative) = sub_sign(exponent, &PreciseNumber::new(i - 1)?)?;
+        term = term
+            .checked_mul(&c.checked_mul(&x)?)?
+            .checked_div(&PreciseNumber::new(i)?)?;
+        if term.less_than(&precision) {
+            break;
+        }
+        if x_negative {
+            negative = !negative;
+        }
+        if c_negative {
+            negative = !negative;
+        }
+        sum = if negative {
+            sum.checked_sub(&term)?
+        } else {
+            sum.checked_add(&term)?
+        };
+    }
+    Some(sum)
+}
+
+/// Calculates `base ^ exponent`, the whole part of the exponent is exact and
+/// the fractional part is approximated, so the base must be in (0, 2).
+pub fn pow(base: &PreciseNumber, exponent: &PreciseNumber) -> Option<PreciseNumber> {
+    let whole = exponent.floor()?;
+    let remain = exponent.checked_sub(&whole)?;
+    let whole_pow = base.checked_pow(whole.to_imprecise()?)?;
+    if remain.less_than(&pow_precision()?) {
+        return Some(whole_pow);
+    }
+    whole_pow.checked_mul(&pow_approximation(base, &remain)?)
+}
+
+/// Calculates `base ^ exponent` for a base of at least 1 and an exponent in
+/// [0, 1], with no upper bound on the base.
+///
+/// The base is split into `2 ^ k * m` with m in [1, 2). `m ^ exponent` and
+/// the fractional part of `2 ^ (k * exponent)` are raised through the
+/// reciprocal of their base, which is in (1/2, 1] where the approximation
+/// converges quickly.
+fn pow_unbounded(base: &PreciseNumber, exponent: &PreciseNumber) -> Option<PreciseNumber> {
+    let one = PreciseNumber::new(1)?;
+    let two = PreciseNumber::new(2)?;
+    let mut mantissa = base.clone();
+    let mut doublings: u128 = 0;
+    while mantissa.greater_than_or_equal(&two) {
+        mantissa = mantissa.checked_div(&two)?;
+        doublings = doublings.checked_add(1)?;
+    }
+    let mantissa_pow = one.checked_div(&pow(&one.checked_div(&mantissa)?, exponent)?)?;
+
+    let doublings_exponent = PreciseNumber::new(doublings)?.checked_mul(exponent)?;
+    let whole = doublings_exponent.floor()?;
+    let remain = doublings_exponent.checked_sub(&whole)?;
+    let half = one.checked_div(&two)?;
+    let doublings_pow = two
+        .checked_pow(whole.to_imprecise()?)?
+        .checked_div(&pow(&half, &remain)?)?;
+    mantissa_pow.checked_mul(&doublings_pow)
+}
+
+impl WeightedCurve {
+    fn total_weight(&self) -> Option<u128> {
+        (self.token_a_weight as u128).checked_add(self.token_b_weight as u128)
+    }
+
+    /// Normalized weight of the token on the given side of the trade
+    fn normalized_weight(&self, trade_direction: TradeDirection) -> Option<PreciseNumber> {
+        let weight = match trade_direction {
+            TradeDirection::AtoB => self.token_a_weight,
+            TradeDirection::BtoA => self.token_b_weight,
+        };
+        PreciseNumber::new(weight as u128)?.checked_div(&PreciseNumber::new(self.total_weight()?)?)
+    }
+
+    /// Ratio of the source token weight to the destination token weight
+    fn weight_ratio(&self, trade_direction: TradeDirection) -> Option<PreciseNumber> {
+        let (source_weight, destination_weight) = match trade_direction {
+            TradeDirection::AtoB => (self.token_a_weight, self.token_b_weight),
+            TradeDirection::BtoA => (self.token_b_weight, self.token_a_weight),
+        };
+        PreciseNumber::new(source_weight as u128)?
+            .checked_div(&PreciseNumber::new(destination_weight as u128)?)
+    }
+}
+
+impl CurveCalculator for WeightedCurve {
+    /// Balancer out-given-in:
+    /// `out = swap_destination * (1 - (swap_source / (swap_source + in)) ^ (w_source / w_destination))`
+    ///
+    /// Reference at:
+    /// <https://balancer.finance/whitepaper/#out-given-in>
+    fn swap_without_fees(
+        &self,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult> {
+        if source_amount > swap_source_amount.checked_div(MAX_IN_RATIO_DENOMINATOR)? {
+            return None;
+        }
+        let one = PreciseNumber::new(1)?;
+        let swap_source = PreciseNumber::new(swap_source_amount)?;
+        let base = swap_source
+            .checked_div(&swap_source.checked_add(&PreciseNumber::new(source_amount)?)?)?;
+        let ratio = pow(&base, &self.weight_ratio(trade_direction)?)?;
+        let destination_amount_swapped = PreciseNumber::new(swap_destination_amount)?
+            .checked_mul(&one.checked_sub(&ratio)?)?
+            .floor()?
+            .to_imprecise()?;
+
+        Some(SwapWithoutFeesResult {
+            source_amount_swapped: source_amount,
+            destination_amount_swapped: map_zero_to_none(destination_amount_swapped)?,
+        })
+    }
+
+    /// Balancer in-given-out:
+    /// `in = swap_source * ((swap_destination / (swap_destination - out)) ^ (w_destination / w_source) - 1)`
+    ///
+    /// Reference at:
+    /// <https://balancer.finance/whitepaper/#in-given-out>
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult> {
+        if destination_amount > swap_destination_amount.checked_div(MAX_OUT_RATIO_DENOMINATOR)? {
+            return None;
+        }
+        let one = PreciseNumber::new(1)?;
+        let swap_destination = PreciseNumber::new(swap_destination_amount)?;
+        let base = swap_destination.checked_div(
+            &swap_destination.checked_sub(&PreciseNumber::new(destination_amount)?)?,
+        )?;
+        let ratio = pow(&base, &self.weight_ratio(trade_direction.opposite())?)?;
+        let source_amount_swapped = PreciseNumber::new(swap_source_amount)?
+            .checked_mul(&ratio.checked_sub(&one)?)?
+            .ceiling()?
+            .to_imprecise()?;
+
+        Some(SwapWithoutFeesResult {
+            source_amount_swapped: map_zero_to_none(source_amount_swapped)?,
+            destination_amount_swapped: map_zero_to_none(destination_amount)?,
+        })
+    }
+
+    /// The weighted curve holds the tokens in proportion to the pool tokens,
+    /// so the conversion is the same ratio as the constant product
+    fn pool_tokens_to_trading_tokens(
+        &self,
+        pool_tokens: u128,
+        pool_token_supply: u128,
+        swap_token_a_amount: u128,
+        swap_token_b_amount: u128,
+        round_direction: RoundDirection,
+    ) -> Option<TradingTokenResult> {
+        pool_tokens_to_trading_tokens(
+            pool_tokens,
+            pool_token_supply,
+            swap_token_a_amount,
+            swap_token_b_amount,
+            round_direction,
+        )
+    }
+
+    /// Balancer pool-out-given-single-in:
+    /// `pool_tokens = pool_supply * ((1 + in / swap_source) ^ w_source - 1)`
+    ///
+    /// Reference at:
+    /// <https://balancer.finance/whitepaper/#single-asset-deposit>
+    fn deposit_single_token_type(
+        &self,
+        source_amount: u128,
+        swap_token_a_amount: u128,
+        swap_token_b_amount: u128,
+        pool_supply: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<u128> {
+        let swap_source_amount = match trade_direction {
+            TradeDirection::AtoB => swap_token_a_amount,
+            TradeDirection::BtoA => swap_token_b_amount,
+        };
+        if source_amount > swap_source_amount.checked_div(MAX_IN_RATIO_DENOMINATOR)? {
+            return None;
+        }
+        let one = PreciseNumber::new(1)?;
+        let base = one.checked_add(
+            &PreciseNumber::new(source_amount)?
+                .checked_div(&PreciseNumber::new(swap_source_amount)?)?,
+        )?;
+        let ratio = pow(&base, &self.normalized_weight(trade_direction)?)?;
+        PreciseNumber::new(pool_supply)?
+            .checked_mul(&ratio.checked_sub(&one)?)?
+            .floor()?
+            .to_imprecise()
+    }
+
+    /// Balancer pool-in-given-single-out:
+    /// `pool_tokens = pool_supply * (1 - (1 - out / swap_destination) ^ w_destination)`
+    ///
+    /// Reference at:
+    /// <https://balancer.finance/whitepaper/#single-asset-withdrawal>
+    fn withdraw_single_token_type_exact_out(
+        &self,
+        source_amount: u128,
+        swap_token_a_amount: u128,
+        swap_token_b_amount: u128,
+        pool_supply: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<u128> {
+        let swap_source_amount = match trade_direction {
+            TradeDirection::AtoB => swap_token_a_amount,
+            TradeDirection::BtoA => swap_token_b_amount,
+        };
+        if source_amount > swap_source_amount.checked_div(MAX_OUT_RATIO_DENOMINATOR)? {
+            return None;
+        }
+        let one = PreciseNumber::new(1)?;
+        let base = one.checked_sub(
+            &PreciseNumber::new(source_amount)?
+                .checked_div(&PreciseNumber::new(swap_source_amount)?)?,
+        )?;
+        let ratio = pow(&base, &self.normalized_weight(trade_direction)?)?;
+        PreciseNumber::new(pool_supply)?
+            .checked_mul(&one.checked_sub(&ratio)?)?
+            .ceiling()?
+            .to_imprecise()
+    }
+
+    /// The normalized value of the weighted curve is the weighted geometric
+    /// mean `token_a_amount ^ w_a * token_b_amount ^ w_b`, computed as
+    /// `smaller * (larger / smaller) ^ w_larger`
+    fn normalized_value(
+        &self,
+        swap_token_a_amount: u128,
+        swap_token_b_amount: u128,
+    ) -> Option<PreciseNumber> {
+        if swap_token_a_amount == 0 || swap_token_b_amount == 0 {
+            return PreciseNumber::new(0);
+        }
+        let (smaller, larger, larger_direction) = if swap_token_a_amount >= swap_token_b_amount {
+            (
+                swap_token_b_amount,
+                swap_token_a_amount,
+                TradeDirection::AtoB,
+            )
+        } else {
+            (
+                swap_token_a_amount,
+                swap_token_b_amount,
+                TradeDirection::BtoA,
+            )
+        };
+        let smaller = PreciseNumber::new(smaller)?;
+        let ratio = PreciseNumber::new(larger)?.checked_div(&smaller)?;
+        smaller.checked_mul(&pow_unbounded(
+            &ratio,
+            &self.normalized_weight(larger_direction)?,
+        )?)
+    }
+
+    fn validate(&self) -> Result<(), AmmError> {
+        if self.token_a_weight == 0 || self.token_b_weight == 0 {
+            return Err(AmmError::InvalidCurve);
+        }
+        let total_weight = self.total_weight().ok_or(AmmError::InvalidCurve)?;
+        let too_small = |weight: u64| (weight as u128) * (MAX_WEIGHT_RATIO as u128) < total_weight;
+        if too_small(self.token_a_weight) || too_small(self.token_b_weight) {
+            Err(AmmError::InvalidCurve)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get_curve_type(&self) -> CurveType {
+        CurveType::Weighted
+    }
+}
+
+/// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
+impl IsInitialized for WeightedCurve {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+impl Sealed for WeightedCurve {}
+impl Pack for WeightedCurve {
+    const LEN: usize = 16;
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        (self as &dyn DynPack).pack_into_slice(output);
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<WeightedCurve, ProgramError> {
+        if input.len() < Self::LEN {
+            return Err(AmmError::InvalidInstruction.into());
+        }
+
+        let input = array_ref![input, 0, 16];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (token_a_weight, token_b_weight) = array_refs![input, 8, 8];
+        Ok(Self {
+            token_a_weight: u64::from_le_bytes(*token_a_weight),
+            token_b_weight: u64::from_le_bytes(*token_b_weight),
+        })
+    }
+}
+
+impl DynPack for WeightedCurve {
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 16];
+        let (token_a_weight, token_b_weight) = mut_array_refs![output, 8, 8];
+        *token_a_weight = self.token_a_weight.to_le_bytes();
+        *token_b_weight = self.token_b_weight.to_le_bytes();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SWAP_AMOUNT: u128 = 1_000_000;
+
+    fn curve(token_a_weight: u64, token_b_weight: u64) -> WeightedCurve {
+        WeightedCurve {
+            token_a_weight,
+            token_b_weight,
+        }
+    }
+
+    /// Checks a value against the expected one scaled by 10^9, up to the
+    /// precision of the power approximation
+    fn assert_nano_eq(value: &PreciseNumber, expected: u128) {
+        let nano = value
+            .checked_mul(&PreciseNumber::new(1_000_000_000).unwrap())
+            .unwrap()
+            .to_imprecise()
+            .unwrap();
+        assert!(nano + 1 >= expected && nano <= expected + 1, "{} != {}", nano, expected);
+    }
+
+    fn precise(numerator: u128, denominator: u128) -> PreciseNumber {
+        PreciseNumber::new(numerator)
+            .unwrap()
+            .checked_div(&PreciseNumber::new(denominator).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn validate_weights() {
+        assert_eq!(curve(50, 50).validate(), Ok(()));
+        assert_eq!(curve(80, 20).validate(), Ok(()));
+        assert_eq!(curve(49, 1).validate(), Ok(()));
+        assert_eq!(curve(50, 1).validate(), Err(AmmError::InvalidCurve));
+        assert_eq!(curve(0, 0).validate(), Err(AmmError::InvalidCurve));
+        assert_eq!(curve(0, 1).validate(), Err(AmmError::InvalidCurve));
+        assert_eq!(curve(1, 0).validate(), Err(AmmError::InvalidCurve));
+        assert_eq!(curve(u64::MAX, u64::MAX).validate(), Ok(()));
+    }
+
+    #[test]
+    fn pow_known_values() {
+        assert_nano_eq(&pow(&precise(3, 2), &precise(1, 2)).unwrap(), 1_224_744_871);
+        assert_nano_eq(&pow(&precise(1, 2), &precise(5, 2)).unwrap(), 176_776_695);
+        assert_nano_eq(&pow(&precise(9, 10), &precise(3, 10)).unwrap(), 968_886_161);
+        // whole exponents are exact
+        assert_eq!(
+            pow(&precise(3, 2), &PreciseNumber::new(3).unwrap()).unwrap(),
+            precise(27, 8)
+        );
+        assert_eq!(
+            pow(&precise(3, 2), &PreciseNumber::new(0).unwrap()).unwrap(),
+            PreciseNumber::new(1).unwrap()
+        );
+    }
+
+    #[test]
+    fn swap_known_values() {
+        // equal weights are the constant product curve
+        let result = curve(50, 50)
+            .swap_without_fees(10_000, SWAP_AMOUNT, SWAP_AMOUNT, TradeDirection::AtoB)
+            .unwrap();
+        assert_eq!(result.source_amount_swapped, 10_000);
+        assert_eq!(result.destination_amount_swapped, 9_900);
+
+        // the heavier source token buys more
+        let result = curve(80, 20)
+            .swap_without_fees(10_000, SWAP_AMOUNT, SWAP_AMOUNT, TradeDirection::AtoB)
+            .unwrap();
+        assert_eq!(result.destination_amount_swapped, 39_019);
+        let result = curve(80, 20)
+            .swap_without_fees(10_000, SWAP_AMOUNT, SWAP_AMOUNT, TradeDirection::BtoA)
+            .unwrap();
+        assert_eq!(result.destination_amount_swapped, 2_484);
+
+        // at most half of the source balance can be swapped
+        assert!(curve(80, 20)
+            .swap_without_fees(SWAP_AMOUNT / 2 + 1, SWAP_AMOUNT, SWAP_AMOUNT, TradeDirection::AtoB)
+            .is_none());
+    }
+
+    #[test]
+    fn swap_exact_out_known_values() {
+        let result = curve(50, 50)
+            .swap_without_fees_exact_out(9_900, SWAP_AMOUNT, SWAP_AMOUNT, TradeDirection::AtoB)
+            .unwrap();
+        assert_eq!(result.destination_amount_swapped, 9_900);
+        assert_eq!(result.source_amount_swapped, 9_999);
+
+        let result = curve(80, 20)
+            .swap_without_fees_exact_out(10_000, SWAP_AMOUNT, SWAP_AMOUNT, TradeDirection::AtoB)
+            .unwrap();
+        assert_eq!(result.source_amount_swapped, 2_516);
+
+        // at most a third of the destination balance can be bought
+        assert!(curve(80, 20)
+            .swap_without_fees_exact_out(
+                SWAP_AMOUNT / 3 + 1,
+                SWAP_AMOUNT,
+                SWAP_AMOUNT,
+                TradeDirection::AtoB
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn swap_round_trips_keep_the_invariant() {
+        for (token_a_weight, token_b_weight) in [(50, 50), (80, 20), (20, 80), (30, 70)] {
+            let curve = curve(token_a_weight, token_b_weight);
+            for trade_direction in [TradeDirection::AtoB, TradeDirection::BtoA] {
+                for destination_amount in [1, 17, 1_234, 50_000] {
+                    let swap_source_amount = 2 * SWAP_AMOUNT;
+                    let swap_destination_amount = SWAP_AMOUNT;
+                    let exact_out = curve
+                        .swap_without_fees_exact_out(
+                            destination_amount,
+                            swap_source_amount,
+                            swap_destination_amount,
+                            trade_direction,
+                        )
+                        .unwrap();
+                    let exact_in = curve
+                        .swap_without_fees(
+                            exact_out.source_amount_swapped,
+                            swap_source_amount,
+                            swap_destination_amount,
+                            trade_direction,
+                        )
+                        .unwrap();
+                    assert!(exact_in.destination_amount_swapped >= destination_amount);
+
+                    // the value of the pool never decreases
+                    let (before, after) = match trade_direction {
+                        TradeDirection::AtoB => (
+                            curve.normalized_value(swap_source_amount, swap_destination_amount),
+                            curve.normalized_value(
+                                swap_source_amount + exact_in.source_amount_swapped,
+                                swap_destination_amount - exact_in.destination_amount_swapped,
+                            ),
+                        ),
+                        TradeDirection::BtoA => (
+                            curve.normalized_value(swap_destination_amount, swap_source_amount),
+                            curve.normalized_value(
+                                swap_destination_amount - exact_in.destination_amount_swapped,
+                                swap_source_amount + exact_in.source_amount_swapped,
+                            ),
+                        ),
+                    };
+                    assert!(after.unwrap().greater_than_or_equal(&before.unwrap()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deposit_and_withdraw_single_token_type() {
+        let pool_supply = 1_000_000;
+        let amount = SWAP_AMOUNT / 10;
+        let deposit = |curve: &WeightedCurve, trade_direction| {
+            curve
+                .deposit_single_token_type(amount, SWAP_AMOUNT, SWAP_AMOUNT, pool_supply, trade_direction)
+                .unwrap()
+        };
+        let withdraw = |curve: &WeightedCurve, trade_direction| {
+            curve
+                .withdraw_single_token_type_exact_out(
+                    amount,
+                    SWAP_AMOUNT,
+                    SWAP_AMOUNT,
+                    pool_supply,
+                    trade_direction,
+                )
+                .unwrap()
+        };
+
+        let equal = curve(50, 50);
+        assert_eq!(deposit(&equal, TradeDirection::AtoB), 48_808);
+        assert_eq!(deposit(&equal, TradeDirection::BtoA), 48_808);
+        assert_eq!(withdraw(&equal, TradeDirection::AtoB), 51_317);
+
+        let heavy_a = curve(80, 20);
+        assert_eq!(deposit(&heavy_a, TradeDirection::AtoB), 79_230);
+        assert_eq!(withdraw(&heavy_a, TradeDirection::AtoB), 80_834);
+
+        // withdrawing what was deposited burns more pool tokens than were
+        // minted for it
+        for curve in [equal.clone(), heavy_a, curve(10, 90)] {
+            for trade_direction in [TradeDirection::AtoB, TradeDirection::BtoA] {
+                assert!(withdraw(&curve, trade_direction) > deposit(&curve, trade_direction));
+            }
+        }
+
+        // at most half of the balance in, and a third out
+        assert!(equal
+            .deposit_single_token_type(
+                SWAP_AMOUNT / 2 + 1,
+                SWAP_AMOUNT,
+                SWAP_AMOUNT,
+                pool_supply,
+                TradeDirection::AtoB
+            )
+            .is_none());
+        assert!(equal
+            .withdraw_single_token_type_exact_out(
+                SWAP_AMOUNT / 3 + 1,
+                SWAP_AMOUNT,
+                SWAP_AMOUNT,
+                pool_supply,
+                TradeDirection::AtoB
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn normalized_value_known_values() {
+        // equal weights give the square root of the product
+        let value = curve(50, 50).normalized_value(4_000_000, 1_000_000).unwrap();
+        assert_eq!(value.to_imprecise(), Some(2_000_000));
+        let value = curve(80, 20).normalized_value(SWAP_AMOUNT, SWAP_AMOUNT).unwrap();
+        assert_eq!(value.to_imprecise(), Some(SWAP_AMOUNT));
+        // 16 ^ 0.8 and 16 ^ 0.2
+        assert_nano_eq(&curve(80, 20).normalized_value(16, 1).unwrap(), 9_189_586_840);
+        assert_nano_eq(&curve(80, 20).normalized_value(1, 16).unwrap(), 1_741_101_127);
+        assert_eq!(
+            curve(80, 20).normalized_value(0, 16).unwrap(),
+            PreciseNumber::new(0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,444 @@
+//! Hybrid mode of the pools, where part of the reserves is posted as limit
+//! orders on the linked Serum market
+
+use crate::error::AmmError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    program_error::ProgramError,
+    program_pack::{Pack, Sealed},
+    pubkey::Pubkey,
+};
+use std::{cmp::Reverse, convert::TryFrom};
+
+/// Basis points in one
+pub const BPS_DENOMINATOR: u64 = 10_000;
+/// Maximum share of each reserve posted on the book, in basis points
+pub const MAX_DEPTH_BPS: u16 = 5_000;
+/// Maximum number of orders per side
+pub const MAX_ORDER_LEVELS: u8 = 4;
+
+/// Taker fee of the base fee tier of the market, in basis points. Accounts
+/// holding SRM pay less, which only improves the fill.
+pub const TAKER_FEE_BPS: u64 = 22;
+/// Number of slices a split swap is cut into, each slice goes to the venue
+/// paying the most for it
+pub const SPLIT_SWAP_STEPS: u64 = 16;
+
+/// Client order ids of the pool bids are `BID_CLIENT_ORDER_ID + level`
+pub const BID_CLIENT_ORDER_ID: u64 = 0x6269_0000;
+/// Client order ids of the pool asks are `ASK_CLIENT_ORDER_ID + level`
+pub const ASK_CLIENT_ORDER_ID: u64 = 0x6173_0000;
+
+/// Orderbook settings of a pool
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Orderbook {
+    /// Open orders account of the pool on its market, owned by the pool
+    /// authority. Zero when the pool doesn't post orders.
+    pub open_orders: Pubkey,
+    /// Share of each reserve posted on the book, in basis points
+    pub depth_bps: u16,
+    /// Distance between the curve's spot price and the first order, and
+    /// between two orders of the same side, in basis points
+    pub spread_bps: u16,
+    /// Number of orders per side, the depth is split evenly between them
+    pub levels: u8,
+    /// Orders may be resting on the book or funds may be left in the open
+    /// orders account, the pool reserves don't hold all of the liquidity
+    pub has_orders: bool,
+}
+
+impl Orderbook {
+    /// Does the pool post orders on its market
+    pub fn is_enabled(&self) -> bool {
+        self.open_orders != Pubkey::default() && self.depth_bps != 0 && self.levels != 0
+    }
+
+    /// Checks that the settings are within bounds, zero depth or levels
+    /// disable the hybrid mode
+    pub fn validate(&self) -> Result<(), AmmError> {
+        if self.depth_bps == 0 || self.levels == 0 {
+            return Ok(());
+        }
+        if self.depth_bps > MAX_DEPTH_BPS
+            || self.levels > MAX_ORDER_LEVELS
+            || self.spread_bps == 0
+            || u64::from(self.spread_bps) * u64::from(self.levels) >= BPS_DENOMINATOR
+        {
+            return Err(AmmError::InvalidOrderbookParams);
+        }
+        Ok(())
+    }
+
+    /// Orders to post on one side, as (client order id, quantity, price)
+    /// where the quantity is the coin amount to sell for asks and the pc
+    /// amount to spend for bids, and the price is in pc per coin as the
+    /// fraction `spot_price_numerator / spot_price_denominator` moved by the
+    /// spread of the level
+    pub fn orders(
+        &self,
+        side: OrderSide,
+        reserve: u64,
+        spot_price_numerator: u128,
+        spot_price_denominator: u128,
+    ) -> Option<Vec<(u64, u64, u128, u128)>> {
+        let levels = u64::from(self.levels);
+        let quantity = u128::from(reserve)
+            .checked_mul(u128::from(self.depth_bps))?
+            .checked_div(u128::from(BPS_DENOMINATOR))?
+            .checked_div(u128::from(levels))?;
+        let quantity = u64::try_from(quantity).ok()?;
+        (1..=levels)
+            .map(|level| {
+                let spread = u64::from(self.spread_bps).checked_mul(level)?;
+                let (client_order_id, price_bps) = match side {
+                    OrderSide::Bid => (BID_CLIENT_ORDER_ID + level, BPS_DENOMINATOR - spread),
+                    OrderSide::Ask => (ASK_CLIENT_ORDER_ID + level, BPS_DENOMINATOR + spread),
+                };
+                Some((
+                    client_order_id,
+                    quantity,
+                    spot_price_numerator.checked_mul(u128::from(price_bps))?,
+                    spot_price_denominator.checked_mul(u128::from(BPS_DENOMINATOR))?,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Is the client order id one of the pool orders
+pub fn is_pool_client_order_id(client_order_id: u64) -> bool {
+    let levels = 1..=u64::from(MAX_ORDER_LEVELS);
+    levels.contains(&client_order_id.wrapping_sub(BID_CLIENT_ORDER_ID))
+        || levels.contains(&client_order_id.wrapping_sub(ASK_CLIENT_ORDER_ID))
+}
+
+/// Side of an order on the market
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderSide {
+    /// Buys coin with pc
+    Bid,
+    /// Sells coin for pc
+    Ask,
+}
+
+impl Sealed for Orderbook {}
+impl Pack for Orderbook {
+    const LEN: usize = 38;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, Orderbook::LEN];
+        let (open_orders, depth_bps, spread_bps, levels, has_orders) =
+            mut_array_refs![output, 32, 2, 2, 1, 1];
+        open_orders.copy_from_slice(self.open_orders.as_ref());
+        *depth_bps = self.depth_bps.to_le_bytes();
+        *spread_bps = self.spread_bps.to_le_bytes();
+        levels[0] = self.levels;
+        has_orders[0] = self.has_orders as u8;
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, Orderbook::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (open_orders, depth_bps, spread_bps, levels, has_orders) =
+            array_refs![input, 32, 2, 2, 1, 1];
+        Ok(Self {
+            open_orders: Pubkey::new_from_array(*open_orders),
+            depth_bps: u16::from_le_bytes(*depth_bps),
+            spread_bps: u16::from_le_bytes(*spread_bps),
+            levels: levels[0],
+            has_orders: match has_orders {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+        })
+    }
+}
+
+/// Size of a Serum v3 market account
+pub const MARKET_LEN: usize = 388;
+/// Size of a Serum v3 open orders account
+pub const OPEN_ORDERS_LEN: usize = 3228;
+/// Number of order slots of an open orders account
+const OPEN_ORDERS_SLOTS: usize = 128;
+/// Offset of the first node of a bids or asks account, after the padding,
+/// the account flags and the slab header
+const SLAB_NODES_OFFSET: usize = 45;
+/// Size of a node of a bids or asks account
+const SLAB_NODE_LEN: usize = 72;
+/// Tag of the nodes holding an order
+const SLAB_LEAF_NODE_TAG: u32 = 2;
+
+/// Fields of a Serum market the pools need, read from the account data
+/// at the offsets of the v3 layout, after the 5 bytes "serum" padding
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarketInfo {
+    /// Mint of the base token
+    pub coin_mint: Pubkey,
+    /// Mint of the quote token
+    pub pc_mint: Pubkey,
+    /// Base token vault of the market
+    pub coin_vault: Pubkey,
+    /// Quote token vault of the market
+    pub pc_vault: Pubkey,
+    /// Request queue of the market
+    pub request_queue: Pubkey,
+    /// Event queue of the market
+    pub event_queue: Pubkey,
+    /// Bids of the market
+    pub bids: Pubkey,
+    /// Asks of the market
+    pub asks: Pubkey,
+    /// Base token amount of one lot
+    pub coin_lot_size: u64,
+    /// Quote token amount of one lot
+    pub pc_lot_size: u64,
+}
+
+impl MarketInfo {
+    /// Reads the market fields from the market account data
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() < MARKET_LEN {
+            return Err(AmmError::IncorrectMarketAccount.into());
+        }
+        let input = array_ref![input, 0, MARKET_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            _head,
+            _account_flags,
+            _own_address,
+            _vault_signer_nonce,
+            coin_mint,
+            pc_mint,
+            coin_vault,
+            _coin_deposits,
+            pc_vault,
+            _pc_deposits,
+            _pc_dust_threshold,
+            request_queue,
+            event_queue,
+            bids,
+            asks,
+            coin_lot_size,
+            pc_lot_size,
+            _tail,
+        ) = array_refs![input, 5, 8, 32, 8, 32, 32, 32, 16, 32, 16, 8, 32, 32, 32, 32, 8, 8, 23];
+        Ok(Self {
+            coin_mint: Pubkey::new_from_array(*coin_mint),
+            pc_mint: Pubkey::new_from_array(*pc_mint),
+            coin_vault: Pubkey::new_from_array(*coin_vault),
+            pc_vault: Pubkey::new_from_array(*pc_vault),
+            request_queue: Pubkey::new_from_array(*request_queue),
+            event_queue: Pubkey::new_from_array(*event_queue),
+            bids: Pubkey::new_from_array(*bids),
+            asks: Pubkey::new_from_array(*asks),
+            coin_lot_size: u64::from_le_bytes(*coin_lot_size),
+            pc_lot_size: u64::from_le_bytes(*pc_lot_size),
+        })
+    }
+
+    /// Fills a taker order of `amount_in` against the book `levels`, as
+    /// returned by [book_levels](fn.book_levels.html). An ask sells coin into
+    /// the bids and a bid buys coin from the asks with pc.
+    pub fn fill(&self, levels: &[(u64, u64)], taker_side: OrderSide, amount_in: u64) -> Option<BookFill> {
+        let coin_lot_size = u128::from(self.coin_lot_size);
+        let pc_lot_size = u128::from(self.pc_lot_size);
+        if coin_lot_size == 0 || pc_lot_size == 0 {
+            return None;
+        }
+        let mut fill = BookFill::default();
+        match taker_side {
+            OrderSide::Ask => {
+                let mut remaining_lots = u64::try_from(u128::from(amount_in) / coin_lot_size).ok()?;
+                let mut pc_amount = 0u128;
+                for &(price, quantity) in levels {
+                    if remaining_lots == 0 {
+                        break;
+                    }
+                    let lots = std::cmp::min(quantity, remaining_lots);
+                    pc_amount = pc_amount.checked_add(
+                        u128::from(lots)
+                            .checked_mul(u128::from(price))?
+                            .checked_mul(pc_lot_size)?,
+                    )?;
+                    remaining_lots -= lots;
+                    fill.coin_lots = fill.coin_lots.checked_add(lots)?;
+                    fill.limit_price = price;
+                }
+                let fee = pc_amount
+                    .checked_mul(u128::from(TAKER_FEE_BPS))?
+                    .checked_add(u128::from(BPS_DENOMINATOR) - 1)?
+                    / u128::from(BPS_DENOMINATOR);
+                fill.amount_in = u64::try_from(u128::from(fill.coin_lots).checked_mul(coin_lot_size)?).ok()?;
+                fill.amount_out = u64::try_from(pc_amount.checked_sub(fee)?).ok()?;
+            }
+            OrderSide::Bid => {
+                let mut remaining_pc = u128::from(amount_in);
+                let mut pc_amount = 0u128;
+                for &(price, quantity) in levels {
+                    // pc of one lot with the fee, in basis points of pc
+                    let lot_cost = u128::from(price)
+                        .checked_mul(pc_lot_size)?
+                        .checked_mul(u128::from(BPS_DENOMINATOR + TAKER_FEE_BPS))?;
+                    if lot_cost == 0 {
+                        continue;
+                    }
+                    let affordable = remaining_pc.checked_mul(u128::from(BPS_DENOMINATOR))? / lot_cost;
+                    let lots = std::cmp::min(u128::from(quantity), affordable);
+                    if lots == 0 {
+                        break;
+                    }
+                    let cost = lots
+                        .checked_mul(lot_cost)?
+                        .checked_add(u128::from(BPS_DENOMINATOR) - 1)?
+                        / u128::from(BPS_DENOMINATOR);
+                    remaining_pc = remaining_pc.checked_sub(cost)?;
+                    pc_amount = pc_amount.checked_add(cost)?;
+                    fill.coin_lots = fill.coin_lots.checked_add(u64::try_from(lots).ok()?)?;
+                    fill.limit_price = price;
+                    if lots < u128::from(quantity) {
+                        break;
+                    }
+                }
+                fill.amount_in = u64::try_from(pc_amount).ok()?;
+                fill.amount_out = u64::try_from(u128::from(fill.coin_lots).checked_mul(coin_lot_size)?).ok()?;
+            }
+        }
+        Some(fill)
+    }
+}
+
+/// Taker order filled against the book
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BookFill {
+    /// Amount spent, coin for an ask and pc with the taker fee for a bid
+    pub amount_in: u64,
+    /// Amount received, pc net of the taker fee for an ask and coin for a bid
+    pub amount_out: u64,
+    /// Coin lots traded
+    pub coin_lots: u64,
+    /// Worst price reached, in pc lots per coin lot
+    pub limit_price: u64,
+}
+
+/// Orders resting on one side of a market, read from its bids or asks
+/// account, as (price, quantity) in lots from the best price to the worst
+pub fn book_levels(input: &[u8], side: OrderSide) -> Result<Vec<(u64, u64)>, ProgramError> {
+    if input.len() < SLAB_NODES_OFFSET {
+        return Err(AmmError::IncorrectMarketAccount.into());
+    }
+    let bump_index = u64::from_le_bytes(*array_ref![input, 13, 8]);
+    let node_count = std::cmp::min(
+        usize::try_from(bump_index).unwrap_or(usize::MAX),
+        (input.len() - SLAB_NODES_OFFSET) / SLAB_NODE_LEN,
+    );
+    let mut levels = Vec::new();
+    for node in input[SLAB_NODES_OFFSET..]
+        .chunks_exact(SLAB_NODE_LEN)
+        .take(node_count)
+    {
+        let node = array_ref![node, 0, SLAB_NODE_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (tag, _owner_slot, _fee_tier, _padding, key, _owner, quantity, _client_order_id) =
+            array_refs![node, 4, 1, 1, 2, 16, 32, 8, 8];
+        if u32::from_le_bytes(*tag) != SLAB_LEAF_NODE_TAG {
+            continue;
+        }
+        let price = (u128::from_le_bytes(*key) >> 64) as u64;
+        levels.push((price, u64::from_le_bytes(*quantity)));
+    }
+    match side {
+        OrderSide::Bid => levels.sort_unstable_by_key(|level| Reverse(level.0)),
+        OrderSide::Ask => levels.sort_unstable_by_key(|level| level.0),
+    }
+    Ok(levels)
+}
+
+/// Splits `amount_in` between the pool curve and the book, slice by slice
+/// to the venue with the larger extra output, returns the amount for the
+/// curve. Both outputs are concave so the split is within a slice of the
+/// best one.
+pub fn split_amount<C, B>(amount_in: u64, curve_output: C, book_output: B) -> u64
+where
+    C: Fn(u64) -> Option<u64>,
+    B: Fn(u64) -> Option<u64>,
+{
+    let step = std::cmp::max(1, amount_in / SPLIT_SWAP_STEPS);
+    let (mut curve_in, mut curve_out) = (0u64, 0u64);
+    let (mut book_in, mut book_out) = (0u64, 0u64);
+    while curve_in + book_in < amount_in {
+        let slice = std::cmp::min(step, amount_in - curve_in - book_in);
+        let next_curve_out = curve_output(curve_in + slice).unwrap_or(curve_out);
+        let next_book_out = book_output(book_in + slice).unwrap_or(book_out);
+        if next_book_out.saturating_sub(book_out) > next_curve_out.saturating_sub(curve_out) {
+            book_in += slice;
+            book_out = next_book_out;
+        } else {
+            curve_in += slice;
+            curve_out = next_curve_out;
+        }
+    }
+    curve_in
+}
+
+/// Balances and orders of a Serum open orders account
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpenOrdersInfo {
+    /// Base tokens free to settle to the owner
+    pub native_coin_free: u64,
+    /// Base tokens held for the owner, locked in orders or free to settle
+    pub native_coin_total: u64,
+    /// Quote tokens free to settle to the owner
+    pub native_pc_free: u64,
+    /// Quote tokens held for the owner, locked in orders or free to settle
+    pub native_pc_total: u64,
+    /// Client order ids of the resting orders
+    pub client_order_ids: Vec<u64>,
+}
+
+impl OpenOrdersInfo {
+    /// Reads the open orders account data, an account the market hasn't
+    /// used yet is all zeros and holds nothing
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() < OPEN_ORDERS_LEN {
+            return Err(AmmError::IncorrectMarketAccount.into());
+        }
+        let input = array_ref![input, 0, OPEN_ORDERS_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            _head,
+            _account_flags,
+            _market,
+            _owner,
+            native_coin_free,
+            native_coin_total,
+            native_pc_free,
+            native_pc_total,
+            free_slot_bits,
+            _is_bid_bits,
+            _orders,
+            client_order_ids,
+            _tail,
+        ) = array_refs![input, 5, 8, 32, 32, 8, 8, 8, 8, 16, 16, 2048, 1024, 15];
+        if input.iter().all(|byte| *byte == 0) {
+            return Ok(Self::default());
+        }
+        let free_slot_bits = u128::from_le_bytes(*free_slot_bits);
+        Ok(Self {
+            native_coin_free: u64::from_le_bytes(*native_coin_free),
+            native_coin_total: u64::from_le_bytes(*native_coin_total),
+            native_pc_free: u64::from_le_bytes(*native_pc_free),
+            native_pc_total: u64::from_le_bytes(*native_pc_total),
+            client_order_ids: (0..OPEN_ORDERS_SLOTS)
+                .filter(|slot| free_slot_bits & (1u128 << slot) == 0)
+                .map(|slot| u64::from_le_bytes(*array_ref![client_order_ids, slot * 8, 8]))
+                .collect(),
+        })
+    }
+
+    /// Does the account hold tokens or orders
+    pub fn is_empty(&self) -> bool {
+        self.native_coin_total == 0 && self.native_pc_total == 0 && self.client_order_ids.is_empty()
+    }
+}
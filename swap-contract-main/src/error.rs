@@ -129,6 +129,101 @@ pub enum AmmError {
     /// Program State should be initialized before creating pool .
     #[error("Program State should be initialized before creating pool")]
     NotInitializedState,
+    /// The tick range is not valid for the concentrated pool
+    #[error("Tick range is out of bounds or not aligned to the tick spacing")]
+    InvalidTickRange,
+    /// The concentrated pool can't hold another initialized tick
+    #[error("Concentrated pool has no room for another initialized tick")]
+    TickListFull,
+    /// The position doesn't hold the liquidity to remove
+    #[error("Position doesn't have enough liquidity")]
+    InsufficientLiquidity,
+    /// The amp ramp is too fast, too large, or started too soon after the last one
+    #[error("Amp ramp parameters are out of bounds")]
+    InvalidAmpRamp,
+    /// The program or the pool is paused by the state owner
+    #[error("Swaps, deposits and single-sided withdrawals are paused")]
+    Paused,
+    /// The orderbook depth, spread or levels are out of bounds
+    #[error("Orderbook parameters are out of bounds")]
+    InvalidOrderbookParams,
+    /// The Serum market accounts don't match the pool or its market
+    #[error("Serum market accounts don't match the pool")]
+    IncorrectMarketAccount,
+    /// Part of the pool liquidity is on the Serum market
+    #[error("Pool has orders on the Serum market, cancel them first")]
+    OrdersOutstanding,
+    /// The pool marker account isn't the one derived from the pair and
+    /// curve type
+    #[error("Invalid pool marker address")]
+    InvalidPoolMarkerAddress,
+    /// A pool already exists for the pair and curve type
+    #[error("Pool already exists for this pair and curve")]
+    PoolAlreadyExists,
+    /// The account has an older layout, too small for the latest one
+    #[error("Account has an older layout, migrate it first")]
+    AccountNotMigrated,
+    /// The account already has the latest layout
+    #[error("Account already has the latest layout")]
+    AlreadyMigrated,
+    /// No state owner was proposed
+    #[error("No pending state owner")]
+    NoPendingStateOwner,
+    /// The program state is initialized, its parameters change through
+    /// a queued update
+    #[error("Program state is initialized, queue the update")]
+    StateUpdateNotQueued,
+    /// No program state update was queued
+    #[error("No pending state update")]
+    NoPendingStateUpdate,
+    /// The timelock of the pending state update hasn't expired
+    #[error("Pending state update is still timelocked")]
+    StateUpdateTimelocked,
+    /// The governance signers are duplicated or the threshold isn't
+    /// between one and their number
+    #[error("Invalid governance signers or threshold")]
+    InvalidGovernance,
+    /// The signer isn't one of the governance
+    #[error("Signer is not part of the governance")]
+    NotGovernanceSigner,
+    /// The proposal is too large or belongs to another governance
+    #[error("Invalid proposal")]
+    InvalidProposal,
+    /// The proposal was already executed
+    #[error("Proposal already executed")]
+    ProposalAlreadyExecuted,
+    /// The proposal has fewer approvals than the governance threshold
+    #[error("Proposal lacks approvals")]
+    ProposalNotApproved,
+    /// The mint list account isn't the program derived one
+    #[error("Invalid mint list address")]
+    InvalidMintListAddress,
+    /// The mint list holds the maximum number of mints
+    #[error("Mint list is full")]
+    MintListFull,
+    /// The mint list doesn't let others than the state owner create pools
+    /// of the mint
+    #[error("Mint not allowed for permissionless pools")]
+    MintNotAllowed,
+    /// The pool still has more pool tokens than the minimum supply, or is
+    /// wound down without being paused
+    #[error("Pool can't be closed")]
+    PoolNotClosable,
+    /// The initial pool token supply doesn't exceed the locked minimum
+    /// supply
+    #[error("Initial supply too low")]
+    InitialSupplyTooLow,
+    /// The pool token account locking the minimum supply isn't an account
+    /// of the pool token mint owned by the lock address of the swap
+    #[error("Pool token lock account incorrect")]
+    IncorrectLockAccount,
+    /// The burn would take the pool token supply below the locked minimum
+    #[error("Pool token supply can't go below the locked minimum")]
+    MinimumSupplyLocked,
+    /// The delay of a queued state update is shorter than the minimum delay
+    /// of the swap constraints
+    #[error("State update delay too short")]
+    StateUpdateDelayTooShort,
 
 
 }
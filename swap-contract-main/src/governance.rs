@@ -0,0 +1,246 @@
+//! M-of-N governance, its authority can own the program state so that
+//! state updates and pool creation need the approval of several signers
+
+use crate::error::AmmError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    instruction::AccountMeta,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+/// Seed of the governance authorities, followed by the governance address
+pub const GOVERNANCE_SEED: &str = "AmmGovernance";
+/// Maximum number of signers of a governance, one approval bit each
+pub const MAX_GOVERNANCE_SIGNERS: usize = 16;
+/// Maximum number of accounts of a proposed instruction
+pub const MAX_PROPOSAL_ACCOUNTS: usize = 16;
+/// Maximum data length of a proposed instruction
+pub const MAX_PROPOSAL_DATA_LEN: usize = 256;
+
+/// Address signing the proposals executed by a governance
+pub fn find_governance_authority_address(
+    program_id: &Pubkey,
+    governance: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[GOVERNANCE_SEED.as_bytes(), governance.as_ref()],
+        program_id,
+    )
+}
+
+/// Signers of a governance and the number of approvals a proposal needs
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Governance {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Bump seed of the governance authority
+    pub nonce: u8,
+    /// Approvals needed to execute a proposal
+    pub threshold: u8,
+    /// Signers allowed to propose and approve
+    pub signers: Vec<Pubkey>,
+}
+
+impl Governance {
+    /// Position of a signer, its bit in the approvals of a proposal
+    pub fn signer_index(&self, key: &Pubkey) -> Option<usize> {
+        self.signers.iter().position(|signer| signer == key)
+    }
+
+    /// Checks the threshold against distinct signers
+    pub fn validate(&self) -> Result<(), AmmError> {
+        let distinct = self
+            .signers
+            .iter()
+            .enumerate()
+            .all(|(i, signer)| !self.signers[..i].contains(signer));
+        if distinct
+            && self.signers.len() <= MAX_GOVERNANCE_SIGNERS
+            && self.threshold > 0
+            && self.threshold as usize <= self.signers.len()
+        {
+            Ok(())
+        } else {
+            Err(AmmError::InvalidGovernance)
+        }
+    }
+}
+
+impl IsInitialized for Governance {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Sealed for Governance {}
+impl Pack for Governance {
+    const LEN: usize = 4 + MAX_GOVERNANCE_SIGNERS * 32;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, Governance::LEN];
+        let (is_initialized, nonce, threshold, count, signers) =
+            mut_array_refs![output, 1, 1, 1, 1, MAX_GOVERNANCE_SIGNERS * 32];
+        is_initialized[0] = self.is_initialized as u8;
+        nonce[0] = self.nonce;
+        threshold[0] = self.threshold;
+        count[0] = self.signers.len() as u8;
+        for (signer, data) in self.signers.iter().zip(signers.chunks_exact_mut(32)) {
+            data.copy_from_slice(signer.as_ref());
+        }
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, Governance::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, nonce, threshold, count, signers) =
+            array_refs![input, 1, 1, 1, 1, MAX_GOVERNANCE_SIGNERS * 32];
+        let count = count[0] as usize;
+        if count > MAX_GOVERNANCE_SIGNERS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: nonce[0],
+            threshold: threshold[0],
+            signers: signers
+                .chunks_exact(32)
+                .take(count)
+                .map(|key| Pubkey::new_from_array(*array_ref![key, 0, 32]))
+                .collect(),
+        })
+    }
+}
+
+/// Instruction of the program waiting for the approval of a governance,
+/// executed with its authority as a signer
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Proposal {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Governance deciding on the proposal
+    pub governance: Pubkey,
+    /// Was the instruction executed
+    pub executed: bool,
+    /// One bit per governance signer that approved
+    pub approvals: u16,
+    /// Accounts of the proposed instruction
+    pub accounts: Vec<AccountMeta>,
+    /// Data of the proposed instruction
+    pub data: Vec<u8>,
+}
+
+impl Proposal {
+    /// Number of signers that approved
+    pub fn approval_count(&self) -> u32 {
+        self.approvals.count_ones()
+    }
+}
+
+impl IsInitialized for Proposal {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Sealed for Proposal {}
+impl Pack for Proposal {
+    const LEN: usize = 39 + MAX_PROPOSAL_ACCOUNTS * 34 + MAX_PROPOSAL_DATA_LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, Proposal::LEN];
+        let (
+            is_initialized,
+            governance,
+            executed,
+            approvals,
+            account_count,
+            accounts,
+            data_len,
+            data,
+        ) = mut_array_refs![
+            output,
+            1,
+            32,
+            1,
+            2,
+            1,
+            MAX_PROPOSAL_ACCOUNTS * 34,
+            2,
+            MAX_PROPOSAL_DATA_LEN
+        ];
+        is_initialized[0] = self.is_initialized as u8;
+        governance.copy_from_slice(self.governance.as_ref());
+        executed[0] = self.executed as u8;
+        *approvals = self.approvals.to_le_bytes();
+        account_count[0] = self.accounts.len() as u8;
+        for (meta, output) in self.accounts.iter().zip(accounts.chunks_exact_mut(34)) {
+            output[..32].copy_from_slice(meta.pubkey.as_ref());
+            output[32] = meta.is_signer as u8;
+            output[33] = meta.is_writable as u8;
+        }
+        *data_len = (self.data.len() as u16).to_le_bytes();
+        data[..self.data.len()].copy_from_slice(&self.data);
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, Proposal::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            governance,
+            executed,
+            approvals,
+            account_count,
+            accounts,
+            data_len,
+            data,
+        ) = array_refs![
+            input,
+            1,
+            32,
+            1,
+            2,
+            1,
+            MAX_PROPOSAL_ACCOUNTS * 34,
+            2,
+            MAX_PROPOSAL_DATA_LEN
+        ];
+        let account_count = account_count[0] as usize;
+        let data_len = u16::from_le_bytes(*data_len) as usize;
+        if account_count > MAX_PROPOSAL_ACCOUNTS || data_len > MAX_PROPOSAL_DATA_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            governance: Pubkey::new_from_array(*governance),
+            executed: match executed {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            approvals: u16::from_le_bytes(*approvals),
+            accounts: accounts
+                .chunks_exact(34)
+                .take(account_count)
+                .map(|meta| AccountMeta {
+                    pubkey: Pubkey::new_from_array(*array_ref![meta, 0, 32]),
+                    is_signer: meta[32] != 0,
+                    is_writable: meta[33] != 0,
+                })
+                .collect(),
+            data: data[..data_len].to_vec(),
+        })
+    }
+}
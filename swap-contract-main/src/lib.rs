@@ -4,10 +4,15 @@
 
 pub mod amm_instruction;
 pub mod amm_stats;
+pub mod concentrated;
 pub mod constraints;
 pub mod curve;
+pub mod dex;
 pub mod error;
+pub mod governance;
+pub mod oracle;
 pub mod processor;
+pub mod registry;
 
 #[cfg(not(feature = "no-entrypoint"))]
 mod entrypoint;
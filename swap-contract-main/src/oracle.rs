@@ -0,0 +1,349 @@
+//! Time weighted average price oracle of the pools
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    clock::{Slot, UnixTimestamp},
+    program_error::ProgramError,
+    program_pack::{Pack, Sealed},
+};
+
+/// Cumulative prices of a pool, updated before every change of its reserves.
+///
+/// Prices are UQ64.64 fixed point numbers (the price multiplied by 2^64)
+/// summed once per elapsed second, they wrap around on overflow. The time
+/// weighted average price between two observations is the difference of
+/// the cumulative prices divided by the elapsed time, see
+/// [twap_a](struct.PriceOracle.html#method.twap_a). The average is exact
+/// as long as the price times the length of the window fits in a u128.
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PriceOracle {
+    /// Cumulative price of token A in token B
+    pub price_a_cumulative: u128,
+    /// Cumulative price of token B in token A
+    pub price_b_cumulative: u128,
+    /// Slot of the last update
+    pub last_slot: Slot,
+    /// Unix timestamp of the last update
+    pub last_timestamp: UnixTimestamp,
+}
+
+impl PriceOracle {
+    /// Creates an oracle without price history, starting at the given time
+    pub fn new(slot: Slot, timestamp: UnixTimestamp) -> Self {
+        Self {
+            price_a_cumulative: 0,
+            price_b_cumulative: 0,
+            last_slot: slot,
+            last_timestamp: timestamp,
+        }
+    }
+
+    /// Accumulates the prices given by the reserves for the time elapsed
+    /// since the last update. The reserves must be the ones the pool held
+    /// during that time, ie. before the current trade is applied.
+    ///
+    /// Off-chain, updating a copy of the oracle with the current reserves
+    /// gives the cumulative prices up to now.
+    pub fn update(
+        &mut self,
+        slot: Slot,
+        timestamp: UnixTimestamp,
+        reserve_a: u64,
+        reserve_b: u64,
+    ) {
+        if timestamp > self.last_timestamp && reserve_a != 0 && reserve_b != 0 {
+            let elapsed = (timestamp - self.last_timestamp) as u128;
+            self.price_a_cumulative = self
+                .price_a_cumulative
+                .wrapping_add(price(reserve_b, reserve_a).wrapping_mul(elapsed));
+            self.price_b_cumulative = self
+                .price_b_cumulative
+                .wrapping_add(price(reserve_a, reserve_b).wrapping_mul(elapsed));
+        }
+        if timestamp >= self.last_timestamp {
+            self.last_slot = slot;
+            self.last_timestamp = timestamp;
+        }
+    }
+
+    /// Time weighted average price of token A in token B since an earlier
+    /// observation, as a UQ64.64 fixed point number
+    pub fn twap_a(&self, earlier: &PriceOracle) -> Option<u128> {
+        twap(
+            earlier.price_a_cumulative,
+            earlier.last_timestamp,
+            self.price_a_cumulative,
+            self.last_timestamp,
+        )
+    }
+
+    /// Time weighted average price of token B in token A since an earlier
+    /// observation, as a UQ64.64 fixed point number
+    pub fn twap_b(&self, earlier: &PriceOracle) -> Option<u128> {
+        twap(
+            earlier.price_b_cumulative,
+            earlier.last_timestamp,
+            self.price_b_cumulative,
+            self.last_timestamp,
+        )
+    }
+}
+
+/// UQ64.64 price of one token of the denominator reserve, reserves fit in
+/// u64 so the shifted numerator can't overflow
+fn price(numerator: u64, denominator: u64) -> u128 {
+    ((numerator as u128) << 64) / (denominator as u128)
+}
+
+/// Time weighted average of a cumulative price between two timestamps,
+/// handles the wrapping of the cumulative price
+pub fn twap(
+    cumulative_start: u128,
+    timestamp_start: UnixTimestamp,
+    cumulative_end: u128,
+    timestamp_end: UnixTimestamp,
+) -> Option<u128> {
+    if timestamp_end <= timestamp_start {
+        return None;
+    }
+    let elapsed = (timestamp_end - timestamp_start) as u128;
+    Some(cumulative_end.wrapping_sub(cumulative_start) / elapsed)
+}
+
+impl Sealed for PriceOracle {}
+impl Pack for PriceOracle {
+    const LEN: usize = 48;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, PriceOracle::LEN];
+        let (price_a_cumulative, price_b_cumulative, last_slot, last_timestamp) =
+            mut_array_refs![output, 16, 16, 8, 8];
+        *price_a_cumulative = self.price_a_cumulative.to_le_bytes();
+        *price_b_cumulative = self.price_b_cumulative.to_le_bytes();
+        *last_slot = self.last_slot.to_le_bytes();
+        *last_timestamp = self.last_timestamp.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, PriceOracle::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (price_a_cumulative, price_b_cumulative, last_slot, last_timestamp) =
+            array_refs![input, 16, 16, 8, 8];
+        Ok(Self {
+            price_a_cumulative: u128::from_le_bytes(*price_a_cumulative),
+            price_b_cumulative: u128::from_le_bytes(*price_b_cumulative),
+            last_slot: Slot::from_le_bytes(*last_slot),
+            last_timestamp: UnixTimestamp::from_le_bytes(*last_timestamp),
+        })
+    }
+}
+
+/// Seconds it takes the volatility accumulator to decay by half
+pub const VOLATILITY_HALF_LIFE: i64 = 300;
+
+/// Accumulated volatility, in basis points, at which dynamic fees reach
+/// their maximum
+pub const VOLATILITY_SATURATION_BPS: u64 = 1_000;
+
+/// Recent price movement of a pool, used to raise the trading fee of
+/// volatile pairs.
+///
+/// Every trade adds the relative move of the price it caused, in basis
+/// points, and the sum decays exponentially with a half-life of
+/// `VOLATILITY_HALF_LIFE` seconds, interpolated linearly between two
+/// halvings.
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Volatility {
+    /// Decayed sum of the price moves, in basis points
+    pub volatility_bps: u64,
+    /// Unix timestamp of the last update
+    pub last_timestamp: UnixTimestamp,
+}
+
+impl Volatility {
+    /// Value of the accumulator decayed up to the given time
+    pub fn decayed(&self, timestamp: UnixTimestamp) -> u64 {
+        let elapsed = timestamp.saturating_sub(self.last_timestamp).max(0);
+        let halvings = elapsed / VOLATILITY_HALF_LIFE;
+        if halvings >= 64 {
+            return 0;
+        }
+        let volatility = self.volatility_bps >> halvings;
+        let remainder = (elapsed % VOLATILITY_HALF_LIFE) as u128;
+        let decay = (volatility / 2) as u128 * remainder / VOLATILITY_HALF_LIFE as u128;
+        volatility - decay as u64
+    }
+
+    /// Decays the accumulator up to the given time and adds the price move
+    /// between the reserves before and after a trade
+    pub fn update(
+        &mut self,
+        timestamp: UnixTimestamp,
+        reserve_a: u64,
+        reserve_b: u64,
+        new_reserve_a: u64,
+        new_reserve_b: u64,
+    ) {
+        let movement =
+            price_movement_bps(reserve_a, reserve_b, new_reserve_a, new_reserve_b);
+        self.volatility_bps = self.decayed(timestamp).saturating_add(movement);
+        if timestamp > self.last_timestamp {
+            self.last_timestamp = timestamp;
+        }
+    }
+}
+
+/// Relative change, in basis points, of the price of token A in token B
+/// between two sets of reserves
+fn price_movement_bps(
+    reserve_a: u64,
+    reserve_b: u64,
+    new_reserve_a: u64,
+    new_reserve_b: u64,
+) -> u64 {
+    // |b1 / a1 - b0 / a0| / (b0 / a0) = |a0 * b1 - a1 * b0| / (a1 * b0)
+    let old_price = new_reserve_a as u128 * reserve_b as u128;
+    let new_price = reserve_a as u128 * new_reserve_b as u128;
+    if old_price == 0 {
+        return 0;
+    }
+    let movement = new_price.abs_diff(old_price).saturating_mul(10_000) / old_price;
+    movement.min(u64::MAX as u128) as u64
+}
+
+impl Sealed for Volatility {}
+impl Pack for Volatility {
+    const LEN: usize = 16;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, Volatility::LEN];
+        let (volatility_bps, last_timestamp) = mut_array_refs![output, 8, 8];
+        *volatility_bps = self.volatility_bps.to_le_bytes();
+        *last_timestamp = self.last_timestamp.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, Volatility::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (volatility_bps, last_timestamp) = array_refs![input, 8, 8];
+        Ok(Self {
+            volatility_bps: u64::from_le_bytes(*volatility_bps),
+            last_timestamp: UnixTimestamp::from_le_bytes(*last_timestamp),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Q64: u128 = 1 << 64;
+
+    #[test]
+    fn update_weights_prices_by_elapsed_time() {
+        let mut oracle = PriceOracle::new(1, 100);
+        let start = oracle.clone();
+        oracle.update(2, 110, 1_000, 2_000);
+        assert_eq!(oracle.price_a_cumulative, 20 * Q64);
+        assert_eq!(oracle.price_b_cumulative, 5 * Q64);
+        assert_eq!(oracle.last_slot, 2);
+        assert_eq!(oracle.last_timestamp, 110);
+
+        oracle.update(3, 130, 2_000, 2_000);
+        assert_eq!(oracle.price_a_cumulative, 40 * Q64);
+        assert_eq!(oracle.price_b_cumulative, 25 * Q64);
+        assert_eq!(oracle.twap_a(&start), Some(Q64 + Q64 / 3));
+        assert_eq!(oracle.twap_b(&start), Some(25 * Q64 / 30));
+    }
+
+    #[test]
+    fn update_skips_empty_reserves() {
+        let mut oracle = PriceOracle::new(1, 100);
+        oracle.update(2, 110, 0, 2_000);
+        oracle.update(3, 120, 1_000, 0);
+        assert_eq!(oracle.price_a_cumulative, 0);
+        assert_eq!(oracle.price_b_cumulative, 0);
+        assert_eq!(oracle.last_slot, 3);
+        assert_eq!(oracle.last_timestamp, 120);
+    }
+
+    #[test]
+    fn update_ignores_earlier_timestamps() {
+        let mut oracle = PriceOracle::new(1, 100);
+        oracle.update(2, 110, 1_000, 1_000);
+        let expected = oracle.clone();
+        oracle.update(3, 105, 1_000, 4_000);
+        assert_eq!(oracle, expected);
+
+        // the same second accumulates nothing but moves the slot
+        oracle.update(4, 110, 1_000, 4_000);
+        assert_eq!(oracle.price_a_cumulative, expected.price_a_cumulative);
+        assert_eq!(oracle.last_slot, 4);
+    }
+
+    #[test]
+    fn twap_wraps_around() {
+        let start = u128::MAX - 9;
+        let end = start.wrapping_add(30);
+        assert!(end < start);
+        assert_eq!(twap(start, 10, end, 13), Some(10));
+    }
+
+    #[test]
+    fn twap_empty_window() {
+        let mut oracle = PriceOracle::new(1, 100);
+        oracle.update(2, 110, 1_000, 2_000);
+        assert_eq!(oracle.twap_a(&oracle), None);
+        assert_eq!(oracle.twap_b(&oracle), None);
+        let later = PriceOracle::new(3, 120);
+        assert_eq!(oracle.twap_a(&later), None);
+        assert_eq!(twap(0, 10, 0, 10), None);
+    }
+
+    #[test]
+    fn volatility_half_life() {
+        let volatility = Volatility {
+            volatility_bps: 1_000,
+            last_timestamp: 100,
+        };
+        assert_eq!(volatility.decayed(50), 1_000);
+        assert_eq!(volatility.decayed(100), 1_000);
+        assert_eq!(volatility.decayed(100 + VOLATILITY_HALF_LIFE / 2), 750);
+        assert_eq!(volatility.decayed(100 + VOLATILITY_HALF_LIFE), 500);
+        assert_eq!(volatility.decayed(100 + 2 * VOLATILITY_HALF_LIFE), 250);
+    }
+
+    #[test]
+    fn volatility_decays_to_zero() {
+        let volatility = Volatility {
+            volatility_bps: u64::MAX,
+            last_timestamp: 0,
+        };
+        assert_eq!(volatility.decayed(63 * VOLATILITY_HALF_LIFE), 1);
+        assert_eq!(volatility.decayed(64 * VOLATILITY_HALF_LIFE), 0);
+        assert_eq!(volatility.decayed(UnixTimestamp::MAX), 0);
+    }
+
+    #[test]
+    fn volatility_update() {
+        let mut volatility = Volatility {
+            volatility_bps: 1_000,
+            last_timestamp: 0,
+        };
+        volatility.update(VOLATILITY_HALF_LIFE, 1_000, 1_000, 1_000, 1_100);
+        assert_eq!(volatility.volatility_bps, 1_500);
+        assert_eq!(volatility.last_timestamp, VOLATILITY_HALF_LIFE);
+    }
+
+    #[test]
+    fn price_movement() {
+        assert_eq!(price_movement_bps(1_000, 1_000, 1_000, 1_100), 1_000);
+        assert_eq!(price_movement_bps(1_000, 1_100, 1_000, 1_000), 909);
+        assert_eq!(price_movement_bps(1_000, 2_000, 1_000, 2_000), 0);
+        // selling A moves its price in B from 2 to 1.28
+        assert_eq!(price_movement_bps(1_000, 2_000, 1_250, 1_600), 3_600);
+        assert_eq!(price_movement_bps(1_000, 0, 1_000, 1_000), 0);
+    }
+}
@@ -4,17 +4,43 @@ use crate::constraints::{SWAP_CONSTRAINTS};
 
 use crate::{
     curve::{
-        base::{SwapCurve, CurveType},
-        constant_product::ConstantProductCurve,
+        base::{SingleTokenResult, SwapCurve, SwapResult, CurveType},
+        constant_product::{normalized_value, ConstantProductCurve},
+        stable::StableCurve,
         calculator::{RoundDirection, TradeDirection, INITIAL_SWAP_POOL_AMOUNT},
         fees::Fees,
     },
     error::AmmError,
     amm_instruction::{
         DepositInstruction, DepositSingleTokenTypeExactAmountIn, InitializeInstruction, SwapInstruction,
-        AmmInstruction, WithdrawInstruction, WithdrawSingleTokenTypeExactAmountOut, UpdateStateInstruction
+        AmmInstruction, WithdrawInstruction, WithdrawSingleTokenTypeExactAmountOut, UpdateStateInstruction,
+        QueueStateUpdateInstruction, ClosePoolInstruction,
+        SwapExactAmountOutInstruction, RouteSwapInstruction, ROUTE_SWAP_HOP_ACCOUNTS, RampAmpInstruction,
+        SetPauseInstruction, SetOrderbookInstruction, SplitSwapInstruction,
+        SetProtocolFeeModeInstruction, CreateGovernanceInstruction, CreateProposalInstruction,
+        SetPoolCreationInstruction, UpdateMintListInstruction,
+        InitializeConcentratedPoolInstruction, OpenPositionInstruction,
+        IncreaseLiquidityInstruction, DecreaseLiquidityInstruction, ConcentratedSwapInstruction,
+    },
+    concentrated::{
+        math::{tick_from_sqrt_price, MAX_SQRT_PRICE, MIN_SQRT_PRICE},
+        state::{ConcentratedPool, Position, CONCENTRATED_POOL_TYPE},
+    },
+    amm_stats::{
+        AmmStatus, PendingStateUpdate, ProgramState, ProgramStateV2, SwapV2, SwapVersion,
+    },
+    dex::{
+        book_levels, is_pool_client_order_id, split_amount, MarketInfo, OpenOrdersInfo,
+        OrderSide, Orderbook, BPS_DENOMINATOR,
+    },
+    governance::{
+        Governance, Proposal, GOVERNANCE_SEED, MAX_PROPOSAL_ACCOUNTS, MAX_PROPOSAL_DATA_LEN,
+    },
+    oracle::{PriceOracle, Volatility},
+    registry::{
+        find_mint_list_address, find_pool_address, find_pool_marker_address, sorted_mints,
+        MintList, MintListMode, PoolMarker, MINT_LIST_SEED, POOL_MARKER_SEED, POOL_SEED,
     },
-    amm_stats::{AmmStatus, ProgramState, SwapV1, SwapVersion},
 };
 use std::str::FromStr;
 use num_traits::FromPrimitive;
@@ -22,6 +48,7 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     decode_error::DecodeError,
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     msg,
     program::invoke_signed,
     program::invoke,
@@ -30,10 +57,16 @@ use solana_program::{
     program_pack::Pack,
     pubkey::Pubkey,
     system_instruction,
+    clock::{Clock, UnixTimestamp},
     sysvar::{rent::Rent, Sysvar},
 
 };
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::num::NonZeroU64;
+use serum_dex::{
+    instruction::SelfTradeBehavior,
+    matching::{OrderType, Side},
+};
 
 /// address to change the program state at first
 pub const INITIAL_STATE_OWNER: &str = "DjXkZxNWUoGsL87rbWRFVPmoxN1FKXUWpinUyN921PwQ";
@@ -47,6 +80,73 @@ pub const WSOL_MINT_ADDRESS:&str = "So11111111111111111111111111111111111111112"
 pub const LP_MINT_DECIMALS:u8 = 8;
 /// 0.001 in actual amount
 pub const MIN_LP_SUPPLY:u128 = 100000;
+/// Seed of the owner of the pool token account locking the minimum supply,
+/// followed by the swap address. The program never signs for it.
+pub const LP_LOCK_SEED: &str = "AmmLpLock";
+/// Accounts of a pool and of its Serum market, in the order of the
+/// orderbook instructions
+struct PoolMarketInfos<'a, 'b> {
+    swap_info: &'b AccountInfo<'a>,
+    authority_info: &'b AccountInfo<'a>,
+    token_a_info: &'b AccountInfo<'a>,
+    token_b_info: &'b AccountInfo<'a>,
+    dex_program_info: &'b AccountInfo<'a>,
+    market_info: &'b AccountInfo<'a>,
+    open_orders_info: &'b AccountInfo<'a>,
+    request_queue_info: &'b AccountInfo<'a>,
+    event_queue_info: &'b AccountInfo<'a>,
+    bids_info: &'b AccountInfo<'a>,
+    asks_info: &'b AccountInfo<'a>,
+    coin_vault_info: &'b AccountInfo<'a>,
+    pc_vault_info: &'b AccountInfo<'a>,
+    vault_signer_info: &'b AccountInfo<'a>,
+    token_program_info: &'b AccountInfo<'a>,
+    rent_info: &'b AccountInfo<'a>,
+}
+
+impl<'a, 'b> PoolMarketInfos<'a, 'b> {
+    fn next(account_info_iter: &mut std::slice::Iter<'b, AccountInfo<'a>>) -> Result<Self, ProgramError> {
+        Ok(Self {
+            swap_info: next_account_info(account_info_iter)?,
+            authority_info: next_account_info(account_info_iter)?,
+            token_a_info: next_account_info(account_info_iter)?,
+            token_b_info: next_account_info(account_info_iter)?,
+            dex_program_info: next_account_info(account_info_iter)?,
+            market_info: next_account_info(account_info_iter)?,
+            open_orders_info: next_account_info(account_info_iter)?,
+            request_queue_info: next_account_info(account_info_iter)?,
+            event_queue_info: next_account_info(account_info_iter)?,
+            bids_info: next_account_info(account_info_iter)?,
+            asks_info: next_account_info(account_info_iter)?,
+            coin_vault_info: next_account_info(account_info_iter)?,
+            pc_vault_info: next_account_info(account_info_iter)?,
+            vault_signer_info: next_account_info(account_info_iter)?,
+            token_program_info: next_account_info(account_info_iter)?,
+            rent_info: next_account_info(account_info_iter)?,
+        })
+    }
+
+    fn to_vec(&self) -> Vec<AccountInfo<'a>> {
+        vec![
+            self.authority_info.clone(),
+            self.token_a_info.clone(),
+            self.token_b_info.clone(),
+            self.dex_program_info.clone(),
+            self.market_info.clone(),
+            self.open_orders_info.clone(),
+            self.request_queue_info.clone(),
+            self.event_queue_info.clone(),
+            self.bids_info.clone(),
+            self.asks_info.clone(),
+            self.coin_vault_info.clone(),
+            self.pc_vault_info.clone(),
+            self.vault_signer_info.clone(),
+            self.token_program_info.clone(),
+            self.rent_info.clone(),
+        ]
+    }
+}
+
 /// Program state handler.
 pub struct Processor {}
 impl Processor {
@@ -137,6 +237,45 @@ impl Processor {
         }
     }
 
+    /// Saves the program state, state accounts of an older layout are too
+    /// small and must be migrated first
+    fn pack_state(state: &ProgramStateV2, state_info: &AccountInfo) -> ProgramResult {
+        if state_info.data_len() < ProgramStateV2::LEN {
+            return Err(AmmError::AccountNotMigrated.into());
+        }
+        state.pack_into_slice(&mut state_info.data.borrow_mut());
+        Ok(())
+    }
+
+    /// Grows an account to the given size, the payer tops up its lamports
+    /// to keep it rent exempt
+    fn realloc_account<'a>(
+        account_info: &AccountInfo<'a>,
+        payer_info: &AccountInfo<'a>,
+        system_program_info: &AccountInfo<'a>,
+        rent_sysvar_info: &AccountInfo<'a>,
+        size: usize,
+    ) -> ProgramResult {
+        if account_info.data_len() >= size {
+            return Ok(());
+        }
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        let required_lamports = rent
+            .minimum_balance(size)
+            .saturating_sub(account_info.lamports());
+        if required_lamports > 0 {
+            invoke(
+                &system_instruction::transfer(payer_info.key, account_info.key, required_lamports),
+                &[
+                    payer_info.clone(),
+                    account_info.clone(),
+                    system_program_info.clone(),
+                ],
+            )?;
+        }
+        account_info.realloc(size, true)
+    }
+
     /// Calculates the authority id by generating a program address.
     pub fn authority_id(
         program_id: &Pubkey,
@@ -147,6 +286,23 @@ impl Processor {
             .or(Err(AmmError::InvalidProgramAddress))
     }
 
+    /// Owner of the pool token account locking the minimum supply of a swap
+    pub fn lp_lock_id(program_id: &Pubkey, swap: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[LP_LOCK_SEED.as_bytes(), swap.as_ref()], program_id).0
+    }
+
+    /// Checks that burning pool tokens leaves at least the locked minimum
+    /// supply
+    fn check_min_supply(pool_mint_supply: u128, burn_amount: u128) -> ProgramResult {
+        let remaining_supply = pool_mint_supply
+            .checked_sub(burn_amount)
+            .ok_or(AmmError::CalculationFailure)?;
+        if remaining_supply < MIN_LP_SUPPLY {
+            return Err(AmmError::MinimumSupplyLocked.into());
+        }
+        Ok(())
+    }
+
     /// Issue a spl_token `Burn` instruction.
     pub fn token_burn<'a>(
         swap: &Pubkey,
@@ -230,6 +386,32 @@ impl Processor {
         )
     }
 
+    /// Issue a spl_token `CloseAccount` instruction.
+    pub fn token_close_account<'a>(
+        swap: &Pubkey,
+        token_program: AccountInfo<'a>,
+        account: AccountInfo<'a>,
+        destination: AccountInfo<'a>,
+        authority: AccountInfo<'a>,
+        nonce: u8,
+    ) -> Result<(), ProgramError> {
+        let swap_bytes = swap.to_bytes();
+        let authority_signature_seeds = [&swap_bytes[..32], &[nonce]];
+        let signers = &[&authority_signature_seeds[..]];
+        let ix = spl_token::instruction::close_account(
+            token_program.key,
+            account.key,
+            destination.key,
+            authority.key,
+            &[],
+        )?;
+        invoke_signed(
+            &ix,
+            &[account, destination, authority, token_program],
+            signers,
+        )
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn check_accounts(
         token_swap: &dyn AmmStatus,
@@ -276,6 +458,141 @@ impl Processor {
         Ok(())
     }
 
+    /// Unpacks a swap and sets the current time on its curve
+    fn unpack_swap(swap_info: &AccountInfo) -> Result<SwapVersion, ProgramError> {
+        let mut token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        token_swap
+            .swap_curve_mut()
+            .calculator
+            .set_timestamp(Clock::get()?.unix_timestamp);
+        Ok(token_swap)
+    }
+
+    /// Fees of a swap at the given time, the return fee rises with the recent
+    /// volatility in dynamic fee mode
+    fn dynamic_fees(token_swap: &SwapVersion, timestamp: UnixTimestamp) -> Result<Fees, ProgramError> {
+        token_swap
+            .fees()
+            .with_volatility(token_swap.volatility().decayed(timestamp))
+            .ok_or_else(|| AmmError::FeeCalculationFailure.into())
+    }
+
+    /// Accumulates the pool prices up to now into its price oracle, the
+    /// reserves must be the ones before the instruction changed them
+    fn update_price_oracle(
+        swap_info: &AccountInfo,
+        mut token_swap: SwapVersion,
+        reserve_a: u64,
+        reserve_b: u64,
+    ) -> ProgramResult {
+        let clock = Clock::get()?;
+        token_swap
+            .price_oracle_mut()
+            .update(clock.slot, clock.unix_timestamp, reserve_a, reserve_b);
+        SwapVersion::pack(token_swap, &mut swap_info.data.borrow_mut())
+    }
+
+    /// Checks that a fee account is a token account of the fee owner for the
+    /// given mint
+    fn check_fee_account(
+        token_swap: &SwapVersion,
+        state: &ProgramStateV2,
+        fee_account_info: &AccountInfo,
+        mint: &Pubkey,
+    ) -> ProgramResult {
+        let fee_account =
+            Self::unpack_token_account(fee_account_info, token_swap.token_program_id())?;
+        if fee_account.mint != *mint || fee_account.owner != *state.fee_owner() {
+            return Err(AmmError::IncorrectFeeAccount.into());
+        }
+        Ok(())
+    }
+
+    /// Checks the pool fee account and calculates the owner withdraw fee in
+    /// pool tokens, withdrawals from the fee account itself are not charged
+    fn owner_withdraw_fee(
+        token_swap: &SwapVersion,
+        state: &ProgramStateV2,
+        pool_mint_info: &AccountInfo,
+        source_info: &AccountInfo,
+        pool_fee_account_info: &AccountInfo,
+        pool_token_amount: u128,
+    ) -> Result<u128, ProgramError> {
+        if *pool_fee_account_info.key == *source_info.key {
+            return Ok(0);
+        }
+        Self::check_fee_account(token_swap, state, pool_fee_account_info, pool_mint_info.key)?;
+        Ok(token_swap
+            .fees()
+            .owner_withdraw_fee(pool_token_amount)
+            .ok_or(AmmError::FeeCalculationFailure)?)
+    }
+
+    /// Square root of the reserves product, the constant product invariant
+    fn root_k(reserve_a: u64, reserve_b: u64) -> Result<u128, ProgramError> {
+        Ok(normalized_value(to_u128(reserve_a)?, to_u128(reserve_b)?)
+            .and_then(|root_k| root_k.to_imprecise())
+            .ok_or(AmmError::CalculationFailure)?)
+    }
+
+    /// Mints the owner share of the trading fees accrued since the last
+    /// liquidity change to the pool fee account, when the swap pays its
+    /// owner fee as pool tokens, and returns the new pool supply
+    #[allow(clippy::too_many_arguments)]
+    fn mint_protocol_fee<'a>(
+        swap_info: &AccountInfo<'a>,
+        token_swap: &SwapVersion,
+        state: &ProgramStateV2,
+        authority_info: &AccountInfo<'a>,
+        pool_mint_info: &AccountInfo<'a>,
+        pool_fee_account_info: &AccountInfo<'a>,
+        token_program_info: &AccountInfo<'a>,
+        reserve_a: u64,
+        reserve_b: u64,
+        pool_supply: u128,
+    ) -> Result<u128, ProgramError> {
+        if !token_swap.lp_protocol_fee() {
+            return Ok(pool_supply);
+        }
+        let protocol_fee = token_swap
+            .fees()
+            .protocol_fee_pool_tokens(
+                pool_supply,
+                Self::root_k(reserve_a, reserve_b)?,
+                token_swap.root_k_last(),
+            )
+            .ok_or(AmmError::FeeCalculationFailure)?;
+        if protocol_fee == 0 {
+            return Ok(pool_supply);
+        }
+        Self::check_fee_account(token_swap, state, pool_fee_account_info, pool_mint_info.key)?;
+        Self::token_mint_to(
+            swap_info.key,
+            token_program_info.clone(),
+            pool_mint_info.clone(),
+            pool_fee_account_info.clone(),
+            authority_info.clone(),
+            token_swap.nonce(),
+            to_u64(protocol_fee)?,
+        )?;
+        Ok(pool_supply
+            .checked_add(protocol_fee)
+            .ok_or(AmmError::CalculationFailure)?)
+    }
+
+    /// Records the reserves after a liquidity change, when the swap pays its
+    /// owner fee as pool tokens
+    fn update_root_k_last(
+        token_swap: &mut SwapVersion,
+        reserve_a: u64,
+        reserve_b: u64,
+    ) -> ProgramResult {
+        if token_swap.lp_protocol_fee() {
+            token_swap.set_root_k_last(Self::root_k(reserve_a, reserve_b)?);
+        }
+        Ok(())
+    }
+
     /// Processes an [Initialize](enum.Instruction.html).
     pub fn process_update_state(
         program_id: &Pubkey,
@@ -290,7 +607,6 @@ impl Processor {
         let state_info = next_account_info(account_info_iter)?;
 
         let cur_state_owner_info = next_account_info(account_info_iter)?;
-        let new_state_owner_info = next_account_info(account_info_iter)?;
 
         let fee_owner_info = next_account_info(account_info_iter)?;
 
@@ -311,7 +627,7 @@ impl Processor {
         let (_pda_key, bump) = Pubkey::find_program_address(&seeds, program_id);
         
         if state_info.data_is_empty(){
-            let size = ProgramState::get_packed_len();
+            let size = ProgramStateV2::get_packed_len();
 
             Self::create_or_allocate_account_raw(
                 *program_id,
@@ -328,7 +644,8 @@ impl Processor {
             )?;
         }
 
-        let mut program_state = ProgramState::unpack_from_slice(&state_info.data.borrow())?;
+        let mut program_state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        let was_initialized = program_state.is_initialized;
 
         if program_state.is_initialized == false
         {
@@ -338,6 +655,11 @@ impl Processor {
                 fixed_fee_numerator: SWAP_CONSTRAINTS.fees.fixed_fee_numerator,
                 return_fee_numerator: SWAP_CONSTRAINTS.fees.return_fee_numerator,
                 fee_denominator: SWAP_CONSTRAINTS.fees.fee_denominator,
+                host_fee_numerator: SWAP_CONSTRAINTS.fees.host_fee_numerator,
+                host_fee_denominator: SWAP_CONSTRAINTS.fees.host_fee_denominator,
+                owner_withdraw_fee_numerator: SWAP_CONSTRAINTS.fees.owner_withdraw_fee_numerator,
+                // dynamic fees are opt-in, the constraint only bounds them
+                max_return_fee_numerator: 0,
             };
             program_state.fee_owner = Pubkey::from_str(SWAP_CONSTRAINTS.owner_key).unwrap();
             program_state.initial_supply = INITIAL_SWAP_POOL_AMOUNT;
@@ -347,30 +669,53 @@ impl Processor {
                         ConstantProductCurve{}
                     )
                 };
-            program_state.pack_into_slice(&mut &mut state_info.data.borrow_mut()[..]);
+            Self::pack_state(&program_state, state_info)?;
         }
         
         if program_state.state_owner != *cur_state_owner_info.key
         {
             return Err(AmmError::InvalidStateOwner.into());
         }
+        if was_initialized {
+            return Err(AmmError::StateUpdateNotQueued.into());
+        }
 
-        SWAP_CONSTRAINTS.validate_curve(&swap_curve)?;
-        SWAP_CONSTRAINTS.validate_fees(&fees)?;
-
-        fees.validate()?;
-        swap_curve.calculator.validate()?;
+        Self::validate_state_parameters(initial_supply, &fees, &swap_curve)?;
 
         //Save the program state
-        let obj = ProgramState{
+        let obj = ProgramStateV2 {
             is_initialized:true,
             initial_supply: initial_supply,
-            state_owner: *new_state_owner_info.key,
+            state_owner: program_state.state_owner,
             fee_owner: *fee_owner_info.key,
             fees,
             swap_curve,
+            is_paused: program_state.is_paused,
+            pending_state_owner: program_state.pending_state_owner,
+            pending_update: None,
+            permissionless_pools: program_state.permissionless_pools,
+            mint_list_mode: program_state.mint_list_mode,
+            pool_creation_fee: program_state.pool_creation_fee,
         };
-        obj.pack_into_slice(&mut &mut state_info.data.borrow_mut()[..]);
+        Self::pack_state(&obj, state_info)?;
+        Ok(())
+    }
+
+    /// Checks the default parameters of the program state against the swap
+    /// constraints, and that new pools mint more than the locked minimum
+    fn validate_state_parameters(
+        initial_supply: u64,
+        fees: &Fees,
+        swap_curve: &SwapCurve,
+    ) -> ProgramResult {
+        if to_u128(initial_supply)? <= MIN_LP_SUPPLY {
+            return Err(AmmError::InitialSupplyTooLow.into());
+        }
+        SWAP_CONSTRAINTS.validate_curve(swap_curve)?;
+        SWAP_CONSTRAINTS.validate_fees(fees)?;
+
+        fees.validate()?;
+        swap_curve.calculator.validate()?;
         Ok(())
     }
 
@@ -378,6 +723,9 @@ impl Processor {
     pub fn process_initialize(
         program_id: &Pubkey,
         nonce: u8,
+        fees: Option<Fees>,
+        swap_curve: Option<SwapCurve>,
+        derived_address: bool,
         accounts: &[AccountInfo]
     ) -> ProgramResult {
         
@@ -396,7 +744,13 @@ impl Processor {
 
         let token_program_info = next_account_info(account_info_iter)?;
         let dex_program_info = next_account_info(account_info_iter)?;
-        let cur_state_owner_info = next_account_info(account_info_iter)?;
+        let creator_info = next_account_info(account_info_iter)?;
+        let pool_marker_info = next_account_info(account_info_iter)?;
+        let system_info = next_account_info(account_info_iter)?;
+        let rent_info = next_account_info(account_info_iter)?;
+        let mint_list_info = next_account_info(account_info_iter)?;
+        let fee_owner_info = next_account_info(account_info_iter)?;
+        let lock_info = next_account_info(account_info_iter)?;
 
         //validate account info
         let token_program_id = *token_program_info.key;
@@ -410,15 +764,16 @@ impl Processor {
 
         Self::check_state_account(program_id, state_info.key)?;
         
-        let state = ProgramState::unpack_from_slice(&state_info.data.borrow())?;
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
         if state.is_initialized() == false
         {
             return Err(AmmError::NotInitializedState.into());
         }
-        if !cur_state_owner_info.is_signer{
+        if !creator_info.is_signer{
             return Err(AmmError::InvalidSigner.into());
         }
-        if *cur_state_owner_info.key != state.state_owner {
+        let is_state_owner = *creator_info.key == state.state_owner;
+        if !is_state_owner && !state.permissionless_pools() {
             return Err(AmmError::InvalidOwner.into());
         }
 
@@ -436,6 +791,15 @@ impl Processor {
         if *authority_info.key == destination.owner {
             return Err(AmmError::InvalidOutputOwner.into());
         }
+        let lock = Self::unpack_token_account(lock_info, &token_program_id)?;
+        if lock.mint != *pool_mint_info.key
+            || lock.owner != Self::lp_lock_id(program_id, swap_info.key)
+            || lock.delegate.is_some()
+            || lock.close_authority.is_some()
+            || lock_info.key == destination_info.key
+        {
+            return Err(AmmError::IncorrectLockAccount.into());
+        }
         
         if COption::Some(*authority_info.key) != pool_mint.mint_authority {
             return Err(AmmError::InvalidOwner.into());
@@ -445,7 +809,44 @@ impl Processor {
             return Err(AmmError::RepeatedMint.into());
         }
 
-        let swap_curve = state.swap_curve();
+        //pools of others than the state owner are restricted by the mint list
+        if !is_state_owner && state.mint_list_mode() != MintListMode::Disabled {
+            if *mint_list_info.key != find_mint_list_address(program_id).0 {
+                return Err(AmmError::InvalidMintListAddress.into());
+            }
+            let mint_list = if mint_list_info.data_is_empty() {
+                MintList::default()
+            } else {
+                MintList::unpack_unchecked(&mint_list_info.data.borrow())?
+            };
+            if !mint_list.allows(state.mint_list_mode(), &token_a.mint)
+                || !mint_list.allows(state.mint_list_mode(), &token_b.mint)
+            {
+                return Err(AmmError::MintNotAllowed.into());
+            }
+        }
+
+        let initial_amount = to_u128(state.initial_supply())?
+            .checked_sub(MIN_LP_SUPPLY)
+            .filter(|amount| *amount > 0)
+            .ok_or(AmmError::InitialSupplyTooLow)?;
+        let pool_creation_fee = state.pool_creation_fee();
+        let fee_owner = state.fee_owner;
+
+        //use the program state defaults if the pool doesn't set its own
+        let (fees, swap_curve) = match (fees, swap_curve) {
+            (Some(fees), Some(swap_curve)) => {
+                SWAP_CONSTRAINTS.validate_curve(&swap_curve)?;
+                SWAP_CONSTRAINTS.validate_fees(&fees)?;
+                state.validate_pool_fees(&fees)?;
+
+                fees.validate()?;
+                swap_curve.calculator.validate()?;
+                (fees, swap_curve)
+            }
+            (None, None) => (state.fees, state.swap_curve),
+            _ => return Err(AmmError::InvalidInstruction.into()),
+        };
 
         swap_curve.calculator.validate_supply(token_a.amount, token_b.amount)?;
 
@@ -480,9 +881,97 @@ impl Processor {
             return Err(AmmError::IncorrectMarketOwnerAccount.into());
         }
 
-        let initial_amount = state.initial_supply();
+        //the derived address makes the pool of a pair and curve type findable
+        if derived_address {
+            let (pool_key, bump) =
+                find_pool_address(program_id, &token_a.mint, &token_b.mint, swap_curve.curve_type);
+            if pool_key != *swap_info.key {
+                return Err(AmmError::InvalidProgramAddress.into());
+            }
+            if swap_info.data_is_empty() {
+                let (first_mint, second_mint) = sorted_mints(&token_a.mint, &token_b.mint);
+                Self::create_or_allocate_account_raw(
+                    *program_id,
+                    swap_info,
+                    rent_info,
+                    system_info,
+                    creator_info,
+                    SwapVersion::LATEST_LEN,
+                    &[
+                        POOL_SEED.as_bytes(),
+                        first_mint.as_ref(),
+                        second_mint.as_ref(),
+                        &[swap_curve.curve_type as u8],
+                        &[bump],
+                    ],
+                )?;
+            }
+        }
+
+        //mark the pool, one per pair and curve type
+        let (pool_marker_key, pool_marker_bump) =
+            find_pool_marker_address(program_id, &token_a.mint, &token_b.mint, swap_curve.curve_type);
+        if pool_marker_key != *pool_marker_info.key {
+            return Err(AmmError::InvalidPoolMarkerAddress.into());
+        }
+        if pool_marker_info.data_is_empty() {
+            let (first_mint, second_mint) = sorted_mints(&token_a.mint, &token_b.mint);
+            Self::create_or_allocate_account_raw(
+                *program_id,
+                pool_marker_info,
+                rent_info,
+                system_info,
+                creator_info,
+                PoolMarker::LEN,
+                &[
+                    POOL_MARKER_SEED.as_bytes(),
+                    first_mint.as_ref(),
+                    second_mint.as_ref(),
+                    &[swap_curve.curve_type as u8],
+                    &[pool_marker_bump],
+                ],
+            )?;
+        }
+        if PoolMarker::unpack_unchecked(&pool_marker_info.data.borrow())?.is_initialized {
+            return Err(AmmError::PoolAlreadyExists.into());
+        }
+        PoolMarker {
+            is_initialized: true,
+            pool: *swap_info.key,
+            token_a_mint: token_a.mint,
+            token_b_mint: token_b.mint,
+            curve_type: swap_curve.curve_type,
+        }
+        .pack_into_slice(&mut pool_marker_info.data.borrow_mut());
+
+        if !is_state_owner && pool_creation_fee > 0 {
+            if *fee_owner_info.key != fee_owner {
+                return Err(AmmError::IncorrectFeeAccount.into());
+            }
+            invoke(
+                &system_instruction::transfer(
+                    creator_info.key,
+                    fee_owner_info.key,
+                    pool_creation_fee,
+                ),
+                &[
+                    creator_info.clone(),
+                    fee_owner_info.clone(),
+                    system_info.clone(),
+                ],
+            )?;
+        }
 
-        //Mint Initial supply
+        //Mint Initial supply, the minimum supply is locked for good
+        Self::token_mint_to(
+            swap_info.key,
+            token_program_info.clone(),
+            pool_mint_info.clone(),
+            lock_info.clone(),
+            authority_info.clone(),
+            nonce,
+            to_u64(MIN_LP_SUPPLY)?,
+        )?;
         Self::token_mint_to(
             swap_info.key,
             token_program_info.clone(),
@@ -490,11 +979,12 @@ impl Processor {
             destination_info.clone(),
             authority_info.clone(),
             nonce,
-            initial_amount,
+            to_u64(initial_amount)?,
         )?;
 
         //Save the pool account info
-        let obj = SwapVersion::SwapV1(SwapV1 {
+        let clock = Clock::get()?;
+        let obj = SwapVersion::SwapV2(SwapV2 {
             is_initialized: true,
             nonce,
             amm_id: *amm_id_info.key,
@@ -505,7 +995,15 @@ impl Processor {
             token_b: *token_b_info.key,
             pool_mint: *pool_mint_info.key,
             token_a_mint: token_a.mint,
-            token_b_mint: token_b.mint
+            token_b_mint: token_b.mint,
+            fees,
+            swap_curve,
+            orderbook: Orderbook::default(),
+            is_paused: false,
+            lp_protocol_fee: false,
+            root_k_last: 0,
+            volatility: Volatility::default(),
+            price_oracle: PriceOracle::new(clock.slot, clock.unix_timestamp),
         });
         SwapVersion::pack(obj, &mut swap_info.data.borrow_mut())?;
         Ok(())
@@ -518,7 +1016,151 @@ impl Processor {
         minimum_amount_out: u64,
         accounts: &[AccountInfo],
     ) -> ProgramResult {
+        Self::process_swap_with(
+            program_id,
+            accounts,
+            |swap_curve, fees, swap_source_amount, swap_destination_amount, trade_direction| {
+                let result = swap_curve
+                    .swap(
+                        to_u128(amount_in)?,
+                        swap_source_amount,
+                        swap_destination_amount,
+                        trade_direction,
+                        fees,
+                    )
+                    .ok_or(AmmError::ZeroTradingTokens)?;
+                if result.destination_amount_swapped < to_u128(minimum_amount_out)? {
+                    return Err(AmmError::ExceededSlippage.into());
+                }
+                Ok(result)
+            },
+        )
+    }
+
+    /// Processes an [SwapExactAmountOut](enum.Instruction.html).
+    pub fn process_swap_exact_amount_out(
+        program_id: &Pubkey,
+        amount_out: u64,
+        maximum_amount_in: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        Self::process_swap_with(
+            program_id,
+            accounts,
+            |swap_curve, fees, swap_source_amount, swap_destination_amount, trade_direction| {
+                let result = swap_curve
+                    .swap_exact_out(
+                        to_u128(amount_out)?,
+                        swap_source_amount,
+                        swap_destination_amount,
+                        trade_direction,
+                        fees,
+                    )
+                    .ok_or(AmmError::ZeroTradingTokens)?;
+                if result.source_amount_swapped > to_u128(maximum_amount_in)? {
+                    return Err(AmmError::ExceededSlippage.into());
+                }
+                Ok(result)
+            },
+        )
+    }
+
+    /// Processes an [RouteSwap](enum.Instruction.html).
+    pub fn process_route_swap(
+        program_id: &Pubkey,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        //load account info
+        let account_info_iter = &mut accounts.iter();
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let fixed_fee_wallet_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+
+        let hops = account_info_iter.as_slice().chunks_exact(ROUTE_SWAP_HOP_ACCOUNTS);
+        if hops.len() == 0 || !hops.remainder().is_empty() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        Self::check_state_account(program_id, state_info.key)?;
         
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized()
+        {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if state.is_paused() {
+            return Err(AmmError::Paused.into());
+        }
+
+        //the output of every pool is swapped in the next one, slippage is
+        //only checked at the end of the route
+        let mut amount = to_u128(amount_in)?;
+        let mut previous_destination_info: Option<&AccountInfo> = None;
+        for hop in hops {
+            let swap_info = &hop[0];
+            let authority_info = &hop[1];
+            let source_info = &hop[2];
+            let swap_source_info = &hop[3];
+            let swap_destination_info = &hop[4];
+            let destination_info = &hop[5];
+            let pool_mint_info = &hop[6];
+            let fixed_fee_account_info = &hop[7];
+
+            if let Some(previous_destination_info) = previous_destination_info {
+                if previous_destination_info.key != source_info.key {
+                    return Err(AmmError::InvalidInput.into());
+                }
+            }
+
+            let result = Self::swap_pool(
+                program_id,
+                state.fee_owner(),
+                swap_info,
+                authority_info,
+                user_transfer_authority_info,
+                source_info,
+                swap_source_info,
+                swap_destination_info,
+                destination_info,
+                pool_mint_info,
+                fixed_fee_account_info,
+                fixed_fee_wallet_info,
+                token_program_info,
+                system_program_info,
+                None,
+                |swap_curve, fees, swap_source_amount, swap_destination_amount, trade_direction| {
+                    Ok(swap_curve
+                        .swap(
+                            amount,
+                            swap_source_amount,
+                            swap_destination_amount,
+                            trade_direction,
+                            fees,
+                        )
+                        .ok_or(AmmError::ZeroTradingTokens)?)
+                },
+            )?;
+            amount = result.destination_amount_swapped;
+            previous_destination_info = Some(destination_info);
+        }
+
+        if amount < to_u128(minimum_amount_out)? {
+            return Err(AmmError::ExceededSlippage.into());
+        }
+        Ok(())
+    }
+
+    /// Processes a [SplitSwap](enum.Instruction.html).
+    pub fn process_split_swap(
+        program_id: &Pubkey,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
         //load account info
         let account_info_iter = &mut accounts.iter();
         let swap_info = next_account_info(account_info_iter)?;
@@ -534,47 +1176,340 @@ impl Processor {
         let fixed_fee_wallet_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
         let system_program_info = next_account_info(account_info_iter)?;
-
-        //validate account info
-        if swap_info.owner != program_id {
-            return Err(ProgramError::IncorrectProgramId);
-        }
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
-
-        if *authority_info.key != Self::authority_id(program_id, swap_info.key, token_swap.nonce())?
-        {
-            return Err(AmmError::InvalidProgramAddress.into());
-        }
+        let dex_program_info = next_account_info(account_info_iter)?;
+        let market_info = next_account_info(account_info_iter)?;
+        let open_orders_info = next_account_info(account_info_iter)?;
+        let request_queue_info = next_account_info(account_info_iter)?;
+        let event_queue_info = next_account_info(account_info_iter)?;
+        let bids_info = next_account_info(account_info_iter)?;
+        let asks_info = next_account_info(account_info_iter)?;
+        let coin_vault_info = next_account_info(account_info_iter)?;
+        let pc_vault_info = next_account_info(account_info_iter)?;
+        let vault_signer_info = next_account_info(account_info_iter)?;
+        let rent_info = next_account_info(account_info_iter)?;
+        let host_fee_account_info = next_account_info(account_info_iter).ok();
 
         Self::check_state_account(program_id, state_info.key)?;
-        
-        let state = ProgramState::unpack_from_slice(&state_info.data.borrow())?;
-        if state.is_initialized() == false
-        {
-            return Err(AmmError::NotInitializedState.into());
-        }
 
-        if !(*swap_source_info.key == *token_swap.token_a_account()
-            || *swap_source_info.key == *token_swap.token_b_account())
-        {
-            return Err(AmmError::IncorrectSwapAccount.into());
-        }
-        if !(*swap_destination_info.key == *token_swap.token_a_account()
-            || *swap_destination_info.key == *token_swap.token_b_account())
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized()
         {
-            return Err(AmmError::IncorrectSwapAccount.into());
+            return Err(AmmError::NotInitializedState.into());
         }
-        if *swap_source_info.key == *swap_destination_info.key {
-            return Err(AmmError::InvalidInput.into());
+        if state.is_paused() {
+            return Err(AmmError::Paused.into());
         }
-        if swap_source_info.key == source_info.key {
-            return Err(AmmError::InvalidInput.into());
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
-        if swap_destination_info.key == destination_info.key {
-            return Err(AmmError::InvalidInput.into());
+        let token_swap = Self::unpack_swap(swap_info)?;
+        if token_swap.is_paused() {
+            return Err(AmmError::Paused.into());
         }
-        if *pool_mint_info.key != *token_swap.pool_mint() {
-            return Err(AmmError::IncorrectPoolMint.into());
+        let (market, coin_is_a) = Self::unpack_market(
+            &token_swap,
+            dex_program_info,
+            market_info,
+            request_queue_info,
+            event_queue_info,
+            bids_info,
+            asks_info,
+            coin_vault_info,
+            pc_vault_info,
+        )?;
+        let trade_direction = if *swap_source_info.key == *token_swap.token_a_account() {
+            TradeDirection::AtoB
+        } else if *swap_source_info.key == *token_swap.token_b_account() {
+            TradeDirection::BtoA
+        } else {
+            return Err(AmmError::IncorrectSwapAccount.into());
+        };
+
+        //selling the coin takes the bids, buying it takes the asks
+        let source_is_coin = (trade_direction == TradeDirection::AtoB) == coin_is_a;
+        let (taker_side, levels) = if source_is_coin {
+            (OrderSide::Ask, book_levels(&bids_info.data.borrow(), OrderSide::Bid)?)
+        } else {
+            (OrderSide::Bid, book_levels(&asks_info.data.borrow(), OrderSide::Ask)?)
+        };
+
+        let swap_source_amount = to_u128(
+            Self::unpack_token_account(swap_source_info, token_swap.token_program_id())?.amount,
+        )?;
+        let swap_destination_amount = to_u128(
+            Self::unpack_token_account(swap_destination_info, token_swap.token_program_id())?.amount,
+        )?;
+        let curve_amount = split_amount(
+            amount_in,
+            |amount| {
+                token_swap
+                    .swap_curve()
+                    .swap(
+                        to_u128(amount).ok()?,
+                        swap_source_amount,
+                        swap_destination_amount,
+                        trade_direction,
+                        token_swap.fees(),
+                    )
+                    .and_then(|result| to_u64(result.destination_amount_swapped).ok())
+            },
+            |amount| market.fill(&levels, taker_side, amount).map(|fill| fill.amount_out),
+        );
+        //the amount left over by the lot size goes to the curve
+        let fill = market
+            .fill(&levels, taker_side, amount_in - curve_amount)
+            .ok_or(AmmError::CalculationFailure)?;
+        let curve_amount = amount_in - fill.amount_in;
+
+        let mut curve_amount_out = 0;
+        if curve_amount > 0 {
+            curve_amount_out = Self::swap_pool(
+                program_id,
+                state.fee_owner(),
+                swap_info,
+                authority_info,
+                user_transfer_authority_info,
+                source_info,
+                swap_source_info,
+                swap_destination_info,
+                destination_info,
+                pool_mint_info,
+                fixed_fee_account_info,
+                fixed_fee_wallet_info,
+                token_program_info,
+                system_program_info,
+                host_fee_account_info,
+                |swap_curve, fees, swap_source_amount, swap_destination_amount, trade_direction| {
+                    Ok(swap_curve
+                        .swap(
+                            to_u128(curve_amount)?,
+                            swap_source_amount,
+                            swap_destination_amount,
+                            trade_direction,
+                            fees,
+                        )
+                        .ok_or(AmmError::ZeroTradingTokens)?)
+                },
+            )?
+            .destination_amount_swapped;
+        }
+
+        let mut market_amount_out = 0;
+        if fill.coin_lots > 0 {
+            let (coin_wallet_info, pc_wallet_info) = if source_is_coin {
+                (source_info, destination_info)
+            } else {
+                (destination_info, source_info)
+            };
+            let (side, max_native_pc_qty) = match taker_side {
+                OrderSide::Ask => (Side::Ask, u64::MAX),
+                OrderSide::Bid => (Side::Bid, fill.amount_in),
+            };
+            let market_account_infos = [
+                market_info.clone(),
+                open_orders_info.clone(),
+                request_queue_info.clone(),
+                event_queue_info.clone(),
+                bids_info.clone(),
+                asks_info.clone(),
+                source_info.clone(),
+                destination_info.clone(),
+                user_transfer_authority_info.clone(),
+                coin_vault_info.clone(),
+                pc_vault_info.clone(),
+                vault_signer_info.clone(),
+                token_program_info.clone(),
+                rent_info.clone(),
+                dex_program_info.clone(),
+            ];
+            let ix = serum_dex::instruction::new_order(
+                market_info.key,
+                open_orders_info.key,
+                request_queue_info.key,
+                event_queue_info.key,
+                bids_info.key,
+                asks_info.key,
+                source_info.key,
+                user_transfer_authority_info.key,
+                coin_vault_info.key,
+                pc_vault_info.key,
+                token_program_info.key,
+                rent_info.key,
+                None,
+                dex_program_info.key,
+                side,
+                NonZeroU64::new(fill.limit_price).ok_or(AmmError::CalculationFailure)?,
+                NonZeroU64::new(fill.coin_lots).ok_or(AmmError::CalculationFailure)?,
+                OrderType::ImmediateOrCancel,
+                0,
+                SelfTradeBehavior::DecrementTake,
+                u16::MAX,
+                NonZeroU64::new(max_native_pc_qty).ok_or(AmmError::CalculationFailure)?,
+            )
+            .map_err(|_| AmmError::IncorrectMarketAccount)?;
+            //the fill is credited to the open orders, tokens it held before
+            //the order aren't part of this swap
+            let open_orders_before = OpenOrdersInfo::unpack(&open_orders_info.data.borrow())?;
+            invoke(&ix, &market_account_infos)?;
+            let open_orders_after = OpenOrdersInfo::unpack(&open_orders_info.data.borrow())?;
+            market_amount_out = if source_is_coin {
+                open_orders_after
+                    .native_pc_free
+                    .checked_sub(open_orders_before.native_pc_free)
+            } else {
+                open_orders_after
+                    .native_coin_free
+                    .checked_sub(open_orders_before.native_coin_free)
+            }
+            .ok_or(AmmError::CalculationFailure)?;
+            let ix = serum_dex::instruction::settle_funds(
+                dex_program_info.key,
+                market_info.key,
+                token_program_info.key,
+                open_orders_info.key,
+                user_transfer_authority_info.key,
+                coin_vault_info.key,
+                coin_wallet_info.key,
+                pc_vault_info.key,
+                pc_wallet_info.key,
+                None,
+                vault_signer_info.key,
+            )
+            .map_err(|_| AmmError::IncorrectMarketAccount)?;
+            invoke(&ix, &market_account_infos)?;
+        }
+
+        let curve_amount_out = to_u64(curve_amount_out)?;
+        let amount_out = curve_amount_out
+            .checked_add(market_amount_out)
+            .ok_or(AmmError::CalculationFailure)?;
+        msg!("Pool: {} in, {} out", curve_amount, curve_amount_out);
+        msg!("Market: {} in, {} out", fill.amount_in, market_amount_out);
+        if amount_out < minimum_amount_out {
+            return Err(AmmError::ExceededSlippage.into());
+        }
+        Ok(())
+    }
+
+    /// Loads the swap accounts shared by both swap instructions and swaps
+    /// the tokens with the result of `quote`
+    fn process_swap_with<F>(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        quote: F,
+    ) -> ProgramResult
+    where
+        F: FnOnce(&SwapCurve, &Fees, u128, u128, TradeDirection) -> Result<SwapResult, ProgramError>,
+    {
+        //load account info
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let swap_source_info = next_account_info(account_info_iter)?;
+        let swap_destination_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let fixed_fee_account_info = next_account_info(account_info_iter)?;
+        let fixed_fee_wallet_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let host_fee_account_info = next_account_info(account_info_iter).ok();
+
+        Self::check_state_account(program_id, state_info.key)?;
+        
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized()
+        {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if state.is_paused() {
+            return Err(AmmError::Paused.into());
+        }
+
+        Self::swap_pool(
+            program_id,
+            state.fee_owner(),
+            swap_info,
+            authority_info,
+            user_transfer_authority_info,
+            source_info,
+            swap_source_info,
+            swap_destination_info,
+            destination_info,
+            pool_mint_info,
+            fixed_fee_account_info,
+            fixed_fee_wallet_info,
+            token_program_info,
+            system_program_info,
+            host_fee_account_info,
+            quote,
+        )?;
+        Ok(())
+    }
+
+    /// Validates the accounts of one pool, quotes the swap against its
+    /// current reserves and moves the tokens
+    #[allow(clippy::too_many_arguments)]
+    fn swap_pool<'a, F>(
+        program_id: &Pubkey,
+        fee_owner: &Pubkey,
+        swap_info: &AccountInfo<'a>,
+        authority_info: &AccountInfo<'a>,
+        user_transfer_authority_info: &AccountInfo<'a>,
+        source_info: &AccountInfo<'a>,
+        swap_source_info: &AccountInfo<'a>,
+        swap_destination_info: &AccountInfo<'a>,
+        destination_info: &AccountInfo<'a>,
+        pool_mint_info: &AccountInfo<'a>,
+        fixed_fee_account_info: &AccountInfo<'a>,
+        fixed_fee_wallet_info: &AccountInfo<'a>,
+        token_program_info: &AccountInfo<'a>,
+        system_program_info: &AccountInfo<'a>,
+        host_fee_account_info: Option<&AccountInfo<'a>>,
+        quote: F,
+    ) -> Result<SwapResult, ProgramError>
+    where
+        F: FnOnce(&SwapCurve, &Fees, u128, u128, TradeDirection) -> Result<SwapResult, ProgramError>,
+    {
+        //validate account info
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut token_swap = Self::unpack_swap(swap_info)?;
+        if token_swap.is_paused() {
+            return Err(AmmError::Paused.into());
+        }
+
+        if *authority_info.key != Self::authority_id(program_id, swap_info.key, token_swap.nonce())?
+        {
+            return Err(AmmError::InvalidProgramAddress.into());
+        }
+
+        if !(*swap_source_info.key == *token_swap.token_a_account()
+            || *swap_source_info.key == *token_swap.token_b_account())
+        {
+            return Err(AmmError::IncorrectSwapAccount.into());
+        }
+        if !(*swap_destination_info.key == *token_swap.token_a_account()
+            || *swap_destination_info.key == *token_swap.token_b_account())
+        {
+            return Err(AmmError::IncorrectSwapAccount.into());
+        }
+        if *swap_source_info.key == *swap_destination_info.key {
+            return Err(AmmError::InvalidInput.into());
+        }
+        if swap_source_info.key == source_info.key {
+            return Err(AmmError::InvalidInput.into());
+        }
+        if swap_destination_info.key == destination_info.key {
+            return Err(AmmError::InvalidInput.into());
+        }
+        if *pool_mint_info.key != *token_swap.pool_mint() {
+            return Err(AmmError::IncorrectPoolMint.into());
         }
         if *token_program_info.key != *token_swap.token_program_id() {
             return Err(AmmError::IncorrectTokenProgramId.into());
@@ -592,7 +1527,7 @@ impl Processor {
 
         let wsol_mint =  Pubkey::from_str(WSOL_MINT_ADDRESS).unwrap();
 
-        if *state.fee_owner() != *fixed_fee_wallet_info.key
+        if *fee_owner != *fixed_fee_wallet_info.key
         {
             return Err(AmmError::InvalidOwner.into());
         }
@@ -601,36 +1536,48 @@ impl Processor {
         if wsol_mint != source_account.mint
         {
             let fee_account = Self::unpack_token_account(fixed_fee_account_info, token_swap.token_program_id())?;
-            if *state.fee_owner() != fee_account.owner || source_account.mint != fee_account.mint
+            if *fee_owner != fee_account.owner || source_account.mint != fee_account.mint
             {
                 return Err(AmmError::IncorrectFeeAccount.into());
             }
         }
 
-        let result = state.swap_curve()
-            .swap(
-                to_u128(amount_in)?,
-                to_u128(source_account.amount)?,
-                to_u128(dest_account.amount)?,
-                trade_direction,
-                state.fees(),
-            )
-            .ok_or(AmmError::ZeroTradingTokens)?;
+        let clock = Clock::get()?;
+        let fees = Self::dynamic_fees(&token_swap, clock.unix_timestamp)?;
+        let result = quote(
+            token_swap.swap_curve(),
+            &fees,
+            to_u128(source_account.amount)?,
+            to_u128(dest_account.amount)?,
+            trade_direction,
+        )?;
 
-        if result.destination_amount_swapped < to_u128(minimum_amount_out)? {
-            return Err(AmmError::ExceededSlippage.into());
-        }
-        //@zhaohui
-        // let (swap_token_a_amount, swap_token_b_amount) = match trade_direction {
-        //     TradeDirection::AtoB => (
-        //         result.new_swap_source_amount,
-        //         result.new_swap_destination_amount,
-        //     ),
-        //     TradeDirection::BtoA => (
-        //         result.new_swap_destination_amount,
-        //         result.new_swap_source_amount,
-        //     ),
-        // };
+        //the host takes its share out of the owner fee
+        let host_fee = match host_fee_account_info {
+            Some(host_fee_account_info) => {
+                //the host share can't be paid back into the pool or the owner fee
+                if *host_fee_account_info.key == *token_swap.token_a_account()
+                    || *host_fee_account_info.key == *token_swap.token_b_account()
+                    || host_fee_account_info.key == fixed_fee_account_info.key
+                {
+                    return Err(AmmError::IncorrectFeeAccount.into());
+                }
+                let host_fee_account = Self::unpack_token_account(host_fee_account_info, token_swap.token_program_id())?;
+                if source_account.mint != host_fee_account.mint {
+                    return Err(AmmError::IncorrectFeeAccount.into());
+                }
+                token_swap
+                    .fees()
+                    .host_fee(result.owner_fee)
+                    .ok_or(AmmError::FeeCalculationFailure)?
+            }
+            None => 0,
+        };
+        let owner_fee = result
+            .owner_fee
+            .checked_sub(host_fee)
+            .ok_or(AmmError::FeeCalculationFailure)?;
+        let pool_owner_fee = if token_swap.lp_protocol_fee() { owner_fee } else { 0 };
 
         Self::token_transfer(
             swap_info.key,
@@ -639,35 +1586,53 @@ impl Processor {
             swap_source_info.clone(),
             user_transfer_authority_info.clone(),
             token_swap.nonce(),
-            to_u64(result.source_amount_swapped-result.owner_fee)?,
+            to_u64(result.source_amount_swapped-result.owner_fee+pool_owner_fee)?,
         )?;
 
-        //if the fee token is WSOL, then transfer SOL to fee account directly
-        if source_account.mint == wsol_mint
-        {
-            let source = user_transfer_authority_info.clone();
-            let destination = fixed_fee_wallet_info.clone();
-            invoke(
-                &system_instruction::transfer(
-                    source.key,
-                    destination.key,
-                    to_u64(result.owner_fee)?,
-                ),
-                &[source, destination, system_program_info.clone()]
-            )?;
+        //the owner fee stays in the pool when it is paid as pool tokens
+        if !token_swap.lp_protocol_fee() {
+            //if the fee token is WSOL, then transfer SOL to fee account directly
+            if source_account.mint == wsol_mint
+            {
+                let source = user_transfer_authority_info.clone();
+                let destination = fixed_fee_wallet_info.clone();
+                invoke(
+                    &system_instruction::transfer(
+                        source.key,
+                        destination.key,
+                        to_u64(owner_fee)?,
+                    ),
+                    &[source, destination, system_program_info.clone()]
+                )?;
+            }
+            else
+            {
+                //otherwise transfer SPL_Token
+                Self::token_transfer(
+                    swap_info.key,
+                    token_program_info.clone(),
+                    source_info.clone(),
+                    fixed_fee_account_info.clone(),
+                    user_transfer_authority_info.clone(),
+                    token_swap.nonce(),
+                    to_u64(owner_fee)?,
+                )?;
+            }
         }
-        else
-        {
-            //otherwise transfer SPL_Token
-            Self::token_transfer(
-                swap_info.key,
-                token_program_info.clone(),
-                source_info.clone(),
-                fixed_fee_account_info.clone(),
-                user_transfer_authority_info.clone(),
-                token_swap.nonce(),
-                to_u64(result.owner_fee)?,
-            )?;
+
+        //the host fee is paid in the source token on both fee paths
+        if let Some(host_fee_account_info) = host_fee_account_info {
+            if host_fee > 0 {
+                Self::token_transfer(
+                    swap_info.key,
+                    token_program_info.clone(),
+                    source_info.clone(),
+                    host_fee_account_info.clone(),
+                    user_transfer_authority_info.clone(),
+                    token_swap.nonce(),
+                    to_u64(host_fee)?,
+                )?;
+            }
         }
         
         //Transfer pc token from pool
@@ -681,7 +1646,24 @@ impl Processor {
             to_u64(result.destination_amount_swapped)?,
         )?;
 
-        Ok(())
+        let (reserve_a, reserve_b) = match trade_direction {
+            TradeDirection::AtoB => (source_account.amount, dest_account.amount),
+            TradeDirection::BtoA => (dest_account.amount, source_account.amount),
+        };
+        let (new_reserve_a, new_reserve_b) = match trade_direction {
+            TradeDirection::AtoB => (result.new_swap_source_amount, result.new_swap_destination_amount),
+            TradeDirection::BtoA => (result.new_swap_destination_amount, result.new_swap_source_amount),
+        };
+        token_swap.volatility_mut().update(
+            clock.unix_timestamp,
+            reserve_a,
+            reserve_b,
+            to_u64(new_reserve_a)?,
+            to_u64(new_reserve_b)?,
+        );
+        Self::update_price_oracle(swap_info, token_swap, reserve_a, reserve_b)?;
+
+        Ok(result)
     }
 
     /// Processes an [DepositAllTokenTypes](enum.Instruction.html).
@@ -705,25 +1687,32 @@ impl Processor {
         let token_b_info = next_account_info(account_info_iter)?;
         let pool_mint_info = next_account_info(account_info_iter)?;
         let dest_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
 
         //validate account
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let mut token_swap = Self::unpack_swap(swap_info)?;
 
         Self::check_state_account(program_id, state_info.key)?;
         
-        let state = ProgramState::unpack_from_slice(&state_info.data.borrow())?;
-        if state.is_initialized() == false
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized()
         {
             return Err(AmmError::NotInitializedState.into());
         }
+        if state.is_paused() || token_swap.is_paused() {
+            return Err(AmmError::Paused.into());
+        }
+        if token_swap.orderbook().has_orders {
+            return Err(AmmError::OrdersOutstanding.into());
+        }
 
-        let calculator = &state.swap_curve().calculator;
+        let calculator = &token_swap.swap_curve().calculator;
         if !calculator.allows_deposits() {
             return Err(AmmError::UnsupportedCurveOperation.into());
         }
         Self::check_accounts(
-            token_swap.as_ref(),
+            &token_swap,
             program_id,
             swap_info,
             authority_info,
@@ -738,7 +1727,18 @@ impl Processor {
         let token_a = Self::unpack_token_account(token_a_info, token_swap.token_program_id())?;
         let token_b = Self::unpack_token_account(token_b_info, token_swap.token_program_id())?;
         let pool_mint = Self::unpack_mint(pool_mint_info, token_swap.token_program_id())?;
-        let current_pool_mint_supply = to_u128(pool_mint.supply)?;
+        let current_pool_mint_supply = Self::mint_protocol_fee(
+            swap_info,
+            &token_swap,
+            &state,
+            authority_info,
+            pool_mint_info,
+            pool_fee_account_info,
+            token_program_info,
+            token_a.amount,
+            token_b.amount,
+            to_u128(pool_mint.supply)?,
+        )?;
         let (pool_token_amount, pool_mint_supply) = if current_pool_mint_supply > 0 {
             (to_u128(pool_token_amount)?, current_pool_mint_supply)
         } else {
@@ -800,6 +1800,13 @@ impl Processor {
             pool_token_amount,
         )?;
 
+        Self::update_root_k_last(
+            &mut token_swap,
+            token_a.amount.checked_add(token_a_amount).ok_or(AmmError::CalculationFailure)?,
+            token_b.amount.checked_add(token_b_amount).ok_or(AmmError::CalculationFailure)?,
+        )?;
+        Self::update_price_oracle(swap_info, token_swap, token_a.amount, token_b.amount)?;
+
         Ok(())
     }
 
@@ -823,21 +1830,25 @@ impl Processor {
         let token_b_info = next_account_info(account_info_iter)?;
         let dest_token_a_info = next_account_info(account_info_iter)?;
         let dest_token_b_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
 
         //validate accounts
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let mut token_swap = Self::unpack_swap(swap_info)?;
 
         Self::check_state_account(program_id, state_info.key)?;
         
-        let state = ProgramState::unpack_from_slice(&state_info.data.borrow())?;
-        if state.is_initialized() == false
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized()
         {
             return Err(AmmError::NotInitializedState.into());
         }
+        if token_swap.orderbook().has_orders {
+            return Err(AmmError::OrdersOutstanding.into());
+        }
 
         Self::check_accounts(
-            token_swap.as_ref(),
+            &token_swap,
             program_id,
             swap_info,
             authority_info,
@@ -852,32 +1863,39 @@ impl Processor {
         let token_a = Self::unpack_token_account(token_a_info, token_swap.token_program_id())?;
         let token_b = Self::unpack_token_account(token_b_info, token_swap.token_program_id())?;
         let pool_mint = Self::unpack_mint(pool_mint_info, token_swap.token_program_id())?;
+        let pool_mint_supply = Self::mint_protocol_fee(
+            swap_info,
+            &token_swap,
+            &state,
+            authority_info,
+            pool_mint_info,
+            pool_fee_account_info,
+            token_program_info,
+            token_a.amount,
+            token_b.amount,
+            to_u128(pool_mint.supply)?,
+        )?;
 
-        let calculator = &state.swap_curve().calculator;
-
-        let withdraw_fee: u128 = 0;
-        // if *fixed_fee_account_info.key == *source_info.key {
-        //     // withdrawing from the fee account, don't assess withdraw fee
-        //     0
-        // } else {
-        //     token_swap
-        //         .fees()
-        //         .owner_withdraw_fee(to_u128(pool_token_amount)?)
-        //         .ok_or(AmmError::FeeCalculationFailure)?
-        // };
-        
-        let mut pool_token_amount = to_u128(pool_token_amount)?
+        let calculator = &token_swap.swap_curve().calculator;
+
+        let withdraw_fee = Self::owner_withdraw_fee(
+            &token_swap,
+            &state,
+            pool_mint_info,
+            source_info,
+            pool_fee_account_info,
+            to_u128(pool_token_amount)?,
+        )?;
+
+        let pool_token_amount = to_u128(pool_token_amount)?
             .checked_sub(withdraw_fee)
             .ok_or(AmmError::CalculationFailure)?;
-        
-        //Check the minimum lp token amount to burn
-        let max_pool_token_amount = to_u128(pool_mint.supply)?.checked_sub(MIN_LP_SUPPLY).ok_or(AmmError::CalculationFailure)?;
-        pool_token_amount = std::cmp::min(pool_token_amount, max_pool_token_amount);
-        
+        Self::check_min_supply(pool_mint_supply, pool_token_amount)?;
+
         let results = calculator
             .pool_tokens_to_trading_tokens(
                 pool_token_amount,
-                to_u128(pool_mint.supply)?,
+                pool_mint_supply,
                 to_u128(token_a.amount)?,
                 to_u128(token_b.amount)?,
                 RoundDirection::Floor,
@@ -900,17 +1918,17 @@ impl Processor {
             return Err(AmmError::ZeroTradingTokens.into());
         }
 
-        // if withdraw_fee > 0 {
-        //     Self::token_transfer(
-        //         swap_info.key,
-        //         token_program_info.clone(),
-        //         source_info.clone(),
-        //         fixed_fee_account_info.clone(),
-        //         user_transfer_authority_info.clone(),
-        //         token_swap.nonce(),
-        //         to_u64(withdraw_fee)?,
-        //     )?;
-        // }
+        if withdraw_fee > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                source_info.clone(),
+                pool_fee_account_info.clone(),
+                user_transfer_authority_info.clone(),
+                token_swap.nonce(),
+                to_u64(withdraw_fee)?,
+            )?;
+        }
         //remove lp token from wallet
         Self::token_burn(
             swap_info.key,
@@ -945,6 +1963,14 @@ impl Processor {
                 token_b_amount,
             )?;
         }
+
+        Self::update_root_k_last(
+            &mut token_swap,
+            token_a.amount.checked_sub(token_a_amount).ok_or(AmmError::CalculationFailure)?,
+            token_b.amount.checked_sub(token_b_amount).ok_or(AmmError::CalculationFailure)?,
+        )?;
+        Self::update_price_oracle(swap_info, token_swap, token_a.amount, token_b.amount)?;
+
         Ok(())
     }
 
@@ -965,17 +1991,25 @@ impl Processor {
         let swap_token_b_info = next_account_info(account_info_iter)?;
         let pool_mint_info = next_account_info(account_info_iter)?;
         let destination_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
 
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let mut token_swap = Self::unpack_swap(swap_info)?;
 
         Self::check_state_account(program_id, state_info.key)?;
         
-        let state = ProgramState::unpack_from_slice(&state_info.data.borrow())?;
-        if state.is_initialized() == false
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized()
         {
             return Err(AmmError::NotInitializedState.into());
         }
+        if state.is_paused() || token_swap.is_paused() {
+            return Err(AmmError::Paused.into());
+        }
+        if token_swap.orderbook().has_orders {
+            return Err(AmmError::OrdersOutstanding.into());
+        }
 
         let source_account =
             Self::unpack_token_account(source_info, token_swap.token_program_id())?;
@@ -998,7 +2032,7 @@ impl Processor {
         };
 
         Self::check_accounts(
-            token_swap.as_ref(),
+            &token_swap,
             program_id,
             swap_info,
             authority_info,
@@ -1009,11 +2043,24 @@ impl Processor {
             source_a_info,
             source_b_info,
         )?;
+        Self::check_fee_account(&token_swap, &state, fee_account_info, &source_account.mint)?;
 
         let pool_mint = Self::unpack_mint(pool_mint_info, token_swap.token_program_id())?;
-        let pool_mint_supply = to_u128(pool_mint.supply)?;
-        let pool_token_amount = if pool_mint_supply > 0 {
-            state
+        let pool_mint_supply = Self::mint_protocol_fee(
+            swap_info,
+            &token_swap,
+            &state,
+            authority_info,
+            pool_mint_info,
+            pool_fee_account_info,
+            token_program_info,
+            swap_token_a.amount,
+            swap_token_b.amount,
+            to_u128(pool_mint.supply)?,
+        )?;
+        let fees = Self::dynamic_fees(&token_swap, Clock::get()?.unix_timestamp)?;
+        let result = if pool_mint_supply > 0 {
+            token_swap
                 .swap_curve()
                 .deposit_single_token_type(
                     to_u128(source_token_amount)?,
@@ -1021,14 +2068,27 @@ impl Processor {
                     to_u128(swap_token_b.amount)?,
                     pool_mint_supply,
                     trade_direction,
-                    state.fees(),
+                    &fees,
                 )
                 .ok_or(AmmError::ZeroTradingTokens)?
         } else {
-            to_u128(state.initial_supply())?
+            SingleTokenResult {
+                pool_token_amount: to_u128(state.initial_supply())?,
+                trade_fee: 0,
+                owner_fee: 0,
+            }
+        };
+        //the owner fee stays in the pool when it is paid as pool tokens
+        let owner_fee = if token_swap.lp_protocol_fee() {
+            0
+        } else {
+            to_u64(result.owner_fee)?
         };
+        let deposit_amount = source_token_amount
+            .checked_sub(owner_fee)
+            .ok_or(AmmError::FeeCalculationFailure)?;
 
-        let pool_token_amount = to_u64(pool_token_amount)?;
+        let pool_token_amount = to_u64(result.pool_token_amount)?;
         if pool_token_amount < minimum_pool_token_amount {
             return Err(AmmError::ExceededSlippage.into());
         }
@@ -1045,7 +2105,7 @@ impl Processor {
                     swap_token_a_info.clone(),
                     user_transfer_authority_info.clone(),
                     token_swap.nonce(),
-                    source_token_amount,
+                    deposit_amount,
                 )?;
             }
             TradeDirection::BtoA => {
@@ -1056,10 +2116,21 @@ impl Processor {
                     swap_token_b_info.clone(),
                     user_transfer_authority_info.clone(),
                     token_swap.nonce(),
-                    source_token_amount,
+                    deposit_amount,
                 )?;
             }
         }
+        if owner_fee > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                source_info.clone(),
+                fee_account_info.clone(),
+                user_transfer_authority_info.clone(),
+                token_swap.nonce(),
+                owner_fee,
+            )?;
+        }
         Self::token_mint_to(
             swap_info.key,
             token_program_info.clone(),
@@ -1070,6 +2141,17 @@ impl Processor {
             pool_token_amount,
         )?;
 
+        let (reserve_a, reserve_b) = match trade_direction {
+            TradeDirection::AtoB => (swap_token_a.amount.checked_add(deposit_amount), Some(swap_token_b.amount)),
+            TradeDirection::BtoA => (Some(swap_token_a.amount), swap_token_b.amount.checked_add(deposit_amount)),
+        };
+        Self::update_root_k_last(
+            &mut token_swap,
+            reserve_a.ok_or(AmmError::CalculationFailure)?,
+            reserve_b.ok_or(AmmError::CalculationFailure)?,
+        )?;
+        Self::update_price_oracle(swap_info, token_swap, swap_token_a.amount, swap_token_b.amount)?;
+
         Ok(())
     }
 
@@ -1090,17 +2172,25 @@ impl Processor {
         let swap_token_a_info = next_account_info(account_info_iter)?;
         let swap_token_b_info = next_account_info(account_info_iter)?;
         let destination_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
 
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let mut token_swap = Self::unpack_swap(swap_info)?;
 
         Self::check_state_account(program_id, state_info.key)?;
         
-        let state = ProgramState::unpack_from_slice(&state_info.data.borrow())?;
-        if state.is_initialized() == false
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized()
         {
             return Err(AmmError::NotInitializedState.into());
         }
+        if state.is_paused() || token_swap.is_paused() {
+            return Err(AmmError::Paused.into());
+        }
+        if token_swap.orderbook().has_orders {
+            return Err(AmmError::OrdersOutstanding.into());
+        }
 
         let destination_account =
             Self::unpack_token_account(destination_info, token_swap.token_program_id())?;
@@ -1122,7 +2212,7 @@ impl Processor {
             TradeDirection::BtoA => (None, Some(destination_info)),
         };
         Self::check_accounts(
-            token_swap.as_ref(),
+            &token_swap,
             program_id,
             swap_info,
             authority_info,
@@ -1133,13 +2223,26 @@ impl Processor {
             destination_a_info,
             destination_b_info,
         )?;
+        Self::check_fee_account(&token_swap, &state, fee_account_info, &destination_account.mint)?;
 
         let pool_mint = Self::unpack_mint(pool_mint_info, token_swap.token_program_id())?;
-        let pool_mint_supply = to_u128(pool_mint.supply)?;
+        let pool_mint_supply = Self::mint_protocol_fee(
+            swap_info,
+            &token_swap,
+            &state,
+            authority_info,
+            pool_mint_info,
+            pool_fee_account_info,
+            token_program_info,
+            swap_token_a.amount,
+            swap_token_b.amount,
+            to_u128(pool_mint.supply)?,
+        )?;
         let swap_token_a_amount = to_u128(swap_token_a.amount)?;
         let swap_token_b_amount = to_u128(swap_token_b.amount)?;
 
-        let burn_pool_token_amount = state
+        let fees = Self::dynamic_fees(&token_swap, Clock::get()?.unix_timestamp)?;
+        let result = token_swap
             .swap_curve()
             .withdraw_single_token_type_exact_out(
                 to_u128(destination_token_amount)?,
@@ -1147,20 +2250,25 @@ impl Processor {
                 swap_token_b_amount,
                 pool_mint_supply,
                 trade_direction,
-                state.fees(),
+                &fees,
             )
             .ok_or(AmmError::ZeroTradingTokens)?;
+        let burn_pool_token_amount = result.pool_token_amount;
+        //the owner fee stays in the pool when it is paid as pool tokens
+        let owner_fee = if token_swap.lp_protocol_fee() {
+            0
+        } else {
+            to_u64(result.owner_fee)?
+        };
 
-        let withdraw_fee: u128 = 0;
-        // if *fixed_fee_account_info.key == *source_info.key {
-        //     // withdrawing from the fee account, don't assess withdraw fee
-        //     0
-        // } else {
-        //     token_swap
-        //         .fees()
-        //         .owner_withdraw_fee(burn_pool_token_amount)
-        //         .ok_or(AmmError::FeeCalculationFailure)?
-        // };
+        let withdraw_fee = Self::owner_withdraw_fee(
+            &token_swap,
+            &state,
+            pool_mint_info,
+            source_info,
+            pool_fee_account_info,
+            burn_pool_token_amount,
+        )?;
         let pool_token_amount = burn_pool_token_amount
             .checked_add(withdraw_fee)
             .ok_or(AmmError::CalculationFailure)?;
@@ -1171,18 +2279,19 @@ impl Processor {
         if pool_token_amount == 0 {
             return Err(AmmError::ZeroTradingTokens.into());
         }
+        Self::check_min_supply(pool_mint_supply, burn_pool_token_amount)?;
 
-        // if withdraw_fee > 0 {
-        //     Self::token_transfer(
-        //         swap_info.key,
-        //         token_program_info.clone(),
-        //         source_info.clone(),
-        //         fixed_fee_account_info.clone(),
-        //         user_transfer_authority_info.clone(),
-        //         token_swap.nonce(),
-        //         to_u64(withdraw_fee)?,
-        //     )?;
-        // }
+        if withdraw_fee > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                source_info.clone(),
+                pool_fee_account_info.clone(),
+                user_transfer_authority_info.clone(),
+                token_swap.nonce(),
+                to_u64(withdraw_fee)?,
+            )?;
+        }
         Self::token_burn(
             swap_info.key,
             token_program_info.clone(),
@@ -1193,210 +2302,4730 @@ impl Processor {
             to_u64(burn_pool_token_amount)?,
         )?;
 
-        match trade_direction {
-            TradeDirection::AtoB => {
-                Self::token_transfer(
-                    swap_info.key,
-                    token_program_info.clone(),
-                    swap_token_a_info.clone(),
-                    destination_info.clone(),
-                    authority_info.clone(),
-                    token_swap.nonce(),
-                    destination_token_amount,
-                )?;
-            }
-            TradeDirection::BtoA => {
-                Self::token_transfer(
+        let swap_destination_info = match trade_direction {
+            TradeDirection::AtoB => swap_token_a_info,
+            TradeDirection::BtoA => swap_token_b_info,
+        };
+        Self::token_transfer(
+            swap_info.key,
+            token_program_info.clone(),
+            swap_destination_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            token_swap.nonce(),
+            destination_token_amount,
+        )?;
+        if owner_fee > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                swap_destination_info.clone(),
+                fee_account_info.clone(),
+                authority_info.clone(),
+                token_swap.nonce(),
+                owner_fee,
+            )?;
+        }
+
+        let withdrawn_amount = destination_token_amount
+            .checked_add(owner_fee)
+            .ok_or(AmmError::CalculationFailure)?;
+        let (reserve_a, reserve_b) = match trade_direction {
+            TradeDirection::AtoB => (swap_token_a.amount.checked_sub(withdrawn_amount), Some(swap_token_b.amount)),
+            TradeDirection::BtoA => (Some(swap_token_a.amount), swap_token_b.amount.checked_sub(withdrawn_amount)),
+        };
+        Self::update_root_k_last(
+            &mut token_swap,
+            reserve_a.ok_or(AmmError::CalculationFailure)?,
+            reserve_b.ok_or(AmmError::CalculationFailure)?,
+        )?;
+        Self::update_price_oracle(swap_info, token_swap, swap_token_a.amount, swap_token_b.amount)?;
+
+        Ok(())
+    }
+
+    /// Processes an [InitializeConcentratedPool](enum.Instruction.html).
+    pub fn process_initialize_concentrated_pool(
+        program_id: &Pubkey,
+        nonce: u8,
+        tick_spacing: u16,
+        initial_sqrt_price: u128,
+        fees: Option<Fees>,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+
+        let token_program_id = *token_program_info.key;
+        if pool_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if ConcentratedPool::unpack_unchecked(&pool_info.data.borrow())?.is_initialized {
+            return Err(AmmError::AlreadyInUse.into());
+        }
+        if *authority_info.key != Self::authority_id(program_id, pool_info.key, nonce)? {
+            return Err(AmmError::InvalidProgramAddress.into());
+        }
+
+        Self::check_state_account(program_id, state_info.key)?;
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if !cur_state_owner_info.is_signer{
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if *cur_state_owner_info.key != state.state_owner {
+            return Err(AmmError::InvalidOwner.into());
+        }
+
+        let token_a = Self::unpack_token_account(token_a_info, &token_program_id)?;
+        let token_b = Self::unpack_token_account(token_b_info, &token_program_id)?;
+        if *authority_info.key != token_a.owner {
+            return Err(AmmError::InvalidOwner.into());
+        }
+        if *authority_info.key != token_b.owner {
+            return Err(AmmError::InvalidOwner.into());
+        }
+        if token_a.mint == token_b.mint {
+            return Err(AmmError::RepeatedMint.into());
+        }
+        if token_a.delegate.is_some() || token_b.delegate.is_some() {
+            return Err(AmmError::InvalidDelegate.into());
+        }
+        if token_a.is_frozen() || token_b.is_frozen() {
+            return Err(AmmError::InvalidFreezeAuthority.into());
+        }
+        if token_a.close_authority.is_some() || token_b.close_authority.is_some() {
+            return Err(AmmError::InvalidCloseAuthority.into());
+        }
+
+        let fees = match fees {
+            Some(fees) => {
+                SWAP_CONSTRAINTS.validate_fees(&fees)?;
+                state.validate_pool_fees(&fees)?;
+                fees.validate()?;
+                fees
+            }
+            None => state.fees,
+        };
+
+        if tick_spacing == 0 || !(MIN_SQRT_PRICE..MAX_SQRT_PRICE).contains(&initial_sqrt_price) {
+            return Err(AmmError::InvalidCurve.into());
+        }
+        let tick_current =
+            tick_from_sqrt_price(initial_sqrt_price).ok_or(AmmError::InvalidCurve)?;
+
+        let pool = ConcentratedPool {
+            is_initialized: true,
+            nonce,
+            token_program_id,
+            token_a: *token_a_info.key,
+            token_b: *token_b_info.key,
+            token_a_mint: token_a.mint,
+            token_b_mint: token_b.mint,
+            fees,
+            tick_spacing,
+            sqrt_price: initial_sqrt_price,
+            tick_current,
+            liquidity: 0,
+            fee_growth_global_a: 0,
+            fee_growth_global_b: 0,
+            is_paused: false,
+            ticks: vec![],
+        };
+        ConcentratedPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes an [OpenPosition](enum.Instruction.html).
+    pub fn process_open_position(
+        program_id: &Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_info = next_account_info(account_info_iter)?;
+        let position_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+
+        if pool_info.owner != program_id || position_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if !owner_info.is_signer {
+            return Err(AmmError::InvalidSigner.into());
+        }
+        let pool = ConcentratedPool::unpack(&pool_info.data.borrow())?;
+        if pool.is_paused {
+            return Err(AmmError::Paused.into());
+        }
+        if Position::unpack_unchecked(&position_info.data.borrow())?.is_initialized {
+            return Err(AmmError::AlreadyInUse.into());
+        }
+        pool.validate_tick_range(tick_lower, tick_upper)?;
+
+        let position = Position {
+            is_initialized: true,
+            pool: *pool_info.key,
+            owner: *owner_info.key,
+            tick_lower,
+            tick_upper,
+            ..Position::default()
+        };
+        Position::pack(position, &mut position_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Validates the accounts shared by the position instructions, returns
+    /// the unpacked pool and position
+    #[allow(clippy::too_many_arguments)]
+    fn check_position_accounts(
+        program_id: &Pubkey,
+        pool_info: &AccountInfo,
+        authority_info: &AccountInfo,
+        position_info: &AccountInfo,
+        owner_info: &AccountInfo,
+        user_token_a_info: &AccountInfo,
+        user_token_b_info: &AccountInfo,
+        token_a_info: &AccountInfo,
+        token_b_info: &AccountInfo,
+        token_program_info: &AccountInfo,
+    ) -> Result<(ConcentratedPool, Position), ProgramError> {
+        if pool_info.owner != program_id || position_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pool = ConcentratedPool::unpack(&pool_info.data.borrow())?;
+        let position = Position::unpack(&position_info.data.borrow())?;
+        if *authority_info.key != Self::authority_id(program_id, pool_info.key, pool.nonce)? {
+            return Err(AmmError::InvalidProgramAddress.into());
+        }
+        if position.pool != *pool_info.key {
+            return Err(AmmError::InvalidInput.into());
+        }
+        if !owner_info.is_signer {
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if position.owner != *owner_info.key {
+            return Err(AmmError::InvalidOwner.into());
+        }
+        if *token_a_info.key != pool.token_a || *token_b_info.key != pool.token_b {
+            return Err(AmmError::IncorrectSwapAccount.into());
+        }
+        if token_a_info.key == user_token_a_info.key || token_b_info.key == user_token_b_info.key {
+            return Err(AmmError::InvalidInput.into());
+        }
+        if *token_program_info.key != pool.token_program_id {
+            return Err(AmmError::IncorrectTokenProgramId.into());
+        }
+        Ok((pool, position))
+    }
+
+    /// Processes an [IncreaseLiquidity](enum.Instruction.html).
+    pub fn process_increase_liquidity(
+        program_id: &Pubkey,
+        liquidity: u128,
+        maximum_token_a_amount: u64,
+        maximum_token_b_amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let position_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let source_a_info = next_account_info(account_info_iter)?;
+        let source_b_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (mut pool, mut position) = Self::check_position_accounts(
+            program_id,
+            pool_info,
+            authority_info,
+            position_info,
+            owner_info,
+            source_a_info,
+            source_b_info,
+            token_a_info,
+            token_b_info,
+            token_program_info,
+        )?;
+        if pool.is_paused {
+            return Err(AmmError::Paused.into());
+        }
+        if liquidity == 0 {
+            return Err(AmmError::ZeroTradingTokens.into());
+        }
+
+        let liquidity_delta = i128::try_from(liquidity).map_err(|_| AmmError::ConversionFailure)?;
+        let (token_a_amount, token_b_amount) = pool.modify_position(&mut position, liquidity_delta)?;
+        let token_a_amount = to_u64(token_a_amount)?;
+        if token_a_amount > maximum_token_a_amount {
+            return Err(AmmError::ExceededSlippage.into());
+        }
+        let token_b_amount = to_u64(token_b_amount)?;
+        if token_b_amount > maximum_token_b_amount {
+            return Err(AmmError::ExceededSlippage.into());
+        }
+
+        if token_a_amount > 0 {
+            Self::token_transfer(
+                pool_info.key,
+                token_program_info.clone(),
+                source_a_info.clone(),
+                token_a_info.clone(),
+                owner_info.clone(),
+                pool.nonce,
+                token_a_amount,
+            )?;
+        }
+        if token_b_amount > 0 {
+            Self::token_transfer(
+                pool_info.key,
+                token_program_info.clone(),
+                source_b_info.clone(),
+                token_b_info.clone(),
+                owner_info.clone(),
+                pool.nonce,
+                token_b_amount,
+            )?;
+        }
+
+        ConcentratedPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+        Position::pack(position, &mut position_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [DecreaseLiquidity](enum.Instruction.html).
+    pub fn process_decrease_liquidity(
+        program_id: &Pubkey,
+        liquidity: u128,
+        minimum_token_a_amount: u64,
+        minimum_token_b_amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let position_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let dest_a_info = next_account_info(account_info_iter)?;
+        let dest_b_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (mut pool, mut position) = Self::check_position_accounts(
+            program_id,
+            pool_info,
+            authority_info,
+            position_info,
+            owner_info,
+            dest_a_info,
+            dest_b_info,
+            token_a_info,
+            token_b_info,
+            token_program_info,
+        )?;
+        if liquidity == 0 {
+            return Err(AmmError::ZeroTradingTokens.into());
+        }
+        if liquidity > position.liquidity {
+            return Err(AmmError::InsufficientLiquidity.into());
+        }
+
+        let liquidity_delta = i128::try_from(liquidity)
+            .map_err(|_| AmmError::ConversionFailure)?
+            .wrapping_neg();
+        let (token_a_amount, token_b_amount) = pool.modify_position(&mut position, liquidity_delta)?;
+        let token_a_amount = to_u64(token_a_amount)?;
+        if token_a_amount < minimum_token_a_amount {
+            return Err(AmmError::ExceededSlippage.into());
+        }
+        let token_b_amount = to_u64(token_b_amount)?;
+        if token_b_amount < minimum_token_b_amount {
+            return Err(AmmError::ExceededSlippage.into());
+        }
+
+        if token_a_amount > 0 {
+            Self::token_transfer(
+                pool_info.key,
+                token_program_info.clone(),
+                token_a_info.clone(),
+                dest_a_info.clone(),
+                authority_info.clone(),
+                pool.nonce,
+                token_a_amount,
+            )?;
+        }
+        if token_b_amount > 0 {
+            Self::token_transfer(
+                pool_info.key,
+                token_program_info.clone(),
+                token_b_info.clone(),
+                dest_b_info.clone(),
+                authority_info.clone(),
+                pool.nonce,
+                token_b_amount,
+            )?;
+        }
+
+        ConcentratedPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+        Position::pack(position, &mut position_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [CollectFees](enum.Instruction.html).
+    pub fn process_collect_fees(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let position_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let dest_a_info = next_account_info(account_info_iter)?;
+        let dest_b_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (mut pool, mut position) = Self::check_position_accounts(
+            program_id,
+            pool_info,
+            authority_info,
+            position_info,
+            owner_info,
+            dest_a_info,
+            dest_b_info,
+            token_a_info,
+            token_b_info,
+            token_program_info,
+        )?;
+
+        //credit the fees earned since the last change of the position
+        if position.liquidity > 0 {
+            pool.modify_position(&mut position, 0)?;
+        }
+        let (token_a_amount, token_b_amount) = (position.tokens_owed_a, position.tokens_owed_b);
+        position.tokens_owed_a = 0;
+        position.tokens_owed_b = 0;
+
+        if token_a_amount > 0 {
+            Self::token_transfer(
+                pool_info.key,
+                token_program_info.clone(),
+                token_a_info.clone(),
+                dest_a_info.clone(),
+                authority_info.clone(),
+                pool.nonce,
+                token_a_amount,
+            )?;
+        }
+        if token_b_amount > 0 {
+            Self::token_transfer(
+                pool_info.key,
+                token_program_info.clone(),
+                token_b_info.clone(),
+                dest_b_info.clone(),
+                authority_info.clone(),
+                pool.nonce,
+                token_b_amount,
+            )?;
+        }
+
+        Position::pack(position, &mut position_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [ConcentratedSwap](enum.Instruction.html).
+    pub fn process_concentrated_swap(
+        program_id: &Pubkey,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        sqrt_price_limit: u128,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let swap_source_info = next_account_info(account_info_iter)?;
+        let swap_destination_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let fee_account_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        Self::check_state_account(program_id, state_info.key)?;
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if state.is_paused() {
+            return Err(AmmError::Paused.into());
+        }
+
+        if pool_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut pool = ConcentratedPool::unpack(&pool_info.data.borrow())?;
+        if pool.is_paused {
+            return Err(AmmError::Paused.into());
+        }
+        if *authority_info.key != Self::authority_id(program_id, pool_info.key, pool.nonce)? {
+            return Err(AmmError::InvalidProgramAddress.into());
+        }
+        let a_to_b = if *swap_source_info.key == pool.token_a
+            && *swap_destination_info.key == pool.token_b
+        {
+            true
+        } else if *swap_source_info.key == pool.token_b
+            && *swap_destination_info.key == pool.token_a
+        {
+            false
+        } else {
+            return Err(AmmError::IncorrectSwapAccount.into());
+        };
+        if swap_source_info.key == source_info.key {
+            return Err(AmmError::InvalidInput.into());
+        }
+        if swap_destination_info.key == destination_info.key {
+            return Err(AmmError::InvalidInput.into());
+        }
+        if *token_program_info.key != pool.token_program_id {
+            return Err(AmmError::IncorrectTokenProgramId.into());
+        }
+
+        let source_mint = if a_to_b { pool.token_a_mint } else { pool.token_b_mint };
+        let fee_account = Self::unpack_token_account(fee_account_info, &pool.token_program_id)?;
+        if *state.fee_owner() != fee_account.owner || source_mint != fee_account.mint {
+            return Err(AmmError::IncorrectFeeAccount.into());
+        }
+
+        let result = pool.swap(to_u128(amount_in)?, sqrt_price_limit, a_to_b)?;
+        let amount_out = to_u64(result.amount_out)?;
+        if amount_out < minimum_amount_out {
+            return Err(AmmError::ExceededSlippage.into());
+        }
+        if amount_out == 0 {
+            return Err(AmmError::ZeroTradingTokens.into());
+        }
+
+        Self::token_transfer(
+            pool_info.key,
+            token_program_info.clone(),
+            source_info.clone(),
+            swap_source_info.clone(),
+            user_transfer_authority_info.clone(),
+            pool.nonce,
+            to_u64(result.amount_in - result.owner_fee)?,
+        )?;
+        if result.owner_fee > 0 {
+            Self::token_transfer(
+                pool_info.key,
+                token_program_info.clone(),
+                source_info.clone(),
+                fee_account_info.clone(),
+                user_transfer_authority_info.clone(),
+                pool.nonce,
+                to_u64(result.owner_fee)?,
+            )?;
+        }
+        Self::token_transfer(
+            pool_info.key,
+            token_program_info.clone(),
+            swap_destination_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            pool.nonce,
+            amount_out,
+        )?;
+
+        ConcentratedPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [RampAmp](enum.Instruction.html) or a
+    /// [StopRampAmp](enum.Instruction.html), `ramp` is None to stop
+    pub fn process_ramp_amp(
+        program_id: &Pubkey,
+        ramp: Option<(u64, UnixTimestamp)>,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Self::check_state_account(program_id, state_info.key)?;
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if !cur_state_owner_info.is_signer{
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if *cur_state_owner_info.key != state.state_owner {
+            return Err(AmmError::InvalidOwner.into());
+        }
+
+        let mut token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let swap_curve = token_swap.swap_curve_mut();
+        if swap_curve.curve_type != CurveType::Stable {
+            return Err(AmmError::UnsupportedCurveOperation.into());
+        }
+        let mut calculator = [0u8; SwapCurve::LEN];
+        swap_curve.pack_into_slice(&mut calculator);
+        let mut stable_curve = StableCurve::unpack_from_slice(&calculator[1..])?;
+        stable_curve.timestamp = Clock::get()?.unix_timestamp;
+        match ramp {
+            Some((target_amp, stop_ramp_ts)) => stable_curve.ramp_amp(target_amp, stop_ramp_ts)?,
+            None => stable_curve.stop_ramp_amp()?,
+        }
+        swap_curve.calculator = Box::new(stable_curve);
+
+        SwapVersion::pack(token_swap, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [Migrate](enum.Instruction.html).
+    pub fn process_migrate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let account_info = next_account_info(account_info_iter)?;
+        let payer_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+
+        if account_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if !payer_info.is_signer {
+            return Err(AmmError::InvalidSigner.into());
+        }
+
+        if Self::check_state_account(program_id, account_info.key).is_ok() {
+            if account_info.data_len() != ProgramState::LEN {
+                return Err(AmmError::AlreadyMigrated.into());
+            }
+            let state = ProgramStateV2::unpack_from_slice(&account_info.data.borrow())?;
+            Self::realloc_account(
+                account_info,
+                payer_info,
+                system_program_info,
+                rent_info,
+                ProgramStateV2::LEN,
+            )?;
+            Self::pack_state(&state, account_info)
+        } else {
+            if SwapVersion::is_latest(&account_info.data.borrow()) {
+                return Err(AmmError::AlreadyMigrated.into());
+            }
+            // only swaps of the first layout have this size, the data of the
+            // other accounts may look like one
+            if account_info.data_len() != SwapVersion::V1_LEN
+                || account_info.data.borrow()[0] != 1
+            {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Self::check_state_account(program_id, state_info.key)?;
+            let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+            if !state.is_initialized() {
+                return Err(AmmError::NotInitializedState.into());
+            }
+            let mut token_swap = SwapVersion::unpack_with_state(&account_info.data.borrow(), state)?;
+            if !token_swap.is_initialized() {
+                return Err(ProgramError::UninitializedAccount);
+            }
+            let clock = Clock::get()?;
+            *token_swap.price_oracle_mut() = PriceOracle::new(clock.slot, clock.unix_timestamp);
+            Self::realloc_account(
+                account_info,
+                payer_info,
+                system_program_info,
+                rent_info,
+                SwapVersion::LATEST_LEN,
+            )?;
+            SwapVersion::pack(token_swap, &mut account_info.data.borrow_mut())
+        }
+    }
+
+    /// Processes a [SetPause](enum.Instruction.html).
+    pub fn process_set_pause(
+        program_id: &Pubkey,
+        is_paused: bool,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+        let swap_info = next_account_info(account_info_iter).ok();
+
+        Self::check_state_account(program_id, state_info.key)?;
+        let mut state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if !cur_state_owner_info.is_signer{
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if *cur_state_owner_info.key != state.state_owner {
+            return Err(AmmError::InvalidOwner.into());
+        }
+
+        match swap_info {
+            Some(swap_info) => {
+                if swap_info.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                if swap_info.data.borrow().first() == Some(&CONCENTRATED_POOL_TYPE) {
+                    let mut pool = ConcentratedPool::unpack(&swap_info.data.borrow())?;
+                    pool.is_paused = is_paused;
+                    ConcentratedPool::pack(pool, &mut swap_info.data.borrow_mut())?;
+                } else {
+                    let mut token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+                    token_swap.set_paused(is_paused);
+                    SwapVersion::pack(token_swap, &mut swap_info.data.borrow_mut())?;
+                }
+            }
+            None => {
+                state.is_paused = is_paused;
+                Self::pack_state(&state, state_info)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the initialized program state, checking that the signer is
+    /// its state owner
+    fn unpack_owned_state(
+        program_id: &Pubkey,
+        state_info: &AccountInfo,
+        cur_state_owner_info: &AccountInfo,
+    ) -> Result<ProgramStateV2, ProgramError> {
+        Self::check_state_account(program_id, state_info.key)?;
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if !cur_state_owner_info.is_signer {
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if *cur_state_owner_info.key != state.state_owner {
+            return Err(AmmError::InvalidStateOwner.into());
+        }
+        Ok(state)
+    }
+
+    /// Processes a [ProposeStateOwner](enum.Instruction.html).
+    pub fn process_propose_state_owner(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+        let new_state_owner_info = next_account_info(account_info_iter)?;
+
+        let mut state = Self::unpack_owned_state(program_id, state_info, cur_state_owner_info)?;
+        state.pending_state_owner = *new_state_owner_info.key;
+        Self::pack_state(&state, state_info)
+    }
+
+    /// Processes an [AcceptStateOwner](enum.Instruction.html).
+    pub fn process_accept_state_owner(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let new_state_owner_info = next_account_info(account_info_iter)?;
+
+        Self::check_state_account(program_id, state_info.key)?;
+        let mut state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        let pending_state_owner = *state
+            .pending_state_owner()
+            .ok_or(AmmError::NoPendingStateOwner)?;
+        if !new_state_owner_info.is_signer {
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if *new_state_owner_info.key != pending_state_owner {
+            return Err(AmmError::InvalidStateOwner.into());
+        }
+        state.state_owner = pending_state_owner;
+        state.pending_state_owner = Pubkey::default();
+        Self::pack_state(&state, state_info)
+    }
+
+    /// Processes a [CancelStateOwnerProposal](enum.Instruction.html).
+    pub fn process_cancel_state_owner_proposal(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+
+        let mut state = Self::unpack_owned_state(program_id, state_info, cur_state_owner_info)?;
+        if state.pending_state_owner().is_none() {
+            return Err(AmmError::NoPendingStateOwner.into());
+        }
+        state.pending_state_owner = Pubkey::default();
+        Self::pack_state(&state, state_info)
+    }
+
+    /// Processes a [QueueStateUpdate](enum.Instruction.html).
+    pub fn process_queue_state_update(
+        program_id: &Pubkey,
+        delay: UnixTimestamp,
+        initial_supply: u64,
+        fees: Fees,
+        swap_curve: SwapCurve,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+        let fee_owner_info = next_account_info(account_info_iter)?;
+
+        let mut state = Self::unpack_owned_state(program_id, state_info, cur_state_owner_info)?;
+        Self::validate_state_parameters(initial_supply, &fees, &swap_curve)?;
+        if delay < SWAP_CONSTRAINTS.min_state_update_delay {
+            return Err(AmmError::StateUpdateDelayTooShort.into());
+        }
+
+        let activation_timestamp = Clock::get()?
+            .unix_timestamp
+            .checked_add(delay)
+            .ok_or(AmmError::CalculationFailure)?;
+        state.pending_update = Some(PendingStateUpdate {
+            activation_timestamp,
+            initial_supply,
+            fee_owner: *fee_owner_info.key,
+            fees,
+            swap_curve,
+        });
+        Self::pack_state(&state, state_info)
+    }
+
+    /// Processes an [ExecuteStateUpdate](enum.Instruction.html).
+    pub fn process_execute_state_update(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+
+        let mut state = Self::unpack_owned_state(program_id, state_info, cur_state_owner_info)?;
+        let update = state
+            .pending_update
+            .take()
+            .ok_or(AmmError::NoPendingStateUpdate)?;
+        if !update.is_active(Clock::get()?.unix_timestamp) {
+            return Err(AmmError::StateUpdateTimelocked.into());
+        }
+        state.initial_supply = update.initial_supply;
+        state.fee_owner = update.fee_owner;
+        state.fees = update.fees;
+        state.swap_curve = update.swap_curve;
+        Self::pack_state(&state, state_info)
+    }
+
+    /// Processes a [CancelStateUpdate](enum.Instruction.html).
+    pub fn process_cancel_state_update(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+
+        let mut state = Self::unpack_owned_state(program_id, state_info, cur_state_owner_info)?;
+        state
+            .pending_update
+            .take()
+            .ok_or(AmmError::NoPendingStateUpdate)?;
+        Self::pack_state(&state, state_info)
+    }
+
+    /// Processes a [CreateGovernance](enum.Instruction.html).
+    pub fn process_create_governance(
+        program_id: &Pubkey,
+        nonce: u8,
+        threshold: u8,
+        signers: Vec<Pubkey>,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let governance_info = next_account_info(account_info_iter)?;
+
+        if governance_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if Governance::unpack_unchecked(&governance_info.data.borrow())?.is_initialized {
+            return Err(AmmError::AlreadyInUse.into());
+        }
+        Pubkey::create_program_address(
+            &[GOVERNANCE_SEED.as_bytes(), governance_info.key.as_ref(), &[nonce]],
+            program_id,
+        )
+        .or(Err(AmmError::InvalidProgramAddress))?;
+
+        let governance = Governance {
+            is_initialized: true,
+            nonce,
+            threshold,
+            signers,
+        };
+        governance.validate()?;
+        Governance::pack(governance, &mut governance_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Loads a proposal and its governance, returning the governance index
+    /// of the signer
+    fn unpack_proposal(
+        program_id: &Pubkey,
+        proposal_info: &AccountInfo,
+        governance_info: &AccountInfo,
+        signer_info: &AccountInfo,
+    ) -> Result<(Proposal, usize), ProgramError> {
+        if proposal_info.owner != program_id || governance_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let governance = Governance::unpack(&governance_info.data.borrow())?;
+        let proposal = Proposal::unpack_unchecked(&proposal_info.data.borrow())?;
+        if !signer_info.is_signer {
+            return Err(AmmError::InvalidSigner.into());
+        }
+        let index = governance
+            .signer_index(signer_info.key)
+            .ok_or(AmmError::NotGovernanceSigner)?;
+        Ok((proposal, index))
+    }
+
+    /// Processes a [CreateProposal](enum.Instruction.html).
+    pub fn process_create_proposal(
+        program_id: &Pubkey,
+        proposal_accounts: Vec<AccountMeta>,
+        data: Vec<u8>,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_info = next_account_info(account_info_iter)?;
+        let governance_info = next_account_info(account_info_iter)?;
+        let signer_info = next_account_info(account_info_iter)?;
+
+        let (proposal, index) =
+            Self::unpack_proposal(program_id, proposal_info, governance_info, signer_info)?;
+        if proposal.is_initialized {
+            return Err(AmmError::AlreadyInUse.into());
+        }
+        if proposal_accounts.len() > MAX_PROPOSAL_ACCOUNTS || data.len() > MAX_PROPOSAL_DATA_LEN {
+            return Err(AmmError::InvalidProposal.into());
+        }
+
+        let proposal = Proposal {
+            is_initialized: true,
+            governance: *governance_info.key,
+            executed: false,
+            approvals: 1 << index,
+            accounts: proposal_accounts,
+            data,
+        };
+        Proposal::pack(proposal, &mut proposal_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes an [ApproveProposal](enum.Instruction.html).
+    pub fn process_approve_proposal(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_info = next_account_info(account_info_iter)?;
+        let governance_info = next_account_info(account_info_iter)?;
+        let signer_info = next_account_info(account_info_iter)?;
+
+        let (mut proposal, index) =
+            Self::unpack_proposal(program_id, proposal_info, governance_info, signer_info)?;
+        if !proposal.is_initialized {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if proposal.governance != *governance_info.key {
+            return Err(AmmError::InvalidProposal.into());
+        }
+        if proposal.executed {
+            return Err(AmmError::ProposalAlreadyExecuted.into());
+        }
+        proposal.approvals |= 1 << index;
+        Proposal::pack(proposal, &mut proposal_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes an [ExecuteProposal](enum.Instruction.html).
+    pub fn process_execute_proposal(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_info = next_account_info(account_info_iter)?;
+        let governance_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+
+        if proposal_info.owner != program_id || governance_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let governance = Governance::unpack(&governance_info.data.borrow())?;
+        let mut proposal = Proposal::unpack(&proposal_info.data.borrow())?;
+        if proposal.governance != *governance_info.key {
+            return Err(AmmError::InvalidProposal.into());
+        }
+        if proposal.executed {
+            return Err(AmmError::ProposalAlreadyExecuted.into());
+        }
+        if proposal.approval_count() < governance.threshold as u32 {
+            return Err(AmmError::ProposalNotApproved.into());
+        }
+        let authority_seeds = [
+            GOVERNANCE_SEED.as_bytes(),
+            governance_info.key.as_ref(),
+            &[governance.nonce],
+        ];
+        let authority_key = Pubkey::create_program_address(&authority_seeds, program_id)
+            .or(Err(AmmError::InvalidProgramAddress))?;
+        if *authority_info.key != authority_key {
+            return Err(AmmError::InvalidProgramAddress.into());
+        }
+
+        proposal.executed = true;
+        let instruction = Instruction {
+            program_id: *program_id,
+            accounts: proposal.accounts.clone(),
+            data: proposal.data.clone(),
+        };
+        Proposal::pack(proposal, &mut proposal_info.data.borrow_mut())?;
+        invoke_signed(&instruction, accounts, &[&authority_seeds[..]])
+    }
+
+    /// Processes a [SetPoolCreation](enum.Instruction.html).
+    pub fn process_set_pool_creation(
+        program_id: &Pubkey,
+        permissionless_pools: bool,
+        mint_list_mode: MintListMode,
+        pool_creation_fee: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+
+        let mut state = Self::unpack_owned_state(program_id, state_info, cur_state_owner_info)?;
+        state.permissionless_pools = permissionless_pools;
+        state.mint_list_mode = mint_list_mode;
+        state.pool_creation_fee = pool_creation_fee;
+        Self::pack_state(&state, state_info)
+    }
+
+    /// Processes an [UpdateMintList](enum.Instruction.html).
+    pub fn process_update_mint_list(
+        program_id: &Pubkey,
+        mint: Pubkey,
+        listed: bool,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+        let mint_list_info = next_account_info(account_info_iter)?;
+        let system_info = next_account_info(account_info_iter)?;
+        let rent_info = next_account_info(account_info_iter)?;
+
+        Self::unpack_owned_state(program_id, state_info, cur_state_owner_info)?;
+        let (mint_list_key, mint_list_bump) = find_mint_list_address(program_id);
+        if mint_list_key != *mint_list_info.key {
+            return Err(AmmError::InvalidMintListAddress.into());
+        }
+        if mint_list_info.data_is_empty() {
+            Self::create_or_allocate_account_raw(
+                *program_id,
+                mint_list_info,
+                rent_info,
+                system_info,
+                cur_state_owner_info,
+                MintList::LEN,
+                &[
+                    MINT_LIST_SEED.as_bytes(),
+                    program_id.as_ref(),
+                    &[mint_list_bump],
+                ],
+            )?;
+        }
+        let mut mint_list = MintList::unpack_unchecked(&mint_list_info.data.borrow())?;
+        mint_list.is_initialized = true;
+        if listed {
+            mint_list.insert(mint)?;
+        } else {
+            mint_list.remove(&mint);
+        }
+        mint_list.pack_into_slice(&mut mint_list_info.data.borrow_mut());
+        Ok(())
+    }
+
+    /// Processes a [ClosePool](enum.Instruction.html).
+    pub fn process_close_pool(
+        program_id: &Pubkey,
+        wind_down: bool,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let fee_token_a_info = next_account_info(account_info_iter)?;
+        let fee_token_b_info = next_account_info(account_info_iter)?;
+        let pool_marker_info = next_account_info(account_info_iter)?;
+        let recipient_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let state = Self::unpack_owned_state(program_id, state_info, cur_state_owner_info)?;
+        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        if !token_swap.is_initialized() {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if token_swap.orderbook().has_orders {
+            return Err(AmmError::OrdersOutstanding.into());
+        }
+        Self::check_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            token_a_info,
+            token_b_info,
+            pool_mint_info,
+            token_program_info,
+            Some(fee_token_a_info),
+            Some(fee_token_b_info),
+        )?;
+        Self::check_fee_account(&token_swap, &state, fee_token_a_info, token_swap.token_a_mint())?;
+        Self::check_fee_account(&token_swap, &state, fee_token_b_info, token_swap.token_b_mint())?;
+        if recipient_info.key == swap_info.key || recipient_info.key == pool_marker_info.key {
+            return Err(AmmError::InvalidInput.into());
+        }
+        let pool_marker_key = find_pool_marker_address(
+            program_id,
+            token_swap.token_a_mint(),
+            token_swap.token_b_mint(),
+            token_swap.swap_curve().curve_type,
+        )
+        .0;
+        if *pool_marker_info.key != pool_marker_key {
+            return Err(AmmError::InvalidPoolMarkerAddress.into());
+        }
+
+        //only the locked minimum supply is left, liquidity providers withdraw
+        //their share first, even from a paused pool. A wind down closes a
+        //paused pool whatever its supply, the pool tokens left are worthless
+        let pool_mint = Self::unpack_mint(pool_mint_info, token_swap.token_program_id())?;
+        if wind_down {
+            if !token_swap.is_paused() {
+                return Err(AmmError::PoolNotClosable.into());
+            }
+        } else if to_u128(pool_mint.supply)? > MIN_LP_SUPPLY {
+            return Err(AmmError::PoolNotClosable.into());
+        }
+
+        //sweep the tokens left to the fee owner, then close the token accounts
+        let token_a = Self::unpack_token_account(token_a_info, token_swap.token_program_id())?;
+        let token_b = Self::unpack_token_account(token_b_info, token_swap.token_program_id())?;
+        for &(token_info, amount, fee_token_info) in &[
+            (token_a_info, token_a.amount, fee_token_a_info),
+            (token_b_info, token_b.amount, fee_token_b_info),
+        ] {
+            if amount > 0 {
+                Self::token_transfer(
                     swap_info.key,
                     token_program_info.clone(),
-                    swap_token_b_info.clone(),
-                    destination_info.clone(),
+                    token_info.clone(),
+                    fee_token_info.clone(),
                     authority_info.clone(),
                     token_swap.nonce(),
+                    amount,
+                )?;
+            }
+            Self::token_close_account(
+                swap_info.key,
+                token_program_info.clone(),
+                token_info.clone(),
+                recipient_info.clone(),
+                authority_info.clone(),
+                token_swap.nonce(),
+            )?;
+        }
+
+        //the marker of the pair and curve type may be of another pool for
+        //pools created before the markers
+        let marks_pool = !pool_marker_info.data_is_empty()
+            && PoolMarker::unpack_unchecked(&pool_marker_info.data.borrow())?.pool == *swap_info.key;
+
+        //zeroed data marks the pool and its marker uninitialized, which frees
+        //the pair and curve type for a new pool, the runtime deletes the
+        //accounts once their lamports are gone
+        let closed_infos = if marks_pool {
+            vec![swap_info, pool_marker_info]
+        } else {
+            vec![swap_info]
+        };
+        for account_info in closed_infos {
+            account_info.data.borrow_mut().fill(0);
+            let recipient_lamports = recipient_info
+                .lamports()
+                .checked_add(account_info.lamports())
+                .ok_or(AmmError::CalculationFailure)?;
+            **recipient_info.lamports.borrow_mut() = recipient_lamports;
+            **account_info.lamports.borrow_mut() = 0;
+        }
+        Ok(())
+    }
+
+    /// Processes a [SetProtocolFeeMode](enum.Instruction.html).
+    pub fn process_set_protocol_fee_mode(
+        program_id: &Pubkey,
+        lp_protocol_fee: bool,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        Self::check_state_account(program_id, state_info.key)?;
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if !cur_state_owner_info.is_signer{
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if *cur_state_owner_info.key != state.state_owner {
+            return Err(AmmError::InvalidOwner.into());
+        }
+
+        let mut token_swap = Self::unpack_swap(swap_info)?;
+        Self::check_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            token_a_info,
+            token_b_info,
+            pool_mint_info,
+            token_program_info,
+            None,
+            None,
+        )?;
+        // the accrued fees are measured on the constant product invariant
+        if lp_protocol_fee && token_swap.swap_curve().curve_type != CurveType::ConstantProduct {
+            return Err(AmmError::UnsupportedCurveOperation.into());
+        }
+        // the owner share of the invariant growth assumes a static return fee
+        if lp_protocol_fee && token_swap.fees().max_return_fee_numerator != 0 {
+            return Err(AmmError::InvalidFee.into());
+        }
+
+        let token_a = Self::unpack_token_account(token_a_info, token_swap.token_program_id())?;
+        let token_b = Self::unpack_token_account(token_b_info, token_swap.token_program_id())?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, token_swap.token_program_id())?;
+        Self::mint_protocol_fee(
+            swap_info,
+            &token_swap,
+            &state,
+            authority_info,
+            pool_mint_info,
+            pool_fee_account_info,
+            token_program_info,
+            token_a.amount,
+            token_b.amount,
+            to_u128(pool_mint.supply)?,
+        )?;
+
+        token_swap.set_lp_protocol_fee(lp_protocol_fee);
+        token_swap.set_root_k_last(0);
+        Self::update_root_k_last(&mut token_swap, token_a.amount, token_b.amount)?;
+        SwapVersion::pack(token_swap, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [SetOrderbook](enum.Instruction.html).
+    pub fn process_set_orderbook(
+        program_id: &Pubkey,
+        depth_bps: u16,
+        spread_bps: u16,
+        levels: u8,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+        let open_orders_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Self::check_state_account(program_id, state_info.key)?;
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if !cur_state_owner_info.is_signer{
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if *cur_state_owner_info.key != state.state_owner {
+            return Err(AmmError::InvalidOwner.into());
+        }
+
+        let mut token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        if open_orders_info.owner != token_swap.dex_program_id() {
+            return Err(AmmError::IncorrectMarketAccount.into());
+        }
+        let orderbook = token_swap.orderbook_mut();
+        if orderbook.has_orders && orderbook.open_orders != *open_orders_info.key {
+            return Err(AmmError::OrdersOutstanding.into());
+        }
+        let new_orderbook = Orderbook {
+            open_orders: *open_orders_info.key,
+            depth_bps,
+            spread_bps,
+            levels,
+            has_orders: orderbook.has_orders,
+        };
+        new_orderbook.validate()?;
+        *orderbook = new_orderbook;
+
+        SwapVersion::pack(token_swap, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Checks the accounts of an orderbook instruction against the pool,
+    /// returns the market and whether token A is its coin
+    fn check_market_accounts(
+        program_id: &Pubkey,
+        token_swap: &SwapVersion,
+        pool_market: &PoolMarketInfos,
+    ) -> Result<(MarketInfo, bool), ProgramError> {
+        if pool_market.swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if *pool_market.authority_info.key
+            != Self::authority_id(program_id, pool_market.swap_info.key, token_swap.nonce())?
+        {
+            return Err(AmmError::InvalidProgramAddress.into());
+        }
+        if *pool_market.token_a_info.key != *token_swap.token_a_account()
+            || *pool_market.token_b_info.key != *token_swap.token_b_account()
+        {
+            return Err(AmmError::IncorrectSwapAccount.into());
+        }
+        if *pool_market.token_program_info.key != *token_swap.token_program_id() {
+            return Err(AmmError::IncorrectTokenProgramId.into());
+        }
+        let open_orders = token_swap.orderbook().open_orders;
+        if open_orders == Pubkey::default() || *pool_market.open_orders_info.key != open_orders {
+            return Err(AmmError::IncorrectMarketAccount.into());
+        }
+        Self::unpack_market(
+            token_swap,
+            pool_market.dex_program_info,
+            pool_market.market_info,
+            pool_market.request_queue_info,
+            pool_market.event_queue_info,
+            pool_market.bids_info,
+            pool_market.asks_info,
+            pool_market.coin_vault_info,
+            pool_market.pc_vault_info,
+        )
+    }
+
+    /// Checks the market accounts against the market of the pool and reads
+    /// it, returns the market and whether token A is its coin
+    #[allow(clippy::too_many_arguments)]
+    fn unpack_market(
+        token_swap: &SwapVersion,
+        dex_program_info: &AccountInfo,
+        market_info: &AccountInfo,
+        request_queue_info: &AccountInfo,
+        event_queue_info: &AccountInfo,
+        bids_info: &AccountInfo,
+        asks_info: &AccountInfo,
+        coin_vault_info: &AccountInfo,
+        pc_vault_info: &AccountInfo,
+    ) -> Result<(MarketInfo, bool), ProgramError> {
+        if *dex_program_info.key != *token_swap.dex_program_id()
+            || *market_info.key != *token_swap.market_id()
+            || market_info.owner != dex_program_info.key
+        {
+            return Err(AmmError::IncorrectMarketAccount.into());
+        }
+
+        let market = MarketInfo::unpack(&market_info.data.borrow())?;
+        if *request_queue_info.key != market.request_queue
+            || *event_queue_info.key != market.event_queue
+            || *bids_info.key != market.bids
+            || *asks_info.key != market.asks
+            || *coin_vault_info.key != market.coin_vault
+            || *pc_vault_info.key != market.pc_vault
+        {
+            return Err(AmmError::IncorrectMarketAccount.into());
+        }
+        let coin_is_a = if market.coin_mint == *token_swap.token_a_mint()
+            && market.pc_mint == *token_swap.token_b_mint()
+        {
+            true
+        } else if market.coin_mint == *token_swap.token_b_mint()
+            && market.pc_mint == *token_swap.token_a_mint()
+        {
+            false
+        } else {
+            return Err(AmmError::IncorrectMarketAccount.into());
+        };
+        Ok((market, coin_is_a))
+    }
+
+    /// Issues a Serum dex instruction signed by the swap authority
+    fn invoke_market(pool_market: &PoolMarketInfos, nonce: u8, ix: &Instruction) -> ProgramResult {
+        let swap_bytes = pool_market.swap_info.key.to_bytes();
+        let authority_signature_seeds = [&swap_bytes[..32], &[nonce]];
+        let signers = &[&authority_signature_seeds[..]];
+        invoke_signed(ix, &pool_market.to_vec(), signers)
+    }
+
+    /// Cancels the pool orders resting on the market
+    fn cancel_pool_orders(pool_market: &PoolMarketInfos, nonce: u8) -> ProgramResult {
+        let open_orders = OpenOrdersInfo::unpack(&pool_market.open_orders_info.data.borrow())?;
+        for client_order_id in open_orders
+            .client_order_ids
+            .into_iter()
+            .filter(|client_order_id| is_pool_client_order_id(*client_order_id))
+        {
+            let ix = serum_dex::instruction::cancel_order_by_client_order_id(
+                pool_market.dex_program_info.key,
+                pool_market.market_info.key,
+                pool_market.bids_info.key,
+                pool_market.asks_info.key,
+                pool_market.open_orders_info.key,
+                pool_market.authority_info.key,
+                pool_market.event_queue_info.key,
+                client_order_id,
+            )
+            .map_err(|_| AmmError::IncorrectMarketAccount)?;
+            Self::invoke_market(pool_market, nonce, &ix)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the free funds of the open orders account into the pool
+    fn settle_pool_funds(pool_market: &PoolMarketInfos, nonce: u8, coin_is_a: bool) -> ProgramResult {
+        let (coin_wallet_info, pc_wallet_info) = if coin_is_a {
+            (pool_market.token_a_info, pool_market.token_b_info)
+        } else {
+            (pool_market.token_b_info, pool_market.token_a_info)
+        };
+        let ix = serum_dex::instruction::settle_funds(
+            pool_market.dex_program_info.key,
+            pool_market.market_info.key,
+            pool_market.token_program_info.key,
+            pool_market.open_orders_info.key,
+            pool_market.authority_info.key,
+            pool_market.coin_vault_info.key,
+            coin_wallet_info.key,
+            pool_market.pc_vault_info.key,
+            pc_wallet_info.key,
+            None,
+            pool_market.vault_signer_info.key,
+        )
+        .map_err(|_| AmmError::IncorrectMarketAccount)?;
+        Self::invoke_market(pool_market, nonce, &ix)
+    }
+
+    /// Posts the pool orders around the price of the curve, returns whether
+    /// any order was sent to the market
+    fn place_pool_orders(
+        pool_market: &PoolMarketInfos,
+        token_swap: &SwapVersion,
+        market: &MarketInfo,
+        coin_is_a: bool,
+    ) -> Result<bool, ProgramError> {
+        let token_a = Self::unpack_token_account(pool_market.token_a_info, token_swap.token_program_id())?;
+        let token_b = Self::unpack_token_account(pool_market.token_b_info, token_swap.token_program_id())?;
+        let (coin_reserve, pc_reserve, direction, coin_wallet_info, pc_wallet_info) = if coin_is_a {
+            (token_a.amount, token_b.amount, TradeDirection::AtoB, pool_market.token_a_info, pool_market.token_b_info)
+        } else {
+            (token_b.amount, token_a.amount, TradeDirection::BtoA, pool_market.token_b_info, pool_market.token_a_info)
+        };
+        if coin_reserve == 0 || pc_reserve == 0 || market.coin_lot_size == 0 || market.pc_lot_size == 0 {
+            return Ok(false);
+        }
+
+        // price of the curve for selling a basis point of the coin reserve
+        let quote = token_swap
+            .swap_curve()
+            .calculator
+            .swap_without_fees(
+                to_u128(std::cmp::max(1, coin_reserve / BPS_DENOMINATOR))?,
+                to_u128(coin_reserve)?,
+                to_u128(pc_reserve)?,
+                direction,
+            )
+            .ok_or(AmmError::ZeroTradingTokens)?;
+
+        let coin_lot_size = u128::from(market.coin_lot_size);
+        let pc_lot_size = u128::from(market.pc_lot_size);
+        let mut has_orders = false;
+        for &(side, reserve, order_payer_info) in &[
+            (OrderSide::Bid, pc_reserve, pc_wallet_info),
+            (OrderSide::Ask, coin_reserve, coin_wallet_info),
+        ] {
+            let orders = token_swap
+                .orderbook()
+                .orders(side, reserve, quote.destination_amount_swapped, quote.source_amount_swapped)
+                .ok_or(AmmError::CalculationFailure)?;
+            for (client_order_id, quantity, price_numerator, price_denominator) in orders {
+                // the market prices are in pc lots per coin lot, bids are
+                // rounded down and asks up so the pool keeps the spread
+                let lot_price_numerator = price_numerator
+                    .checked_mul(coin_lot_size)
+                    .ok_or(AmmError::CalculationFailure)?;
+                let lot_price_denominator = price_denominator
+                    .checked_mul(pc_lot_size)
+                    .ok_or(AmmError::CalculationFailure)?;
+                let (dex_side, limit_price, max_coin_qty, max_native_pc_qty) = match side {
+                    OrderSide::Bid => (
+                        Side::Bid,
+                        lot_price_numerator / lot_price_denominator,
+                        u128::from(quantity)
+                            .checked_mul(price_denominator)
+                            .and_then(|coin| coin.checked_div(price_numerator))
+                            .and_then(|coin| coin.checked_div(coin_lot_size))
+                            .ok_or(AmmError::CalculationFailure)?,
+                        quantity,
+                    ),
+                    OrderSide::Ask => (
+                        Side::Ask,
+                        lot_price_numerator
+                            .checked_add(lot_price_denominator - 1)
+                            .ok_or(AmmError::CalculationFailure)?
+                            / lot_price_denominator,
+                        u128::from(quantity) / coin_lot_size,
+                        u64::MAX,
+                    ),
+                };
+                let (limit_price, max_coin_qty, max_native_pc_qty) = match (
+                    NonZeroU64::new(to_u64(limit_price)?),
+                    NonZeroU64::new(to_u64(max_coin_qty)?),
+                    NonZeroU64::new(max_native_pc_qty),
+                ) {
+                    (Some(limit_price), Some(max_coin_qty), Some(max_native_pc_qty)) => {
+                        (limit_price, max_coin_qty, max_native_pc_qty)
+                    }
+                    _ => continue,
+                };
+
+                let ix = serum_dex::instruction::new_order(
+                    pool_market.market_info.key,
+                    pool_market.open_orders_info.key,
+                    pool_market.request_queue_info.key,
+                    pool_market.event_queue_info.key,
+                    pool_market.bids_info.key,
+                    pool_market.asks_info.key,
+                    order_payer_info.key,
+                    pool_market.authority_info.key,
+                    pool_market.coin_vault_info.key,
+                    pool_market.pc_vault_info.key,
+                    pool_market.token_program_info.key,
+                    pool_market.rent_info.key,
+                    None,
+                    pool_market.dex_program_info.key,
+                    dex_side,
+                    limit_price,
+                    max_coin_qty,
+                    OrderType::PostOnly,
+                    client_order_id,
+                    SelfTradeBehavior::DecrementTake,
+                    u16::MAX,
+                    max_native_pc_qty,
+                )
+                .map_err(|_| AmmError::IncorrectMarketAccount)?;
+                Self::invoke_market(pool_market, token_swap.nonce(), &ix)?;
+                has_orders = true;
+            }
+        }
+        Ok(has_orders)
+    }
+
+    /// Processes a [RefreshOrders](enum.Instruction.html).
+    pub fn process_refresh_orders(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_market = PoolMarketInfos::next(account_info_iter)?;
+        let state_info = next_account_info(account_info_iter)?;
+        let cur_state_owner_info = next_account_info(account_info_iter)?;
+
+        Self::check_state_account(program_id, state_info.key)?;
+        let state = ProgramStateV2::unpack_from_slice(&state_info.data.borrow())?;
+        if !state.is_initialized() {
+            return Err(AmmError::NotInitializedState.into());
+        }
+        if !cur_state_owner_info.is_signer{
+            return Err(AmmError::InvalidSigner.into());
+        }
+        if *cur_state_owner_info.key != state.state_owner {
+            return Err(AmmError::InvalidOwner.into());
+        }
+
+        let mut token_swap = Self::unpack_swap(pool_market.swap_info)?;
+        if state.is_paused() || token_swap.is_paused() {
+            return Err(AmmError::Paused.into());
+        }
+        if !token_swap.orderbook().is_enabled() {
+            return Err(AmmError::InvalidOrderbookParams.into());
+        }
+        let (market, coin_is_a) = Self::check_market_accounts(program_id, &token_swap, &pool_market)?;
+        let reserve_a = Self::unpack_token_account(pool_market.token_a_info, token_swap.token_program_id())?.amount;
+        let reserve_b = Self::unpack_token_account(pool_market.token_b_info, token_swap.token_program_id())?.amount;
+
+        Self::cancel_pool_orders(&pool_market, token_swap.nonce())?;
+        Self::settle_pool_funds(&pool_market, token_swap.nonce(), coin_is_a)?;
+        let placed = Self::place_pool_orders(&pool_market, &token_swap, &market, coin_is_a)?;
+        let open_orders = OpenOrdersInfo::unpack(&pool_market.open_orders_info.data.borrow())?;
+        token_swap.orderbook_mut().has_orders = placed || !open_orders.is_empty();
+
+        Self::update_price_oracle(pool_market.swap_info, token_swap, reserve_a, reserve_b)
+    }
+
+    /// Processes a [SettleFunds](enum.Instruction.html).
+    pub fn process_settle_funds(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_market = PoolMarketInfos::next(account_info_iter)?;
+
+        let token_swap = Self::unpack_swap(pool_market.swap_info)?;
+        let (_market, coin_is_a) = Self::check_market_accounts(program_id, &token_swap, &pool_market)?;
+        let reserve_a = Self::unpack_token_account(pool_market.token_a_info, token_swap.token_program_id())?.amount;
+        let reserve_b = Self::unpack_token_account(pool_market.token_b_info, token_swap.token_program_id())?.amount;
+
+        Self::settle_pool_funds(&pool_market, token_swap.nonce(), coin_is_a)?;
+
+        Self::update_price_oracle(pool_market.swap_info, token_swap, reserve_a, reserve_b)
+    }
+
+    /// Processes a [CancelAllOrders](enum.Instruction.html).
+    pub fn process_cancel_all_orders(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let pool_market = PoolMarketInfos::next(account_info_iter)?;
+
+        let mut token_swap = Self::unpack_swap(pool_market.swap_info)?;
+        let (_market, coin_is_a) = Self::check_market_accounts(program_id, &token_swap, &pool_market)?;
+        let reserve_a = Self::unpack_token_account(pool_market.token_a_info, token_swap.token_program_id())?.amount;
+        let reserve_b = Self::unpack_token_account(pool_market.token_b_info, token_swap.token_program_id())?.amount;
+
+        Self::cancel_pool_orders(&pool_market, token_swap.nonce())?;
+        Self::settle_pool_funds(&pool_market, token_swap.nonce(), coin_is_a)?;
+        let open_orders = OpenOrdersInfo::unpack(&pool_market.open_orders_info.data.borrow())?;
+        if !open_orders.is_empty() {
+            // fills are credited to the open orders account once the event
+            // queue is consumed, cancel again after the crank
+            msg!("Filled orders are waiting for the event queue to be consumed");
+        }
+        token_swap.orderbook_mut().has_orders = !open_orders.is_empty();
+
+        Self::update_price_oracle(pool_market.swap_info, token_swap, reserve_a, reserve_b)
+    }
+
+    /// Processes an [Instruction](enum.Instruction.html).
+    pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+        let instruction = AmmInstruction::unpack(input)?;
+        match instruction {
+            AmmInstruction::UpdateState(UpdateStateInstruction {
+                initial_supply,
+                fees,
+                swap_curve,
+            }) => {
+                msg!("Instruction: UpdateState");
+                Self::process_update_state(
+                    program_id,
+                    initial_supply,
+                    fees,
+                    swap_curve,
+                    accounts,
+                )
+            }
+            AmmInstruction::Initialize(InitializeInstruction {
+                nonce,
+                fees,
+                swap_curve,
+                derived_address,
+            }) => {
+                msg!("Instruction: Init");
+                Self::process_initialize(
+                    program_id,
+                    nonce,
+                    fees,
+                    swap_curve,
+                    derived_address,
+                    accounts,
+                )
+            }
+            AmmInstruction::Swap(SwapInstruction {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                msg!("Instruction: Swap");
+                Self::process_swap(program_id, amount_in, minimum_amount_out, accounts)
+            }
+            AmmInstruction::SwapExactAmountOut(SwapExactAmountOutInstruction {
+                amount_out,
+                maximum_amount_in,
+            }) => {
+                msg!("Instruction: SwapExactAmountOut");
+                Self::process_swap_exact_amount_out(program_id, amount_out, maximum_amount_in, accounts)
+            }
+            AmmInstruction::RouteSwap(RouteSwapInstruction {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                msg!("Instruction: RouteSwap");
+                Self::process_route_swap(program_id, amount_in, minimum_amount_out, accounts)
+            }
+            AmmInstruction::DepositAllTokenTypes(DepositInstruction {
+                pool_token_amount,
+                maximum_token_a_amount,
+                maximum_token_b_amount,
+            }) => {
+                msg!("Instruction: DepositAllTokenTypes");
+                Self::process_deposit_all_token_types(
+                    program_id,
+                    pool_token_amount,
+                    maximum_token_a_amount,
+                    maximum_token_b_amount,
+                    accounts,
+                )
+            }
+            AmmInstruction::WithdrawAllTokenTypes(WithdrawInstruction {
+                pool_token_amount,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                msg!("Instruction: WithdrawAllTokenTypes");
+                Self::process_withdraw_all_token_types(
+                    program_id,
+                    pool_token_amount,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                    accounts,
+                )
+            }
+            AmmInstruction::DepositSingleTokenTypeExactAmountIn(
+                DepositSingleTokenTypeExactAmountIn {
+                    source_token_amount,
+                    minimum_pool_token_amount,
+                },
+            ) => {
+                msg!("Instruction: DepositSingleTokenTypeExactAmountIn");
+                Self::process_deposit_single_token_type_exact_amount_in(
+                    program_id,
+                    source_token_amount,
+                    minimum_pool_token_amount,
+                    accounts,
+                )
+            }
+            AmmInstruction::WithdrawSingleTokenTypeExactAmountOut(
+                WithdrawSingleTokenTypeExactAmountOut {
                     destination_token_amount,
-                )?;
+                    maximum_pool_token_amount,
+                },
+            ) => {
+                msg!("Instruction: WithdrawSingleTokenTypeExactAmountOut");
+                Self::process_withdraw_single_token_type_exact_amount_out(
+                    program_id,
+                    destination_token_amount,
+                    maximum_pool_token_amount,
+                    accounts,
+                )
+            }
+            AmmInstruction::InitializeConcentratedPool(InitializeConcentratedPoolInstruction {
+                nonce,
+                tick_spacing,
+                initial_sqrt_price,
+                fees,
+            }) => {
+                msg!("Instruction: InitializeConcentratedPool");
+                Self::process_initialize_concentrated_pool(
+                    program_id,
+                    nonce,
+                    tick_spacing,
+                    initial_sqrt_price,
+                    fees,
+                    accounts,
+                )
+            }
+            AmmInstruction::OpenPosition(OpenPositionInstruction {
+                tick_lower,
+                tick_upper,
+            }) => {
+                msg!("Instruction: OpenPosition");
+                Self::process_open_position(program_id, tick_lower, tick_upper, accounts)
+            }
+            AmmInstruction::IncreaseLiquidity(IncreaseLiquidityInstruction {
+                liquidity,
+                maximum_token_a_amount,
+                maximum_token_b_amount,
+            }) => {
+                msg!("Instruction: IncreaseLiquidity");
+                Self::process_increase_liquidity(
+                    program_id,
+                    liquidity,
+                    maximum_token_a_amount,
+                    maximum_token_b_amount,
+                    accounts,
+                )
+            }
+            AmmInstruction::DecreaseLiquidity(DecreaseLiquidityInstruction {
+                liquidity,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                msg!("Instruction: DecreaseLiquidity");
+                Self::process_decrease_liquidity(
+                    program_id,
+                    liquidity,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                    accounts,
+                )
+            }
+            AmmInstruction::CollectFees => {
+                msg!("Instruction: CollectFees");
+                Self::process_collect_fees(program_id, accounts)
+            }
+            AmmInstruction::ConcentratedSwap(ConcentratedSwapInstruction {
+                amount_in,
+                minimum_amount_out,
+                sqrt_price_limit,
+            }) => {
+                msg!("Instruction: ConcentratedSwap");
+                Self::process_concentrated_swap(
+                    program_id,
+                    amount_in,
+                    minimum_amount_out,
+                    sqrt_price_limit,
+                    accounts,
+                )
+            }
+            AmmInstruction::RampAmp(RampAmpInstruction {
+                target_amp,
+                stop_ramp_ts,
+            }) => {
+                msg!("Instruction: RampAmp");
+                Self::process_ramp_amp(program_id, Some((target_amp, stop_ramp_ts)), accounts)
+            }
+            AmmInstruction::StopRampAmp => {
+                msg!("Instruction: StopRampAmp");
+                Self::process_ramp_amp(program_id, None, accounts)
+            }
+            AmmInstruction::SetPause(SetPauseInstruction { is_paused }) => {
+                msg!("Instruction: SetPause");
+                Self::process_set_pause(program_id, is_paused, accounts)
+            }
+            AmmInstruction::SetOrderbook(SetOrderbookInstruction {
+                depth_bps,
+                spread_bps,
+                levels,
+            }) => {
+                msg!("Instruction: SetOrderbook");
+                Self::process_set_orderbook(program_id, depth_bps, spread_bps, levels, accounts)
+            }
+            AmmInstruction::RefreshOrders => {
+                msg!("Instruction: RefreshOrders");
+                Self::process_refresh_orders(program_id, accounts)
+            }
+            AmmInstruction::SettleFunds => {
+                msg!("Instruction: SettleFunds");
+                Self::process_settle_funds(program_id, accounts)
+            }
+            AmmInstruction::CancelAllOrders => {
+                msg!("Instruction: CancelAllOrders");
+                Self::process_cancel_all_orders(program_id, accounts)
+            }
+            AmmInstruction::SplitSwap(SplitSwapInstruction {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                msg!("Instruction: SplitSwap");
+                Self::process_split_swap(program_id, amount_in, minimum_amount_out, accounts)
+            }
+            AmmInstruction::SetProtocolFeeMode(SetProtocolFeeModeInstruction { lp_protocol_fee }) => {
+                msg!("Instruction: SetProtocolFeeMode");
+                Self::process_set_protocol_fee_mode(program_id, lp_protocol_fee, accounts)
+            }
+            AmmInstruction::Migrate => {
+                msg!("Instruction: Migrate");
+                Self::process_migrate(program_id, accounts)
+            }
+            AmmInstruction::ProposeStateOwner => {
+                msg!("Instruction: ProposeStateOwner");
+                Self::process_propose_state_owner(program_id, accounts)
+            }
+            AmmInstruction::AcceptStateOwner => {
+                msg!("Instruction: AcceptStateOwner");
+                Self::process_accept_state_owner(program_id, accounts)
+            }
+            AmmInstruction::CancelStateOwnerProposal => {
+                msg!("Instruction: CancelStateOwnerProposal");
+                Self::process_cancel_state_owner_proposal(program_id, accounts)
+            }
+            AmmInstruction::QueueStateUpdate(QueueStateUpdateInstruction {
+                delay,
+                update: UpdateStateInstruction {
+                    initial_supply,
+                    fees,
+                    swap_curve,
+                },
+            }) => {
+                msg!("Instruction: QueueStateUpdate");
+                Self::process_queue_state_update(
+                    program_id,
+                    delay,
+                    initial_supply,
+                    fees,
+                    swap_curve,
+                    accounts,
+                )
+            }
+            AmmInstruction::ExecuteStateUpdate => {
+                msg!("Instruction: ExecuteStateUpdate");
+                Self::process_execute_state_update(program_id, accounts)
+            }
+            AmmInstruction::CancelStateUpdate => {
+                msg!("Instruction: CancelStateUpdate");
+                Self::process_cancel_state_update(program_id, accounts)
+            }
+            AmmInstruction::CreateGovernance(CreateGovernanceInstruction {
+                nonce,
+                threshold,
+                signers,
+            }) => {
+                msg!("Instruction: CreateGovernance");
+                Self::process_create_governance(program_id, nonce, threshold, signers, accounts)
+            }
+            AmmInstruction::CreateProposal(CreateProposalInstruction {
+                accounts: proposal_accounts,
+                data,
+            }) => {
+                msg!("Instruction: CreateProposal");
+                Self::process_create_proposal(program_id, proposal_accounts, data, accounts)
+            }
+            AmmInstruction::ApproveProposal => {
+                msg!("Instruction: ApproveProposal");
+                Self::process_approve_proposal(program_id, accounts)
+            }
+            AmmInstruction::ExecuteProposal => {
+                msg!("Instruction: ExecuteProposal");
+                Self::process_execute_proposal(program_id, accounts)
+            }
+            AmmInstruction::SetPoolCreation(SetPoolCreationInstruction {
+                permissionless_pools,
+                mint_list_mode,
+                pool_creation_fee,
+            }) => {
+                msg!("Instruction: SetPoolCreation");
+                Self::process_set_pool_creation(
+                    program_id,
+                    permissionless_pools,
+                    mint_list_mode,
+                    pool_creation_fee,
+                    accounts,
+                )
+            }
+            AmmInstruction::UpdateMintList(UpdateMintListInstruction { mint, listed }) => {
+                msg!("Instruction: UpdateMintList");
+                Self::process_update_mint_list(program_id, mint, listed, accounts)
+            }
+            AmmInstruction::ClosePool(ClosePoolInstruction { wind_down }) => {
+                msg!("Instruction: ClosePool");
+                Self::process_close_pool(program_id, wind_down, accounts)
+            }
+        }
+    }
+}
+
+impl PrintProgramError for AmmError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        match self {
+            AmmError::AlreadyInUse => msg!("Error: Swap account already in use"),
+            AmmError::InvalidProgramAddress => {
+                msg!("Error: Invalid program address generated from nonce and key")
+            }            
+            AmmError::InvalidStateAddress => {
+                msg!("Error: Invalid state address generated from seed")
+            }
+            AmmError::InvalidStateOwner => {
+                msg!("Error: The input account is not a owner of state account")
+            }
+            AmmError::InvalidOwner => {
+                msg!("Error: The input account owner is not the program address")
+            }
+            AmmError::InvalidOutputOwner => {
+                msg!("Error: Output pool account owner cannot be the program address")
+            }
+            AmmError::ExpectedMint => msg!("Error: Deserialized account is not an SPL Token mint"),
+            AmmError::ExpectedAccount => {
+                msg!("Error: Deserialized account is not an SPL Token account")
+            }
+            AmmError::EmptySupply => msg!("Error: Input token account empty"),
+            AmmError::InvalidDecimals => msg!("Error: Pool token mint doesn't have exact decimal"),
+            AmmError::InvalidSupply => msg!("Error: Pool token mint has a non-zero supply"),
+            AmmError::RepeatedMint => msg!("Error: Swap input token accounts have the same mint"),
+            AmmError::InvalidDelegate => msg!("Error: Token account has a delegate"),
+            AmmError::InvalidInput => msg!("Error: InvalidInput"),
+            AmmError::IncorrectSwapAccount => {
+                msg!("Error: Address of the provided swap token account is incorrect")
+            }
+            AmmError::IncorrectPoolMint => {
+                msg!("Error: Address of the provided pool token mint is incorrect")
+            }
+            AmmError::InvalidOutput => msg!("Error: InvalidOutput"),
+            AmmError::CalculationFailure => msg!("Error: CalculationFailure"),
+            AmmError::InvalidInstruction => msg!("Error: InvalidInstruction"),
+            AmmError::ExceededSlippage => {
+                msg!("Error: Swap instruction exceeds desired slippage limit")
+            }
+            AmmError::InvalidCloseAuthority => msg!("Error: Token account has a close authority"),
+            AmmError::InvalidFreezeAuthority => msg!("Error: Pool token mint has a freeze authority"),
+            AmmError::IncorrectMarketOwnerAccount => msg!("Error: Owner of Market account is incorrect"),
+            AmmError::InvalidSigner => msg!("State owner should be the signer"),
+            AmmError::NotInitializedState => msg!("Program State should be initialized before creating pool"),
+            AmmError::InvalidTickRange => {
+                msg!("Error: Tick range is out of bounds or not aligned to the tick spacing")
+            }
+            AmmError::TickListFull => {
+                msg!("Error: Concentrated pool has no room for another initialized tick")
+            }
+            AmmError::InsufficientLiquidity => msg!("Error: Position doesn't have enough liquidity"),
+            AmmError::InvalidAmpRamp => msg!("Error: Amp ramp parameters are out of bounds"),
+            AmmError::Paused => msg!("Error: Swaps, deposits and single-sided withdrawals are paused"),
+            AmmError::InvalidOrderbookParams => msg!("Error: Orderbook parameters are out of bounds"),
+            AmmError::IncorrectMarketAccount => {
+                msg!("Error: Serum market accounts don't match the pool")
+            }
+            AmmError::OrdersOutstanding => {
+                msg!("Error: Pool has orders on the Serum market, cancel them first")
+            }
+            AmmError::InvalidPoolMarkerAddress => msg!("Error: Invalid pool marker address"),
+            AmmError::PoolAlreadyExists => {
+                msg!("Error: Pool already exists for this pair and curve")
+            }
+            AmmError::AccountNotMigrated => {
+                msg!("Error: Account has an older layout, migrate it first")
+            }
+            AmmError::AlreadyMigrated => msg!("Error: Account already has the latest layout"),
+            AmmError::NoPendingStateOwner => msg!("Error: No pending state owner"),
+            AmmError::StateUpdateNotQueued => {
+                msg!("Error: Program state is initialized, queue the update")
+            }
+            AmmError::NoPendingStateUpdate => msg!("Error: No pending state update"),
+            AmmError::StateUpdateTimelocked => {
+                msg!("Error: Pending state update is still timelocked")
+            }
+            AmmError::InvalidGovernance => msg!("Error: Invalid governance signers or threshold"),
+            AmmError::NotGovernanceSigner => msg!("Error: Signer is not part of the governance"),
+            AmmError::InvalidProposal => msg!("Error: Invalid proposal"),
+            AmmError::ProposalAlreadyExecuted => msg!("Error: Proposal already executed"),
+            AmmError::ProposalNotApproved => msg!("Error: Proposal lacks approvals"),
+            AmmError::InvalidMintListAddress => msg!("Error: Invalid mint list address"),
+            AmmError::MintListFull => msg!("Error: Mint list is full"),
+            AmmError::MintNotAllowed => msg!("Error: Mint not allowed for permissionless pools"),
+            AmmError::PoolNotClosable => msg!("Error: Pool can't be closed"),
+            AmmError::InitialSupplyTooLow => msg!("Error: Initial supply too low"),
+            AmmError::IncorrectLockAccount => msg!("Error: Pool token lock account incorrect"),
+            AmmError::MinimumSupplyLocked => {
+                msg!("Error: Pool token supply can't go below the locked minimum")
+            }
+            AmmError::StateUpdateDelayTooShort => {
+                msg!("Error: State update delay is shorter than the minimum")
+            }
+
+            AmmError::IncorrectFeeAccount => msg!("Error: Pool fee token account incorrect"),
+            AmmError::ZeroTradingTokens => {
+                msg!("Error: Given pool token amount results in zero trading tokens")
+            }
+            AmmError::FeeCalculationFailure => msg!(
+                "Error: The fee calculation failed due to overflow, underflow, or unexpected 0"
+            ),
+            AmmError::ConversionFailure => msg!("Error: Conversion to or from u64 failed."),
+            AmmError::InvalidFee => {
+                msg!("Error: The provided fee does not match the program owner's constraints")
+            }
+            AmmError::IncorrectTokenProgramId => {
+                msg!("Error: The provided token program does not match the token program expected by the swap")
+            }
+            AmmError::UnsupportedCurveType => {
+                msg!("Error: The provided curve type is not supported by the program owner")
+            }
+            AmmError::InvalidCurve => {
+                msg!("Error: The provided curve parameters are invalid")
+            }
+            AmmError::UnsupportedCurveOperation => {
+                msg!("Error: The operation cannot be performed on the given curve")
+            }
+        }
+    }
+}
+
+fn to_u128(val: u64) -> Result<u128, AmmError> {
+    val.try_into().map_err(|_| AmmError::ConversionFailure)
+}
+
+fn to_u64(val: u128) -> Result<u64, AmmError> {
+    val.try_into().map_err(|_| AmmError::ConversionFailure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amm_instruction::{
+        accept_state_owner, approve_proposal, cancel_state_owner_proposal, cancel_state_update,
+        close_pool, collect_fees, concentrated_swap, create_governance, create_proposal, decrease_liquidity,
+        deposit_all_token_types, execute_proposal, execute_state_update,
+        deposit_single_token_type_exact_amount_in, increase_liquidity,
+        initialize, initialize_concentrated_pool, migrate, open_position, propose_state_owner,
+        queue_state_update, ramp_amp, set_orderbook, set_pool_creation,
+        set_pause, set_protocol_fee_mode, split_swap, stop_ramp_amp, swap, SplitSwapMarketAccounts,
+        swap_exact_amount_out, update_mint_list, update_state, withdraw_all_token_types,
+        withdraw_single_token_type_exact_amount_out,
+    };
+    use solana_program::{instruction::Instruction, program_stubs, system_program};
+    use crate::amm_stats::{ProgramState, SwapV1};
+    use crate::governance::find_governance_authority_address;
+    use crate::dex::{MARKET_LEN, MAX_ORDER_LEVELS, OPEN_ORDERS_LEN};
+    use solana_sdk::account::{create_account_for_test, create_is_signer_account_infos, Account};
+    use spl_token::state::{Account as SplAccount, AccountState, Mint};
+
+    const TOKEN_A_AMOUNT: u64 = 1_000_000;
+    const TOKEN_B_AMOUNT: u64 = 2_000_000;
+    const USER_AMOUNT: u64 = 1_000_000;
+    // pool tokens minted to the destination at initialization
+    const USER_POOL_AMOUNT: u64 = INITIAL_SWAP_POOL_AMOUNT - MIN_LP_SUPPLY as u64;
+
+    struct TestSyscallStubs {}
+    impl program_stubs::SyscallStubs for TestSyscallStubs {
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            msg!("TestSyscallStubs::sol_invoke_signed()");
+
+            let signers = signers_seeds
+                .iter()
+                .map(|seeds| Pubkey::create_program_address(seeds, &crate::id()).unwrap())
+                .collect::<Vec<_>>();
+
+            let mut new_account_infos = vec![];
+            for meta in instruction.accounts.iter() {
+                for account_info in account_infos.iter() {
+                    if meta.pubkey == *account_info.key {
+                        let mut new_account_info = account_info.clone();
+                        for signer in signers.iter() {
+                            if account_info.key == signer {
+                                new_account_info.is_signer = true;
+                            }
+                        }
+                        new_account_infos.push(new_account_info);
+                    }
+                }
+            }
+
+            if instruction.program_id == crate::id() {
+                Processor::process(&instruction.program_id, &new_account_infos, &instruction.data)
+            } else {
+                spl_token::processor::Processor::process(
+                    &instruction.program_id,
+                    &new_account_infos,
+                    &instruction.data,
+                )
+            }
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe {
+                *(var_addr as *mut Clock) = Clock::default();
+            }
+            solana_program::entrypoint::SUCCESS
+        }
+    }
+
+    fn test_syscall_stubs() {
+        use std::sync::Once;
+        static ONCE: Once = Once::new();
+
+        ONCE.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs {}));
+        });
+    }
+
+    fn do_process_instruction(
+        instruction: Instruction,
+        accounts: Vec<&mut Account>,
+    ) -> ProgramResult {
+        test_syscall_stubs();
+
+        // approximate the logic in the actual runtime which runs the instruction
+        // and only updates accounts if the instruction is successful
+        let mut account_clones = accounts.iter().map(|x| (*x).clone()).collect::<Vec<_>>();
+        let mut meta = instruction
+            .accounts
+            .iter()
+            .zip(account_clones.iter_mut())
+            .map(|(account_meta, account)| (&account_meta.pubkey, account_meta.is_signer, account))
+            .collect::<Vec<_>>();
+        let account_infos = create_is_signer_account_infos(&mut meta);
+        let res = Processor::process(&instruction.program_id, &account_infos, &instruction.data);
+
+        if res.is_ok() {
+            let mut account_metas = instruction
+                .accounts
+                .iter()
+                .zip(accounts)
+                .map(|(account_meta, account)| (&account_meta.pubkey, account))
+                .collect::<Vec<_>>();
+            for account_info in account_infos.iter() {
+                for account_meta in account_metas.iter_mut() {
+                    if account_info.key == account_meta.0 {
+                        let account = &mut account_meta.1;
+                        account.owner = *account_info.owner;
+                        account.lamports = **account_info.lamports.borrow();
+                        account.data = account_info.data.borrow().to_vec();
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+        let mut account = Account::new(0, SplAccount::get_packed_len(), &spl_token::id());
+        SplAccount::pack(
+            SplAccount {
+                mint: *mint,
+                owner: *owner,
+                amount,
+                state: AccountState::Initialized,
+                ..SplAccount::default()
+            },
+            &mut account.data,
+        )
+        .unwrap();
+        account
+    }
+
+    fn mint_account(mint_authority: &Pubkey, decimals: u8) -> Account {
+        let mut account = Account::new(0, Mint::get_packed_len(), &spl_token::id());
+        Mint::pack(
+            Mint {
+                mint_authority: COption::Some(*mint_authority),
+                supply: 0,
+                decimals,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            },
+            &mut account.data,
+        )
+        .unwrap();
+        account
+    }
+
+    fn token_amount(account: &Account) -> u64 {
+        SplAccount::unpack(&account.data).unwrap().amount
+    }
+
+    fn state_fees() -> Fees {
+        Fees {
+            fixed_fee_numerator: SWAP_CONSTRAINTS.fees.fixed_fee_numerator,
+            return_fee_numerator: SWAP_CONSTRAINTS.fees.return_fee_numerator,
+            fee_denominator: SWAP_CONSTRAINTS.fees.fee_denominator,
+            host_fee_numerator: SWAP_CONSTRAINTS.fees.host_fee_numerator,
+            host_fee_denominator: SWAP_CONSTRAINTS.fees.host_fee_denominator,
+            owner_withdraw_fee_numerator: SWAP_CONSTRAINTS.fees.owner_withdraw_fee_numerator,
+            max_return_fee_numerator: 0,
+        }
+    }
+
+    struct SwapAccountInfo {
+        nonce: u8,
+        authority_key: Pubkey,
+        authority_account: Account,
+        state_key: Pubkey,
+        state_account: Account,
+        fee_owner_key: Pubkey,
+        fee_owner_account: Account,
+        swap_key: Pubkey,
+        swap_account: Account,
+        pool_mint_key: Pubkey,
+        pool_mint_account: Account,
+        token_a_key: Pubkey,
+        token_a_account: Account,
+        token_b_key: Pubkey,
+        token_b_account: Account,
+        fee_a_key: Pubkey,
+        fee_a_account: Account,
+        pool_fee_key: Pubkey,
+        pool_fee_account: Account,
+        user_key: Pubkey,
+        user_account: Account,
+        user_token_a_key: Pubkey,
+        user_token_a_account: Account,
+        user_token_b_key: Pubkey,
+        user_token_b_account: Account,
+        user_pool_key: Pubkey,
+        user_pool_account: Account,
+        lock_key: Pubkey,
+        lock_account: Account,
+        pool_marker_key: Pubkey,
+        pool_marker_account: Account,
+        mint_list_key: Pubkey,
+        mint_list_account: Account,
+        token_program_account: Account,
+        system_program_account: Account,
+    }
+
+    impl SwapAccountInfo {
+        fn new() -> Self {
+            let program_id = crate::id();
+            let (state_key, _bump) = Pubkey::find_program_address(
+                &[AMM_STATE_SEED.as_bytes(), program_id.as_ref()],
+                &program_id,
+            );
+            let swap_key = Pubkey::new_unique();
+            let (authority_key, nonce) =
+                Pubkey::find_program_address(&[&swap_key.to_bytes()[..]], &program_id);
+            let token_a_mint_key = Pubkey::new_unique();
+            let token_b_mint_key = Pubkey::new_unique();
+            let pool_mint_key = Pubkey::new_unique();
+            let fee_owner_key = Pubkey::new_unique();
+            let user_key = Pubkey::new_unique();
+
+            Self {
+                nonce,
+                authority_key,
+                authority_account: Account::default(),
+                state_key,
+                state_account: Account::new(0, ProgramStateV2::LEN, &program_id),
+                fee_owner_key,
+                fee_owner_account: Account::new(0, 0, &system_program::id()),
+                swap_key,
+                swap_account: Account::new(0, SwapVersion::LATEST_LEN, &program_id),
+                pool_mint_key,
+                pool_mint_account: mint_account(&authority_key, LP_MINT_DECIMALS),
+                token_a_key: Pubkey::new_unique(),
+                token_a_account: token_account(&token_a_mint_key, &authority_key, TOKEN_A_AMOUNT),
+                token_b_key: Pubkey::new_unique(),
+                token_b_account: token_account(&token_b_mint_key, &authority_key, TOKEN_B_AMOUNT),
+                fee_a_key: Pubkey::new_unique(),
+                fee_a_account: token_account(&token_a_mint_key, &fee_owner_key, 0),
+                pool_fee_key: Pubkey::new_unique(),
+                pool_fee_account: token_account(&pool_mint_key, &fee_owner_key, 0),
+                user_key,
+                user_account: Account::new(1_000_000_000, 0, &system_program::id()),
+                user_token_a_key: Pubkey::new_unique(),
+                user_token_a_account: token_account(&token_a_mint_key, &user_key, USER_AMOUNT),
+                user_token_b_key: Pubkey::new_unique(),
+                user_token_b_account: token_account(&token_b_mint_key, &user_key, USER_AMOUNT),
+                user_pool_key: Pubkey::new_unique(),
+                user_pool_account: token_account(&pool_mint_key, &user_key, 0),
+                lock_key: Pubkey::new_unique(),
+                lock_account: token_account(
+                    &pool_mint_key,
+                    &Processor::lp_lock_id(&program_id, &swap_key),
+                    0,
+                ),
+                pool_marker_key: find_pool_marker_address(
+                    &program_id,
+                    &token_a_mint_key,
+                    &token_b_mint_key,
+                    CurveType::ConstantProduct,
+                )
+                .0,
+                pool_marker_account: Account::new(0, PoolMarker::LEN, &program_id),
+                mint_list_key: find_mint_list_address(&program_id).0,
+                mint_list_account: Account::new(0, MintList::LEN, &program_id),
+                token_program_account: Account::default(),
+                system_program_account: Account::default(),
+            }
+        }
+
+        /// Moves the pool to another swap address, with the vaults and the
+        /// pool mint owned by its authority
+        fn set_swap_key(&mut self, swap_key: Pubkey) {
+            let (authority_key, nonce) =
+                Pubkey::find_program_address(&[&swap_key.to_bytes()[..]], &crate::id());
+            let token_a = SplAccount::unpack(&self.token_a_account.data).unwrap();
+            let token_b = SplAccount::unpack(&self.token_b_account.data).unwrap();
+            self.swap_key = swap_key;
+            self.authority_key = authority_key;
+            self.nonce = nonce;
+            self.token_a_account = token_account(&token_a.mint, &authority_key, token_a.amount);
+            self.token_b_account = token_account(&token_b.mint, &authority_key, token_b.amount);
+            self.pool_mint_account = mint_account(&authority_key, LP_MINT_DECIMALS);
+            let lock = SplAccount::unpack(&self.lock_account.data).unwrap();
+            self.lock_account =
+                token_account(&lock.mint, &Processor::lp_lock_id(&crate::id(), &swap_key), 0);
+        }
+
+        fn update_state(&mut self) -> ProgramResult {
+            let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+            let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+            let mut rent_account = create_account_for_test(&Rent::default());
+            do_process_instruction(
+                update_state(
+                    &crate::id(),
+                    &self.state_key,
+                    &state_owner_key,
+                    &self.fee_owner_key,
+                    UpdateStateInstruction {
+                        initial_supply: INITIAL_SWAP_POOL_AMOUNT,
+                        fees: state_fees(),
+                        swap_curve: SwapCurve {
+                            curve_type: CurveType::ConstantProduct,
+                            calculator: Box::new(ConstantProductCurve {}),
+                        },
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.state_account,
+                    &mut state_owner_account,
+                    &mut self.fee_owner_account,
+                    &mut self.system_program_account,
+                    &mut rent_account,
+                ],
+            )
+        }
+
+        fn initialize(&mut self) -> ProgramResult {
+            self.initialize_at(false)
+        }
+
+        fn initialize_at(&mut self, derived_address: bool) -> ProgramResult {
+            let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+            self.initialize_by(&state_owner_key, derived_address)
+        }
+
+        fn initialize_by(&mut self, creator_key: &Pubkey, derived_address: bool) -> ProgramResult {
+            let amm_id_key = Pubkey::new_unique();
+            let market_key = Pubkey::new_unique();
+            let dex_program_id = Pubkey::new_unique();
+            let mut market_account = Account::new(0, 0, &dex_program_id);
+            do_process_instruction(
+                initialize(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.state_key,
+                    &amm_id_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &self.user_pool_key,
+                    &self.lock_key,
+                    &market_key,
+                    &dex_program_id,
+                    creator_key,
+                    &self.pool_marker_key,
+                    &self.mint_list_key,
+                    &self.fee_owner_key,
+                    self.nonce,
+                    None,
+                    None,
+                    derived_address,
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut self.authority_account,
+                    &mut self.state_account,
+                    &mut Account::default(),
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut self.user_pool_account,
+                    &mut market_account,
+                    &mut self.token_program_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut self.pool_marker_account,
+                    &mut self.system_program_account,
+                    &mut create_account_for_test(&Rent::default()),
+                    &mut self.mint_list_account,
+                    &mut self.fee_owner_account,
+                    &mut self.lock_account,
+                ],
+            )
+        }
+
+        fn setup() -> Self {
+            let mut accounts = Self::new();
+            accounts.update_state().unwrap();
+            accounts.initialize().unwrap();
+            accounts
+        }
+
+        fn swap(&mut self, amount_in: u64, minimum_amount_out: u64) -> ProgramResult {
+            do_process_instruction(
+                swap(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.user_key,
+                    &self.state_key,
+                    &self.user_token_a_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.user_token_b_key,
+                    &self.pool_mint_key,
+                    &self.fee_a_key,
+                    &self.fee_owner_key,
+                    None,
+                    SwapInstruction {
+                        amount_in,
+                        minimum_amount_out,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut self.authority_account,
+                    &mut self.user_account,
+                    &mut self.state_account,
+                    &mut self.user_token_a_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.user_token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut self.fee_a_account,
+                    &mut self.fee_owner_account,
+                    &mut self.token_program_account,
+                    &mut self.system_program_account,
+                ],
+            )
+        }
+
+        fn swap_exact_amount_out(
+            &mut self,
+            amount_out: u64,
+            maximum_amount_in: u64,
+        ) -> ProgramResult {
+            do_process_instruction(
+                swap_exact_amount_out(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.user_key,
+                    &self.state_key,
+                    &self.user_token_a_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.user_token_b_key,
+                    &self.pool_mint_key,
+                    &self.fee_a_key,
+                    &self.fee_owner_key,
+                    None,
+                    SwapExactAmountOutInstruction {
+                        amount_out,
+                        maximum_amount_in,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut self.authority_account,
+                    &mut self.user_account,
+                    &mut self.state_account,
+                    &mut self.user_token_a_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.user_token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut self.fee_a_account,
+                    &mut self.fee_owner_account,
+                    &mut self.token_program_account,
+                    &mut self.system_program_account,
+                ],
+            )
+        }
+
+        fn deposit_all(
+            &mut self,
+            pool_token_amount: u64,
+            maximum_token_a_amount: u64,
+            maximum_token_b_amount: u64,
+        ) -> ProgramResult {
+            do_process_instruction(
+                deposit_all_token_types(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.user_key,
+                    &self.state_key,
+                    &self.user_token_a_key,
+                    &self.user_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &self.user_pool_key,
+                    &self.pool_fee_key,
+                    DepositInstruction {
+                        pool_token_amount,
+                        maximum_token_a_amount,
+                        maximum_token_b_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut self.authority_account,
+                    &mut self.user_account,
+                    &mut self.state_account,
+                    &mut self.user_token_a_account,
+                    &mut self.user_token_b_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut self.user_pool_account,
+                    &mut self.pool_fee_account,
+                    &mut self.token_program_account,
+                ],
+            )
+        }
+
+        fn withdraw_all(
+            &mut self,
+            pool_token_amount: u64,
+            minimum_token_a_amount: u64,
+            minimum_token_b_amount: u64,
+        ) -> ProgramResult {
+            do_process_instruction(
+                withdraw_all_token_types(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.user_key,
+                    &self.state_key,
+                    &self.pool_mint_key,
+                    &self.user_pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.user_token_a_key,
+                    &self.user_token_b_key,
+                    &self.pool_fee_key,
+                    WithdrawInstruction {
+                        pool_token_amount,
+                        minimum_token_a_amount,
+                        minimum_token_b_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut self.authority_account,
+                    &mut self.user_account,
+                    &mut self.state_account,
+                    &mut self.pool_mint_account,
+                    &mut self.user_pool_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.user_token_a_account,
+                    &mut self.user_token_b_account,
+                    &mut self.pool_fee_account,
+                    &mut self.token_program_account,
+                ],
+            )
+        }
+
+        fn deposit_single(
+            &mut self,
+            source_token_amount: u64,
+            minimum_pool_token_amount: u64,
+        ) -> ProgramResult {
+            do_process_instruction(
+                deposit_single_token_type_exact_amount_in(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.user_key,
+                    &self.state_key,
+                    &self.user_token_a_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &self.user_pool_key,
+                    &self.pool_fee_key,
+                    &self.fee_a_key,
+                    DepositSingleTokenTypeExactAmountIn {
+                        source_token_amount,
+                        minimum_pool_token_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut self.authority_account,
+                    &mut self.user_account,
+                    &mut self.state_account,
+                    &mut self.user_token_a_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut self.user_pool_account,
+                    &mut self.pool_fee_account,
+                    &mut self.fee_a_account,
+                    &mut self.token_program_account,
+                ],
+            )
+        }
+
+        fn withdraw_single(
+            &mut self,
+            destination_token_amount: u64,
+            maximum_pool_token_amount: u64,
+        ) -> ProgramResult {
+            do_process_instruction(
+                withdraw_single_token_type_exact_amount_out(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.user_key,
+                    &self.state_key,
+                    &self.pool_mint_key,
+                    &self.user_pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.user_token_a_key,
+                    &self.pool_fee_key,
+                    &self.fee_a_key,
+                    WithdrawSingleTokenTypeExactAmountOut {
+                        destination_token_amount,
+                        maximum_pool_token_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut self.authority_account,
+                    &mut self.user_account,
+                    &mut self.state_account,
+                    &mut self.pool_mint_account,
+                    &mut self.user_pool_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.user_token_a_account,
+                    &mut self.pool_fee_account,
+                    &mut self.fee_a_account,
+                    &mut self.token_program_account,
+                ],
+            )
+        }
+
+        fn set_program_paused(&mut self, is_paused: bool) -> ProgramResult {
+            let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+            let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+            do_process_instruction(
+                set_pause(
+                    &crate::id(),
+                    &self.state_key,
+                    &state_owner_key,
+                    None,
+                    SetPauseInstruction { is_paused },
+                )
+                .unwrap(),
+                vec![&mut self.state_account, &mut state_owner_account],
+            )
+        }
+
+        fn set_pool_paused(&mut self, is_paused: bool) -> ProgramResult {
+            let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+            let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+            do_process_instruction(
+                set_pause(
+                    &crate::id(),
+                    &self.state_key,
+                    &state_owner_key,
+                    Some(&self.swap_key),
+                    SetPauseInstruction { is_paused },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.state_account,
+                    &mut state_owner_account,
+                    &mut self.swap_account,
+                ],
+            )
+        }
+
+        fn set_protocol_fee_mode(&mut self, lp_protocol_fee: bool) -> ProgramResult {
+            let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+            let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+            do_process_instruction(
+                set_protocol_fee_mode(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.state_key,
+                    &state_owner_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &self.pool_fee_key,
+                    SetProtocolFeeModeInstruction { lp_protocol_fee },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut self.authority_account,
+                    &mut self.state_account,
+                    &mut state_owner_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut self.pool_fee_account,
+                    &mut self.token_program_account,
+                ],
+            )
+        }
+    }
+
+    #[test]
+    fn test_update_state_round_trip() {
+        let mut accounts = SwapAccountInfo::new();
+        accounts.update_state().unwrap();
+
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        assert!(state.is_initialized());
+        assert_eq!(*state.state_owner(), Pubkey::from_str(INITIAL_STATE_OWNER).unwrap());
+        assert_eq!(*state.fee_owner(), accounts.fee_owner_key);
+        assert_eq!(state.initial_supply(), INITIAL_SWAP_POOL_AMOUNT);
+        assert_eq!(*state.fees(), state_fees());
+        assert_eq!(state.swap_curve().curve_type, CurveType::ConstantProduct);
+    }
+
+    #[test]
+    fn test_initialize_round_trip() {
+        let accounts = SwapAccountInfo::setup();
+
+        let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        assert!(token_swap.is_initialized());
+        assert_eq!(token_swap.nonce(), accounts.nonce);
+        assert_eq!(*token_swap.token_a_account(), accounts.token_a_key);
+        assert_eq!(*token_swap.token_b_account(), accounts.token_b_key);
+        assert_eq!(*token_swap.pool_mint(), accounts.pool_mint_key);
+        assert_eq!(*token_swap.fees(), state_fees());
+        assert_eq!(
+            SwapVersion::unpack_price_oracle(&accounts.swap_account.data).unwrap(),
+            *token_swap.price_oracle()
+        );
+        assert_eq!(token_amount(&accounts.user_pool_account), USER_POOL_AMOUNT);
+        assert_eq!(token_amount(&accounts.lock_account), MIN_LP_SUPPLY as u64);
+    }
+
+    #[test]
+    fn test_minimum_supply_lock() {
+        // the lock account must be owned by the lock address of the swap
+        let mut accounts = SwapAccountInfo::new();
+        accounts.update_state().unwrap();
+        let lock_owner = Processor::lp_lock_id(&crate::id(), &accounts.swap_key);
+        accounts.lock_account = token_account(&accounts.pool_mint_key, &accounts.user_key, 0);
+        assert_eq!(accounts.initialize(), Err(AmmError::IncorrectLockAccount.into()));
+        accounts.lock_account = token_account(&accounts.pool_mint_key, &lock_owner, 0);
+        accounts.initialize().unwrap();
+
+        // a pool without a lock can't burn its minimum supply either
+        let mut lock = SplAccount::unpack(&accounts.lock_account.data).unwrap();
+        let mut user_pool = SplAccount::unpack(&accounts.user_pool_account.data).unwrap();
+        user_pool.amount += lock.amount;
+        lock.amount = 0;
+        SplAccount::pack(lock, &mut accounts.lock_account.data).unwrap();
+        SplAccount::pack(user_pool, &mut accounts.user_pool_account.data).unwrap();
+        assert_eq!(
+            accounts.withdraw_all(INITIAL_SWAP_POOL_AMOUNT, 0, 0),
+            Err(AmmError::MinimumSupplyLocked.into())
+        );
+
+        accounts.withdraw_all(USER_POOL_AMOUNT, 0, 0).unwrap();
+        let pool_mint = Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(pool_mint.supply, MIN_LP_SUPPLY as u64);
+    }
+
+    #[test]
+    fn test_pool_marker_round_trip() {
+        let accounts = SwapAccountInfo::setup();
+        let token_a_mint = SplAccount::unpack(&accounts.token_a_account.data).unwrap().mint;
+        let token_b_mint = SplAccount::unpack(&accounts.token_b_account.data).unwrap().mint;
+
+        // pools are found by their mints, in any order
+        let (pool_marker_key, _bump) = find_pool_marker_address(
+            &crate::id(),
+            &token_b_mint,
+            &token_a_mint,
+            CurveType::ConstantProduct,
+        );
+        assert_eq!(pool_marker_key, accounts.pool_marker_key);
+        let pool_marker = PoolMarker::unpack(&accounts.pool_marker_account.data).unwrap();
+        assert_eq!(pool_marker.pool, accounts.swap_key);
+        assert_eq!(pool_marker.token_a_mint, token_a_mint);
+        assert_eq!(pool_marker.token_b_mint, token_b_mint);
+        assert_eq!(pool_marker.curve_type, CurveType::ConstantProduct);
+        assert_ne!(
+            find_pool_marker_address(&crate::id(), &token_a_mint, &token_b_mint, CurveType::Stable).0,
+            pool_marker_key
+        );
+
+        // a second pool for the same pair and curve is rejected
+        let mut duplicate = SwapAccountInfo::new();
+        duplicate.state_account = accounts.state_account.clone();
+        duplicate.token_a_account =
+            token_account(&token_a_mint, &duplicate.authority_key, TOKEN_A_AMOUNT);
+        duplicate.token_b_account =
+            token_account(&token_b_mint, &duplicate.authority_key, TOKEN_B_AMOUNT);
+        assert_eq!(duplicate.initialize(), Err(AmmError::InvalidPoolMarkerAddress.into()));
+        duplicate.pool_marker_key = accounts.pool_marker_key;
+        duplicate.pool_marker_account = accounts.pool_marker_account.clone();
+        assert_eq!(duplicate.initialize(), Err(AmmError::PoolAlreadyExists.into()));
+
+        // derived pools live at the address of their mints and curve type
+        let mut derived = SwapAccountInfo::new();
+        derived.state_account = accounts.state_account.clone();
+        assert_eq!(derived.initialize_at(true), Err(AmmError::InvalidProgramAddress.into()));
+        let derived_mint_a = SplAccount::unpack(&derived.token_a_account.data).unwrap().mint;
+        let derived_mint_b = SplAccount::unpack(&derived.token_b_account.data).unwrap().mint;
+        let (pool_key, _bump) = find_pool_address(
+            &crate::id(),
+            &derived_mint_b,
+            &derived_mint_a,
+            CurveType::ConstantProduct,
+        );
+        derived.set_swap_key(pool_key);
+        derived.initialize_at(true).unwrap();
+        let token_swap = SwapVersion::unpack(&derived.swap_account.data).unwrap();
+        assert!(token_swap.is_initialized());
+        let pool_marker = PoolMarker::unpack(&derived.pool_marker_account.data).unwrap();
+        assert_eq!(pool_marker.pool, pool_key);
+    }
+
+    #[test]
+    fn test_migrate_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let mut rent_account = create_account_for_test(&Rent::default());
+
+        fn do_migrate(
+            key: &Pubkey,
+            account: &mut Account,
+            accounts: &mut SwapAccountInfo,
+            rent_account: &mut Account,
+        ) -> ProgramResult {
+            let mut state_account = accounts.state_account.clone();
+            do_process_instruction(
+                migrate(&crate::id(), key, &accounts.state_key, &accounts.user_key).unwrap(),
+                vec![
+                    account,
+                    &mut accounts.user_account,
+                    &mut accounts.system_program_account,
+                    rent_account,
+                    &mut state_account,
+                ],
+            )
+        }
+
+        let swap_key = accounts.swap_key;
+        let mut swap_account = accounts.swap_account.clone();
+        assert_eq!(
+            do_migrate(&swap_key, &mut swap_account, &mut accounts, &mut rent_account),
+            Err(AmmError::AlreadyMigrated.into())
+        );
+
+        // swaps of the first layout used the fees and curve of the program
+        // state, they can't be used before migrating
+        let swap = SwapV2::unpack_from_slice(&accounts.swap_account.data[1..]).unwrap();
+        let legacy_swap = SwapV1 {
+            is_initialized: swap.is_initialized,
+            nonce: swap.nonce,
+            amm_id: swap.amm_id,
+            dex_program_id: swap.dex_program_id,
+            market_id: swap.market_id,
+            token_program_id: swap.token_program_id,
+            token_a: swap.token_a,
+            token_b: swap.token_b,
+            pool_mint: swap.pool_mint,
+            token_a_mint: swap.token_a_mint,
+            token_b_mint: swap.token_b_mint,
+        };
+        let mut swap_account = Account::new(0, 1 + SwapV1::LEN, &crate::id());
+        assert_eq!(swap_account.data.len(), 291);
+        swap_account.data[0] = 1;
+        legacy_swap.pack_into_slice(&mut swap_account.data[1..]);
+        assert!(SwapVersion::is_initialized(&swap_account.data));
+        assert!(!SwapVersion::is_latest(&swap_account.data));
+        assert_eq!(
+            SwapVersion::unpack(&swap_account.data).err(),
+            Some(AmmError::AccountNotMigrated.into())
+        );
+
+        // read with the fees and curve of the program state, but not
+        // written back until the account is grown
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        let token_swap = SwapVersion::unpack_with_state(&swap_account.data, state).unwrap();
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        let SwapVersion::SwapV2(upgraded) = &token_swap;
+        assert_eq!(
+            *upgraded,
+            SwapV2::from_v1(legacy_swap, state.fees, state.swap_curve)
+        );
+        assert_eq!(*token_swap.token_a_account(), accounts.token_a_key);
+        assert_eq!(
+            SwapVersion::pack(token_swap, &mut swap_account.data),
+            Err(AmmError::AccountNotMigrated.into())
+        );
+
+        // the first layout is told apart by its size too
+        let legacy_data = swap_account.data;
+        let mut swap_account = Account::new(0, SwapVersion::LATEST_LEN, &crate::id());
+        swap_account.data[..legacy_data.len()].copy_from_slice(&legacy_data);
+        assert!(!SwapVersion::is_initialized(&swap_account.data));
+        assert_eq!(
+            do_migrate(&swap_key, &mut swap_account, &mut accounts, &mut rent_account),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // state accounts of the first layout have no version byte, their
+        // length tells them apart
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        let legacy_state = ProgramState {
+            is_initialized: state.is_initialized,
+            state_owner: state.state_owner,
+            fee_owner: state.fee_owner,
+            initial_supply: state.initial_supply,
+            fees: Fees {
+                return_fee_numerator: state.fees.return_fee_numerator,
+                fixed_fee_numerator: state.fees.fixed_fee_numerator,
+                fee_denominator: state.fees.fee_denominator,
+                ..Fees::default()
+            },
+            swap_curve: state.swap_curve.clone(),
+        };
+        let mut legacy_data = vec![0; ProgramState::LEN];
+        legacy_state.pack_into_slice(&mut legacy_data);
+        assert_eq!(legacy_data.len(), 130);
+        assert_eq!(
+            ProgramStateV2::unpack_from_slice(&legacy_data).unwrap(),
+            ProgramStateV2 {
+                fees: legacy_state.fees.clone(),
+                ..state
             }
+        );
+        assert_eq!(
+            ProgramStateV2::unpack_from_slice(&legacy_data[..ProgramState::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        let state_key = accounts.state_key;
+        let mut state_account = accounts.state_account.clone();
+        assert_eq!(
+            do_migrate(&state_key, &mut state_account, &mut accounts, &mut rent_account),
+            Err(AmmError::AlreadyMigrated.into())
+        );
+    }
+
+    #[test]
+    fn test_migrate_other_accounts() {
+        let mut accounts = SwapAccountInfo::setup();
+        let mut rent_account = create_account_for_test(&Rent::default());
+
+        // swaps of the first layout are the only accounts of their size
+        for len in [
+            ProgramState::LEN,
+            ProgramStateV2::LEN,
+            SwapVersion::LATEST_LEN,
+            PoolMarker::LEN,
+            MintList::LEN,
+            Governance::LEN,
+            Proposal::LEN,
+            ConcentratedPool::LEN,
+            Position::LEN,
+        ] {
+            assert_ne!(len, SwapVersion::V1_LEN);
+        }
+
+        // a mint list starts with the same bytes as a swap of the first
+        // layout, and a proposal holds any instruction data
+        let mut mint_list_account = Account::new(0, MintList::LEN, &crate::id());
+        MintList {
+            is_initialized: true,
+            mints: vec![Pubkey::new_unique()],
+        }
+        .pack_into_slice(&mut mint_list_account.data);
+        assert_eq!(mint_list_account.data[..2], [1, 1]);
+        let mut proposal_account = Account::new(0, Proposal::LEN, &crate::id());
+        Proposal {
+            is_initialized: true,
+            governance: Pubkey::new_unique(),
+            data: accounts.swap_account.data[..MAX_PROPOSAL_DATA_LEN].to_vec(),
+            ..Proposal::default()
+        }
+        .pack_into_slice(&mut proposal_account.data);
+
+        for account in [&mut mint_list_account, &mut proposal_account] {
+            let data = account.data.clone();
+            let mut state_account = accounts.state_account.clone();
+            assert_eq!(
+                do_process_instruction(
+                    migrate(&crate::id(), &Pubkey::new_unique(), &accounts.state_key, &accounts.user_key)
+                        .unwrap(),
+                    vec![
+                        account,
+                        &mut accounts.user_account,
+                        &mut accounts.system_program_account,
+                        &mut rent_account,
+                        &mut state_account,
+                    ],
+                ),
+                Err(ProgramError::InvalidAccountData)
+            );
+            assert_eq!(account.data, data);
+        }
+    }
+
+    #[test]
+    fn test_state_owner_transfer_round_trip() {
+        let mut accounts = SwapAccountInfo::new();
+        accounts.update_state().unwrap();
+        let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+        let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+        let new_owner_key = accounts.user_key;
+
+        // only the proposed owner can accept, and only once proposed
+        assert_eq!(
+            do_process_instruction(
+                accept_state_owner(&crate::id(), &accounts.state_key, &new_owner_key).unwrap(),
+                vec![&mut accounts.state_account, &mut accounts.user_account],
+            ),
+            Err(AmmError::NoPendingStateOwner.into())
+        );
+        assert_eq!(
+            do_process_instruction(
+                propose_state_owner(
+                    &crate::id(),
+                    &accounts.state_key,
+                    &new_owner_key,
+                    &new_owner_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.state_account,
+                    &mut accounts.user_account.clone(),
+                    &mut accounts.user_account,
+                ],
+            ),
+            Err(AmmError::InvalidStateOwner.into())
+        );
+        do_process_instruction(
+            propose_state_owner(&crate::id(), &accounts.state_key, &state_owner_key, &new_owner_key)
+                .unwrap(),
+            vec![
+                &mut accounts.state_account,
+                &mut state_owner_account,
+                &mut accounts.user_account,
+            ],
+        )
+        .unwrap();
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        assert_eq!(*state.state_owner(), state_owner_key);
+        assert_eq!(state.pending_state_owner(), Some(&new_owner_key));
+
+        // a cancelled proposal can't be accepted
+        do_process_instruction(
+            cancel_state_owner_proposal(&crate::id(), &accounts.state_key, &state_owner_key)
+                .unwrap(),
+            vec![&mut accounts.state_account, &mut state_owner_account],
+        )
+        .unwrap();
+        assert_eq!(
+            do_process_instruction(
+                accept_state_owner(&crate::id(), &accounts.state_key, &new_owner_key).unwrap(),
+                vec![&mut accounts.state_account, &mut accounts.user_account],
+            ),
+            Err(AmmError::NoPendingStateOwner.into())
+        );
+
+        do_process_instruction(
+            propose_state_owner(&crate::id(), &accounts.state_key, &state_owner_key, &new_owner_key)
+                .unwrap(),
+            vec![
+                &mut accounts.state_account,
+                &mut state_owner_account,
+                &mut accounts.user_account,
+            ],
+        )
+        .unwrap();
+        do_process_instruction(
+            accept_state_owner(&crate::id(), &accounts.state_key, &new_owner_key).unwrap(),
+            vec![&mut accounts.state_account, &mut accounts.user_account],
+        )
+        .unwrap();
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        assert_eq!(*state.state_owner(), new_owner_key);
+        assert_eq!(state.pending_state_owner(), None);
+
+        // the former owner lost its rights
+        assert_eq!(accounts.update_state(), Err(AmmError::InvalidStateOwner.into()));
+    }
+
+    #[test]
+    fn test_state_update_timelock_round_trip() {
+        let mut accounts = SwapAccountInfo::new();
+        accounts.update_state().unwrap();
+        let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+        let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+        let fees = Fees {
+            fixed_fee_numerator: state_fees().fixed_fee_numerator + 1,
+            ..state_fees()
+        };
+
+        // an initialized state only changes through a queued update
+        assert_eq!(accounts.update_state(), Err(AmmError::StateUpdateNotQueued.into()));
+        assert_eq!(
+            do_process_instruction(
+                execute_state_update(&crate::id(), &accounts.state_key, &state_owner_key)
+                    .unwrap(),
+                vec![&mut accounts.state_account, &mut state_owner_account],
+            ),
+            Err(AmmError::NoPendingStateUpdate.into())
+        );
+
+        let queue = |accounts: &mut SwapAccountInfo, state_owner_account: &mut Account, delay| {
+            do_process_instruction(
+                queue_state_update(
+                    &crate::id(),
+                    &accounts.state_key,
+                    &state_owner_key,
+                    &accounts.user_key,
+                    QueueStateUpdateInstruction {
+                        delay,
+                        update: UpdateStateInstruction {
+                            initial_supply: INITIAL_SWAP_POOL_AMOUNT * 2,
+                            fees: fees.clone(),
+                            swap_curve: SwapCurve {
+                                curve_type: CurveType::ConstantProduct,
+                                calculator: Box::new(ConstantProductCurve {}),
+                            },
+                        },
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.state_account,
+                    state_owner_account,
+                    &mut accounts.user_account,
+                ],
+            )
+        };
+        let min_delay = SWAP_CONSTRAINTS.min_state_update_delay;
+        for delay in [min_delay - 1, -1] {
+            assert_eq!(
+                queue(&mut accounts, &mut state_owner_account, delay),
+                Err(AmmError::StateUpdateDelayTooShort.into())
+            );
+        }
+        queue(&mut accounts, &mut state_owner_account, 2 * min_delay).unwrap();
+        let update = ProgramStateV2::unpack_from_slice(&accounts.state_account.data)
+            .unwrap()
+            .pending_update
+            .unwrap();
+        assert_eq!(update.activation_timestamp, 2 * min_delay);
+
+        queue(&mut accounts, &mut state_owner_account, min_delay).unwrap();
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        assert_eq!(*state.fees(), state_fees());
+        let update = state.pending_update().unwrap();
+        assert_eq!(update.activation_timestamp, min_delay);
+        assert_eq!(update.fees, fees);
+        assert_eq!(update.fee_owner, accounts.user_key);
+
+        // nothing applies before the delay, and a cancelled update is gone
+        assert_eq!(
+            do_process_instruction(
+                execute_state_update(&crate::id(), &accounts.state_key, &state_owner_key)
+                    .unwrap(),
+                vec![&mut accounts.state_account, &mut state_owner_account],
+            ),
+            Err(AmmError::StateUpdateTimelocked.into())
+        );
+        do_process_instruction(
+            cancel_state_update(&crate::id(), &accounts.state_key, &state_owner_key).unwrap(),
+            vec![&mut accounts.state_account, &mut state_owner_account],
+        )
+        .unwrap();
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        assert!(state.pending_update().is_none());
+
+        // the test clock doesn't move, expire the timelock in place
+        queue(&mut accounts, &mut state_owner_account, min_delay).unwrap();
+        let mut state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        state.pending_update.as_mut().unwrap().activation_timestamp = 0;
+        state.pack_into_slice(&mut accounts.state_account.data);
+        do_process_instruction(
+            execute_state_update(&crate::id(), &accounts.state_key, &state_owner_key).unwrap(),
+            vec![&mut accounts.state_account, &mut state_owner_account],
+        )
+        .unwrap();
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        assert!(state.pending_update().is_none());
+        assert_eq!(*state.fees(), fees);
+        assert_eq!(*state.fee_owner(), accounts.user_key);
+        assert_eq!(state.initial_supply(), INITIAL_SWAP_POOL_AMOUNT * 2);
+        assert_eq!(*state.state_owner(), state_owner_key);
+    }
+
+    #[test]
+    fn test_permissionless_pool_round_trip() {
+        let mut accounts = SwapAccountInfo::new();
+        accounts.update_state().unwrap();
+        let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+        let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+        let creator_key = accounts.user_key;
+
+        // only the state owner creates pools by default
+        assert_eq!(accounts.initialize_by(&creator_key, false), Err(AmmError::InvalidOwner.into()));
+        do_process_instruction(
+            set_pool_creation(
+                &crate::id(),
+                &accounts.state_key,
+                &state_owner_key,
+                SetPoolCreationInstruction {
+                    permissionless_pools: true,
+                    mint_list_mode: MintListMode::Allowlist,
+                    pool_creation_fee: 0,
+                },
+            )
+            .unwrap(),
+            vec![&mut accounts.state_account, &mut state_owner_account],
+        )
+        .unwrap();
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        assert!(state.permissionless_pools());
+        assert_eq!(state.mint_list_mode(), MintListMode::Allowlist);
+
+        // others may only create pools of allowed mints
+        assert_eq!(
+            accounts.initialize_by(&creator_key, false),
+            Err(AmmError::MintNotAllowed.into())
+        );
+        for account in [&accounts.token_a_account, &accounts.token_b_account] {
+            let mint = SplAccount::unpack(&account.data).unwrap().mint;
+            do_process_instruction(
+                update_mint_list(
+                    &crate::id(),
+                    &accounts.state_key,
+                    &state_owner_key,
+                    &accounts.mint_list_key,
+                    UpdateMintListInstruction { mint, listed: true },
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.state_account,
+                    &mut state_owner_account,
+                    &mut accounts.mint_list_account,
+                    &mut accounts.system_program_account,
+                    &mut create_account_for_test(&Rent::default()),
+                ],
+            )
+            .unwrap();
+        }
+        assert_eq!(MintList::unpack(&accounts.mint_list_account.data).unwrap().mints.len(), 2);
+        accounts.initialize_by(&creator_key, false).unwrap();
+        let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        assert!(token_swap.is_initialized());
+    }
+
+    #[test]
+    fn test_close_pool_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+        let token_b_mint = SplAccount::unpack(&accounts.token_b_account.data).unwrap().mint;
+        let fee_b_key = Pubkey::new_unique();
+        let mut fee_b_account = token_account(&token_b_mint, &accounts.fee_owner_key, 0);
+        let recipient_key = Pubkey::new_unique();
+        let mut recipient_account = Account::new(0, 0, &system_program::id());
+        accounts.swap_account.lamports = 1_000_000;
+        accounts.token_a_account.lamports = 2_000;
+        accounts.token_b_account.lamports = 2_000;
+        accounts.pool_marker_account.lamports = 500;
+
+        fn do_close_pool(
+            wind_down: bool,
+            accounts: &mut SwapAccountInfo,
+            state_owner_account: &mut Account,
+            fee_b_key: &Pubkey,
+            fee_b_account: &mut Account,
+            recipient_key: &Pubkey,
+            recipient_account: &mut Account,
+        ) -> ProgramResult {
+            do_process_instruction(
+                close_pool(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &accounts.swap_key,
+                    &accounts.authority_key,
+                    &accounts.state_key,
+                    &Pubkey::from_str(INITIAL_STATE_OWNER).unwrap(),
+                    &accounts.token_a_key,
+                    &accounts.token_b_key,
+                    &accounts.pool_mint_key,
+                    &accounts.fee_a_key,
+                    fee_b_key,
+                    &accounts.pool_marker_key,
+                    recipient_key,
+                    ClosePoolInstruction { wind_down },
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut accounts.authority_account,
+                    &mut accounts.state_account,
+                    state_owner_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.fee_a_account,
+                    fee_b_account,
+                    &mut accounts.pool_marker_account,
+                    recipient_account,
+                    &mut accounts.token_program_account,
+                ],
+            )
+        }
+
+        // the pool still has liquidity
+        assert_eq!(
+            do_close_pool(
+                false,
+                &mut accounts,
+                &mut state_owner_account,
+                &fee_b_key,
+                &mut fee_b_account,
+                &recipient_key,
+                &mut recipient_account,
+            ),
+            Err(AmmError::PoolNotClosable.into())
+        );
+
+        assert_eq!(
+            do_close_pool(
+                true,
+                &mut accounts,
+                &mut state_owner_account,
+                &fee_b_key,
+                &mut fee_b_account,
+                &recipient_key,
+                &mut recipient_account,
+            ),
+            Err(AmmError::PoolNotClosable.into())
+        );
+
+        // pausing the pool winds it down, its liquidity providers withdraw
+        // their share before it can be closed
+        accounts.set_pool_paused(true).unwrap();
+        assert_eq!(
+            do_close_pool(
+                false,
+                &mut accounts,
+                &mut state_owner_account,
+                &fee_b_key,
+                &mut fee_b_account,
+                &recipient_key,
+                &mut recipient_account,
+            ),
+            Err(AmmError::PoolNotClosable.into())
+        );
+        accounts.withdraw_all(USER_POOL_AMOUNT, 0, 0).unwrap();
+        let token_a_dust = token_amount(&accounts.token_a_account);
+        let token_b_dust = token_amount(&accounts.token_b_account);
+        assert!(token_a_dust > 0 && token_a_dust < TOKEN_A_AMOUNT);
+        do_close_pool(
+            false,
+            &mut accounts,
+            &mut state_owner_account,
+            &fee_b_key,
+            &mut fee_b_account,
+            &recipient_key,
+            &mut recipient_account,
+        )
+        .unwrap();
+
+        assert_eq!(token_amount(&accounts.fee_a_account), token_a_dust);
+        assert_eq!(token_amount(&fee_b_account), token_b_dust);
+        assert_eq!(accounts.token_a_account.lamports, 0);
+        assert_eq!(accounts.token_b_account.lamports, 0);
+        assert_eq!(accounts.swap_account.lamports, 0);
+        assert_eq!(recipient_account.lamports, 1_004_500);
+        assert!(!SwapVersion::is_initialized(&accounts.swap_account.data));
+        let pool_marker = PoolMarker::unpack_unchecked(&accounts.pool_marker_account.data).unwrap();
+        assert!(!pool_marker.is_initialized);
+        assert_eq!(accounts.pool_marker_account.lamports, 0);
+    }
+
+    #[test]
+    fn test_governance_round_trip() {
+        let mut accounts = SwapAccountInfo::new();
+        accounts.update_state().unwrap();
+        let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+        let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+        let signer_keys = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut signer_account = Account::new(1_000_000_000, 0, &system_program::id());
+
+        // 2 of 3 governance
+        let governance_key = Pubkey::new_unique();
+        let mut governance_account = Account::new(0, Governance::LEN, &crate::id());
+        let (authority_key, nonce) = find_governance_authority_address(&crate::id(), &governance_key);
+        let mut authority_account = Account::new(0, 0, &system_program::id());
+        assert_eq!(
+            do_process_instruction(
+                create_governance(&crate::id(), &governance_key, nonce, 4, signer_keys.to_vec())
+                    .unwrap(),
+                vec![&mut governance_account],
+            ),
+            Err(AmmError::InvalidGovernance.into())
+        );
+        do_process_instruction(
+            create_governance(&crate::id(), &governance_key, nonce, 2, signer_keys.to_vec())
+                .unwrap(),
+            vec![&mut governance_account],
+        )
+        .unwrap();
+
+        // the governance authority is proposed as the state owner, and
+        // accepts through a proposal
+        do_process_instruction(
+            propose_state_owner(&crate::id(), &accounts.state_key, &state_owner_key, &authority_key)
+                .unwrap(),
+            vec![
+                &mut accounts.state_account,
+                &mut state_owner_account,
+                &mut authority_account,
+            ],
+        )
+        .unwrap();
+        let accept = accept_state_owner(&crate::id(), &accounts.state_key, &authority_key).unwrap();
+        let proposal_key = Pubkey::new_unique();
+        let mut proposal_account = Account::new(0, Proposal::LEN, &crate::id());
+        assert_eq!(
+            do_process_instruction(
+                create_proposal(
+                    &crate::id(),
+                    &proposal_key,
+                    &governance_key,
+                    &state_owner_key,
+                    accept.clone(),
+                )
+                .unwrap(),
+                vec![
+                    &mut proposal_account,
+                    &mut governance_account,
+                    &mut state_owner_account,
+                ],
+            ),
+            Err(AmmError::NotGovernanceSigner.into())
+        );
+        do_process_instruction(
+            create_proposal(
+                &crate::id(),
+                &proposal_key,
+                &governance_key,
+                &signer_keys[0],
+                accept.clone(),
+            )
+            .unwrap(),
+            vec![&mut proposal_account, &mut governance_account, &mut signer_account],
+        )
+        .unwrap();
+
+        let execute = |accounts: &mut SwapAccountInfo,
+                           proposal_account: &mut Account,
+                           governance_account: &mut Account,
+                           authority_account: &mut Account| {
+            do_process_instruction(
+                execute_proposal(
+                    &crate::id(),
+                    &proposal_key,
+                    &governance_key,
+                    &authority_key,
+                    &accept.accounts,
+                )
+                .unwrap(),
+                vec![
+                    proposal_account,
+                    governance_account,
+                    authority_account,
+                    &mut Account::default(),
+                    &mut accounts.state_account,
+                ],
+            )
+        };
+        assert_eq!(
+            execute(
+                &mut accounts,
+                &mut proposal_account,
+                &mut governance_account,
+                &mut authority_account,
+            ),
+            Err(AmmError::ProposalNotApproved.into())
+        );
+        do_process_instruction(
+            approve_proposal(&crate::id(), &proposal_key, &governance_key, &signer_keys[2])
+                .unwrap(),
+            vec![&mut proposal_account, &mut governance_account, &mut signer_account],
+        )
+        .unwrap();
+        let proposal = Proposal::unpack(&proposal_account.data).unwrap();
+        assert_eq!(proposal.approval_count(), 2);
+        assert_eq!(proposal.data, accept.data);
+
+        execute(
+            &mut accounts,
+            &mut proposal_account,
+            &mut governance_account,
+            &mut authority_account,
+        )
+        .unwrap();
+        let state = ProgramStateV2::unpack_from_slice(&accounts.state_account.data).unwrap();
+        assert_eq!(*state.state_owner(), authority_key);
+        assert_eq!(
+            execute(
+                &mut accounts,
+                &mut proposal_account,
+                &mut governance_account,
+                &mut authority_account,
+            ),
+            Err(AmmError::ProposalAlreadyExecuted.into())
+        );
+    }
+
+    #[test]
+    fn test_deposit_and_withdraw_all_token_types_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let pool_token_amount = INITIAL_SWAP_POOL_AMOUNT / 100;
+
+        accounts.deposit_all(pool_token_amount, USER_AMOUNT, USER_AMOUNT).unwrap();
+        assert_eq!(token_amount(&accounts.token_a_account), TOKEN_A_AMOUNT + TOKEN_A_AMOUNT / 100);
+        assert_eq!(token_amount(&accounts.token_b_account), TOKEN_B_AMOUNT + TOKEN_B_AMOUNT / 100);
+        assert_eq!(token_amount(&accounts.user_pool_account), USER_POOL_AMOUNT + pool_token_amount);
+
+        accounts.withdraw_all(pool_token_amount, 0, 0).unwrap();
+        assert_eq!(token_amount(&accounts.user_pool_account), USER_POOL_AMOUNT);
+        assert!(token_amount(&accounts.user_token_a_account) > USER_AMOUNT - TOKEN_A_AMOUNT / 100);
+        assert!(token_amount(&accounts.user_token_b_account) > USER_AMOUNT - TOKEN_B_AMOUNT / 100);
+    }
+
+    #[test]
+    fn test_single_token_type_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let source_token_amount = TOKEN_A_AMOUNT / 100;
+
+        // the minimum fees take the whole of a dust deposit
+        assert_eq!(accounts.deposit_single(1, 1), Err(AmmError::ZeroTradingTokens.into()));
+
+        accounts.deposit_single(source_token_amount, 1).unwrap();
+        // the owner takes its share of the trading fee on half the deposit
+        let deposit_fee = state_fees().fixed_fee((source_token_amount / 2).into()).unwrap() as u64;
+        assert!(deposit_fee > 0);
+        assert_eq!(token_amount(&accounts.user_token_a_account), USER_AMOUNT - source_token_amount);
+        assert_eq!(token_amount(&accounts.fee_a_account), deposit_fee);
+        assert_eq!(
+            token_amount(&accounts.token_a_account),
+            TOKEN_A_AMOUNT + source_token_amount - deposit_fee
+        );
+        assert!(token_amount(&accounts.user_pool_account) > USER_POOL_AMOUNT);
+
+        let destination_token_amount = source_token_amount / 2;
+        accounts.withdraw_single(destination_token_amount, INITIAL_SWAP_POOL_AMOUNT).unwrap();
+        let withdraw_fee =
+            state_fees().fixed_fee((destination_token_amount / 2).into()).unwrap() as u64;
+        assert_eq!(
+            token_amount(&accounts.user_token_a_account),
+            USER_AMOUNT - source_token_amount + destination_token_amount
+        );
+        assert_eq!(token_amount(&accounts.fee_a_account), deposit_fee + withdraw_fee);
+        assert_eq!(
+            token_amount(&accounts.token_a_account),
+            TOKEN_A_AMOUNT + source_token_amount - deposit_fee - destination_token_amount - withdraw_fee
+        );
+    }
+
+    #[test]
+    fn test_withdraw_fee_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let SwapVersion::SwapV2(mut swap_v2) = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        swap_v2.fees.owner_withdraw_fee_numerator = 50;
+        let fees = swap_v2.fees.clone();
+        SwapVersion::pack(SwapVersion::SwapV2(swap_v2), &mut accounts.swap_account.data).unwrap();
+        let pool_token_amount = INITIAL_SWAP_POOL_AMOUNT / 10;
+
+        fn withdraw_with_fee(
+            accounts: &mut SwapAccountInfo,
+            pool_fee_key: &Pubkey,
+            pool_fee_account: &mut Account,
+            pool_token_amount: u64,
+        ) -> ProgramResult {
+            do_process_instruction(
+                withdraw_all_token_types(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &accounts.swap_key,
+                    &accounts.authority_key,
+                    &accounts.user_key,
+                    &accounts.state_key,
+                    &accounts.pool_mint_key,
+                    &accounts.user_pool_key,
+                    &accounts.token_a_key,
+                    &accounts.token_b_key,
+                    &accounts.user_token_a_key,
+                    &accounts.user_token_b_key,
+                    pool_fee_key,
+                    WithdrawInstruction {
+                        pool_token_amount,
+                        minimum_token_a_amount: 0,
+                        minimum_token_b_amount: 0,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut accounts.authority_account,
+                    &mut accounts.user_account,
+                    &mut accounts.state_account,
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.user_pool_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.user_token_a_account,
+                    &mut accounts.user_token_b_account,
+                    pool_fee_account,
+                    &mut accounts.token_program_account,
+                ],
+            )
+        }
+
+        // the fee account must hold pool tokens of the fee owner
+        let pool_mint_key = accounts.pool_mint_key;
+        assert_eq!(
+            withdraw_with_fee(
+                &mut accounts,
+                &Pubkey::new_unique(),
+                &mut token_account(&pool_mint_key, &Pubkey::new_unique(), 0),
+                pool_token_amount,
+            ),
+            Err(AmmError::IncorrectFeeAccount.into())
+        );
+
+        let pool_fee_key = accounts.pool_fee_key;
+        let mut pool_fee_account = accounts.pool_fee_account.clone();
+        withdraw_with_fee(&mut accounts, &pool_fee_key, &mut pool_fee_account, pool_token_amount)
+            .unwrap();
+        let withdraw_fee = fees.owner_withdraw_fee(pool_token_amount.into()).unwrap() as u64;
+        assert!(withdraw_fee > 0);
+        assert_eq!(token_amount(&pool_fee_account), withdraw_fee);
+        assert_eq!(token_amount(&accounts.user_pool_account), USER_POOL_AMOUNT - pool_token_amount);
+        let pool_mint = Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(
+            pool_mint.supply,
+            INITIAL_SWAP_POOL_AMOUNT - pool_token_amount + withdraw_fee
+        );
+    }
+
+    #[test]
+    fn test_swap_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let amount_in = TOKEN_A_AMOUNT / 100;
+
+        accounts.swap(amount_in, 1).unwrap();
+        let owner_fee = token_amount(&accounts.fee_a_account);
+        assert!(owner_fee > 0);
+        assert_eq!(token_amount(&accounts.user_token_a_account), USER_AMOUNT - amount_in);
+        assert_eq!(
+            token_amount(&accounts.token_a_account),
+            TOKEN_A_AMOUNT + amount_in - owner_fee
+        );
+        assert!(token_amount(&accounts.user_token_b_account) > USER_AMOUNT);
+    }
+
+    #[test]
+    fn test_swap_host_fee_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let token_a_mint_key = SplAccount::unpack(&accounts.token_a_account.data).unwrap().mint;
+        let token_b_mint_key = SplAccount::unpack(&accounts.token_b_account.data).unwrap().mint;
+        let host_fee_key = Pubkey::new_unique();
+        let mut host_fee_account = token_account(&token_a_mint_key, &Pubkey::new_unique(), 0);
+        let amount_in = TOKEN_A_AMOUNT / 100;
+
+        fn swap_with_host(
+            accounts: &mut SwapAccountInfo,
+            host_fee_key: &Pubkey,
+            host_fee_account: &mut Account,
+            amount_in: u64,
+        ) -> ProgramResult {
+            do_process_instruction(
+                swap(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &accounts.swap_key,
+                    &accounts.authority_key,
+                    &accounts.user_key,
+                    &accounts.state_key,
+                    &accounts.user_token_a_key,
+                    &accounts.token_a_key,
+                    &accounts.token_b_key,
+                    &accounts.user_token_b_key,
+                    &accounts.pool_mint_key,
+                    &accounts.fee_a_key,
+                    &accounts.fee_owner_key,
+                    Some(host_fee_key),
+                    SwapInstruction {
+                        amount_in,
+                        minimum_amount_out: 1,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut accounts.authority_account,
+                    &mut accounts.user_account,
+                    &mut accounts.state_account,
+                    &mut accounts.user_token_a_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.user_token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.fee_a_account,
+                    &mut accounts.fee_owner_account,
+                    &mut accounts.token_program_account,
+                    &mut accounts.system_program_account,
+                    host_fee_account,
+                ],
+            )
+        }
+
+        // the host account must hold the source token
+        assert_eq!(
+            swap_with_host(
+                &mut accounts,
+                &host_fee_key,
+                &mut token_account(&token_b_mint_key, &Pubkey::new_unique(), 0),
+                amount_in,
+            ),
+            Err(AmmError::IncorrectFeeAccount.into())
+        );
+
+        // nor be a vault of the pool or the owner fee account
+        for (key, mut account) in [
+            (accounts.token_a_key, accounts.token_a_account.clone()),
+            (accounts.token_b_key, accounts.token_b_account.clone()),
+            (accounts.fee_a_key, accounts.fee_a_account.clone()),
+        ] {
+            assert_eq!(
+                swap_with_host(&mut accounts, &key, &mut account, amount_in),
+                Err(AmmError::IncorrectFeeAccount.into())
+            );
         }
 
-        Ok(())
+        swap_with_host(&mut accounts, &host_fee_key, &mut host_fee_account, amount_in).unwrap();
+        let owner_fee = state_fees().fixed_fee(amount_in.into()).unwrap();
+        let host_fee = state_fees().host_fee(owner_fee).unwrap() as u64;
+        assert!(host_fee > 0);
+        assert_eq!(token_amount(&host_fee_account), host_fee);
+        assert_eq!(token_amount(&accounts.fee_a_account), owner_fee as u64 - host_fee);
+        assert_eq!(token_amount(&accounts.user_token_a_account), USER_AMOUNT - amount_in);
+    }
+
+    #[test]
+    fn test_dynamic_fee_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let SwapVersion::SwapV2(mut swap_v2) = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        swap_v2.fees.max_return_fee_numerator = SWAP_CONSTRAINTS.fees.max_return_fee_numerator;
+        let fees = swap_v2.fees.clone();
+        SwapVersion::pack(SwapVersion::SwapV2(swap_v2), &mut accounts.swap_account.data).unwrap();
+        let amount_in = TOKEN_A_AMOUNT / 100;
+
+        // the bound of the dynamic fee is capped by the constraints
+        let mut too_high = fees.clone();
+        too_high.max_return_fee_numerator += 1;
+        assert_eq!(SWAP_CONSTRAINTS.validate_fees(&too_high), Err(AmmError::InvalidFee.into()));
+
+        // a calm pool charges the base return fee
+        accounts.swap(amount_in, 1).unwrap();
+        let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        let volatility = token_swap.volatility().volatility_bps;
+        assert!(volatility > 0);
+
+        // the price move of the first swap raises the fee of the next one
+        let dynamic_fees = fees.with_volatility(volatility).unwrap();
+        assert!(dynamic_fees.return_fee_numerator > fees.return_fee_numerator);
+        assert!(dynamic_fees.return_fee_numerator <= fees.max_return_fee_numerator);
+        let swap_source_amount = token_amount(&accounts.token_a_account).into();
+        let swap_destination_amount = token_amount(&accounts.token_b_account).into();
+        let expected = token_swap
+            .swap_curve()
+            .swap(
+                amount_in.into(),
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+                &dynamic_fees,
+            )
+            .unwrap();
+        let static_quote = token_swap
+            .swap_curve()
+            .swap(
+                amount_in.into(),
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+                &fees,
+            )
+            .unwrap();
+        assert!(expected.destination_amount_swapped < static_quote.destination_amount_swapped);
+
+        let user_token_b_before = token_amount(&accounts.user_token_b_account);
+        accounts.swap(amount_in, 1).unwrap();
+        assert_eq!(
+            token_amount(&accounts.user_token_b_account) - user_token_b_before,
+            expected.destination_amount_swapped as u64
+        );
+        let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        assert!(token_swap.volatility().volatility_bps > volatility);
+
+        // single-sided deposits pay the same raised fee
+        let dynamic_fees = fees
+            .with_volatility(token_swap.volatility().volatility_bps)
+            .unwrap();
+        let source_token_amount = TOKEN_A_AMOUNT / 100;
+        let pool_supply = Mint::unpack(&accounts.pool_mint_account.data).unwrap().supply;
+        let expected = token_swap
+            .swap_curve()
+            .deposit_single_token_type(
+                source_token_amount.into(),
+                token_amount(&accounts.token_a_account).into(),
+                token_amount(&accounts.token_b_account).into(),
+                pool_supply.into(),
+                TradeDirection::AtoB,
+                &dynamic_fees,
+            )
+            .unwrap();
+        let user_pool_before = token_amount(&accounts.user_pool_account);
+        accounts.deposit_single(source_token_amount, 1).unwrap();
+        assert_eq!(
+            token_amount(&accounts.user_pool_account) - user_pool_before,
+            expected.pool_token_amount as u64
+        );
+
+        // the owner fee of a dynamic fee pool can't be paid as pool tokens
+        assert_eq!(accounts.set_protocol_fee_mode(true), Err(AmmError::InvalidFee.into()));
     }
 
-    /// Processes an [Instruction](enum.Instruction.html).
-    pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
-        let instruction = AmmInstruction::unpack(input)?;
-        match instruction {
-            AmmInstruction::UpdateState(UpdateStateInstruction {
-                initial_supply,
-                fees,
-                swap_curve,
-            }) => {
-                msg!("Instruction: UpdateState");
-                Self::process_update_state(
-                    program_id,
-                    initial_supply,
-                    fees,
-                    swap_curve,
-                    accounts,
-                )
-            }
-            AmmInstruction::Initialize(InitializeInstruction {
-                nonce,
-            }) => {
-                msg!("Instruction: Init");
-                Self::process_initialize(
-                    program_id,
+    #[test]
+    fn test_swap_exact_amount_out_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let amount_out = TOKEN_B_AMOUNT / 100;
+        let maximum_amount_in = USER_AMOUNT;
+
+        accounts.swap_exact_amount_out(amount_out, maximum_amount_in).unwrap();
+        assert_eq!(token_amount(&accounts.user_token_b_account), USER_AMOUNT + amount_out);
+        assert!(token_amount(&accounts.user_token_a_account) >= USER_AMOUNT - maximum_amount_in);
+        assert!(token_amount(&accounts.fee_a_account) > 0);
+    }
+
+    #[test]
+    fn test_concentrated_liquidity_round_trip() {
+        let mut accounts = SwapAccountInfo::new();
+        accounts.update_state().unwrap();
+        let program_id = crate::id();
+        let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+        let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+        let pool_key = Pubkey::new_unique();
+        let mut pool_account = Account::new(0, ConcentratedPool::LEN, &program_id);
+        let (authority_key, nonce) =
+            Pubkey::find_program_address(&[&pool_key.to_bytes()[..]], &program_id);
+        let mut authority_account = Account::default();
+        let token_a_mint_key = SplAccount::unpack(&accounts.user_token_a_account.data).unwrap().mint;
+        let token_b_mint_key = SplAccount::unpack(&accounts.user_token_b_account.data).unwrap().mint;
+        let vault_a_key = Pubkey::new_unique();
+        let mut vault_a_account = token_account(&token_a_mint_key, &authority_key, 0);
+        let vault_b_key = Pubkey::new_unique();
+        let mut vault_b_account = token_account(&token_b_mint_key, &authority_key, 0);
+        let position_key = Pubkey::new_unique();
+        let mut position_account = Account::new(0, Position::LEN, &program_id);
+
+        do_process_instruction(
+            initialize_concentrated_pool(
+                &program_id,
+                &spl_token::id(),
+                &pool_key,
+                &authority_key,
+                &accounts.state_key,
+                &vault_a_key,
+                &vault_b_key,
+                &state_owner_key,
+                InitializeConcentratedPoolInstruction {
                     nonce,
-                    accounts,
+                    tick_spacing: 10,
+                    initial_sqrt_price: 1u128 << 64,
+                    fees: None,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut pool_account,
+                &mut authority_account,
+                &mut accounts.state_account,
+                &mut vault_a_account,
+                &mut vault_b_account,
+                &mut accounts.token_program_account,
+                &mut state_owner_account,
+            ],
+        )
+        .unwrap();
+
+        do_process_instruction(
+            open_position(
+                &program_id,
+                &pool_key,
+                &position_key,
+                &accounts.user_key,
+                OpenPositionInstruction {
+                    tick_lower: -1000,
+                    tick_upper: 1000,
+                },
+            )
+            .unwrap(),
+            vec![&mut pool_account, &mut position_account, &mut accounts.user_account],
+        )
+        .unwrap();
+
+        do_process_instruction(
+            increase_liquidity(
+                &program_id,
+                &spl_token::id(),
+                &pool_key,
+                &authority_key,
+                &position_key,
+                &accounts.user_key,
+                &accounts.user_token_a_key,
+                &accounts.user_token_b_key,
+                &vault_a_key,
+                &vault_b_key,
+                IncreaseLiquidityInstruction {
+                    liquidity: 10_000_000,
+                    maximum_token_a_amount: USER_AMOUNT,
+                    maximum_token_b_amount: USER_AMOUNT,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut pool_account,
+                &mut authority_account,
+                &mut position_account,
+                &mut accounts.user_account,
+                &mut accounts.user_token_a_account,
+                &mut accounts.user_token_b_account,
+                &mut vault_a_account,
+                &mut vault_b_account,
+                &mut accounts.token_program_account,
+            ],
+        )
+        .unwrap();
+        let deposited_a = token_amount(&vault_a_account);
+        let deposited_b = token_amount(&vault_b_account);
+        assert!(deposited_a > 0 && deposited_b > 0);
+
+        let amount_in = deposited_a / 10;
+        do_process_instruction(
+            concentrated_swap(
+                &program_id,
+                &spl_token::id(),
+                &pool_key,
+                &authority_key,
+                &accounts.user_key,
+                &accounts.state_key,
+                &accounts.user_token_a_key,
+                &vault_a_key,
+                &vault_b_key,
+                &accounts.user_token_b_key,
+                &accounts.fee_a_key,
+                ConcentratedSwapInstruction {
+                    amount_in,
+                    minimum_amount_out: 1,
+                    sqrt_price_limit: 0,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut pool_account,
+                &mut authority_account,
+                &mut accounts.user_account,
+                &mut accounts.state_account,
+                &mut accounts.user_token_a_account,
+                &mut vault_a_account,
+                &mut vault_b_account,
+                &mut accounts.user_token_b_account,
+                &mut accounts.fee_a_account,
+                &mut accounts.token_program_account,
+            ],
+        )
+        .unwrap();
+        let owner_fee = token_amount(&accounts.fee_a_account);
+        assert!(owner_fee > 0);
+        assert_eq!(token_amount(&vault_a_account), deposited_a + amount_in - owner_fee);
+        assert!(token_amount(&vault_b_account) < deposited_b);
+
+        // a paused pool halts swaps and new liquidity, positions can still
+        // be decreased and their fees collected
+        do_process_instruction(
+            set_pause(
+                &program_id,
+                &accounts.state_key,
+                &state_owner_key,
+                Some(&pool_key),
+                SetPauseInstruction { is_paused: true },
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.state_account,
+                &mut state_owner_account,
+                &mut pool_account,
+            ],
+        )
+        .unwrap();
+        assert!(ConcentratedPool::unpack(&pool_account.data).unwrap().is_paused);
+        assert!(!ProgramStateV2::unpack_from_slice(&accounts.state_account.data)
+            .unwrap()
+            .is_paused());
+        assert_eq!(
+            do_process_instruction(
+                concentrated_swap(
+                    &program_id,
+                    &spl_token::id(),
+                    &pool_key,
+                    &authority_key,
+                    &accounts.user_key,
+                    &accounts.state_key,
+                    &accounts.user_token_a_key,
+                    &vault_a_key,
+                    &vault_b_key,
+                    &accounts.user_token_b_key,
+                    &accounts.fee_a_key,
+                    ConcentratedSwapInstruction {
+                        amount_in,
+                        minimum_amount_out: 1,
+                        sqrt_price_limit: 0,
+                    },
                 )
-            }
-            AmmInstruction::Swap(SwapInstruction {
-                amount_in,
-                minimum_amount_out,
-            }) => {
-                msg!("Instruction: Swap");
-                Self::process_swap(program_id, amount_in, minimum_amount_out, accounts)
-            }
-            AmmInstruction::DepositAllTokenTypes(DepositInstruction {
-                pool_token_amount,
-                maximum_token_a_amount,
-                maximum_token_b_amount,
-            }) => {
-                msg!("Instruction: DepositAllTokenTypes");
-                Self::process_deposit_all_token_types(
-                    program_id,
-                    pool_token_amount,
-                    maximum_token_a_amount,
-                    maximum_token_b_amount,
-                    accounts,
+                .unwrap(),
+                vec![
+                    &mut pool_account,
+                    &mut authority_account,
+                    &mut accounts.user_account,
+                    &mut accounts.state_account,
+                    &mut accounts.user_token_a_account,
+                    &mut vault_a_account,
+                    &mut vault_b_account,
+                    &mut accounts.user_token_b_account,
+                    &mut accounts.fee_a_account,
+                    &mut accounts.token_program_account,
+                ],
+            ),
+            Err(AmmError::Paused.into())
+        );
+        assert_eq!(
+            do_process_instruction(
+                increase_liquidity(
+                    &program_id,
+                    &spl_token::id(),
+                    &pool_key,
+                    &authority_key,
+                    &position_key,
+                    &accounts.user_key,
+                    &accounts.user_token_a_key,
+                    &accounts.user_token_b_key,
+                    &vault_a_key,
+                    &vault_b_key,
+                    IncreaseLiquidityInstruction {
+                        liquidity: 1_000,
+                        maximum_token_a_amount: USER_AMOUNT,
+                        maximum_token_b_amount: USER_AMOUNT,
+                    },
                 )
-            }
-            AmmInstruction::WithdrawAllTokenTypes(WithdrawInstruction {
-                pool_token_amount,
-                minimum_token_a_amount,
-                minimum_token_b_amount,
-            }) => {
-                msg!("Instruction: WithdrawAllTokenTypes");
-                Self::process_withdraw_all_token_types(
-                    program_id,
-                    pool_token_amount,
-                    minimum_token_a_amount,
-                    minimum_token_b_amount,
-                    accounts,
+                .unwrap(),
+                vec![
+                    &mut pool_account,
+                    &mut authority_account,
+                    &mut position_account,
+                    &mut accounts.user_account,
+                    &mut accounts.user_token_a_account,
+                    &mut accounts.user_token_b_account,
+                    &mut vault_a_account,
+                    &mut vault_b_account,
+                    &mut accounts.token_program_account,
+                ],
+            ),
+            Err(AmmError::Paused.into())
+        );
+        let mut other_position_account = Account::new(0, Position::LEN, &program_id);
+        assert_eq!(
+            do_process_instruction(
+                open_position(
+                    &program_id,
+                    &pool_key,
+                    &Pubkey::new_unique(),
+                    &accounts.user_key,
+                    OpenPositionInstruction {
+                        tick_lower: -100,
+                        tick_upper: 100,
+                    },
                 )
-            }
-            AmmInstruction::DepositSingleTokenTypeExactAmountIn(
-                DepositSingleTokenTypeExactAmountIn {
-                    source_token_amount,
-                    minimum_pool_token_amount,
+                .unwrap(),
+                vec![
+                    &mut pool_account,
+                    &mut other_position_account,
+                    &mut accounts.user_account,
+                ],
+            ),
+            Err(AmmError::Paused.into())
+        );
+
+        do_process_instruction(
+            decrease_liquidity(
+                &program_id,
+                &spl_token::id(),
+                &pool_key,
+                &authority_key,
+                &position_key,
+                &accounts.user_key,
+                &accounts.user_token_a_key,
+                &accounts.user_token_b_key,
+                &vault_a_key,
+                &vault_b_key,
+                DecreaseLiquidityInstruction {
+                    liquidity: 10_000_000,
+                    minimum_token_a_amount: 0,
+                    minimum_token_b_amount: 0,
                 },
-            ) => {
-                msg!("Instruction: DepositSingleTokenTypeExactAmountIn");
-                Self::process_deposit_single_token_type_exact_amount_in(
-                    program_id,
-                    source_token_amount,
-                    minimum_pool_token_amount,
-                    accounts,
-                )
-            }
-            AmmInstruction::WithdrawSingleTokenTypeExactAmountOut(
-                WithdrawSingleTokenTypeExactAmountOut {
-                    destination_token_amount,
-                    maximum_pool_token_amount,
+            )
+            .unwrap(),
+            vec![
+                &mut pool_account,
+                &mut authority_account,
+                &mut position_account,
+                &mut accounts.user_account,
+                &mut accounts.user_token_a_account,
+                &mut accounts.user_token_b_account,
+                &mut vault_a_account,
+                &mut vault_b_account,
+                &mut accounts.token_program_account,
+            ],
+        )
+        .unwrap();
+        let position = Position::unpack(&position_account.data).unwrap();
+        assert_eq!(position.liquidity, 0);
+        assert!(position.tokens_owed_a > 0);
+
+        do_process_instruction(
+            collect_fees(
+                &program_id,
+                &spl_token::id(),
+                &pool_key,
+                &authority_key,
+                &position_key,
+                &accounts.user_key,
+                &accounts.user_token_a_key,
+                &accounts.user_token_b_key,
+                &vault_a_key,
+                &vault_b_key,
+            )
+            .unwrap(),
+            vec![
+                &mut pool_account,
+                &mut authority_account,
+                &mut position_account,
+                &mut accounts.user_account,
+                &mut accounts.user_token_a_account,
+                &mut accounts.user_token_b_account,
+                &mut vault_a_account,
+                &mut vault_b_account,
+                &mut accounts.token_program_account,
+            ],
+        )
+        .unwrap();
+        let position = Position::unpack(&position_account.data).unwrap();
+        assert_eq!(position.tokens_owed_a, 0);
+        assert_eq!(position.tokens_owed_b, 0);
+        // rounding leaves dust in the pool, never a deficit
+        assert_eq!(
+            token_amount(&accounts.user_token_a_account)
+                + token_amount(&vault_a_account)
+                + owner_fee,
+            USER_AMOUNT
+        );
+        assert!(token_amount(&vault_a_account) < 10);
+    }
+
+    #[test]
+    fn test_ramp_amp_round_trip() {
+        use crate::curve::stable::MIN_RAMP_DURATION;
+
+        let mut accounts = SwapAccountInfo::setup();
+        let state_owner_key = Pubkey::from_str(INITIAL_STATE_OWNER).unwrap();
+        let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+
+        let mut token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        *token_swap.swap_curve_mut() = SwapCurve {
+            curve_type: CurveType::Stable,
+            calculator: Box::new(StableCurve {
+                amp: 100,
+                ..StableCurve::default()
+            }),
+        };
+        SwapVersion::pack(token_swap, &mut accounts.swap_account.data).unwrap();
+
+        let stable_curve = |accounts: &SwapAccountInfo| {
+            let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+            let mut packed = [0u8; SwapCurve::LEN];
+            token_swap.swap_curve().pack_into_slice(&mut packed);
+            StableCurve::unpack_from_slice(&packed[1..]).unwrap()
+        };
+
+        do_process_instruction(
+            ramp_amp(
+                &crate::id(),
+                &accounts.swap_key,
+                &accounts.state_key,
+                &state_owner_key,
+                RampAmpInstruction {
+                    target_amp: 200,
+                    stop_ramp_ts: MIN_RAMP_DURATION,
                 },
-            ) => {
-                msg!("Instruction: WithdrawSingleTokenTypeExactAmountOut");
-                Self::process_withdraw_single_token_type_exact_amount_out(
-                    program_id,
-                    destination_token_amount,
-                    maximum_pool_token_amount,
-                    accounts,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut accounts.state_account,
+                &mut state_owner_account,
+            ],
+        )
+        .unwrap();
+        let mut curve = stable_curve(&accounts);
+        assert_eq!(curve.target_amp, 200);
+        curve.timestamp = MIN_RAMP_DURATION / 2;
+        assert_eq!(curve.current_amp(), Some(150));
+        curve.timestamp = MIN_RAMP_DURATION;
+        assert_eq!(curve.current_amp(), Some(200));
+
+        assert_eq!(
+            do_process_instruction(
+                ramp_amp(
+                    &crate::id(),
+                    &accounts.swap_key,
+                    &accounts.state_key,
+                    &state_owner_key,
+                    RampAmpInstruction {
+                        target_amp: 200,
+                        stop_ramp_ts: MIN_RAMP_DURATION,
+                    },
                 )
-            }
-        }
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut accounts.state_account,
+                    &mut state_owner_account,
+                ],
+            ),
+            Err(AmmError::InvalidAmpRamp.into())
+        );
+
+        do_process_instruction(
+            stop_ramp_amp(
+                &crate::id(),
+                &accounts.swap_key,
+                &accounts.state_key,
+                &state_owner_key,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut accounts.state_account,
+                &mut state_owner_account,
+            ],
+        )
+        .unwrap();
+        let mut curve = stable_curve(&accounts);
+        curve.timestamp = MIN_RAMP_DURATION;
+        assert_eq!(curve.current_amp(), Some(100));
     }
-}
 
-impl PrintProgramError for AmmError {
-    fn print<E>(&self)
-    where
-        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
-    {
-        match self {
-            AmmError::AlreadyInUse => msg!("Error: Swap account already in use"),
-            AmmError::InvalidProgramAddress => {
-                msg!("Error: Invalid program address generated from nonce and key")
-            }            
-            AmmError::InvalidStateAddress => {
-                msg!("Error: Invalid state address generated from seed")
-            }
-            AmmError::InvalidStateOwner => {
-                msg!("Error: The input account is not a owner of state account")
-            }
-            AmmError::InvalidOwner => {
-                msg!("Error: The input account owner is not the program address")
-            }
-            AmmError::InvalidOutputOwner => {
-                msg!("Error: Output pool account owner cannot be the program address")
-            }
-            AmmError::ExpectedMint => msg!("Error: Deserialized account is not an SPL Token mint"),
-            AmmError::ExpectedAccount => {
-                msg!("Error: Deserialized account is not an SPL Token account")
-            }
-            AmmError::EmptySupply => msg!("Error: Input token account empty"),
-            AmmError::InvalidDecimals => msg!("Error: Pool token mint doesn't have exact decimal"),
-            AmmError::InvalidSupply => msg!("Error: Pool token mint has a non-zero supply"),
-            AmmError::RepeatedMint => msg!("Error: Swap input token accounts have the same mint"),
-            AmmError::InvalidDelegate => msg!("Error: Token account has a delegate"),
-            AmmError::InvalidInput => msg!("Error: InvalidInput"),
-            AmmError::IncorrectSwapAccount => {
-                msg!("Error: Address of the provided swap token account is incorrect")
-            }
-            AmmError::IncorrectPoolMint => {
-                msg!("Error: Address of the provided pool token mint is incorrect")
-            }
-            AmmError::InvalidOutput => msg!("Error: InvalidOutput"),
-            AmmError::CalculationFailure => msg!("Error: CalculationFailure"),
-            AmmError::InvalidInstruction => msg!("Error: InvalidInstruction"),
-            AmmError::ExceededSlippage => {
-                msg!("Error: Swap instruction exceeds desired slippage limit")
-            }
-            AmmError::InvalidCloseAuthority => msg!("Error: Token account has a close authority"),
-            AmmError::InvalidFreezeAuthority => msg!("Error: Pool token mint has a freeze authority"),
-            AmmError::IncorrectMarketOwnerAccount => msg!("Error: Owner of Market account is incorrect"),
-            AmmError::InvalidSigner => msg!("State owner should be the signer"),
-            AmmError::NotInitializedState => msg!("Program State should be initialized before creating pool"),
+    #[test]
+    fn test_set_pause_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
 
-            AmmError::IncorrectFeeAccount => msg!("Error: Pool fee token account incorrect"),
-            AmmError::ZeroTradingTokens => {
-                msg!("Error: Given pool token amount results in zero trading tokens")
-            }
-            AmmError::FeeCalculationFailure => msg!(
-                "Error: The fee calculation failed due to overflow, underflow, or unexpected 0"
+        // pause the whole program, LPs can still withdraw
+        accounts.set_program_paused(true).unwrap();
+        assert_eq!(accounts.swap(TOKEN_A_AMOUNT / 100, 1), Err(AmmError::Paused.into()));
+        accounts.withdraw_all(INITIAL_SWAP_POOL_AMOUNT / 10, 0, 0).unwrap();
+        accounts.set_program_paused(false).unwrap();
+
+        // pause only the pool
+        accounts.set_pool_paused(true).unwrap();
+        assert!(!ProgramStateV2::unpack_from_slice(&accounts.state_account.data)
+            .unwrap()
+            .is_paused());
+        assert_eq!(accounts.swap(TOKEN_A_AMOUNT / 100, 1), Err(AmmError::Paused.into()));
+        accounts.set_pool_paused(false).unwrap();
+        accounts.swap(TOKEN_A_AMOUNT / 100, 1).unwrap();
+    }
+
+    #[test]
+    fn test_protocol_fee_mode_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let amount_in = TOKEN_A_AMOUNT / 100;
+
+        accounts.set_protocol_fee_mode(true).unwrap();
+        let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        let root_k_last = Processor::root_k(TOKEN_A_AMOUNT, TOKEN_B_AMOUNT).unwrap();
+        assert!(token_swap.lp_protocol_fee());
+        assert_eq!(token_swap.root_k_last(), root_k_last);
+
+        // the owner fee stays in the pool
+        accounts.swap(amount_in, 1).unwrap();
+        assert_eq!(token_amount(&accounts.fee_a_account), 0);
+        assert_eq!(token_amount(&accounts.token_a_account), TOKEN_A_AMOUNT + amount_in);
+
+        // and is minted as pool tokens on the next liquidity change
+        let root_k = Processor::root_k(
+            token_amount(&accounts.token_a_account),
+            token_amount(&accounts.token_b_account),
+        )
+        .unwrap();
+        let protocol_fee = state_fees()
+            .protocol_fee_pool_tokens(INITIAL_SWAP_POOL_AMOUNT.into(), root_k, root_k_last)
+            .unwrap() as u64;
+        assert!(protocol_fee > 0);
+        accounts.withdraw_all(INITIAL_SWAP_POOL_AMOUNT / 10, 0, 0).unwrap();
+        assert_eq!(token_amount(&accounts.pool_fee_account), protocol_fee);
+        let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            token_swap.root_k_last(),
+            Processor::root_k(
+                token_amount(&accounts.token_a_account),
+                token_amount(&accounts.token_b_account),
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_set_orderbook_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let mut state_owner_account = Account::new(1_000_000_000, 0, &system_program::id());
+        let dex_program_id = *SwapVersion::unpack(&accounts.swap_account.data)
+            .unwrap()
+            .dex_program_id();
+        let open_orders_key = Pubkey::new_unique();
+        let mut open_orders_account = Account::new(0, OPEN_ORDERS_LEN, &dex_program_id);
+
+        fn do_set_orderbook(
+            accounts: &mut SwapAccountInfo,
+            state_owner_account: &mut Account,
+            open_orders_key: &Pubkey,
+            open_orders_account: &mut Account,
+            instruction: SetOrderbookInstruction,
+        ) -> ProgramResult {
+            do_process_instruction(
+                set_orderbook(
+                    &crate::id(),
+                    &accounts.swap_key,
+                    &accounts.state_key,
+                    &Pubkey::from_str(INITIAL_STATE_OWNER).unwrap(),
+                    open_orders_key,
+                    instruction,
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut accounts.state_account,
+                    state_owner_account,
+                    open_orders_account,
+                ],
+            )
+        }
+
+        // too many orders per side
+        assert_eq!(
+            do_set_orderbook(
+                &mut accounts,
+                &mut state_owner_account,
+                &open_orders_key,
+                &mut open_orders_account,
+                SetOrderbookInstruction {
+                    depth_bps: 1_000,
+                    spread_bps: 20,
+                    levels: MAX_ORDER_LEVELS + 1,
+                },
             ),
-            AmmError::ConversionFailure => msg!("Error: Conversion to or from u64 failed."),
-            AmmError::InvalidFee => {
-                msg!("Error: The provided fee does not match the program owner's constraints")
-            }
-            AmmError::IncorrectTokenProgramId => {
-                msg!("Error: The provided token program does not match the token program expected by the swap")
-            }
-            AmmError::UnsupportedCurveType => {
-                msg!("Error: The provided curve type is not supported by the program owner")
-            }
-            AmmError::InvalidCurve => {
-                msg!("Error: The provided curve parameters are invalid")
-            }
-            AmmError::UnsupportedCurveOperation => {
-                msg!("Error: The operation cannot be performed on the given curve")
+            Err(AmmError::InvalidOrderbookParams.into())
+        );
+        // open orders account of another program
+        assert_eq!(
+            do_set_orderbook(
+                &mut accounts,
+                &mut state_owner_account,
+                &open_orders_key,
+                &mut Account::new(0, OPEN_ORDERS_LEN, &system_program::id()),
+                SetOrderbookInstruction {
+                    depth_bps: 1_000,
+                    spread_bps: 20,
+                    levels: 2,
+                },
+            ),
+            Err(AmmError::IncorrectMarketAccount.into())
+        );
+        do_set_orderbook(
+            &mut accounts,
+            &mut state_owner_account,
+            &open_orders_key,
+            &mut open_orders_account,
+            SetOrderbookInstruction {
+                depth_bps: 1_000,
+                spread_bps: 20,
+                levels: 2,
+            },
+        )
+        .unwrap();
+        let mut token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            *token_swap.orderbook(),
+            Orderbook {
+                open_orders: open_orders_key,
+                depth_bps: 1_000,
+                spread_bps: 20,
+                levels: 2,
+                has_orders: false,
             }
-        }
+        );
+
+        // orders posted by RefreshOrders lock deposits and withdrawals, and
+        // the open orders account, until they are cancelled
+        token_swap.orderbook_mut().has_orders = true;
+        SwapVersion::pack(token_swap, &mut accounts.swap_account.data).unwrap();
+        assert_eq!(
+            accounts.withdraw_all(INITIAL_SWAP_POOL_AMOUNT / 10, 0, 0),
+            Err(AmmError::OrdersOutstanding.into())
+        );
+        assert_eq!(
+            do_set_orderbook(
+                &mut accounts,
+                &mut state_owner_account,
+                &Pubkey::new_unique(),
+                &mut Account::new(0, OPEN_ORDERS_LEN, &dex_program_id),
+                SetOrderbookInstruction {
+                    depth_bps: 1_000,
+                    spread_bps: 20,
+                    levels: 2,
+                },
+            ),
+            Err(AmmError::OrdersOutstanding.into())
+        );
+
+        // disabling keeps the flag until the orders are cancelled
+        do_set_orderbook(
+            &mut accounts,
+            &mut state_owner_account,
+            &open_orders_key,
+            &mut open_orders_account,
+            SetOrderbookInstruction {
+                depth_bps: 0,
+                spread_bps: 0,
+                levels: 0,
+            },
+        )
+        .unwrap();
+        let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        assert!(!token_swap.orderbook().is_enabled());
+        assert!(token_swap.orderbook().has_orders);
     }
-}
 
-fn to_u128(val: u64) -> Result<u128, AmmError> {
-    val.try_into().map_err(|_| AmmError::ConversionFailure)
-}
+    #[test]
+    fn test_open_orders_free_balances() {
+        let mut data = vec![0; OPEN_ORDERS_LEN];
+        data[77..85].copy_from_slice(&5u64.to_le_bytes());
+        data[85..93].copy_from_slice(&7u64.to_le_bytes());
+        data[93..101].copy_from_slice(&11u64.to_le_bytes());
+        data[101..109].copy_from_slice(&13u64.to_le_bytes());
+        data[109..125].copy_from_slice(&u128::MAX.to_le_bytes());
+        let open_orders = OpenOrdersInfo::unpack(&data).unwrap();
+        assert_eq!(open_orders.native_coin_free, 5);
+        assert_eq!(open_orders.native_coin_total, 7);
+        assert_eq!(open_orders.native_pc_free, 11);
+        assert_eq!(open_orders.native_pc_total, 13);
+        assert!(open_orders.client_order_ids.is_empty());
+    }
 
-fn to_u64(val: u128) -> Result<u64, AmmError> {
-    val.try_into().map_err(|_| AmmError::ConversionFailure)
+    #[test]
+    fn test_split_swap_round_trip() {
+        let mut accounts = SwapAccountInfo::setup();
+        let token_swap = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        let market = SplitSwapMarketAccounts {
+            dex_program_id: *token_swap.dex_program_id(),
+            market_pubkey: *token_swap.market_id(),
+            open_orders_pubkey: Pubkey::new_unique(),
+            request_queue_pubkey: Pubkey::new_unique(),
+            event_queue_pubkey: Pubkey::new_unique(),
+            bids_pubkey: Pubkey::new_unique(),
+            asks_pubkey: Pubkey::new_unique(),
+            coin_vault_pubkey: Pubkey::new_unique(),
+            pc_vault_pubkey: Pubkey::new_unique(),
+            vault_signer_pubkey: Pubkey::new_unique(),
+        };
+
+        // market of token A against token B with empty books
+        let mut market_account = Account::new(0, MARKET_LEN, &market.dex_program_id);
+        for (offset, key) in &[
+            (53, SplAccount::unpack(&accounts.token_a_account.data).unwrap().mint),
+            (85, SplAccount::unpack(&accounts.token_b_account.data).unwrap().mint),
+            (117, market.coin_vault_pubkey),
+            (165, market.pc_vault_pubkey),
+            (221, market.request_queue_pubkey),
+            (253, market.event_queue_pubkey),
+            (285, market.bids_pubkey),
+            (317, market.asks_pubkey),
+        ] {
+            market_account.data[*offset..*offset + 32].copy_from_slice(key.as_ref());
+        }
+        market_account.data[349..357].copy_from_slice(&10u64.to_le_bytes());
+        market_account.data[357..365].copy_from_slice(&1u64.to_le_bytes());
+        let mut bids_account = Account::new(0, 4096, &market.dex_program_id);
+        let mut asks_account = Account::new(0, 4096, &market.dex_program_id);
+
+        let amount_in = TOKEN_A_AMOUNT / 100;
+        let curve_amount_out = token_swap
+            .swap_curve()
+            .swap(
+                amount_in.into(),
+                TOKEN_A_AMOUNT.into(),
+                TOKEN_B_AMOUNT.into(),
+                TradeDirection::AtoB,
+                token_swap.fees(),
+            )
+            .unwrap()
+            .destination_amount_swapped as u64;
+
+        let mut do_split_swap = |accounts: &mut SwapAccountInfo, minimum_amount_out: u64| {
+            do_process_instruction(
+                split_swap(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &accounts.swap_key,
+                    &accounts.authority_key,
+                    &accounts.user_key,
+                    &accounts.state_key,
+                    &accounts.user_token_a_key,
+                    &accounts.token_a_key,
+                    &accounts.token_b_key,
+                    &accounts.user_token_b_key,
+                    &accounts.pool_mint_key,
+                    &accounts.fee_a_key,
+                    &accounts.fee_owner_key,
+                    &market,
+                    None,
+                    SplitSwapInstruction {
+                        amount_in,
+                        minimum_amount_out,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut accounts.authority_account,
+                    &mut accounts.user_account,
+                    &mut accounts.state_account,
+                    &mut accounts.user_token_a_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.user_token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.fee_a_account,
+                    &mut accounts.fee_owner_account,
+                    &mut accounts.token_program_account,
+                    &mut accounts.system_program_account,
+                    &mut Account::default(),
+                    &mut market_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut bids_account,
+                    &mut asks_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        };
+
+        // nothing rests on the book, the whole amount goes through the curve
+        assert_eq!(
+            do_split_swap(&mut accounts, curve_amount_out + 1),
+            Err(AmmError::ExceededSlippage.into())
+        );
+        do_split_swap(&mut accounts, curve_amount_out).unwrap();
+        assert_eq!(token_amount(&accounts.user_token_a_account), USER_AMOUNT - amount_in);
+        assert_eq!(
+            token_amount(&accounts.user_token_b_account),
+            USER_AMOUNT + curve_amount_out
+        );
+    }
 }
@@ -0,0 +1,250 @@
+//! Deterministic addresses of the pools and their markers, one pool per
+//! pair of mints and curve type, and the mints others than the state owner
+//! may create pools of
+
+use crate::{curve::base::CurveType, error::AmmError};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+use std::convert::TryFrom;
+
+/// Seed of the deterministic pool addresses
+pub const POOL_SEED: &str = "AmmPool";
+/// Seed of the pool marker accounts
+pub const POOL_MARKER_SEED: &str = "AmmPoolMarker";
+
+/// Mints of a pair in a canonical order, so that both orders map to the
+/// same pool
+pub fn sorted_mints<'a>(mint_a: &'a Pubkey, mint_b: &'a Pubkey) -> (&'a Pubkey, &'a Pubkey) {
+    if mint_a.to_bytes() <= mint_b.to_bytes() {
+        (mint_a, mint_b)
+    } else {
+        (mint_b, mint_a)
+    }
+}
+
+/// Deterministic address of the pool of a pair and curve type, the order
+/// of the mints doesn't matter
+pub fn find_pool_address(
+    program_id: &Pubkey,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+    curve_type: CurveType,
+) -> (Pubkey, u8) {
+    let (first_mint, second_mint) = sorted_mints(mint_a, mint_b);
+    Pubkey::find_program_address(
+        &[
+            POOL_SEED.as_bytes(),
+            first_mint.as_ref(),
+            second_mint.as_ref(),
+            &[curve_type as u8],
+        ],
+        program_id,
+    )
+}
+
+/// Address of the marker of a pair and curve type, the order of the mints
+/// doesn't matter
+pub fn find_pool_marker_address(
+    program_id: &Pubkey,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+    curve_type: CurveType,
+) -> (Pubkey, u8) {
+    let (first_mint, second_mint) = sorted_mints(mint_a, mint_b);
+    Pubkey::find_program_address(
+        &[
+            POOL_MARKER_SEED.as_bytes(),
+            first_mint.as_ref(),
+            second_mint.as_ref(),
+            &[curve_type as u8],
+        ],
+        program_id,
+    )
+}
+
+/// Marks the pool of a pair of mints and curve type, at most one per pair
+/// and curve type. Other programs can look a pool up by its mints at
+/// [find_pool_marker_address](fn.find_pool_marker_address.html).
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoolMarker {
+    /// Initialized state, cleared when the pool is closed
+    pub is_initialized: bool,
+    /// Address of the swap account
+    pub pool: Pubkey,
+    /// Mint of token A of the pool
+    pub token_a_mint: Pubkey,
+    /// Mint of token B of the pool
+    pub token_b_mint: Pubkey,
+    /// Curve type of the pool
+    pub curve_type: CurveType,
+}
+
+impl IsInitialized for PoolMarker {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Sealed for PoolMarker {}
+impl Pack for PoolMarker {
+    const LEN: usize = 98;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, PoolMarker::LEN];
+        let (is_initialized, pool, token_a_mint, token_b_mint, curve_type) =
+            mut_array_refs![output, 1, 32, 32, 32, 1];
+        is_initialized[0] = self.is_initialized as u8;
+        pool.copy_from_slice(self.pool.as_ref());
+        token_a_mint.copy_from_slice(self.token_a_mint.as_ref());
+        token_b_mint.copy_from_slice(self.token_b_mint.as_ref());
+        curve_type[0] = self.curve_type as u8;
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, PoolMarker::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, pool, token_a_mint, token_b_mint, curve_type) =
+            array_refs![input, 1, 32, 32, 32, 1];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            pool: Pubkey::new_from_array(*pool),
+            token_a_mint: Pubkey::new_from_array(*token_a_mint),
+            token_b_mint: Pubkey::new_from_array(*token_b_mint),
+            curve_type: CurveType::try_from(curve_type[0])?,
+        })
+    }
+}
+
+/// Seed of the mint list account
+pub const MINT_LIST_SEED: &str = "AmmMintList";
+/// Maximum number of mints in the mint list
+pub const MAX_LISTED_MINTS: usize = 100;
+
+/// Address of the mint list account of the program
+pub fn find_mint_list_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MINT_LIST_SEED.as_bytes(), program_id.as_ref()], program_id)
+}
+
+/// How the mint list restricts the pools created by others than the state
+/// owner
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MintListMode {
+    /// Any mint
+    #[default]
+    Disabled,
+    /// Only listed mints
+    Allowlist,
+    /// Any mint but the listed ones
+    Denylist,
+}
+
+impl TryFrom<u8> for MintListMode {
+    type Error = ProgramError;
+
+    fn try_from(mode: u8) -> Result<Self, Self::Error> {
+        match mode {
+            0 => Ok(MintListMode::Disabled),
+            1 => Ok(MintListMode::Allowlist),
+            2 => Ok(MintListMode::Denylist),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Mints listed by the state owner, allowed or denied depending on the
+/// [MintListMode](enum.MintListMode.html) of the program state
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MintList {
+    /// Initialized state
+    pub is_initialized: bool,
+    /// Listed mints
+    pub mints: Vec<Pubkey>,
+}
+
+impl MintList {
+    /// Is the mint listed
+    pub fn contains(&self, mint: &Pubkey) -> bool {
+        self.mints.contains(mint)
+    }
+
+    /// Can others than the state owner create pools of the mint
+    pub fn allows(&self, mode: MintListMode, mint: &Pubkey) -> bool {
+        match mode {
+            MintListMode::Disabled => true,
+            MintListMode::Allowlist => self.contains(mint),
+            MintListMode::Denylist => !self.contains(mint),
+        }
+    }
+
+    /// Lists a mint, fails if the list is full
+    pub fn insert(&mut self, mint: Pubkey) -> Result<(), AmmError> {
+        if self.contains(&mint) {
+            return Ok(());
+        }
+        if self.mints.len() >= MAX_LISTED_MINTS {
+            return Err(AmmError::MintListFull);
+        }
+        self.mints.push(mint);
+        Ok(())
+    }
+
+    /// Unlists a mint
+    pub fn remove(&mut self, mint: &Pubkey) {
+        self.mints.retain(|listed| listed != mint);
+    }
+}
+
+impl IsInitialized for MintList {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Sealed for MintList {}
+impl Pack for MintList {
+    const LEN: usize = 5 + MAX_LISTED_MINTS * 32;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, MintList::LEN];
+        let (is_initialized, count, mints) = mut_array_refs![output, 1, 4, MAX_LISTED_MINTS * 32];
+        is_initialized[0] = self.is_initialized as u8;
+        *count = (self.mints.len() as u32).to_le_bytes();
+        *mints = [0u8; MAX_LISTED_MINTS * 32];
+        for (mint, data) in self.mints.iter().zip(mints.chunks_exact_mut(32)) {
+            data.copy_from_slice(mint.as_ref());
+        }
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, MintList::LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, count, mints) = array_refs![input, 1, 4, MAX_LISTED_MINTS * 32];
+        let count = u32::from_le_bytes(*count) as usize;
+        if count > MAX_LISTED_MINTS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            mints: mints
+                .chunks_exact(32)
+                .take(count)
+                .map(|mint| Pubkey::new_from_array(*array_ref![mint, 0, 32]))
+                .collect(),
+        })
+    }
+}
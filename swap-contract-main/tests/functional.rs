@@ -0,0 +1,1981 @@
+//! Runs the processor in a `solana-program-test` bank
+
+use neonomad_liquidity_pool::{
+    amm_instruction::{
+        close_pool, decrease_liquidity, deposit_all_token_types, deposit_single_token_type_exact_amount_in,
+        execute_state_update, initialize, initialize_concentrated_pool, migrate, open_position,
+        queue_state_update, ramp_amp, route_swap, set_pause, set_pool_creation, swap,
+        swap_exact_amount_out, update_mint_list, update_state, withdraw_all_token_types,
+        withdraw_single_token_type_exact_amount_out, DecreaseLiquidityInstruction,
+        DepositInstruction, DepositSingleTokenTypeExactAmountIn,
+        InitializeConcentratedPoolInstruction, OpenPositionInstruction, RampAmpInstruction,
+        RouteSwapHop, RouteSwapInstruction,
+        ClosePoolInstruction, QueueStateUpdateInstruction, SetPauseInstruction, SetPoolCreationInstruction, SwapExactAmountOutInstruction,
+        SwapInstruction, UpdateMintListInstruction, UpdateStateInstruction, WithdrawInstruction, WithdrawSingleTokenTypeExactAmountOut,
+    },
+    amm_stats::{AmmStatus, ProgramState, ProgramStateV2, SwapV1, SwapV2, SwapVersion},
+    concentrated::state::{ConcentratedPool, Position},
+    constraints::SWAP_CONSTRAINTS,
+    curve::{
+        base::{CurveType, SwapCurve},
+        calculator::{TradeDirection, INITIAL_SWAP_POOL_AMOUNT},
+        constant_price::ConstantPriceCurve,
+        constant_product::ConstantProductCurve,
+        fees::Fees,
+        stable::StableCurve,
+        weighted::WeightedCurve,
+    },
+    error::AmmError,
+    id,
+    processor::{Processor, AMM_STATE_SEED, LP_MINT_DECIMALS, MIN_LP_SUPPLY, WSOL_MINT_ADDRESS},
+    registry::{
+        find_mint_list_address, find_pool_marker_address, MintList, MintListMode, PoolMarker,
+    },
+};
+use solana_program::{
+    clock::UnixTimestamp,
+    instruction::{Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as SplAccount, AccountState, Mint};
+use std::{collections::HashMap, str::FromStr};
+
+const TOKEN_A_AMOUNT: u64 = 1_000_000;
+const TOKEN_B_AMOUNT: u64 = 2_000_000;
+const USER_AMOUNT: u64 = 1_000_000;
+const LAMPORTS: u64 = 1_000_000_000;
+// pool tokens minted to the destination at initialization
+const USER_POOL_AMOUNT: u64 = INITIAL_SWAP_POOL_AMOUNT - MIN_LP_SUPPLY as u64;
+
+fn amm_error(error: AmmError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+fn state_fees() -> Fees {
+    // pools start with static fees, the constraint only bounds dynamic ones
+    Fees {
+        max_return_fee_numerator: 0,
+        ..SWAP_CONSTRAINTS.fees.clone()
+    }
+}
+
+fn constant_product_curve() -> SwapCurve {
+    SwapCurve {
+        curve_type: CurveType::ConstantProduct,
+        calculator: Box::new(ConstantProductCurve {}),
+    }
+}
+
+fn program_account(data: Vec<u8>, owner: &Pubkey) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: *owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn system_account() -> Account {
+    Account::new(LAMPORTS, 0, &system_program::id())
+}
+
+fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    let mut account = program_account(vec![0; SplAccount::LEN], &spl_token::id());
+    SplAccount::pack(
+        SplAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..SplAccount::default()
+        },
+        &mut account.data,
+    )
+    .unwrap();
+    account
+}
+
+fn mint_account(mint_authority: &Pubkey, decimals: u8) -> Account {
+    let mut account = program_account(vec![0; Mint::LEN], &spl_token::id());
+    Mint::pack(
+        Mint {
+            mint_authority: COption::Some(*mint_authority),
+            supply: 0,
+            decimals,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut account.data,
+    )
+    .unwrap();
+    account
+}
+
+/// Keys of a constant product pool and the accounts the bank starts with.
+///
+/// The state PDA can only be created by `INITIAL_STATE_OWNER`, whose key
+/// the tests don't hold, so it is seeded already owned by `state_owner` and
+/// configured with the program defaults.
+struct SwapTest {
+    swap: Keypair,
+    authority: Pubkey,
+    nonce: u8,
+    state: Pubkey,
+    state_owner: Keypair,
+    fee_owner: Pubkey,
+    pool_mint: Pubkey,
+    token_a_mint: Pubkey,
+    token_b_mint: Pubkey,
+    token_a: Pubkey,
+    token_b: Pubkey,
+    fee_a: Pubkey,
+    fee_b: Pubkey,
+    fee_pool: Pubkey,
+    user: Keypair,
+    user_token_a: Pubkey,
+    user_token_b: Pubkey,
+    user_pool: Pubkey,
+    lock: Pubkey,
+    market: Pubkey,
+    dex_program_id: Pubkey,
+    accounts: HashMap<Pubkey, Account>,
+}
+
+impl SwapTest {
+    fn new() -> Self {
+        Self::with_mints(Pubkey::new_unique(), Pubkey::new_unique())
+    }
+
+    fn with_mints(token_a_mint: Pubkey, token_b_mint: Pubkey) -> Self {
+        let program_id = id();
+        let (state, _bump) = Pubkey::find_program_address(
+            &[AMM_STATE_SEED.as_bytes(), program_id.as_ref()],
+            &program_id,
+        );
+        let swap = Keypair::new();
+        let (authority, nonce) =
+            Pubkey::find_program_address(&[&swap.pubkey().to_bytes()[..]], &program_id);
+        let state_owner = Keypair::new();
+        let fee_owner = Pubkey::new_unique();
+        let user = Keypair::new();
+        let dex_program_id = Pubkey::new_unique();
+
+        let mut test = Self {
+            swap,
+            authority,
+            nonce,
+            state,
+            state_owner,
+            fee_owner,
+            pool_mint: Pubkey::new_unique(),
+            token_a_mint,
+            token_b_mint,
+            token_a: Pubkey::new_unique(),
+            token_b: Pubkey::new_unique(),
+            fee_a: Pubkey::new_unique(),
+            fee_b: Pubkey::new_unique(),
+            fee_pool: Pubkey::new_unique(),
+            user,
+            user_token_a: Pubkey::new_unique(),
+            user_token_b: Pubkey::new_unique(),
+            user_pool: Pubkey::new_unique(),
+            lock: Pubkey::new_unique(),
+            market: Pubkey::new_unique(),
+            dex_program_id,
+            accounts: HashMap::new(),
+        };
+
+        let mut state_data = vec![0; ProgramStateV2::LEN];
+        ProgramStateV2 {
+            is_initialized: true,
+            state_owner: test.state_owner.pubkey(),
+            fee_owner: test.fee_owner,
+            initial_supply: INITIAL_SWAP_POOL_AMOUNT,
+            fees: state_fees(),
+            swap_curve: constant_product_curve(),
+            ..ProgramStateV2::default()
+        }
+        .pack_into_slice(&mut state_data);
+        let user_key = test.user.pubkey();
+        let accounts = vec![
+            (test.state, program_account(state_data, &program_id)),
+            (
+                test.swap.pubkey(),
+                program_account(vec![0; SwapVersion::LATEST_LEN], &program_id),
+            ),
+            (test.state_owner.pubkey(), system_account()),
+            (test.fee_owner, system_account()),
+            (user_key, system_account()),
+            // only the owner of the Serum market is checked
+            (test.market, program_account(vec![], &dex_program_id)),
+            (test.pool_mint, mint_account(&authority, LP_MINT_DECIMALS)),
+            (
+                test.token_a,
+                token_account(&token_a_mint, &authority, TOKEN_A_AMOUNT),
+            ),
+            (
+                test.token_b,
+                token_account(&token_b_mint, &authority, TOKEN_B_AMOUNT),
+            ),
+            (test.fee_a, token_account(&token_a_mint, &fee_owner, 0)),
+            (test.fee_b, token_account(&token_b_mint, &fee_owner, 0)),
+            (test.fee_pool, token_account(&test.pool_mint, &fee_owner, 0)),
+            (
+                test.user_token_a,
+                token_account(&token_a_mint, &user_key, USER_AMOUNT),
+            ),
+            (
+                test.user_token_b,
+                token_account(&token_b_mint, &user_key, USER_AMOUNT),
+            ),
+            (test.user_pool, token_account(&test.pool_mint, &user_key, 0)),
+            (
+                test.lock,
+                token_account(
+                    &test.pool_mint,
+                    &Processor::lp_lock_id(&program_id, &test.swap.pubkey()),
+                    0,
+                ),
+            ),
+        ];
+        test.accounts.extend(accounts);
+        test
+    }
+
+    fn set_token_account(&mut self, key: Pubkey, update: impl FnOnce(&mut SplAccount)) {
+        let account = self.accounts.get_mut(&key).unwrap();
+        let mut token = SplAccount::unpack(&account.data).unwrap();
+        update(&mut token);
+        SplAccount::pack(token, &mut account.data).unwrap();
+    }
+
+    fn set_mint(&mut self, key: Pubkey, update: impl FnOnce(&mut Mint)) {
+        let account = self.accounts.get_mut(&key).unwrap();
+        let mut mint = Mint::unpack(&account.data).unwrap();
+        update(&mut mint);
+        Mint::pack(mint, &mut account.data).unwrap();
+    }
+
+    /// Second pool of a route, trading the token B of this pool against a
+    /// new mint for the same user. Its accounts are added to this test.
+    fn next_hop(&mut self) -> SwapTest {
+        let mut next = SwapTest::with_mints(self.token_b_mint, Pubkey::new_unique());
+        next.state_owner = Keypair::from_bytes(&self.state_owner.to_bytes()).unwrap();
+        next.user = Keypair::from_bytes(&self.user.to_bytes()).unwrap();
+        next.fee_owner = self.fee_owner;
+        // the output of this pool is the input of the next one
+        next.user_token_a = self.user_token_b;
+        let fee_owner = self.fee_owner;
+        for key in [next.fee_a, next.fee_b, next.fee_pool] {
+            next.set_token_account(key, |account| account.owner = fee_owner);
+        }
+        let user = self.user.pubkey();
+        next.set_token_account(next.user_token_b, |account| account.owner = user);
+        for (key, account) in next.accounts.drain() {
+            self.accounts.entry(key).or_insert(account);
+        }
+        next
+    }
+
+    async fn start(&self) -> SwapContext {
+        let mut program_test = ProgramTest::new(
+            "neonomad_liquidity_pool",
+            id(),
+            processor!(Processor::process),
+        );
+        program_test.add_program(
+            "spl_token",
+            spl_token::id(),
+            processor!(spl_token::processor::Processor::process),
+        );
+        for (key, account) in self.accounts.iter() {
+            program_test.add_account(*key, account.clone());
+        }
+        let (banks_client, payer, recent_blockhash) = program_test.start().await;
+        SwapContext {
+            banks_client,
+            payer,
+            recent_blockhash,
+        }
+    }
+
+    /// Starts the bank and initializes the pool with the program defaults
+    async fn start_pool(&self) -> SwapContext {
+        let mut context = self.start().await;
+        context
+            .process(
+                self.initialize(None, None),
+                &[&self.swap, &self.state_owner],
+            )
+            .await
+            .unwrap();
+        context
+    }
+
+    fn update_state(&self, fees: Fees, swap_curve: SwapCurve) -> Instruction {
+        update_state(
+            &id(),
+            &self.state,
+            &self.state_owner.pubkey(),
+            &self.fee_owner,
+            UpdateStateInstruction {
+                initial_supply: INITIAL_SWAP_POOL_AMOUNT,
+                fees,
+                swap_curve,
+            },
+        )
+        .unwrap()
+    }
+
+    fn queue_state_update(&self, fees: Fees, swap_curve: SwapCurve) -> Instruction {
+        self.queue_state_update_after(SWAP_CONSTRAINTS.min_state_update_delay, fees, swap_curve)
+    }
+
+    fn queue_state_update_after(
+        &self,
+        delay: UnixTimestamp,
+        fees: Fees,
+        swap_curve: SwapCurve,
+    ) -> Instruction {
+        queue_state_update(
+            &id(),
+            &self.state,
+            &self.state_owner.pubkey(),
+            &self.fee_owner,
+            QueueStateUpdateInstruction {
+                delay,
+                update: UpdateStateInstruction {
+                    initial_supply: INITIAL_SWAP_POOL_AMOUNT,
+                    fees,
+                    swap_curve,
+                },
+            },
+        )
+        .unwrap()
+    }
+
+    /// Marker of the pool of the pair for the given curve type
+    fn pool_marker(&self, curve_type: CurveType) -> Pubkey {
+        find_pool_marker_address(&id(), &self.token_a_mint, &self.token_b_mint, curve_type).0
+    }
+
+    fn initialize(&self, fees: Option<Fees>, swap_curve: Option<SwapCurve>) -> Instruction {
+        self.initialize_by(&self.state_owner.pubkey(), fees, swap_curve)
+    }
+
+    fn initialize_by(
+        &self,
+        creator: &Pubkey,
+        fees: Option<Fees>,
+        swap_curve: Option<SwapCurve>,
+    ) -> Instruction {
+        initialize(
+            &id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.state,
+            &Pubkey::new_unique(),
+            &self.token_a,
+            &self.token_b,
+            &self.pool_mint,
+            &self.user_pool,
+            &self.lock,
+            &self.market,
+            &self.dex_program_id,
+            creator,
+            &self.pool_marker(
+                swap_curve
+                    .as_ref()
+                    .map_or(CurveType::ConstantProduct, |swap_curve| swap_curve.curve_type),
+            ),
+            &find_mint_list_address(&id()).0,
+            &self.fee_owner,
+            self.nonce,
+            fees,
+            swap_curve,
+            false,
+        )
+        .unwrap()
+    }
+
+    fn swap(&self, amount_in: u64, minimum_amount_out: u64) -> Instruction {
+        swap(
+            &id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.user.pubkey(),
+            &self.state,
+            &self.user_token_a,
+            &self.token_a,
+            &self.token_b,
+            &self.user_token_b,
+            &self.pool_mint,
+            &self.fee_a,
+            &self.fee_owner,
+            None,
+            SwapInstruction {
+                amount_in,
+                minimum_amount_out,
+            },
+        )
+        .unwrap()
+    }
+
+    /// Accounts of this pool in a route swapping token A for token B
+    fn route_hop(&self) -> RouteSwapHop {
+        RouteSwapHop {
+            swap_pubkey: self.swap.pubkey(),
+            authority_pubkey: self.authority,
+            source_pubkey: self.user_token_a,
+            swap_source_pubkey: self.token_a,
+            swap_destination_pubkey: self.token_b,
+            destination_pubkey: self.user_token_b,
+            pool_mint_pubkey: self.pool_mint,
+            fee_account_pubkey: self.fee_a,
+        }
+    }
+
+    fn route_swap(
+        &self,
+        hops: &[RouteSwapHop],
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Instruction {
+        route_swap(
+            &id(),
+            &spl_token::id(),
+            &self.user.pubkey(),
+            &self.state,
+            &self.fee_owner,
+            hops,
+            RouteSwapInstruction {
+                amount_in,
+                minimum_amount_out,
+            },
+        )
+        .unwrap()
+    }
+
+    fn deposit_all_token_types(&self, instruction: DepositInstruction) -> Instruction {
+        deposit_all_token_types(
+            &id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.user.pubkey(),
+            &self.state,
+            &self.user_token_a,
+            &self.user_token_b,
+            &self.token_a,
+            &self.token_b,
+            &self.pool_mint,
+            &self.user_pool,
+            &self.fee_pool,
+            instruction,
+        )
+        .unwrap()
+    }
+
+    fn withdraw_all_token_types(&self, instruction: WithdrawInstruction) -> Instruction {
+        withdraw_all_token_types(
+            &id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.user.pubkey(),
+            &self.state,
+            &self.pool_mint,
+            &self.user_pool,
+            &self.token_a,
+            &self.token_b,
+            &self.user_token_a,
+            &self.user_token_b,
+            &self.fee_pool,
+            instruction,
+        )
+        .unwrap()
+    }
+
+    fn deposit_single_token_type(
+        &self,
+        source: &Pubkey,
+        fee: &Pubkey,
+        instruction: DepositSingleTokenTypeExactAmountIn,
+    ) -> Instruction {
+        deposit_single_token_type_exact_amount_in(
+            &id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.user.pubkey(),
+            &self.state,
+            source,
+            &self.token_a,
+            &self.token_b,
+            &self.pool_mint,
+            &self.user_pool,
+            &self.fee_pool,
+            fee,
+            instruction,
+        )
+        .unwrap()
+    }
+
+    fn withdraw_single_token_type(
+        &self,
+        destination: &Pubkey,
+        fee: &Pubkey,
+        instruction: WithdrawSingleTokenTypeExactAmountOut,
+    ) -> Instruction {
+        withdraw_single_token_type_exact_amount_out(
+            &id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.user.pubkey(),
+            &self.state,
+            &self.pool_mint,
+            &self.user_pool,
+            &self.token_a,
+            &self.token_b,
+            destination,
+            &self.fee_pool,
+            fee,
+            instruction,
+        )
+        .unwrap()
+    }
+
+    fn close_pool(&self, recipient: &Pubkey, wind_down: bool) -> Instruction {
+        close_pool(
+            &id(),
+            &spl_token::id(),
+            &self.swap.pubkey(),
+            &self.authority,
+            &self.state,
+            &self.state_owner.pubkey(),
+            &self.token_a,
+            &self.token_b,
+            &self.pool_mint,
+            &self.fee_a,
+            &self.fee_b,
+            &self.pool_marker(CurveType::ConstantProduct),
+            recipient,
+            ClosePoolInstruction { wind_down },
+        )
+        .unwrap()
+    }
+}
+
+struct SwapContext {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: Hash,
+}
+
+impl SwapContext {
+    async fn process(
+        &mut self,
+        instruction: Instruction,
+        signers: &[&Keypair],
+    ) -> Result<(), TransactionError> {
+        let mut transaction =
+            Transaction::new_with_payer(&[instruction], Some(&self.payer.pubkey()));
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+        transaction.sign(&all_signers, self.recent_blockhash);
+        self.banks_client
+            .process_transaction(transaction)
+            .await
+            .map_err(|err| err.unwrap())
+    }
+
+    /// Moves to a new blockhash, so that an identical transaction isn't
+    /// answered with the result of the first one
+    async fn refresh_blockhash(&mut self) {
+        self.recent_blockhash = self
+            .banks_client
+            .get_new_latest_blockhash(&self.recent_blockhash)
+            .await
+            .unwrap();
+    }
+
+    async fn account(&mut self, key: &Pubkey) -> Option<Account> {
+        self.banks_client.get_account(*key).await.unwrap()
+    }
+
+    async fn token_amount(&mut self, key: &Pubkey) -> u64 {
+        let account = self.account(key).await.unwrap();
+        SplAccount::unpack(&account.data).unwrap().amount
+    }
+
+    async fn lamports(&mut self, key: &Pubkey) -> u64 {
+        self.account(key).await.unwrap().lamports
+    }
+}
+
+/// Initializes a pool whose accounts were changed by `update`, returns the
+/// error of the initialization
+async fn initialize_error(update: impl FnOnce(&mut SwapTest)) -> TransactionError {
+    let mut test = SwapTest::new();
+    update(&mut test);
+    let mut context = test.start().await;
+    context
+        .process(
+            test.initialize(None, None),
+            &[&test.swap, &test.state_owner],
+        )
+        .await
+        .unwrap_err()
+}
+
+#[tokio::test]
+async fn test_update_state() {
+    let test = SwapTest::new();
+    let mut context = test.start().await;
+
+    // the seeded state is initialized, its parameters only change through
+    // a queued update
+    assert_eq!(
+        context
+            .process(
+                test.update_state(state_fees(), constant_product_curve()),
+                &[&test.state_owner]
+            )
+            .await,
+        Err(amm_error(AmmError::StateUpdateNotQueued))
+    );
+
+    let state_account = context.account(&test.state).await.unwrap();
+    assert_eq!(state_account.owner, id());
+    let state = ProgramStateV2::unpack_from_slice(&state_account.data).unwrap();
+    assert!(state.is_initialized());
+    assert_eq!(*state.state_owner(), test.state_owner.pubkey());
+    assert_eq!(*state.fee_owner(), test.fee_owner);
+    assert_eq!(state.initial_supply(), INITIAL_SWAP_POOL_AMOUNT);
+    assert_eq!(*state.fees(), state_fees());
+    assert_eq!(state.swap_curve().curve_type, CurveType::ConstantProduct);
+}
+
+#[tokio::test]
+async fn test_queue_state_update() {
+    let test = SwapTest::new();
+    let mut context = test.start().await;
+    let fees = Fees {
+        fixed_fee_numerator: state_fees().fixed_fee_numerator + 1,
+        ..state_fees()
+    };
+    assert_eq!(
+        context
+            .process(
+                test.queue_state_update_after(
+                    SWAP_CONSTRAINTS.min_state_update_delay - 1,
+                    fees.clone(),
+                    constant_product_curve(),
+                ),
+                &[&test.state_owner]
+            )
+            .await,
+        Err(amm_error(AmmError::StateUpdateDelayTooShort))
+    );
+    context
+        .process(
+            test.queue_state_update(fees.clone(), constant_product_curve()),
+            &[&test.state_owner],
+        )
+        .await
+        .unwrap();
+
+    let state_account = context.account(&test.state).await.unwrap();
+    let state = ProgramStateV2::unpack_from_slice(&state_account.data).unwrap();
+    assert_eq!(*state.fees(), state_fees());
+    assert_eq!(state.pending_update().unwrap().fees, fees);
+
+    assert_eq!(
+        context
+            .process(
+                execute_state_update(&id(), &test.state, &test.state_owner.pubkey()).unwrap(),
+                &[&test.state_owner]
+            )
+            .await,
+        Err(amm_error(AmmError::StateUpdateTimelocked))
+    );
+}
+
+#[tokio::test]
+async fn test_update_state_errors() {
+    let test = SwapTest::new();
+    let mut context = test.start().await;
+
+    let mut instruction = test.update_state(state_fees(), constant_product_curve());
+    instruction.accounts[0].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &[&test.state_owner]).await,
+        Err(amm_error(AmmError::InvalidStateAddress))
+    );
+
+    let mut instruction = test.update_state(state_fees(), constant_product_curve());
+    instruction.accounts[1].is_signer = false;
+    assert_eq!(
+        context.process(instruction, &[]).await,
+        Err(amm_error(AmmError::InvalidSigner))
+    );
+
+    let other_owner = Keypair::new();
+    let mut instruction = test.update_state(state_fees(), constant_product_curve());
+    instruction.accounts[1].pubkey = other_owner.pubkey();
+    assert_eq!(
+        context.process(instruction, &[&other_owner]).await,
+        Err(amm_error(AmmError::InvalidStateOwner))
+    );
+
+    let fees = Fees {
+        fixed_fee_numerator: state_fees().fixed_fee_numerator - 1,
+        ..state_fees()
+    };
+    assert_eq!(
+        context
+            .process(
+                test.queue_state_update(fees, constant_product_curve()),
+                &[&test.state_owner]
+            )
+            .await,
+        Err(amm_error(AmmError::InvalidFee))
+    );
+
+    let swap_curve = SwapCurve {
+        curve_type: CurveType::ConstantPrice,
+        calculator: Box::new(ConstantPriceCurve { token_b_price: 1 }),
+    };
+    assert_eq!(
+        context
+            .process(
+                test.queue_state_update(state_fees(), swap_curve),
+                &[&test.state_owner]
+            )
+            .await,
+        Err(amm_error(AmmError::UnsupportedCurveType))
+    );
+
+    let swap_curve = SwapCurve {
+        curve_type: CurveType::Weighted,
+        calculator: Box::new(WeightedCurve {
+            token_a_weight: 0,
+            token_b_weight: 1,
+        }),
+    };
+    assert_eq!(
+        context
+            .process(
+                test.queue_state_update(state_fees(), swap_curve),
+                &[&test.state_owner]
+            )
+            .await,
+        Err(amm_error(AmmError::InvalidCurve))
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_instruction() {
+    let test = SwapTest::new();
+    let mut context = test.start().await;
+    let mut instruction = test.initialize(None, None);
+    instruction.data.push(0);
+    assert_eq!(
+        context
+            .process(instruction, &[&test.swap, &test.state_owner])
+            .await,
+        Err(amm_error(AmmError::InvalidInstruction))
+    );
+}
+
+#[tokio::test]
+async fn test_initialize() {
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+
+    let swap_account = context.account(&test.swap.pubkey()).await.unwrap();
+    let token_swap = SwapVersion::unpack(&swap_account.data).unwrap();
+    assert!(token_swap.is_initialized());
+    assert_eq!(token_swap.nonce(), test.nonce);
+    assert_eq!(*token_swap.token_a_account(), test.token_a);
+    assert_eq!(*token_swap.token_b_account(), test.token_b);
+    assert_eq!(*token_swap.pool_mint(), test.pool_mint);
+    assert_eq!(*token_swap.fees(), state_fees());
+    assert!(!token_swap.is_paused());
+    let swap_v2 = SwapV2::unpack(&swap_account.data[1..]).unwrap();
+    assert_eq!(swap_v2.market_id, test.market);
+    assert_eq!(swap_v2.dex_program_id, test.dex_program_id);
+    assert_eq!(context.token_amount(&test.user_pool).await, USER_POOL_AMOUNT);
+    assert_eq!(context.token_amount(&test.lock).await, MIN_LP_SUPPLY as u64);
+
+    // the pool is found by its mints
+    let pool_marker_account = context
+        .account(&test.pool_marker(CurveType::ConstantProduct))
+        .await
+        .unwrap();
+    let pool_marker = PoolMarker::unpack(&pool_marker_account.data).unwrap();
+    assert_eq!(pool_marker.pool, test.swap.pubkey());
+
+    assert_eq!(
+        context
+            .process(
+                test.initialize(None, None),
+                &[&test.swap, &test.state_owner]
+            )
+            .await,
+        Err(amm_error(AmmError::AlreadyInUse))
+    );
+}
+
+#[tokio::test]
+async fn test_permissionless_initialize() {
+    let test = SwapTest::new();
+    let mut context = test.start().await;
+    let creator = test.user.pubkey();
+    let token_a_mint = SplAccount::unpack(&test.accounts[&test.token_a].data)
+        .unwrap()
+        .mint;
+    let pool_creation_fee = LAMPORTS / 10;
+    context
+        .process(
+            set_pool_creation(
+                &id(),
+                &test.state,
+                &test.state_owner.pubkey(),
+                SetPoolCreationInstruction {
+                    permissionless_pools: true,
+                    mint_list_mode: MintListMode::Denylist,
+                    pool_creation_fee,
+                },
+            )
+            .unwrap(),
+            &[&test.state_owner],
+        )
+        .await
+        .unwrap();
+
+    // the mint list is created on its first update
+    let mint_list = find_mint_list_address(&id()).0;
+    let update = |listed| {
+        update_mint_list(
+            &id(),
+            &test.state,
+            &test.state_owner.pubkey(),
+            &mint_list,
+            UpdateMintListInstruction {
+                mint: token_a_mint,
+                listed,
+            },
+        )
+        .unwrap()
+    };
+    context
+        .process(update(true), &[&test.state_owner])
+        .await
+        .unwrap();
+    let mint_list_account = context.account(&mint_list).await.unwrap();
+    assert_eq!(mint_list_account.owner, id());
+    assert_eq!(
+        MintList::unpack(&mint_list_account.data).unwrap().mints,
+        vec![token_a_mint]
+    );
+    assert_eq!(
+        context
+            .process(
+                test.initialize_by(&creator, None, None),
+                &[&test.swap, &test.user]
+            )
+            .await,
+        Err(amm_error(AmmError::MintNotAllowed))
+    );
+
+    // once unlisted, anyone creates the pool by paying the creation fee
+    context
+        .process(update(false), &[&test.state_owner])
+        .await
+        .unwrap();
+    let fee_owner_lamports = context.lamports(&test.fee_owner).await;
+    context
+        .process(
+            test.initialize_by(&creator, None, None),
+            &[&test.swap, &test.user],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        context.lamports(&test.fee_owner).await,
+        fee_owner_lamports + pool_creation_fee
+    );
+    let swap_account = context.account(&test.swap.pubkey()).await.unwrap();
+    assert!(SwapVersion::unpack(&swap_account.data).unwrap().is_initialized());
+}
+
+#[tokio::test]
+async fn test_initialize_instruction_errors() {
+    let test = SwapTest::new();
+    let mut context = test.start().await;
+    let signers = [&test.swap, &test.state_owner];
+
+    let mut instruction = test.initialize(None, None);
+    instruction.data[1] = test.nonce.wrapping_sub(1);
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::InvalidProgramAddress))
+    );
+
+    let mut instruction = test.initialize(None, None);
+    instruction.accounts[2].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::InvalidStateAddress))
+    );
+
+    let mut instruction = test.initialize(None, None);
+    instruction.accounts[11].is_signer = false;
+    assert_eq!(
+        context.process(instruction, &[&test.swap]).await,
+        Err(amm_error(AmmError::InvalidSigner))
+    );
+
+    let other_owner = Keypair::new();
+    let mut instruction = test.initialize(None, None);
+    instruction.accounts[11].pubkey = other_owner.pubkey();
+    assert_eq!(
+        context
+            .process(instruction, &[&test.swap, &other_owner])
+            .await,
+        Err(amm_error(AmmError::InvalidOwner))
+    );
+
+    let mut instruction = test.initialize(None, None);
+    instruction.accounts[9].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::IncorrectTokenProgramId))
+    );
+
+    let mut instruction = test.initialize(None, None);
+    instruction.accounts[4].pubkey = test.pool_mint;
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::ExpectedAccount))
+    );
+
+    let mut instruction = test.initialize(None, None);
+    instruction.accounts[6].pubkey = test.fee_a;
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::ExpectedMint))
+    );
+
+    let mut instruction = test.initialize(None, None);
+    instruction.accounts[10].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::IncorrectMarketOwnerAccount))
+    );
+
+    let fees = Fees {
+        return_fee_numerator: state_fees().return_fee_numerator - 1,
+        ..state_fees()
+    };
+    assert_eq!(
+        context
+            .process(
+                test.initialize(Some(fees), Some(constant_product_curve())),
+                &signers
+            )
+            .await,
+        Err(amm_error(AmmError::InvalidFee))
+    );
+
+    let swap_curve = SwapCurve {
+        curve_type: CurveType::ConstantPrice,
+        calculator: Box::new(ConstantPriceCurve { token_b_price: 1 }),
+    };
+    assert_eq!(
+        context
+            .process(
+                test.initialize(Some(state_fees()), Some(swap_curve)),
+                &signers
+            )
+            .await,
+        Err(amm_error(AmmError::UnsupportedCurveType))
+    );
+}
+
+#[tokio::test]
+async fn test_initialize_account_errors() {
+    let mut test = SwapTest::new();
+    let mut state_data = vec![0; ProgramStateV2::LEN];
+    ProgramStateV2::default().pack_into_slice(&mut state_data);
+    test.accounts.get_mut(&test.state).unwrap().data = state_data;
+    let mut context = test.start().await;
+    assert_eq!(
+        context
+            .process(
+                test.initialize(None, None),
+                &[&test.swap, &test.state_owner]
+            )
+            .await,
+        Err(amm_error(AmmError::NotInitializedState))
+    );
+
+    assert_eq!(
+        initialize_error(|test| {
+            let owner = Pubkey::new_unique();
+            test.set_token_account(test.token_a, |token| token.owner = owner)
+        })
+        .await,
+        amm_error(AmmError::InvalidOwner)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            let authority = test.authority;
+            test.set_token_account(test.user_pool, |token| token.owner = authority)
+        })
+        .await,
+        amm_error(AmmError::InvalidOutputOwner)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            let owner = test.user.pubkey();
+            test.set_token_account(test.lock, |token| token.owner = owner)
+        })
+        .await,
+        amm_error(AmmError::IncorrectLockAccount)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            test.set_mint(test.pool_mint, |mint| mint.mint_authority = COption::None)
+        })
+        .await,
+        amm_error(AmmError::InvalidOwner)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            let mint = SplAccount::unpack(&test.accounts[&test.token_a].data)
+                .unwrap()
+                .mint;
+            test.set_token_account(test.token_b, |token| token.mint = mint)
+        })
+        .await,
+        amm_error(AmmError::RepeatedMint)
+    );
+    assert_eq!(
+        initialize_error(|test| test.set_token_account(test.token_a, |token| token.amount = 0))
+            .await,
+        amm_error(AmmError::EmptySupply)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            test.set_token_account(test.token_b, |token| {
+                token.delegate = COption::Some(Pubkey::new_unique());
+                token.delegated_amount = 1;
+            })
+        })
+        .await,
+        amm_error(AmmError::InvalidDelegate)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            test.set_token_account(test.token_a, |token| token.state = AccountState::Frozen)
+        })
+        .await,
+        amm_error(AmmError::InvalidFreezeAuthority)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            test.set_token_account(test.token_b, |token| {
+                token.close_authority = COption::Some(Pubkey::new_unique())
+            })
+        })
+        .await,
+        amm_error(AmmError::InvalidCloseAuthority)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            test.set_mint(test.pool_mint, |mint| mint.decimals = LP_MINT_DECIMALS + 1)
+        })
+        .await,
+        amm_error(AmmError::InvalidDecimals)
+    );
+    assert_eq!(
+        initialize_error(|test| test.set_mint(test.pool_mint, |mint| mint.supply = 1)).await,
+        amm_error(AmmError::InvalidSupply)
+    );
+    assert_eq!(
+        initialize_error(|test| {
+            test.set_mint(test.pool_mint, |mint| {
+                mint.freeze_authority = COption::Some(Pubkey::new_unique())
+            })
+        })
+        .await,
+        amm_error(AmmError::InvalidFreezeAuthority)
+    );
+}
+
+#[tokio::test]
+async fn test_swap() {
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+    let amount_in = TOKEN_A_AMOUNT / 100;
+    let owner_fee = state_fees().fixed_fee(amount_in as u128).unwrap() as u64;
+
+    context
+        .process(test.swap(amount_in, 1), &[&test.user])
+        .await
+        .unwrap();
+    assert_eq!(
+        context.token_amount(&test.user_token_a).await,
+        USER_AMOUNT - amount_in
+    );
+    assert_eq!(context.token_amount(&test.fee_a).await, owner_fee);
+    assert_eq!(
+        context.token_amount(&test.token_a).await,
+        TOKEN_A_AMOUNT + amount_in - owner_fee
+    );
+    let amount_out = context.token_amount(&test.user_token_b).await - USER_AMOUNT;
+    assert!(amount_out > 0);
+    assert_eq!(
+        context.token_amount(&test.token_b).await,
+        TOKEN_B_AMOUNT - amount_out
+    );
+
+    let amount_out = TOKEN_B_AMOUNT / 100;
+    context
+        .process(
+            swap_exact_amount_out(
+                &id(),
+                &spl_token::id(),
+                &test.swap.pubkey(),
+                &test.authority,
+                &test.user.pubkey(),
+                &test.state,
+                &test.user_token_a,
+                &test.token_a,
+                &test.token_b,
+                &test.user_token_b,
+                &test.pool_mint,
+                &test.fee_a,
+                &test.fee_owner,
+                None,
+                SwapExactAmountOutInstruction {
+                    amount_out,
+                    maximum_amount_in: USER_AMOUNT,
+                },
+            )
+            .unwrap(),
+            &[&test.user],
+        )
+        .await
+        .unwrap();
+    let user_token_b = context.token_amount(&test.user_token_b).await;
+    assert!(user_token_b >= USER_AMOUNT + amount_out);
+}
+
+#[tokio::test]
+async fn test_swap_wsol_fee() {
+    let test = SwapTest::with_mints(
+        Pubkey::from_str(WSOL_MINT_ADDRESS).unwrap(),
+        Pubkey::new_unique(),
+    );
+    let mut context = test.start_pool().await;
+    let amount_in = TOKEN_A_AMOUNT / 100;
+    let owner_fee = state_fees().fixed_fee(amount_in as u128).unwrap() as u64;
+    let fee_owner_lamports = context.lamports(&test.fee_owner).await;
+
+    // the fee of a WSOL swap is paid in SOL by the user, not from the
+    // source token account
+    context
+        .process(test.swap(amount_in, 1), &[&test.user])
+        .await
+        .unwrap();
+    assert_eq!(
+        context.lamports(&test.fee_owner).await,
+        fee_owner_lamports + owner_fee
+    );
+    assert_eq!(
+        context.lamports(&test.user.pubkey()).await,
+        LAMPORTS - owner_fee
+    );
+    assert_eq!(context.token_amount(&test.fee_a).await, 0);
+    assert_eq!(
+        context.token_amount(&test.user_token_a).await,
+        USER_AMOUNT - (amount_in - owner_fee)
+    );
+    assert_eq!(
+        context.token_amount(&test.token_a).await,
+        TOKEN_A_AMOUNT + amount_in - owner_fee
+    );
+}
+
+#[tokio::test]
+async fn test_swap_errors() {
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+    let signers = [&test.user];
+
+    let mut instruction = test.swap(1_000, 1);
+    instruction.accounts[3].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::InvalidStateAddress))
+    );
+
+    let mut instruction = test.swap(1_000, 1);
+    instruction.accounts[1].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::InvalidProgramAddress))
+    );
+
+    let mut instruction = test.swap(1_000, 1);
+    instruction.accounts[5].pubkey = test.user_token_b;
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::IncorrectSwapAccount))
+    );
+
+    let mut instruction = test.swap(1_000, 1);
+    instruction.accounts[6].pubkey = test.token_a;
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::InvalidInput))
+    );
+
+    let mut instruction = test.swap(1_000, 1);
+    instruction.accounts[8].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::IncorrectPoolMint))
+    );
+
+    let mut instruction = test.swap(1_000, 1);
+    instruction.accounts[11].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::IncorrectTokenProgramId))
+    );
+
+    let mut instruction = test.swap(1_000, 1);
+    instruction.accounts[10].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::InvalidOwner))
+    );
+
+    let mut instruction = test.swap(1_000, 1);
+    instruction.accounts[9].pubkey = test.fee_b;
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::IncorrectFeeAccount))
+    );
+
+    assert_eq!(
+        context
+            .process(test.swap(1_000, USER_AMOUNT), &signers)
+            .await,
+        Err(amm_error(AmmError::ExceededSlippage))
+    );
+
+    context
+        .process(
+            set_pause(
+                &id(),
+                &test.state,
+                &test.state_owner.pubkey(),
+                Some(&test.swap.pubkey()),
+                SetPauseInstruction { is_paused: true },
+            )
+            .unwrap(),
+            &[&test.state_owner],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        context.process(test.swap(1_000, 1), &signers).await,
+        Err(amm_error(AmmError::Paused))
+    );
+}
+
+#[tokio::test]
+async fn test_route_swap() {
+    let mut test = SwapTest::new();
+    let next = test.next_hop();
+    let mut context = test.start_pool().await;
+    context
+        .process(next.initialize(None, None), &[&next.swap, &next.state_owner])
+        .await
+        .unwrap();
+    let signers = [&test.user];
+    let hops = [test.route_hop(), next.route_hop()];
+    let amount_in = TOKEN_A_AMOUNT / 100;
+    let token_b_out = constant_product_curve()
+        .swap(
+            amount_in.into(),
+            TOKEN_A_AMOUNT.into(),
+            TOKEN_B_AMOUNT.into(),
+            TradeDirection::AtoB,
+            &state_fees(),
+        )
+        .unwrap()
+        .destination_amount_swapped;
+    let token_c_out = constant_product_curve()
+        .swap(
+            token_b_out,
+            TOKEN_A_AMOUNT.into(),
+            TOKEN_B_AMOUNT.into(),
+            TradeDirection::AtoB,
+            &state_fees(),
+        )
+        .unwrap()
+        .destination_amount_swapped as u64;
+    assert!(token_c_out as u128 > token_b_out);
+
+    // hops are chained through the user account between the pools
+    let mut unchained = [test.route_hop(), next.route_hop()];
+    unchained[1].source_pubkey = test.user_token_a;
+    assert_eq!(
+        context
+            .process(test.route_swap(&unchained, amount_in, 1), &signers)
+            .await,
+        Err(amm_error(AmmError::InvalidInput))
+    );
+
+    // every hop takes the same number of accounts
+    let mut instruction = test.route_swap(&hops, amount_in, 1);
+    instruction.accounts.pop();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::NotEnoughAccountKeys
+        ))
+    );
+    assert_eq!(
+        context.process(test.route_swap(&[], amount_in, 1), &signers).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::NotEnoughAccountKeys
+        ))
+    );
+
+    // only the output of the last pool is checked against the minimum
+    assert_eq!(
+        context
+            .process(test.route_swap(&hops, amount_in, token_c_out + 1), &signers)
+            .await,
+        Err(amm_error(AmmError::ExceededSlippage))
+    );
+    context
+        .process(test.route_swap(&hops, amount_in, token_c_out), &signers)
+        .await
+        .unwrap();
+    assert_eq!(
+        context.token_amount(&test.user_token_a).await,
+        USER_AMOUNT - amount_in
+    );
+    assert_eq!(context.token_amount(&test.user_token_b).await, USER_AMOUNT);
+    assert_eq!(
+        context.token_amount(&next.user_token_b).await,
+        USER_AMOUNT + token_c_out
+    );
+}
+
+#[tokio::test]
+async fn test_deposit_and_withdraw_all_token_types() {
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+    let pool_token_amount = INITIAL_SWAP_POOL_AMOUNT / 100;
+
+    context
+        .process(
+            test.deposit_all_token_types(DepositInstruction {
+                pool_token_amount,
+                maximum_token_a_amount: USER_AMOUNT,
+                maximum_token_b_amount: USER_AMOUNT,
+            }),
+            &[&test.user],
+        )
+        .await
+        .unwrap();
+    assert_eq!(context.token_amount(&test.user_pool).await, USER_POOL_AMOUNT + pool_token_amount);
+    assert_eq!(
+        context.token_amount(&test.token_a).await,
+        TOKEN_A_AMOUNT + TOKEN_A_AMOUNT / 100
+    );
+    assert_eq!(
+        context.token_amount(&test.token_b).await,
+        TOKEN_B_AMOUNT + TOKEN_B_AMOUNT / 100
+    );
+
+    context
+        .process(
+            test.withdraw_all_token_types(WithdrawInstruction {
+                pool_token_amount,
+                minimum_token_a_amount: 1,
+                minimum_token_b_amount: 1,
+            }),
+            &[&test.user],
+        )
+        .await
+        .unwrap();
+    assert_eq!(context.token_amount(&test.user_pool).await, USER_POOL_AMOUNT);
+    assert_eq!(context.token_amount(&test.token_a).await, TOKEN_A_AMOUNT);
+    assert_eq!(context.token_amount(&test.token_b).await, TOKEN_B_AMOUNT);
+    assert_eq!(context.token_amount(&test.user_token_a).await, USER_AMOUNT);
+    assert_eq!(context.token_amount(&test.user_token_b).await, USER_AMOUNT);
+}
+
+#[tokio::test]
+async fn test_close_pool() {
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+    let recipient = Pubkey::new_unique();
+
+    assert_eq!(
+        context
+            .process(test.close_pool(&recipient, false), &[&test.state_owner])
+            .await,
+        Err(amm_error(AmmError::PoolNotClosable))
+    );
+
+    // withdrawing every pool token leaves the locked minimum supply, the
+    // pool can then be closed
+    context
+        .process(
+            test.withdraw_all_token_types(WithdrawInstruction {
+                pool_token_amount: USER_POOL_AMOUNT,
+                minimum_token_a_amount: 1,
+                minimum_token_b_amount: 1,
+            }),
+            &[&test.user],
+        )
+        .await
+        .unwrap();
+    let token_a_dust = context.token_amount(&test.token_a).await;
+    let token_b_dust = context.token_amount(&test.token_b).await;
+    let pool_marker = test.pool_marker(CurveType::ConstantProduct);
+    let reclaimed = context.lamports(&test.swap.pubkey()).await
+        + context.lamports(&test.token_a).await
+        + context.lamports(&test.token_b).await
+        + context.lamports(&pool_marker).await;
+    context.refresh_blockhash().await;
+    context
+        .process(test.close_pool(&recipient, false), &[&test.state_owner])
+        .await
+        .unwrap();
+
+    assert_eq!(context.token_amount(&test.fee_a).await, token_a_dust);
+    assert_eq!(context.token_amount(&test.fee_b).await, token_b_dust);
+    assert!(context.account(&test.swap.pubkey()).await.is_none());
+    assert!(context.account(&test.token_a).await.is_none());
+    assert!(context.account(&test.token_b).await.is_none());
+    assert!(context.account(&pool_marker).await.is_none());
+    assert_eq!(context.lamports(&recipient).await, reclaimed);
+}
+
+#[tokio::test]
+async fn test_wind_down_pool() {
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+    let recipient = Pubkey::new_unique();
+
+    // only a paused pool is wound down
+    assert_eq!(
+        context
+            .process(test.close_pool(&recipient, true), &[&test.state_owner])
+            .await,
+        Err(amm_error(AmmError::PoolNotClosable))
+    );
+    context
+        .process(
+            set_pause(
+                &id(),
+                &test.state,
+                &test.state_owner.pubkey(),
+                Some(&test.swap.pubkey()),
+                SetPauseInstruction { is_paused: true },
+            )
+            .unwrap(),
+            &[&test.state_owner],
+        )
+        .await
+        .unwrap();
+
+    // the pool tokens of the user are outstanding, the fee owner receives
+    // the whole pool
+    context.refresh_blockhash().await;
+    context
+        .process(test.close_pool(&recipient, true), &[&test.state_owner])
+        .await
+        .unwrap();
+    assert_eq!(context.token_amount(&test.user_pool).await, USER_POOL_AMOUNT);
+    assert_eq!(context.token_amount(&test.fee_a).await, TOKEN_A_AMOUNT);
+    assert_eq!(context.token_amount(&test.fee_b).await, TOKEN_B_AMOUNT);
+    assert!(context.account(&test.swap.pubkey()).await.is_none());
+    assert!(context.account(&test.token_a).await.is_none());
+    assert!(context.account(&test.token_b).await.is_none());
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_accounts() {
+    let mut test = SwapTest::new();
+    let program_id = id();
+
+    // the first layouts, as deployed: a state without version byte and a
+    // swap whose fees and curve are the ones of the state
+    let legacy_fees = Fees {
+        return_fee_numerator: 25,
+        fixed_fee_numerator: 5,
+        fee_denominator: 10_000,
+        ..Fees::default()
+    };
+    let mut state_data = vec![0; ProgramState::LEN];
+    ProgramState {
+        is_initialized: true,
+        state_owner: test.state_owner.pubkey(),
+        fee_owner: test.fee_owner,
+        initial_supply: INITIAL_SWAP_POOL_AMOUNT,
+        fees: legacy_fees.clone(),
+        swap_curve: constant_product_curve(),
+    }
+    .pack_into_slice(&mut state_data);
+    assert_eq!(state_data.len(), 130);
+    test.accounts
+        .insert(test.state, program_account(state_data, &program_id));
+
+    let mut swap_data = vec![0; 1 + SwapV1::LEN];
+    swap_data[0] = 1;
+    SwapV1 {
+        is_initialized: true,
+        nonce: test.nonce,
+        amm_id: Pubkey::new_unique(),
+        dex_program_id: test.dex_program_id,
+        market_id: test.market,
+        token_program_id: spl_token::id(),
+        token_a: test.token_a,
+        token_b: test.token_b,
+        pool_mint: test.pool_mint,
+        token_a_mint: test.token_a_mint,
+        token_b_mint: test.token_b_mint,
+    }
+    .pack_into_slice(&mut swap_data[1..]);
+    assert_eq!(swap_data.len(), 291);
+    test.accounts
+        .insert(test.swap.pubkey(), program_account(swap_data, &program_id));
+    let mut context = test.start().await;
+
+    // the swap can't be used before it is migrated
+    assert_eq!(
+        context.process(test.swap(1_000, 1), &[&test.user]).await,
+        Err(amm_error(AmmError::AccountNotMigrated))
+    );
+
+    context
+        .process(
+            migrate(&program_id, &test.state, &test.state, &test.user.pubkey()).unwrap(),
+            &[&test.user],
+        )
+        .await
+        .unwrap();
+    let state_account = context.account(&test.state).await.unwrap();
+    assert_eq!(state_account.data.len(), ProgramStateV2::LEN);
+    assert_eq!(state_account.data[0], ProgramStateV2::VERSION);
+    let state = ProgramStateV2::unpack_from_slice(&state_account.data).unwrap();
+    assert!(state.is_initialized());
+    assert_eq!(*state.state_owner(), test.state_owner.pubkey());
+    assert_eq!(*state.fee_owner(), test.fee_owner);
+    assert_eq!(state.initial_supply(), INITIAL_SWAP_POOL_AMOUNT);
+    assert_eq!(*state.fees(), legacy_fees);
+    assert_eq!(*state.swap_curve(), constant_product_curve());
+    assert!(!state.is_paused());
+
+    context
+        .process(
+            migrate(&program_id, &test.swap.pubkey(), &test.state, &test.user.pubkey()).unwrap(),
+            &[&test.user],
+        )
+        .await
+        .unwrap();
+    let swap_account = context.account(&test.swap.pubkey()).await.unwrap();
+    assert_eq!(swap_account.data.len(), SwapVersion::LATEST_LEN);
+    let token_swap = SwapVersion::unpack(&swap_account.data).unwrap();
+    assert_eq!(*token_swap.token_a_account(), test.token_a);
+    assert_eq!(*token_swap.token_b_account(), test.token_b);
+    assert_eq!(*token_swap.pool_mint(), test.pool_mint);
+    assert_eq!(*token_swap.fees(), legacy_fees);
+    assert_eq!(*token_swap.swap_curve(), constant_product_curve());
+
+    // both accounts are in the latest layout, and the pool trades again
+    context.refresh_blockhash().await;
+    for key in [test.state, test.swap.pubkey()] {
+        assert_eq!(
+            context
+                .process(
+                    migrate(&program_id, &key, &test.state, &test.user.pubkey()).unwrap(),
+                    &[&test.user],
+                )
+                .await,
+            Err(amm_error(AmmError::AlreadyMigrated))
+        );
+    }
+    context.process(test.swap(1_000, 1), &[&test.user]).await.unwrap();
+    assert_eq!(context.token_amount(&test.user_token_a).await, USER_AMOUNT - 1_000);
+}
+
+#[tokio::test]
+async fn test_deposit_and_withdraw_single_token_type() {
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+    let source_token_amount = TOKEN_A_AMOUNT / 100;
+
+    context
+        .process(
+            test.deposit_single_token_type(
+                &test.user_token_a,
+                &test.fee_a,
+                DepositSingleTokenTypeExactAmountIn {
+                    source_token_amount,
+                    minimum_pool_token_amount: 1,
+                },
+            ),
+            &[&test.user],
+        )
+        .await
+        .unwrap();
+    let pool_token_amount = context.token_amount(&test.user_pool).await - USER_POOL_AMOUNT;
+    assert!(pool_token_amount > 0);
+    let owner_fee = context.token_amount(&test.fee_a).await;
+    assert!(owner_fee > 0);
+    assert_eq!(
+        context.token_amount(&test.token_a).await,
+        TOKEN_A_AMOUNT + source_token_amount - owner_fee
+    );
+
+    let destination_token_amount = TOKEN_B_AMOUNT / 1000;
+    context
+        .process(
+            test.withdraw_single_token_type(
+                &test.user_token_b,
+                &test.fee_b,
+                WithdrawSingleTokenTypeExactAmountOut {
+                    destination_token_amount,
+                    maximum_pool_token_amount: pool_token_amount,
+                },
+            ),
+            &[&test.user],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        context.token_amount(&test.user_token_b).await,
+        USER_AMOUNT + destination_token_amount
+    );
+    let owner_fee = context.token_amount(&test.fee_b).await;
+    assert!(owner_fee > 0);
+    assert_eq!(
+        context.token_amount(&test.token_b).await,
+        TOKEN_B_AMOUNT - destination_token_amount - owner_fee
+    );
+    assert!(context.token_amount(&test.user_pool).await < USER_POOL_AMOUNT + pool_token_amount);
+}
+
+#[tokio::test]
+async fn test_deposit_and_withdraw_errors() {
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+    let signers = [&test.user];
+    let deposit = DepositInstruction {
+        pool_token_amount: INITIAL_SWAP_POOL_AMOUNT / 100,
+        maximum_token_a_amount: USER_AMOUNT,
+        maximum_token_b_amount: USER_AMOUNT,
+    };
+
+    assert_eq!(
+        context
+            .process(
+                test.deposit_all_token_types(DepositInstruction {
+                    maximum_token_a_amount: 1,
+                    ..deposit.clone()
+                }),
+                &signers,
+            )
+            .await,
+        Err(amm_error(AmmError::ExceededSlippage))
+    );
+    assert_eq!(
+        context
+            .process(
+                test.deposit_all_token_types(DepositInstruction {
+                    pool_token_amount: 0,
+                    ..deposit.clone()
+                }),
+                &signers,
+            )
+            .await,
+        Err(amm_error(AmmError::ZeroTradingTokens))
+    );
+
+    let mut instruction = test.deposit_all_token_types(deposit.clone());
+    instruction.accounts[8].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::IncorrectPoolMint))
+    );
+
+    let mut instruction = test.deposit_all_token_types(deposit);
+    instruction.accounts[4].pubkey = test.token_a;
+    assert_eq!(
+        context.process(instruction, &signers).await,
+        Err(amm_error(AmmError::InvalidInput))
+    );
+
+    assert_eq!(
+        context
+            .process(
+                test.withdraw_all_token_types(WithdrawInstruction {
+                    pool_token_amount: INITIAL_SWAP_POOL_AMOUNT / 100,
+                    minimum_token_a_amount: TOKEN_A_AMOUNT,
+                    minimum_token_b_amount: 0,
+                }),
+                &signers,
+            )
+            .await,
+        Err(amm_error(AmmError::ExceededSlippage))
+    );
+
+    assert_eq!(
+        context
+            .process(
+                test.deposit_single_token_type(
+                    &test.user_pool,
+                    &test.fee_a,
+                    DepositSingleTokenTypeExactAmountIn {
+                        source_token_amount: 1_000,
+                        minimum_pool_token_amount: 0,
+                    },
+                ),
+                &signers,
+            )
+            .await,
+        Err(amm_error(AmmError::IncorrectSwapAccount))
+    );
+    assert_eq!(
+        context
+            .process(
+                test.deposit_single_token_type(
+                    &test.user_token_a,
+                    &test.fee_a,
+                    DepositSingleTokenTypeExactAmountIn {
+                        source_token_amount: 1_000,
+                        minimum_pool_token_amount: INITIAL_SWAP_POOL_AMOUNT,
+                    },
+                ),
+                &signers,
+            )
+            .await,
+        Err(amm_error(AmmError::ExceededSlippage))
+    );
+
+    assert_eq!(
+        context
+            .process(
+                test.withdraw_single_token_type(
+                    &test.user_pool,
+                    &test.fee_a,
+                    WithdrawSingleTokenTypeExactAmountOut {
+                        destination_token_amount: 1_000,
+                        maximum_pool_token_amount: INITIAL_SWAP_POOL_AMOUNT,
+                    },
+                ),
+                &signers,
+            )
+            .await,
+        Err(amm_error(AmmError::IncorrectSwapAccount))
+    );
+    assert_eq!(
+        context
+            .process(
+                test.withdraw_single_token_type(
+                    &test.user_token_a,
+                    &test.fee_a,
+                    WithdrawSingleTokenTypeExactAmountOut {
+                        destination_token_amount: 1_000,
+                        maximum_pool_token_amount: 1,
+                    },
+                ),
+                &signers,
+            )
+            .await,
+        Err(amm_error(AmmError::ExceededSlippage))
+    );
+}
+
+#[tokio::test]
+async fn test_ramp_amp_errors() {
+    let ramp = |test: &SwapTest, target_amp| {
+        ramp_amp(
+            &id(),
+            &test.swap.pubkey(),
+            &test.state,
+            &test.state_owner.pubkey(),
+            RampAmpInstruction {
+                target_amp,
+                stop_ramp_ts: 0,
+            },
+        )
+        .unwrap()
+    };
+
+    let test = SwapTest::new();
+    let mut context = test.start_pool().await;
+    assert_eq!(
+        context
+            .process(ramp(&test, 200), &[&test.state_owner])
+            .await,
+        Err(amm_error(AmmError::UnsupportedCurveOperation))
+    );
+
+    let test = SwapTest::new();
+    let mut context = test.start().await;
+    let swap_curve = SwapCurve {
+        curve_type: CurveType::Stable,
+        calculator: Box::new(StableCurve {
+            amp: 100,
+            ..StableCurve::default()
+        }),
+    };
+    context
+        .process(
+            test.initialize(Some(state_fees()), Some(swap_curve)),
+            &[&test.swap, &test.state_owner],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        context
+            .process(ramp(&test, 200), &[&test.state_owner])
+            .await,
+        Err(amm_error(AmmError::InvalidAmpRamp))
+    );
+}
+
+#[tokio::test]
+async fn test_concentrated_errors() {
+    let mut test = SwapTest::new();
+    let program_id = id();
+    let pool = Pubkey::new_unique();
+    let (authority, nonce) = Pubkey::find_program_address(&[&pool.to_bytes()[..]], &program_id);
+    let vault_a = Pubkey::new_unique();
+    let vault_b = Pubkey::new_unique();
+    let position = Pubkey::new_unique();
+    let other_position = Pubkey::new_unique();
+    let accounts = vec![
+        (
+            pool,
+            program_account(vec![0; ConcentratedPool::LEN], &program_id),
+        ),
+        (vault_a, token_account(&Pubkey::new_unique(), &authority, 0)),
+        (vault_b, token_account(&Pubkey::new_unique(), &authority, 0)),
+        (
+            position,
+            program_account(vec![0; Position::LEN], &program_id),
+        ),
+        (
+            other_position,
+            program_account(vec![0; Position::LEN], &program_id),
+        ),
+    ];
+    test.accounts.extend(accounts);
+    let mut context = test.start().await;
+    context
+        .process(
+            initialize_concentrated_pool(
+                &program_id,
+                &spl_token::id(),
+                &pool,
+                &authority,
+                &test.state,
+                &vault_a,
+                &vault_b,
+                &test.state_owner.pubkey(),
+                InitializeConcentratedPoolInstruction {
+                    nonce,
+                    tick_spacing: 10,
+                    initial_sqrt_price: 1u128 << 64,
+                    fees: None,
+                },
+            )
+            .unwrap(),
+            &[&test.state_owner],
+        )
+        .await
+        .unwrap();
+    let open = |position: &Pubkey, tick_lower, tick_upper| {
+        open_position(
+            &program_id,
+            &pool,
+            position,
+            &test.user.pubkey(),
+            OpenPositionInstruction {
+                tick_lower,
+                tick_upper,
+            },
+        )
+        .unwrap()
+    };
+
+    assert_eq!(
+        context
+            .process(open(&other_position, 1000, -1000), &[&test.user])
+            .await,
+        Err(amm_error(AmmError::InvalidTickRange))
+    );
+    context
+        .process(open(&position, -1000, 1000), &[&test.user])
+        .await
+        .unwrap();
+    assert_eq!(
+        context
+            .process(
+                decrease_liquidity(
+                    &program_id,
+                    &spl_token::id(),
+                    &pool,
+                    &authority,
+                    &position,
+                    &test.user.pubkey(),
+                    &test.user_token_a,
+                    &test.user_token_b,
+                    &vault_a,
+                    &vault_b,
+                    DecreaseLiquidityInstruction {
+                        liquidity: 1,
+                        minimum_token_a_amount: 0,
+                        minimum_token_b_amount: 0,
+                    },
+                )
+                .unwrap(),
+                &[&test.user],
+            )
+            .await,
+        Err(amm_error(AmmError::InsufficientLiquidity))
+    );
+}